@@ -0,0 +1,235 @@
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
+
+use eyre::Context;
+use tempfile::NamedTempFile;
+
+/// How many times to try renaming a tempfile into place, for errors that
+/// might not happen again, like a virus scanner holding the file open.
+const ATTEMPTS: u32 = 3;
+
+/// Replace the file at `path`, or create it, with `contents`, so that it
+/// either has all of them or is as it was.
+///
+/// The contents go into a tempfile beside `path` first, to be renamed over
+/// it, so the rename doesn't cross filesystems. If it's replacing a file, it
+/// gets that file's permissions, its owner where allowed, and its
+/// modification time too if `keep_mtime`.
+pub fn replace(path: &Path, contents: &[u8], keep_mtime: bool) -> eyre::Result<()> {
+    let original = fs::metadata(path).ok();
+    let tmpfile = write_beside(path, contents, original.as_ref(), keep_mtime)?;
+    persist(
+        tmpfile,
+        path,
+        false,
+        original.as_ref().filter(|_| keep_mtime),
+    )
+    .context("couldn't rename tempfile over original path")
+}
+
+/// Write `contents` to `to`, which mustn't exist yet, as [`replace`] would,
+/// then remove `from`, whose permissions and owner it gets.
+///
+/// On filesystems that ignore case or Unicode normalization, `to` may name
+/// the same file as `from`, which is then replaced instead.
+pub fn relocate(from: &Path, to: &Path, contents: &[u8], keep_mtime: bool) -> eyre::Result<()> {
+    let original = fs::metadata(from).ok();
+    let same = same_file(from, to);
+    let tmpfile = write_beside(to, contents, original.as_ref(), keep_mtime)?;
+    persist(tmpfile, to, !same, original.as_ref().filter(|_| keep_mtime))
+        .context("couldn't rename tempfile to new path")?;
+    if !same {
+        fs::remove_file(from).context("couldn't remove file from old path")?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn write_beside(
+    path: &Path,
+    contents: &[u8],
+    like: Option<&Metadata>,
+    keep_mtime: bool,
+) -> eyre::Result<NamedTempFile> {
+    // the system's temp dir will do if the target's is somewhere we can't
+    // create files, but renaming from there may cross filesystems
+    let mut tmpfile = NamedTempFile::new_in(directory(path))
+        .or_else(|_| NamedTempFile::new())
+        .context("couldn't create tempfile")?;
+    tmpfile
+        .write_all(contents)
+        .context("couldn't write fixed file to tempfile")?;
+    tmpfile
+        .as_file()
+        .sync_all()
+        .context("couldn't flush tempfile to disk")?;
+    if let Some(like) = like {
+        if keep_mtime {
+            set_mtime(tmpfile.as_file(), like).context("couldn't keep modification time")?;
+        }
+        copy_ownership(tmpfile.path(), like).context("couldn't copy permissions to tempfile")?;
+    }
+    Ok(tmpfile)
+}
+
+/// Rename `tmpfile` to `path`, trying again after errors that might pass,
+/// and copying it beside `path` to rename from there if renaming would
+/// cross filesystems.
+fn persist(
+    mut tmpfile: NamedTempFile,
+    path: &Path,
+    noclobber: bool,
+    mtime_of: Option<&Metadata>,
+) -> eyre::Result<()> {
+    let mut attempt = 1;
+    loop {
+        let persisted = if noclobber {
+            tmpfile.persist_noclobber(path)
+        } else {
+            tmpfile.persist(path)
+        };
+        let err = match persisted {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+        match err.error.kind() {
+            ErrorKind::CrossesDevices => {
+                return copy_then_rename(err.file.path(), path, noclobber, mtime_of);
+            }
+            kind if attempt < ATTEMPTS && transient(kind) => {
+                thread::sleep(Duration::from_millis(50 << attempt));
+                attempt += 1;
+                tmpfile = err.file;
+            }
+            _ => return Err(err.error.into()),
+        }
+    }
+}
+
+fn copy_then_rename(
+    from: &Path,
+    to: &Path,
+    noclobber: bool,
+    mtime_of: Option<&Metadata>,
+) -> eyre::Result<()> {
+    if noclobber && to.exists() {
+        return Err(io::Error::from(ErrorKind::AlreadyExists).into());
+    }
+    let name = to.file_name().unwrap_or_default().to_string_lossy();
+    let sibling = directory(to).join(format!(".{}.frontmatter-fixer-{}", name, process::id()));
+    let copy = || -> io::Result<()> {
+        let mut file = File::create(&sibling)?;
+        io::copy(&mut File::open(from)?, &mut file)?;
+        file.sync_all()?;
+        if let Some(metadata) = mtime_of {
+            set_mtime(&file, metadata)?;
+        }
+        copy_ownership(&sibling, &fs::metadata(from)?)?;
+        fs::rename(&sibling, to)
+    };
+    let copied = copy();
+    if copied.is_err() {
+        let _ = fs::remove_file(&sibling);
+    }
+    copied.context("couldn't copy tempfile across filesystems")
+}
+
+fn set_mtime(file: &File, like: &Metadata) -> io::Result<()> {
+    file.set_modified(like.modified()?)
+}
+
+/// Give the file at `path` the permissions of the file `like` describes,
+/// and its owner where allowed.
+fn copy_ownership(path: &Path, like: &Metadata) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{chown, MetadataExt};
+        // only root can give files away, so others keep their own
+        let _ = chown(path, Some(like.uid()), Some(like.gid()));
+    }
+    fs::set_permissions(path, like.permissions())
+}
+
+fn directory(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn transient(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ) || (cfg!(windows) && kind == ErrorKind::PermissionDenied)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    #[test]
+    fn keeps_permissions_and_mtime() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("a.md");
+        fs::write(&path, "old\n")?;
+        let mut permissions = fs::metadata(&path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions)?;
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::open(&path)?.set_modified(old)?;
+
+        replace(&path, b"new\n", true)?;
+        assert_eq!("new\n", fs::read_to_string(&path)?);
+        let metadata = fs::metadata(&path)?;
+        assert!(metadata.permissions().readonly());
+        assert_eq!(old, metadata.modified()?);
+
+        let moved = dir.path().join("b.md");
+        relocate(&path, &moved, b"moved\n", false)?;
+        assert!(!path.exists());
+        assert!(fs::metadata(&moved)?.permissions().readonly());
+        fs::write(&path, "taken\n")?;
+        assert!(relocate(&moved, &path, b"again\n", false).is_err());
+        // as when only its case changes on a case-insensitive filesystem
+        relocate(&moved, &moved, b"again\n", false)?;
+        assert_eq!("again\n", fs::read_to_string(&moved)?);
+        Ok(())
+    }
+
+    #[test]
+    fn copies_across_filesystems() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        fs::write(&from, "fixed\n")?;
+        fs::write(&to, "original\n")?;
+        copy_then_rename(&from, &to, false, None)?;
+        assert_eq!("fixed\n", fs::read_to_string(&to)?);
+        assert_eq!(2, fs::read_dir(dir.path())?.count());
+        assert!(copy_then_rename(&from, &to, true, None).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,3156 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    fs::{create_dir_all, read, read_dir, read_to_string, remove_file},
+    io::{self, stdout, Read, Write},
+    iter,
+    num::NonZeroUsize,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use eyre::{eyre, Context};
+use serde_yaml as yaml;
+
+use crate::{
+    atomic, checksum, coerce,
+    config::Config,
+    confirm::{self, Confirmer},
+    convert::Converter,
+    corpus::{self, Corpus},
+    date, diff,
+    duplicates::{self, Merge},
+    envelope::{self, Envelope},
+    error::FixError,
+    estimate::{self, Estimate},
+    fixer::{Fixer, Script, ScriptOptions},
+    frontmatter::{self, BlankLine, Fidelity, Format, LineEndings, TextStyle},
+    generate::Generators,
+    git, hygiene,
+    inherit::Inheritance,
+    journal::Journal,
+    links::{self, LinkIndex},
+    locales::Locales,
+    manifest::RunManifest,
+    migrate::{self, Versions},
+    nfc,
+    order::KeyOrder,
+    output::{self, Output, Status},
+    patch::{self, Operation, Patches},
+    permalink::{self, Permalink},
+    plan::OutputClaims,
+    provenance::{Provenance, Transform},
+    publish, query,
+    render::Template,
+    replay::{Bundle, RecordedCorpus, RecordedInput},
+    report::{Findings, Report, ReportFormat},
+    safety::{self, Destructive},
+    schema::Schema,
+    snapshot::{self, Change},
+    stamp::{Manifest, Stamp, StampTarget},
+    stats::RunStats,
+    tally::Tally,
+    title,
+    translations::Translations,
+    typos::{KeyStats, TypoFixer},
+    wasm,
+};
+
+/// The path meaning standard input, whose result goes to standard output
+/// instead of modifying a file.
+pub const STDIN_PATH: &str = "-";
+
+/// The error `run` fails with when `--check` finds files that would change,
+/// so callers can tell it apart from files failing.
+#[derive(Debug)]
+pub struct WouldChange(pub usize);
+
+impl fmt::Display for WouldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} files would change", self.0)
+    }
+}
+
+impl Error for WouldChange {}
+
+/// The status to exit with after `result`, whatever ran: 2 if `--check`
+/// found files that would change, 1 if anything else failed.
+pub fn exit_code(result: &eyre::Result<()>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(err) if err.downcast_ref::<WouldChange>().is_some() => 2,
+        Err(_) => 1,
+    }
+}
+
+/// Fix every file `cfg` asks for, then write whatever manifests, stats and
+/// reports it asks for.
+pub fn run(cfg: Config) -> eyre::Result<()> {
+    run_noting_writes(cfg, &mut Vec::new())
+}
+
+/// [`run`], adding every file it writes to `written`: the files fixed or
+/// moved, their backups, sidecars and rendered pages, and the manifests,
+/// stats, journal and reports, even if it fails partway.
+pub fn run_noting_writes(cfg: Config, written: &mut Vec<PathBuf>) -> eyre::Result<()> {
+    // puts the console back afterwards unless the caller got it ready
+    let _console = output::init_console();
+    let cfg = Config {
+        dry_run: cfg.dry_run || cfg.check,
+        ..cfg
+    };
+    let corpora = corpus::load(&cfg)?;
+    let all_paths: Vec<String> = corpora
+        .iter()
+        .flat_map(|corpus| corpus.cfg.paths.iter().cloned())
+        .collect();
+    let stdin_paths = all_paths.iter().filter(|path| *path == STDIN_PATH).count();
+    if stdin_paths > 1 {
+        return Err(eyre!("can only read stdin ({}) once", STDIN_PATH));
+    }
+    if stdin_paths == 1 && (cfg.repl || cfg.inline_scripts.iter().any(|s| s == "-")) {
+        return Err(eyre!(
+            "can't read a file from stdin ({}) while reading the script from it",
+            STDIN_PATH
+        ));
+    }
+    if stdin_paths == 1 && !cfg.dry_run {
+        let to_stdout = if cfg.diff {
+            Some("--diff")
+        } else if cfg.reports.iter().any(|spec| spec.path.is_none()) {
+            Some("a report")
+        } else {
+            None
+        };
+        if let Some(to_stdout) = to_stdout {
+            return Err(eyre!(
+                "can't write {} to stdout while filtering stdin ({}) to it",
+                to_stdout,
+                STDIN_PATH
+            ));
+        }
+    }
+    if stdin_paths == 1 && cfg.interactive {
+        return Err(eyre!(
+            "can't read a file from stdin ({}) while asking about each file",
+            STDIN_PATH
+        ));
+    }
+    if stdin_paths == 1 && cfg.confirm_over.is_some() {
+        return Err(eyre!(
+            "can't read a file from stdin ({}) while estimating changes first",
+            STDIN_PATH
+        ));
+    }
+    if let Some(output_dir) = &cfg.output_dir {
+        check_output_claims(output_dir, &all_paths)?;
+    }
+    if let Some(threshold) = cfg.confirm_over {
+        if !preflight(&corpora, threshold)? {
+            return Err(eyre!("cancelled before changing any files"));
+        }
+    }
+    if cfg.commit_message.is_some() {
+        git::ensure_clean_index()?;
+    }
+    let output = Output::new(&cfg).with_progress(&cfg);
+    output.start(all_paths.len());
+
+    let mut stamp_manifest = match &cfg.version_stamp {
+        Some(StampTarget::Manifest(path)) => Some(Manifest::load(path)?),
+        _ => None,
+    };
+    let mut run_manifest = cfg.manifest_path.as_ref().map(|_| RunManifest::default());
+    let mut journal = cfg.journal_path.as_ref().map(|_| Journal::default());
+    let mut run_stats = RunStats::new(cfg.dry_run);
+    let mut changed_paths: Vec<PathBuf> = Vec::new();
+    let mut report = Report::default();
+
+    let mut ok_paths: Vec<String> = Vec::new();
+    let mut skipped_paths: Vec<(String, Skip)> = Vec::new();
+    let mut err_paths: Vec<(String, eyre::Report)> = Vec::new();
+    let mut would_change: Vec<String> = Vec::new();
+    // each named corpus's files processed successfully, skipped and failed
+    let mut corpus_totals: Vec<(&str, usize, usize, usize)> = Vec::new();
+
+    let msg_process = if cfg.dry_run {
+        "would process"
+    } else {
+        "processed"
+    };
+    let msg_skip = if cfg.dry_run { "would skip" } else { "skipped" };
+    let msg_rename = if cfg.dry_run { "would move" } else { "moved" };
+    let msg_fail = if cfg.dry_run {
+        "would fail to process"
+    } else {
+        "failed to process"
+    };
+
+    // one set of claims, so corpora can't clobber each other's files either
+    let claims = Arc::new(Mutex::new(OutputClaims::default()));
+    let pipelines = corpora
+        .iter()
+        .map(|corpus| {
+            Ok(Pipeline {
+                output: output.clone(),
+                claims: claims.clone(),
+                ..Pipeline::new(&corpus.cfg)?
+            })
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    if let Some(bundle_path) = &cfg.record {
+        record(bundle_path, &cfg, &corpora, &pipelines)?;
+        written.push(bundle_path.clone());
+    }
+
+    for (corpus, pipeline) in corpora.iter().zip(pipelines) {
+        let corpus_cfg = &corpus.cfg;
+        let counts_before = (ok_paths.len(), skipped_paths.len(), err_paths.len());
+
+        // for --rewrite-references: the files as the first pass left them,
+        // and how their frontmatter changed
+        let mut reference_targets = Vec::new();
+        let mut key_changes = Vec::new();
+        // for --merge-duplicates: the duplicates merged into an original,
+        // and the files that came through and so can take their frontmatter
+        let mut merged = Vec::new();
+        let mut came_through = HashSet::new();
+        let in_place = |path: &str| {
+            (path != STDIN_PATH && corpus_cfg.output_dir.is_none()).then(|| PathBuf::from(path))
+        };
+
+        let results = process_all(&pipeline, corpus_cfg)?;
+        for (path, outcome) in corpus_cfg.paths.iter().zip(results) {
+            let (elapsed, result) = match outcome {
+                Some(outcome) => outcome,
+                // --fail-fast stopped before this file
+                None => continue,
+            };
+            run_stats.record_file(
+                elapsed,
+                result.as_ref().is_ok_and(Processed::changed),
+                result.is_err(),
+            );
+            // filtering stdin, the result goes to stdout
+            let filtered = path == STDIN_PATH && !cfg.dry_run;
+            match result {
+                Ok(Processed {
+                    output: unchanged,
+                    skipped: Some(skip),
+                    ..
+                }) => {
+                    if filtered {
+                        stdout().write_all(&unchanged)?;
+                    }
+                    output.verbose(format_args!(
+                        "{} file {} ({})",
+                        msg_skip,
+                        path,
+                        skip.reason()
+                    ));
+                    reference_targets.extend(in_place(path).map(|target| (path.clone(), target)));
+                    report.record_skipped(path, skip.reason());
+                    skipped_paths.push((path.clone(), skip));
+                }
+                Ok(processed) if processed.merged_into.is_some() => {
+                    merged.push((path.clone(), processed));
+                }
+                Ok(processed) => {
+                    came_through.insert(links::normalize(Path::new(path)));
+                    let target = processed.written_to.clone().or_else(|| in_place(path));
+                    reference_targets.extend(target.map(|target| (path.clone(), target)));
+                    key_changes.extend(processed.key_changes.iter().cloned());
+                    written.extend(processed.written_to.iter().cloned());
+                    written.extend(processed.also_written.iter().cloned());
+                    if cfg.check && processed.changed() {
+                        would_change.push(path.clone());
+                    }
+                    if cfg.dry_run && !processed.destructive.is_empty() {
+                        output.status(format_args!(
+                            "{} would {}",
+                            path,
+                            safety::describe(&processed.destructive)
+                        ));
+                    }
+                    if cfg.print_result || filtered {
+                        stdout().write_all(&processed.output)?;
+                    }
+                    if cfg.diff {
+                        let fixed = String::from_utf8_lossy(&processed.output);
+                        if let Some(diff) = diff::unified(&processed.original, &fixed, path) {
+                            stdout().write_all(diff.as_bytes())?;
+                        }
+                    }
+                    output.verbose(format_args!("{} file {} successfully", msg_process, path));
+                    if let Some(renamed_to) = &processed.renamed_to {
+                        output.verbose(format_args!(
+                            "{} {} -> {}",
+                            msg_rename,
+                            path,
+                            renamed_to.display()
+                        ));
+                    }
+                    if let Some(run_manifest) = &mut run_manifest {
+                        run_manifest.record_success(
+                            path,
+                            processed.original.as_bytes(),
+                            &processed.output,
+                            processed.written_to.as_deref(),
+                        );
+                    }
+                    if processed.changed() {
+                        if let Some(written_to) = &processed.written_to {
+                            if let (Some(journal), None) = (&mut journal, &cfg.output_dir) {
+                                journal.record(
+                                    path,
+                                    &processed.original,
+                                    &processed.output,
+                                    written_to,
+                                );
+                            }
+                            changed_paths.push(written_to.clone());
+                            // commit the move, not just the new file
+                            if processed.renamed_to.is_some() && cfg.output_dir.is_none() {
+                                changed_paths.push(PathBuf::from(path));
+                            }
+                        }
+                        if let (Some(manifest), Some(stamp)) =
+                            (&mut stamp_manifest, &pipeline.stamp)
+                        {
+                            manifest.record(path, stamp);
+                        }
+                    }
+                    let (_, document) = frontmatter::split_prefix(
+                        &processed.original,
+                        &corpus_cfg.frontmatter_prefix,
+                    );
+                    if corpus_cfg.envelope(path).unwrap(document).is_some() {
+                        report.record_success(path, processed.changed());
+                    } else {
+                        report.record_skipped(path, "no frontmatter");
+                    }
+                    if let Some(metadata_patch) = processed.metadata_patch {
+                        report.record_patch(path, metadata_patch);
+                    }
+                    ok_paths.push(path.clone());
+                }
+                Err(e) => {
+                    output.verbose(format_args!("{} file {}: {:?}", msg_fail, path, &e));
+                    if let Some(run_manifest) = &mut run_manifest {
+                        run_manifest.record_failure(path, &e);
+                    }
+                    report.record_failure(path, &e);
+                    err_paths.push((path.clone(), e));
+                }
+            }
+        }
+
+        // only once their originals have come through, so no duplicate's
+        // frontmatter is lost with it
+        for (path, processed) in merged {
+            let original = processed.merged_into.as_deref().expect("merged");
+            if !came_through.contains(original) {
+                let skip = Skip::Unmerged;
+                output.verbose(format_args!(
+                    "{} file {} ({})",
+                    msg_skip,
+                    path,
+                    skip.reason()
+                ));
+                report.record_skipped(&path, skip.reason());
+                skipped_paths.push((path, skip));
+                continue;
+            }
+            match delete_duplicate(corpus_cfg, &path, &processed.original, written) {
+                Ok(deleted) => {
+                    output.verbose(format_args!(
+                        "{} duplicate {} of {}",
+                        if cfg.dry_run {
+                            "would delete"
+                        } else {
+                            "deleted"
+                        },
+                        path,
+                        original.display()
+                    ));
+                    if cfg.check {
+                        would_change.push(path.clone());
+                    }
+                    if let Some(run_manifest) = &mut run_manifest {
+                        run_manifest.record_success(
+                            &path,
+                            processed.original.as_bytes(),
+                            &processed.output,
+                            None,
+                        );
+                    }
+                    if deleted {
+                        if let Some(journal) = &mut journal {
+                            journal.record_deletion(&path, &processed.original);
+                        }
+                        changed_paths.push(PathBuf::from(&path));
+                    }
+                    report.record_success(&path, true);
+                    ok_paths.push(path);
+                }
+                Err(e) => {
+                    output.verbose(format_args!("{} file {}: {:?}", msg_fail, path, &e));
+                    if let Some(run_manifest) = &mut run_manifest {
+                        run_manifest.record_failure(&path, &e);
+                    }
+                    report.record_failure(&path, &e);
+                    err_paths.push((path, e));
+                }
+            }
+        }
+
+        if let (Some(script), false) = (&pipeline.references, key_changes.is_empty()) {
+            let (targets, changes) = (&reference_targets, &key_changes);
+            for rewritten in rewrite_references(&pipeline, script, targets, changes)? {
+                let path = &rewritten.path;
+                let written_before = changed_paths.contains(&rewritten.target);
+                match write_rewritten(&pipeline, corpus_cfg, &rewritten, written_before, written) {
+                    Ok(false) => {
+                        output.verbose(format_args!(
+                            "{} rewriting references in {} ({})",
+                            msg_skip,
+                            path,
+                            Skip::Declined.reason()
+                        ));
+                    }
+                    Ok(true) => {
+                        output.verbose(format_args!(
+                            "{} references in {}",
+                            if cfg.dry_run {
+                                "would rewrite"
+                            } else {
+                                "rewrote"
+                            },
+                            path
+                        ));
+                        if cfg.check && !would_change.contains(path) {
+                            would_change.push(path.clone());
+                        }
+                        if cfg.dry_run {
+                            continue;
+                        }
+                        if let (Some(journal), None) = (&mut journal, &corpus_cfg.output_dir) {
+                            journal.record(
+                                path,
+                                &rewritten.original,
+                                rewritten.output.as_bytes(),
+                                &rewritten.target,
+                            );
+                        }
+                        if !written_before {
+                            changed_paths.push(rewritten.target.clone());
+                        }
+                    }
+                    Err(e) => {
+                        output.verbose(format_args!("{} file {}: {:?}", msg_fail, path, &e));
+                        report.record_failure(path, &e);
+                        ok_paths.retain(|ok_path| ok_path != path);
+                        skipped_paths.retain(|(skipped_path, _)| skipped_path != path);
+                        err_paths.push((path.clone(), e));
+                    }
+                }
+            }
+        }
+
+        // only once every file has had the migrations, or those left out
+        // would never get them
+        if let (false, Some(versions), Some(state)) = (
+            cfg.dry_run,
+            &pipeline.migrations,
+            &corpus_cfg.migration_state,
+        ) {
+            let left_out = err_paths.len() - counts_before.2
+                + skipped_paths
+                    .iter()
+                    .skip(counts_before.1)
+                    .filter(|(_, skip)| *skip != Skip::Ignored)
+                    .count();
+            let why = if corpus_cfg.git_changed {
+                Some("only files changed in git were selected".to_owned())
+            } else if left_out > 0 {
+                Some(format!("{} files weren't migrated", left_out))
+            } else {
+                None
+            };
+            match why {
+                None => {
+                    versions.save_state(state)?;
+                    written.push(state.clone());
+                }
+                Some(why) => err_paths.push((
+                    state.display().to_string(),
+                    eyre!(
+                        "not advancing the corpus to migration {}: {}; run it on every file, or \
+                         drop --migration-state to record each file's progress in its own \
+                         `migration` key",
+                        versions.latest(),
+                        why
+                    ),
+                )),
+            }
+        }
+        let failed = err_paths.len() > counts_before.2;
+        if let Some(name) = &corpus.name {
+            corpus_totals.push((
+                name.as_str(),
+                ok_paths.len() - counts_before.0,
+                skipped_paths.len() - counts_before.1,
+                err_paths.len() - counts_before.2,
+            ));
+        }
+        if cfg.fail_fast && failed {
+            output.error("stopping after the first failure");
+            break;
+        }
+    }
+
+    output.finish();
+    if let Some(manifest) = &stamp_manifest {
+        if !cfg.dry_run {
+            manifest.save().context("couldn't save stamp manifest")?;
+            written.push(manifest.path().to_owned());
+        }
+    }
+    if let (Some(run_manifest), Some(manifest_path)) = (&run_manifest, &cfg.manifest_path) {
+        modify_file(manifest_path, &run_manifest.to_json()?).context("couldn't write manifest")?;
+        written.push(manifest_path.clone());
+    }
+    if let (Some(journal), Some(journal_path)) = (&journal, &cfg.journal_path) {
+        journal.save(journal_path)?;
+        written.push(journal_path.clone());
+        if !journal.is_empty() {
+            output.status(format_args!(
+                "to undo, run: frontmatter-fixer undo {}",
+                journal_path.display()
+            ));
+        }
+    }
+    if let Some(stats_file) = &cfg.stats_file {
+        run_stats.append_to(stats_file)?;
+        written.push(stats_file.clone());
+    }
+    for spec in &cfg.reports {
+        let rendered = report.render(spec.format)?;
+        match &spec.path {
+            Some(path) => {
+                modify_file(path, &rendered).context("couldn't write report")?;
+                written.push(path.clone());
+            }
+            None => stdout().write_all(&rendered)?,
+        }
+    }
+    if let Some(message) = &cfg.commit_message {
+        if !err_paths.is_empty() {
+            output.status("not committing: some files failed to process");
+        } else if changed_paths.is_empty() {
+            output.status("not committing: no files changed");
+        } else {
+            git::commit(&changed_paths, message, cfg.commit_sign)?;
+            output.status(format_args!(
+                "committed {} changed files",
+                changed_paths.len()
+            ));
+        }
+    }
+
+    for (name, ok, skipped, failed) in corpus_totals {
+        output.status(format_args!(
+            "{}: {} {} files successfully, {} {}, {} {}",
+            name, msg_process, ok, msg_skip, skipped, msg_fail, failed
+        ));
+    }
+    output.status(format_args!(
+        "{} {} files total",
+        msg_process,
+        ok_paths.len() + skipped_paths.len() + err_paths.len()
+    ));
+    let count_skipped = |skip| skipped_paths.iter().filter(|(_, s)| *s == skip).count();
+    let (script_skipped, ignored) = (count_skipped(Skip::Script), count_skipped(Skip::Ignored));
+    if script_skipped > 0 {
+        output.status(format_args!(
+            "{} {} files at the script's request",
+            msg_skip, script_skipped
+        ));
+    }
+    if ignored > 0 {
+        output.status(format_args!(
+            "{} {} files marked to be ignored",
+            msg_skip, ignored
+        ));
+    }
+    let unmerged = count_skipped(Skip::Unmerged);
+    if unmerged > 0 {
+        output.status(format_args!(
+            "{} {} duplicates whose originals weren't written",
+            msg_skip, unmerged
+        ));
+    }
+    let unselected = count_skipped(Skip::Unselected);
+    if unselected > 0 {
+        output.status(format_args!(
+            "{} {} files not selected by --where",
+            msg_skip, unselected
+        ));
+    }
+    let declined = count_skipped(Skip::Declined);
+    if declined > 0 {
+        output.status(format_args!(
+            "{} {} files declined when asked",
+            msg_skip, declined
+        ));
+    }
+    if !err_paths.is_empty() {
+        output.status(format_args!(
+            "{} {} files successfully",
+            msg_process,
+            ok_paths.len()
+        ));
+        output.error(format_args!("{} {} files:", msg_fail, err_paths.len()));
+        for (path, err) in &err_paths {
+            output.error(format_args!("{}: {:?}", path, err));
+        }
+        return Err(eyre!("{} {} files", msg_fail, err_paths.len()));
+    }
+    if !would_change.is_empty() {
+        for path in &would_change {
+            output.status(format_args!("would change {}", path));
+        }
+        return Err(WouldChange(would_change.len()).into());
+    }
+
+    Ok(())
+}
+
+/// For `--record`, save the run's arguments and every corpus's options,
+/// scripts, schema and files to a bundle at `bundle_path`.
+fn record(
+    bundle_path: &Path,
+    cfg: &Config,
+    corpora: &[Corpus],
+    pipelines: &[Pipeline],
+) -> eyre::Result<()> {
+    let mut recorded = Vec::with_capacity(corpora.len());
+    for (corpus, pipeline) in corpora.iter().zip(pipelines) {
+        let corpus_cfg = &corpus.cfg;
+        let schema = corpus_cfg
+            .schema_path
+            .as_ref()
+            .map(|path| read_to_string(path).context("couldn't read schema to record"))
+            .transpose()?;
+        // not there yet before the first run
+        let migration_state = corpus_cfg
+            .migration_state
+            .as_ref()
+            .and_then(|path| read_to_string(path).ok());
+        let mut recorded_corpus = RecordedCorpus::record(
+            corpus.name.clone(),
+            corpus.options.clone(),
+            pipeline
+                .scripts
+                .iter()
+                .flatten()
+                .map(|script| script.source.clone())
+                .collect(),
+            schema,
+            migration_state,
+            &corpus_cfg.paths,
+            cfg.record_redact_content,
+        );
+        recorded_corpus.inputs = record_inputs(corpus_cfg)?;
+        recorded.push(recorded_corpus);
+    }
+    // parsed some other way than from a command line, as in tests
+    let args = match cfg.resolved_args.as_slice() {
+        [] => std::env::args().collect(),
+        args => args.to_vec(),
+    };
+    let bundle = Bundle::new(args, recorded);
+    modify_file(bundle_path, &bundle.to_archive()?).context("couldn't write bundle")
+}
+
+/// The other files a run with `cfg` reads, named for where [`replay_config`]
+/// puts them: every module its scripts could `require`, its WASM modules,
+/// patches, migrations and references script.
+fn record_inputs(cfg: &Config) -> eyre::Result<Vec<RecordedInput>> {
+    let mut inputs = Vec::new();
+    let mut add = |path: &Path, name: String| -> eyre::Result<()> {
+        let contents = read(path).context(format!("couldn't read {} to record", path.display()))?;
+        inputs.push(RecordedInput {
+            path: name,
+            contents,
+        });
+        Ok(())
+    };
+    for (i, dir) in cfg.script_options().lua_path.iter().enumerate() {
+        for module in lua_modules(dir)? {
+            add(&dir.join(&module), format!("lua/{}/{}", i, module))?;
+        }
+    }
+    for (i, path) in cfg.wasm_modules.iter().enumerate() {
+        add(path, format!("wasm/{}.wasm", i))?;
+    }
+    if let Some(path) = cfg.patch_path.as_ref().or(cfg.patch_map_path.as_ref()) {
+        add(path, "patch.json".to_owned())?;
+    }
+    if let Some(dir) = &cfg.migrations_dir {
+        let entries = read_dir(dir).context(format!("couldn't read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !name.starts_with('.') && path.is_file() {
+                add(&path, format!("migrations/{}", name))?;
+            }
+        }
+    }
+    if let Some(path) = &cfg.rewrite_references {
+        add(Path::new(path), "references.lua".to_owned())?;
+    }
+    Ok(inputs)
+}
+
+/// The `.lua` files under `dir`, relative to it with `/` separators, as
+/// `require` would find them; none if it doesn't exist.
+fn lua_modules(dir: &Path) -> eyre::Result<Vec<String>> {
+    let mut modules = Vec::new();
+    let mut dirs = vec![(dir.to_owned(), String::new())];
+    while let Some((dir, prefix)) = dirs.pop() {
+        let entries = match read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).context(format!("couldn't read {}", dir.display())),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            let module = format!("{}{}", prefix, name);
+            if path.is_dir() {
+                dirs.push((path.clone(), format!("{}/", module)));
+            } else if name.ends_with(".lua") {
+                modules.push(module);
+            }
+        }
+    }
+    Ok(modules)
+}
+
+/// Rerun a recorded bundle in a scratch directory, leaving it behind so
+/// the results can be inspected.
+pub fn replay(bundle_path: &Path) -> eyre::Result<()> {
+    let bundle = Bundle::load(bundle_path)?;
+    if bundle.tool_version != env!("CARGO_PKG_VERSION") {
+        eprintln!(
+            "warning: bundle was recorded with version {}, replaying with {}",
+            bundle.tool_version,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+
+    let scratch =
+        std::env::temp_dir().join(format!("frontmatter-fixer-replay-{}", std::process::id()));
+    create_dir_all(&scratch).context("couldn't create scratch directory")?;
+    let run_cfg = Config::try_parse_from(&bundle.args).context("couldn't parse recorded args")?;
+    let cfgs = bundle
+        .corpora
+        .iter()
+        .map(|corpus| {
+            let dir = scratch.join(corpus.name.as_deref().unwrap_or("run"));
+            replay_config(&run_cfg, corpus, &dir)
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    eprintln!(
+        "replaying {} in {}",
+        bundle_path.display(),
+        scratch.display()
+    );
+    let mut result = Ok(());
+    for (corpus, cfg) in bundle.corpora.iter().zip(cfgs) {
+        if let Some(name) = &corpus.name {
+            eprintln!("replaying corpus {}", name);
+        }
+        let corpus_result = run(cfg);
+        if result.is_ok() {
+            result = corpus_result;
+        }
+    }
+    result
+}
+
+/// How to rerun `corpus` from a bundle recorded with `run_cfg`: against
+/// copies of its files and other inputs under `dir`, with everything the
+/// run would write there too, and nothing looked up in this working tree
+/// that was recorded. Whatever it was recorded with, it runs sandboxed.
+fn replay_config(run_cfg: &Config, corpus: &RecordedCorpus, dir: &Path) -> eyre::Result<Config> {
+    if run_cfg.watch {
+        return Err(eyre!("can't replay --watch, which never finishes"));
+    }
+    let mut cfg = match (&corpus.options, &corpus.name) {
+        (Some(options), name) => {
+            let args = iter::once("frontmatter-fixer".to_owned()).chain(options.iter().cloned());
+            let mut cfg = Config::try_parse_from(args).context(format!(
+                "couldn't parse recorded options for corpus {}",
+                name.as_deref().unwrap_or("")
+            ))?;
+            cfg.inherit_run_options(run_cfg);
+            cfg
+        }
+        (None, _) => run_cfg.clone(),
+    };
+
+    // where modules were required from, before script files are replaced
+    // by their recorded source
+    let lua_dirs = cfg.script_options().lua_path.len();
+
+    // which files to fix was settled when recording
+    cfg.corpora = Vec::new();
+    cfg.recursive = false;
+    cfg.git_changed = false;
+    cfg.paths = Vec::new();
+    for file in &corpus.files {
+        let scratch_path = output_path(&dir.join("files"), &file.path)?;
+        if let Some(contents) = &file.contents {
+            if let Some(parent) = scratch_path.parent() {
+                create_dir_all(parent)?;
+            }
+            std::fs::write(&scratch_path, contents)?;
+        }
+        cfg.paths.push(scratch_path.display().to_string());
+    }
+    create_dir_all(dir)?;
+    // recipes too, whose source was recorded with the rest
+    cfg.inline_scripts = corpus.scripts.clone();
+    cfg.script_paths = Vec::new();
+    cfg.recipes = Vec::new();
+    if let Some(schema) = &corpus.schema {
+        let schema_path = dir.join("schema.yaml");
+        std::fs::write(&schema_path, schema)?;
+        cfg.schema_path = Some(schema_path);
+    }
+    if cfg.migration_state.is_some() {
+        let state_path = dir.join("migration-state");
+        if let Some(state) = &corpus.migration_state {
+            std::fs::write(&state_path, state)?;
+        }
+        cfg.migration_state = Some(state_path);
+    }
+    // and everything else it read
+    for input in &corpus.inputs {
+        input.write_to(dir)?;
+    }
+    cfg.lua_paths = (0..lua_dirs)
+        .map(|i| dir.join("lua").join(i.to_string()))
+        .collect();
+    cfg.wasm_modules = (0..cfg.wasm_modules.len())
+        .map(|i| dir.join("wasm").join(format!("{}.wasm", i)))
+        .collect();
+    if cfg.patch_path.is_some() {
+        cfg.patch_path = Some(dir.join("patch.json"));
+    }
+    if cfg.patch_map_path.is_some() {
+        cfg.patch_map_path = Some(dir.join("patch.json"));
+    }
+    if cfg.migrations_dir.is_some() {
+        let migrations_dir = dir.join("migrations");
+        create_dir_all(&migrations_dir)?;
+        cfg.migrations_dir = Some(migrations_dir);
+    }
+    if cfg.rewrite_references.is_some() {
+        let references = dir.join("references.lua");
+        cfg.rewrite_references = Some(references.display().to_string());
+    }
+    if cfg.no_sandbox {
+        eprintln!("sandboxing scripts: replaying with --no-sandbox would trust the bundle");
+    }
+    cfg.no_sandbox = false;
+    cfg.script_timeout = None;
+    cfg.script_memory = None;
+    cfg.script_instructions = None;
+
+    // everything else the run writes goes in the scratch directory
+    if cfg.output_dir.is_some() {
+        cfg.output_dir = Some(dir.join("output"));
+    }
+    if let Some(StampTarget::Manifest(_)) = cfg.version_stamp {
+        cfg.version_stamp = Some(StampTarget::Manifest(dir.join("stamps.yaml")));
+    }
+    if cfg.manifest_path.is_some() {
+        cfg.manifest_path = Some(dir.join("manifest.json"));
+    }
+    if cfg.journal_path.is_some() {
+        cfg.journal_path = Some(dir.join("journal.json"));
+    }
+    if cfg.stats_file.is_some() {
+        cfg.stats_file = Some(dir.join("stats.jsonl"));
+    }
+    if cfg.render_dir.is_some() {
+        cfg.render_dir = Some(dir.join("rendered"));
+    }
+    for spec in &mut cfg.reports {
+        if let Some(path) = &spec.path {
+            let name = path.file_name().unwrap_or("report".as_ref());
+            spec.path = Some(dir.join(name));
+        }
+    }
+    if cfg.commit_message.is_some() {
+        eprintln!("not committing: replaying with --commit would commit here");
+        cfg.commit_message = None;
+        cfg.commit_sign = false;
+    }
+    cfg.record = None;
+    cfg.replay = None;
+    cfg.print_result = true;
+    cfg.verbose = true;
+    Ok(cfg)
+}
+
+/// How long processing a file took, and how it went.
+type Outcome = (Duration, eyre::Result<Processed>);
+
+/// For `--confirm-over`: estimate what the run would do from a dry run of
+/// a sample of each corpus's files, and if it would change more files than
+/// `threshold`, ask whether to go on.
+fn preflight(corpora: &[Corpus], threshold: usize) -> eyre::Result<bool> {
+    let mut estimate = Estimate::default();
+    for corpus in corpora {
+        let cfg = Config {
+            dry_run: true,
+            interactive: false,
+            // to see which transforms touch each file
+            provenance_comments: Transform::ALL.to_vec(),
+            paths: estimate::sample(&corpus.cfg.paths, estimate::SAMPLE_SIZE)
+                .into_iter()
+                .cloned()
+                .collect(),
+            ..corpus.cfg.clone()
+        };
+        let pipeline = Pipeline::new(&cfg)?;
+        let fixer = pipeline.fixer()?;
+        for path in &cfg.paths {
+            estimate.record(&process(&pipeline, &fixer, path, &cfg));
+        }
+        estimate.matched += corpus.cfg.paths.len();
+    }
+    eprintln!("{}", estimate);
+    if estimate.changes() <= threshold {
+        return Ok(true);
+    }
+    confirm::ask(&format!(
+        "go on and change about {} files?",
+        estimate.changes()
+    ))
+}
+
+/// Process every file in `cfg.paths` on `cfg.jobs` threads, each with its
+/// own Lua state, returning outcomes in the same order as the paths.
+///
+/// With `--ordered-output`, threads take a directory at a time rather than a
+/// file, so each directory's files are written one after another in the
+/// order given, while other directories' are written alongside.
+///
+/// With `--fail-fast`, files not yet started when one fails are left
+/// unprocessed, with no outcome.
+fn process_all(pipeline: &Pipeline, cfg: &Config) -> eyre::Result<Vec<Option<Outcome>>> {
+    let process_timed = |fixer: &Fixer, path: &str| {
+        let started = Instant::now();
+        let result = process(pipeline, fixer, path, cfg);
+        (started.elapsed(), result)
+    };
+
+    let jobs = match cfg.jobs {
+        Some(0) => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        Some(jobs) => jobs,
+        None => 1,
+    };
+    let stop = AtomicBool::new(false);
+    let process_unless_stopped = |fixer: &Fixer, path: &str| {
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        let outcome = process_timed(fixer, path);
+        if cfg.fail_fast && outcome.1.is_err() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        pipeline.output.advance(path, status(&outcome.1));
+        Some(outcome)
+    };
+
+    if jobs <= 1 || cfg.paths.len() <= 1 {
+        let fixer = pipeline.fixer()?;
+        return Ok(cfg
+            .paths
+            .iter()
+            .map(|path| process_unless_stopped(&fixer, path))
+            .collect());
+    }
+
+    let batches = if cfg.ordered_output {
+        by_directory(&cfg.paths)
+    } else {
+        (0..cfg.paths.len()).map(|index| vec![index]).collect()
+    };
+    let next = AtomicUsize::new(0);
+    let outcomes: Mutex<Vec<Option<Outcome>>> =
+        Mutex::new(cfg.paths.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(batches.len()))
+            .map(|_| {
+                scope.spawn(|| -> eyre::Result<()> {
+                    let fixer = pipeline.fixer()?;
+                    loop {
+                        let batch = match batches.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            Some(batch) => batch,
+                            None => return Ok(()),
+                        };
+                        for &index in batch {
+                            let outcome = process_unless_stopped(&fixer, &cfg.paths[index]);
+                            outcomes.lock().expect("worker panicked")[index] = outcome;
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("worker panicked"))
+    })?;
+    Ok(outcomes.into_inner().expect("worker panicked"))
+}
+
+/// The indices of `paths` grouped by the directory they're in, directories
+/// in the order their first file comes and files in the order given.
+fn by_directory(paths: &[String]) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    let mut directories = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let batch = *directories.entry(directory).or_insert_with(|| {
+            batches.push(Vec::new());
+            batches.len() - 1
+        });
+        batches[batch].push(index);
+    }
+    batches
+}
+
+/// What happened to a file, for the progress bar.
+fn status(result: &eyre::Result<Processed>) -> Status {
+    match result {
+        Ok(processed) if processed.skipped.is_some() => Status::Skipped,
+        Ok(processed) if processed.changed() => Status::Changed,
+        Ok(_) => Status::Unchanged,
+        Err(_) => Status::Failed,
+    }
+}
+
+/// Everything needed to process files that only needs setting up once.
+///
+/// Lua states can't be shared between threads, so each thread processing
+/// files needs its own `Fixer` from `fixer()`.
+pub struct Pipeline {
+    /// `None` to run a REPL instead.
+    scripts: Option<Vec<Script>>,
+    schema: Option<Schema>,
+    typo_fixer: Option<TypoFixer>,
+    stamp: Option<Stamp>,
+    translations: Option<Translations>,
+    locales: Option<Locales>,
+    permalinks: Option<(Permalink, permalink::Index)>,
+    duplicates: Option<duplicates::Index>,
+    links: Option<LinkIndex>,
+    patches: Option<Patches>,
+    wasm_modules: Vec<wasm::Module>,
+    generators: Generators,
+    /// What an aggregating script's collect pass gathered, and the
+    /// frontmatter it saw.
+    state: Option<(yaml::Value, Arc<Tally>)>,
+    template: Option<Template>,
+    confirmer: Option<Confirmer>,
+    script_options: ScriptOptions,
+    migrations: Option<Versions>,
+    /// Resolves `extends` for `--flatten-inheritance`.
+    inheritance: Option<Inheritance>,
+    /// The script `--rewrite-references` runs in a second pass.
+    references: Option<Script>,
+    /// Which file is writing each path, claimed as files are fixed, since
+    /// scripts and conversions can move them onto each other.
+    claims: Arc<Mutex<OutputClaims>>,
+    output: Output,
+}
+
+impl Pipeline {
+    pub fn new(cfg: &Config) -> eyre::Result<Self> {
+        if cfg.fidelity == Fidelity::ByteIdentical {
+            let reformatting = [
+                (cfg.reformat_frontmatter, "--reformat-frontmatter"),
+                (cfg.normalize_output, "--normalize-output"),
+                (cfg.line_endings != LineEndings::Preserve, "--line-endings"),
+                (
+                    cfg.blank_line_after_frontmatter != BlankLine::Preserve,
+                    "--blank-line-after-frontmatter",
+                ),
+            ];
+            if let Some((_, flag)) = reformatting.iter().find(|(reformats, _)| *reformats) {
+                return Err(eyre!(
+                    "--fidelity byte-identical can't be kept with {}",
+                    flag
+                ));
+            }
+        }
+        let scripts = cfg.scripts()?;
+        let schema = cfg.schema_path.as_deref().map(Schema::load).transpose()?;
+        let typo_fixer = cfg
+            .fix_key_typos
+            .then(|| TypoFixer::new(&collect_key_stats(cfg), schema.as_ref()));
+        let stamp = cfg
+            .version_stamp
+            .is_some()
+            .then(|| Stamp::new(scripts.as_deref().map(script_sources).as_deref()));
+        let translations = cfg
+            .translation_source
+            .as_deref()
+            .map(|source_dir| Translations::new(source_dir, cfg.translation_hash_key.as_deref()));
+        let locales = (!cfg.locale_keys.is_empty()).then(|| Locales::new(cfg.locales.clone()));
+        let permalinks = cfg
+            .permalink()
+            .filter(|_| cfg.unique_permalinks)
+            .map(|permalink| {
+                let index = permalink::Index::build(&permalink, &cfg.paths);
+                (permalink, index)
+            });
+        let duplicates = cfg
+            .find_duplicates
+            .then(|| duplicates::Index::build(&cfg.paths, cfg.duplicate_distance));
+        let links = cfg
+            .convert_links
+            .is_some()
+            .then(|| LinkIndex::build(&cfg.paths));
+        let template = cfg
+            .render_template
+            .as_deref()
+            .map(Template::load)
+            .transpose()?;
+        let state = match (&scripts, cfg.aggregate) {
+            (Some(scripts), true) => {
+                let (state, tally) = collect_state(aggregating_script(scripts)?, cfg)?;
+                Some((state, Arc::new(tally)))
+            }
+            _ => None,
+        };
+        let patches = match (&cfg.patch_path, &cfg.patch_map_path) {
+            (Some(path), _) => Some(Patches::load(path, false)?),
+            (None, Some(path)) => Some(Patches::load(path, true)?),
+            (None, None) => None,
+        };
+        let wasm_modules = cfg
+            .wasm_modules
+            .iter()
+            .map(|path| wasm::Module::new(path))
+            .collect::<eyre::Result<_>>()?;
+        let migrations = cfg
+            .migrations_dir
+            .as_deref()
+            .map(|dir| Versions::load(dir, cfg.migration_state.as_deref()))
+            .transpose()?;
+        let references = cfg
+            .rewrite_references
+            .as_ref()
+            .map(|path| -> eyre::Result<_> {
+                let source = read_to_string(path)
+                    .context(format!("couldn't read references script {}", path))?;
+                Ok(Script {
+                    name: path.clone(),
+                    source,
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            scripts,
+            schema,
+            typo_fixer,
+            stamp,
+            translations,
+            locales,
+            permalinks,
+            duplicates,
+            links,
+            patches,
+            wasm_modules,
+            generators: Generators::default(),
+            state,
+            template,
+            confirmer: cfg.interactive.then(Confirmer::default),
+            script_options: cfg.script_options(),
+            migrations,
+            inheritance: cfg.flatten_inheritance.then(Inheritance::default),
+            references,
+            claims: Arc::default(),
+            output: Output::new(cfg),
+        })
+    }
+
+    /// Have the scripts of fixers from `fixer()` note each line they run.
+    pub(crate) fn trace_scripts(&mut self) {
+        self.script_options.trace = true;
+    }
+
+    pub fn fixer(&self) -> eyre::Result<Fixer> {
+        match (&self.scripts, &self.state) {
+            (Some(scripts), Some((state, tally))) => {
+                let script = aggregating_script(scripts)?;
+                let fixer =
+                    Fixer::aggregating(script, &self.script_options).context("couldn't setup")?;
+                fixer.set_state(state)?;
+                fixer.set_corpus(Arc::clone(tally))?;
+                Ok(fixer)
+            }
+            (Some(scripts), None) => {
+                Fixer::chained(scripts, &self.script_options).context("couldn't setup")
+            }
+            (None, _) => Fixer::repl(&self.script_options).context("couldn't setup"),
+        }
+    }
+}
+
+/// Run the `--rewrite-references` script over each of `targets`, a file's
+/// path and where the first pass left it, for the `changes` that pass made.
+/// Returns those it rewrote, for [`write_rewritten`] to write back.
+fn rewrite_references(
+    pipeline: &Pipeline,
+    script: &Script,
+    targets: &[(String, PathBuf)],
+    changes: &[Change],
+) -> eyre::Result<Vec<Rewritten>> {
+    let fixer = Fixer::rewriting(script, &pipeline.script_options)
+        .context("couldn't setup references script")?;
+    let mut rewritten = Vec::new();
+    for (path, target) in targets {
+        let original = read_to_string(target).context(format!("couldn't read {}", path))?;
+        let output = fixer
+            .rewrite_references(target, &original, changes)
+            .context(format!("couldn't rewrite references in {}", path))?;
+        if let Some(output) = output {
+            rewritten.push(Rewritten {
+                path: path.clone(),
+                target: target.clone(),
+                original,
+                output,
+            });
+        }
+    }
+    Ok(rewritten)
+}
+
+/// A file whose references the references script rewrote.
+struct Rewritten {
+    path: String,
+    /// Where the first pass left it.
+    target: PathBuf,
+    /// What it held after the first pass.
+    original: String,
+    output: String,
+}
+
+/// Write a file whose references were rewritten, with the same checks and
+/// backup as the first pass gave it. It's backed up unless `written_before`,
+/// when its backup already holds what it was before the run. Returns whether
+/// it was (or would be) written, which it isn't if declined, adding what it
+/// wrote to `written`.
+fn write_rewritten(
+    pipeline: &Pipeline,
+    cfg: &Config,
+    rewritten: &Rewritten,
+    written_before: bool,
+    written: &mut Vec<PathBuf>,
+) -> eyre::Result<bool> {
+    let Rewritten {
+        path,
+        target,
+        original,
+        output,
+    } = rewritten;
+    let envelope = &cfg.envelope(path);
+    if cfg.meta_only {
+        check_meta_only(
+            original,
+            output.as_bytes(),
+            &cfg.frontmatter_prefix,
+            envelope,
+        )?;
+    }
+    let in_place = !cfg.dry_run && cfg.output_dir.is_none();
+    if in_place && !cfg.yes && !cfg.interactive {
+        let parse = |s| {
+            let (_, document) = frontmatter::split_prefix(s, &cfg.frontmatter_prefix);
+            let document = envelope::unwrap(envelope, document).into_owned();
+            let (metadata, content) = frontmatter::parse_as(&document, cfg.from_format);
+            (metadata.and_then(Result::ok), content.to_owned())
+        };
+        let ((original_metadata, original_content), (metadata, content)) =
+            (parse(original), parse(output));
+        let destructive = safety::classify(
+            original_metadata.as_ref(),
+            metadata.as_ref(),
+            &original_content,
+            &content,
+            None,
+        );
+        if !destructive.is_empty() {
+            return Err(eyre!(
+                "rewriting references would {}: pass --yes to allow destructive changes, or \
+                 --interactive to confirm each file",
+                safety::describe(&destructive)
+            ));
+        }
+    }
+    if let Some(confirmer) = &pipeline.confirmer {
+        if !confirmer.confirm(path, original, output, None)? {
+            return Ok(false);
+        }
+    }
+    if cfg.dry_run {
+        return Ok(true);
+    }
+    if let (None, Some(suffix), false) = (&cfg.output_dir, &cfg.backup_suffix, written_before) {
+        let mut backup = target.clone().into_os_string();
+        backup.push(suffix);
+        modify_file(Path::new(&backup), original.as_bytes())
+            .context("couldn't write backup")
+            .map_err(|err| FixError::write(Path::new(&backup), err))?;
+        written.push(backup.into());
+    }
+    atomic::replace(target, output.as_bytes(), cfg.keep_mtime)
+        .context("couldn't modify file")
+        .map_err(|err| FixError::write(target, err))?;
+    written.push(target.clone());
+    Ok(true)
+}
+
+/// For `--merge-duplicates`, delete the duplicate at `path`, which held
+/// `original`, backing it up first if asked. Returns whether it was deleted,
+/// which it isn't in a dry run or when exporting to another directory, adding
+/// the backup to `written`.
+fn delete_duplicate(
+    cfg: &Config,
+    path: &str,
+    original: &str,
+    written: &mut Vec<PathBuf>,
+) -> eyre::Result<bool> {
+    if cfg.dry_run || cfg.output_dir.is_some() {
+        return Ok(false);
+    }
+    if let Some(suffix) = &cfg.backup_suffix {
+        let mut backup = PathBuf::from(path).into_os_string();
+        backup.push(suffix);
+        modify_file(Path::new(&backup), original.as_bytes())
+            .context("couldn't write backup")
+            .map_err(|err| FixError::write(Path::new(&backup), err))?;
+        written.push(backup.into());
+    }
+    remove_file(path)
+        .context("couldn't delete duplicate")
+        .map_err(|err| FixError::write(Path::new(path), err))?;
+    Ok(true)
+}
+
+/// The one script `--aggregate` runs.
+fn aggregating_script(scripts: &[Script]) -> eyre::Result<&str> {
+    match scripts {
+        [script] => Ok(&script.source),
+        _ => Err(eyre!("--aggregate needs exactly one script")),
+    }
+}
+
+/// All the scripts' source, as one script for the version stamp's hash.
+fn script_sources(scripts: &[Script]) -> String {
+    scripts
+        .iter()
+        .map(|script| script.source.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Run an aggregating script's collect pass over all files, returning what
+/// it gathered and the frontmatter it saw.
+///
+/// Files that can't be read or parsed are skipped here; they'll fail when
+/// processed.
+fn collect_state(script: &str, cfg: &Config) -> eyre::Result<(yaml::Value, Tally)> {
+    let collector = Fixer::aggregating(script, &cfg.script_options()).context("couldn't setup")?;
+    let mut tally = Tally::default();
+    for path in &cfg.paths {
+        if path == STDIN_PATH {
+            return Err(eyre!("can't aggregate over stdin ({})", STDIN_PATH));
+        }
+        let original = match read_to_string(path) {
+            Ok(original) => original,
+            Err(_) => continue,
+        };
+        let (_, document) = frontmatter::split_prefix(&original, &cfg.frontmatter_prefix);
+        let document = envelope::unwrap(&cfg.envelope(path), document);
+        match frontmatter::parse_as(&document, cfg.from_format) {
+            (Some(Err(_)), _) => continue,
+            (Some(Ok(metadata)), _) => tally.record(metadata),
+            (None, _) => {}
+        }
+        collector
+            .collect(Path::new(path), &document, cfg.from_format)
+            .context(format!("couldn't collect from {}", path))?;
+    }
+    Ok((collector.state()?, tally))
+}
+
+/// Tally key usage across all files, for spotting rare misspelled keys.
+///
+/// Files that can't be read or parsed are skipped here; they'll fail when
+/// processed.
+fn collect_key_stats(cfg: &Config) -> KeyStats {
+    let mut stats = KeyStats::default();
+    for path in &cfg.paths {
+        if let Ok(original) = read_to_string(path) {
+            let (_, document) = frontmatter::split_prefix(&original, &cfg.frontmatter_prefix);
+            let document = envelope::unwrap(&cfg.envelope(path), document);
+            if let (Some(Ok(metadata)), _) = frontmatter::parse_as(&document, cfg.from_format) {
+                stats.record(&metadata);
+            }
+        }
+    }
+    stats
+}
+
+/// The result of fixing a single file.
+pub struct Processed {
+    pub original: String,
+    pub output: Vec<u8>,
+    /// Where the output was written, unless this was a dry run, skipped or
+    /// read from stdin.
+    pub written_to: Option<PathBuf>,
+    /// Where the script asked for the file to be moved, even if this was a
+    /// dry run.
+    pub renamed_to: Option<PathBuf>,
+    /// Anything else written for it: its backup, JSON Patch sidecar and
+    /// rendered page.
+    pub also_written: Vec<PathBuf>,
+    /// How the frontmatter changed, if `--json-patch-sidecars` or a
+    /// json-patch report asked.
+    pub metadata_patch: Option<Vec<Operation>>,
+    /// Why the file was left alone, if it was, in which case the output is
+    /// just the original and nothing was written.
+    pub skipped: Option<Skip>,
+    /// The transforms that added or modified keys, of those
+    /// `--provenance-comments` tracks.
+    pub touched_by: Vec<Transform>,
+    /// What the change would lose, whether or not it was written.
+    pub destructive: Vec<Destructive>,
+    /// How the frontmatter's keys changed, if `--rewrite-references` is
+    /// to update other files for them.
+    pub key_changes: Vec<Change>,
+    /// The original a duplicate's frontmatter was merged into, for
+    /// `--merge-duplicates` to delete it once that's come through; the
+    /// output is then empty but nothing was written yet.
+    pub merged_into: Option<PathBuf>,
+}
+
+/// Why a file was left alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Skip {
+    /// The script called `skip()`.
+    Script,
+    /// The file is marked to be ignored; see [`frontmatter::is_ignored`].
+    Ignored,
+    /// The file's frontmatter doesn't meet the `--where` conditions.
+    Unselected,
+    /// Writing the file was declined when asked by `--interactive`.
+    Declined,
+    /// The file is a duplicate for `--merge-duplicates`, but its original
+    /// failed or was left alone, so it's kept.
+    Unmerged,
+}
+
+impl Skip {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::Script => "skipped by script",
+            Self::Ignored => "ignored",
+            Self::Unselected => "not selected by --where",
+            Self::Declined => "declined when asked",
+            Self::Unmerged => "original wasn't written",
+        }
+    }
+}
+
+impl Processed {
+    pub fn changed(&self) -> bool {
+        self.output != self.original.as_bytes() || self.renamed_to.is_some()
+    }
+}
+
+/// Run the pipeline over a single file, writing the result unless this is a
+/// dry run. Fails with a [`FixError`] if the file's frontmatter doesn't
+/// parse, a script fails on it, or it can't be written.
+pub fn process(
+    pipeline: &Pipeline,
+    fixer: &Fixer,
+    path: &str,
+    cfg: &Config,
+) -> eyre::Result<Processed> {
+    process_file(pipeline, fixer, path, cfg).map_err(|err| FixError::classify(Path::new(path), err))
+}
+
+fn process_file(
+    pipeline: &Pipeline,
+    fixer: &Fixer,
+    path: &str,
+    cfg: &Config,
+) -> eyre::Result<Processed> {
+    let original = if path == STDIN_PATH {
+        let mut original = String::new();
+        io::stdin()
+            .read_to_string(&mut original)
+            .context("couldn't read stdin")?;
+        original
+    } else {
+        read_to_string(path).context("couldn't read file contents")?
+    };
+    let converter = Converter::for_path(&cfg.convert, Path::new(path));
+    let converted = converter
+        .map(|converter| converter.convert(&original))
+        .transpose()
+        .context("couldn't convert to Markdown")?;
+    let converted_to = converter.map(|_| Path::new(path).with_extension("md"));
+    let source = converted.as_deref().unwrap_or(&original);
+    let style = TextStyle::detect(source);
+    // owned, so the original can be moved into what's returned
+    let text = style.normalize(source).into_owned();
+    let written_style = if cfg.normalize_output {
+        TextStyle::default()
+    } else {
+        cfg.line_endings.apply(style)
+    };
+    let (prefix, enveloped) = frontmatter::split_prefix(&text, &cfg.frontmatter_prefix);
+    let envelope = &match &converted_to {
+        Some(converted_to) => cfg.envelope(&converted_to.to_string_lossy()),
+        None => cfg.envelope(path),
+    };
+    let unwrapped = envelope::unwrap(envelope, enveloped);
+    let document: &str = &unwrapped;
+    let locate_keys = |findings: Findings| findings.locate_keys(document, prefix.lines().count());
+
+    let original_metadata = (!cfg.provenance_comments.is_empty())
+        .then(|| frontmatter::parse_as(document, cfg.from_format).0?.ok())
+        .flatten();
+    let mut provenance = Provenance::new(&cfg.provenance_comments, original_metadata.as_ref());
+
+    let leave_alone = |original: String, skip: Skip| -> eyre::Result<Processed> {
+        let output = original.clone().into_bytes();
+        Ok(Processed {
+            original,
+            output,
+            written_to: None,
+            renamed_to: None,
+            also_written: Vec::new(),
+            metadata_patch: None,
+            skipped: Some(skip),
+            touched_by: Vec::new(),
+            destructive: Vec::new(),
+            key_changes: Vec::new(),
+            merged_into: None,
+        })
+    };
+    if frontmatter::is_ignored(document, cfg.from_format) {
+        return leave_alone(original, Skip::Ignored);
+    }
+    if !cfg.conditions.is_empty() {
+        let metadata = frontmatter::parse_as(document, cfg.from_format)
+            .0
+            .transpose()?;
+        if !query::matches_all(&cfg.conditions, metadata.as_ref()) {
+            return leave_alone(original, Skip::Unselected);
+        }
+    }
+
+    let (mut fixed_metadata, mut content) = if path == STDIN_PATH {
+        fixer.fix(document, cfg.from_format)?
+    } else {
+        fixer.fix_file(Path::new(path), document, cfg.from_format)?
+    };
+    if pipeline.scripts.is_some() {
+        pipeline
+            .output
+            .verbose(format_args!("scripts used on {}: {}", path, fixer.usage()));
+    }
+    if fixer.skipped()? {
+        return leave_alone(original, Skip::Script);
+    }
+    let mut renamed_to = match fixer.renamed_to()? {
+        Some(_) if path == STDIN_PATH => return Err(eyre!("can't rename stdin")),
+        Some(new_path) => {
+            let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+            let new_path = links::normalize(&dir.join(new_path));
+            if !cfg.no_sandbox {
+                let root = links::normalize(cfg.corpus_root.as_deref().unwrap_or(dir));
+                if !stays_within(&new_path, &root) {
+                    return Err(eyre!(
+                        "can't move to {}, outside {}, when sandboxed",
+                        new_path.display(),
+                        root.display()
+                    ));
+                }
+            }
+            Some(new_path).filter(|new_path| *new_path != links::normalize(Path::new(path)))
+        }
+        None => converted_to,
+    };
+    provenance.checkpoint(Transform::Script, fixed_metadata.as_ref());
+
+    if let (Some(inheritance), Some(metadata)) = (&pipeline.inheritance, fixed_metadata.as_mut()) {
+        if let Some(resolved) = inheritance.resolve(Path::new(path), metadata)? {
+            *metadata = resolved;
+        }
+    }
+    provenance.checkpoint(Transform::Inherit, fixed_metadata.as_ref());
+
+    for module in &pipeline.wasm_modules {
+        fixed_metadata = module
+            .apply(path, fixed_metadata.as_ref(), &content)
+            .context(format!("couldn't run WASM module {}", module.name()))?;
+    }
+    provenance.checkpoint(Transform::Wasm, fixed_metadata.as_ref());
+
+    if let Some(patch) = pipeline.patches.as_ref().and_then(|p| p.for_path(path)) {
+        fixed_metadata = patch
+            .apply(fixed_metadata.as_ref())
+            .context("couldn't apply patch")?;
+    }
+    provenance.checkpoint(Transform::Patch, fixed_metadata.as_ref());
+
+    let edited = cfg
+        .edits
+        .apply(&mut fixed_metadata, &pipeline.generators, path)?;
+    if !edited.is_empty() {
+        pipeline
+            .output
+            .verbose(format_args!("edited {}: {}", path, edited.join(", ")));
+    }
+    provenance.checkpoint(Transform::Set, fixed_metadata.as_ref());
+
+    if let Some(metadata) = fixed_metadata.as_mut() {
+        let mut migrated = migrate::apply(metadata, &cfg.migrations)?;
+        if let Some(versions) = &pipeline.migrations {
+            migrated.extend(versions.apply(metadata)?);
+        }
+        if !migrated.is_empty() {
+            let migrated = migrated.join(", ");
+            pipeline
+                .output
+                .verbose(format_args!("migrated in {}: {}", path, migrated));
+        }
+    }
+    provenance.checkpoint(Transform::Migrate, fixed_metadata.as_ref());
+
+    if let (Some(typo_fixer), Some(metadata)) = (&pipeline.typo_fixer, fixed_metadata.as_mut()) {
+        let fixes = typo_fixer.apply(metadata)?;
+        if !fixes.is_empty() {
+            pipeline.output.verbose(format_args!(
+                "{} key typos in {}: {}",
+                if cfg.dry_run { "would fix" } else { "fixed" },
+                path,
+                fixes.join(", ")
+            ));
+        }
+    }
+    provenance.checkpoint(Transform::KeyTypos, fixed_metadata.as_ref());
+
+    if let Some(metadata) = fixed_metadata.as_mut() {
+        let coerced = coerce::apply(metadata, &cfg.coerce)?;
+        if !coerced.is_empty() {
+            let coerced = coerced.join(", ");
+            pipeline
+                .output
+                .verbose(format_args!("coerced in {}: {}", path, coerced));
+        }
+    }
+    provenance.checkpoint(Transform::Coerce, fixed_metadata.as_ref());
+
+    if cfg.publish_scheduled {
+        if let Some(metadata) = fixed_metadata.as_mut() {
+            if let Some(promotion) = publish::promote(metadata, date::unix_now()) {
+                pipeline.output.status(format_args!(
+                    "{} {}: {} {} has passed",
+                    if cfg.dry_run {
+                        "would publish"
+                    } else {
+                        "published"
+                    },
+                    path,
+                    promotion.key,
+                    promotion.value
+                ));
+            }
+        }
+    }
+    provenance.checkpoint(Transform::Publish, fixed_metadata.as_ref());
+
+    if let (Some(archive), Some(metadata)) = (&cfg.archive_expired, fixed_metadata.as_mut()) {
+        let source = renamed_to.clone().unwrap_or_else(|| PathBuf::from(path));
+        let expired = publish::expire(metadata, &source, archive, date::unix_now());
+        if let Some((due, moved_to)) = expired {
+            if moved_to.is_some() && path == STDIN_PATH {
+                return Err(eyre!("can't move stdin into an archive"));
+            }
+            pipeline.output.status(format_args!(
+                "{} {}: {} {} has passed",
+                if cfg.dry_run {
+                    "would archive"
+                } else {
+                    "archived"
+                },
+                path,
+                due.key,
+                due.value
+            ));
+            renamed_to = moved_to.or(renamed_to);
+        }
+    }
+    provenance.checkpoint(Transform::Archive, fixed_metadata.as_ref());
+
+    if let (Some(remove), Some(metadata)) = (cfg.dedupe_title, fixed_metadata.as_mut()) {
+        if let Some(deduped) = title::dedupe(metadata, &content, remove) {
+            content = Cow::Owned(deduped);
+        }
+    }
+    if cfg.promote_title {
+        if let Some(promoted) = title::promote(&mut fixed_metadata, &content) {
+            content = Cow::Owned(promoted);
+        }
+    }
+
+    if let (Some(style), Some(links)) = (cfg.convert_links, &pipeline.links) {
+        let converted = links.convert(Path::new(path), &content, style);
+        if !converted.unresolved.is_empty() {
+            let unresolved = converted.unresolved.join(", ");
+            pipeline
+                .output
+                .status(format_args!("unresolved links in {}: {}", path, unresolved));
+        }
+        if let Some(converted) = converted.content {
+            content = Cow::Owned(converted);
+        }
+    }
+
+    if let Some(schema) = &pipeline.schema {
+        let metadata = fixed_metadata.as_ref().unwrap_or(&yaml::Value::Null);
+        schema
+            .validate(metadata, cfg.deny_unknown_keys)
+            .map_err(locate_keys)?;
+    }
+
+    if let (Some(translations), Some(metadata)) = (&pipeline.translations, fixed_metadata.as_ref())
+    {
+        translations
+            .check(Path::new(path), metadata)
+            .map_err(locate_keys)?;
+    }
+
+    if let (Some(locales), Some(metadata)) = (&pipeline.locales, fixed_metadata.as_ref()) {
+        locales
+            .check(Path::new(path), metadata, &cfg.locale_keys)
+            .map_err(locate_keys)?;
+    }
+
+    if let (Some((permalink, index)), Some(metadata)) =
+        (&pipeline.permalinks, fixed_metadata.as_ref())
+    {
+        index
+            .check(permalink, Path::new(path), metadata)
+            .map_err(locate_keys)?;
+    }
+
+    // a duplicate merged into its original is deleted
+    let mut delete = None;
+    if let Some(duplicates) = &pipeline.duplicates {
+        match (
+            cfg.merge_duplicates,
+            duplicates.original_of(Path::new(path)),
+        ) {
+            (None, _) => duplicates.check(Path::new(path))?,
+            (Some(_), Some(original)) => delete = Some(original),
+            (Some(merge), None) => {
+                let duplicates = duplicates.duplicates_of(Path::new(path));
+                if !duplicates.is_empty() && fixed_metadata.is_none() {
+                    // somewhere for their frontmatter to go
+                    fixed_metadata = Some(yaml::Value::Mapping(yaml::Mapping::new()));
+                }
+                if let Some(metadata) = fixed_metadata.as_mut() {
+                    let permalink = cfg.permalink();
+                    for duplicate in duplicates {
+                        if let Some(meta) = duplicate.meta {
+                            duplicates::merge(metadata, meta);
+                        }
+                        if merge == Merge::Alias {
+                            let url = match &permalink {
+                                Some(permalink) => permalink.url(duplicate.path, duplicate.meta),
+                                None => Some(format!(
+                                    "/{}/",
+                                    duplicate.path.with_extension("").display()
+                                )),
+                            };
+                            if let Some(url) = url {
+                                duplicates::add_alias(metadata, url);
+                            }
+                        }
+                        pipeline.output.verbose(format_args!(
+                            "merged {} into {}",
+                            duplicate.path.display(),
+                            path
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if let (Some(redaction), Some(metadata)) = (&cfg.redact, fixed_metadata.as_mut()) {
+        let removed = redaction.apply(metadata);
+        if !removed.is_empty() {
+            let removed = removed.join(", ");
+            pipeline
+                .output
+                .verbose(format_args!("redacted from {}: {}", path, removed));
+        }
+    }
+
+    if cfg.trim_trailing_ws {
+        if let Some(trimmed) = hygiene::trim_trailing_whitespace(&content) {
+            pipeline
+                .output
+                .verbose(format_args!("trimmed trailing whitespace in {}", path));
+            content = Cow::Owned(trimmed);
+        }
+    }
+    if cfg.ensure_final_newline {
+        if let Some(terminated) = hygiene::ensure_final_newline(&content) {
+            pipeline
+                .output
+                .verbose(format_args!("added final newline to {}", path));
+            content = Cow::Owned(terminated);
+        }
+    }
+
+    if let (Some(key), Some(metadata)) = (&cfg.content_hash_key, fixed_metadata.as_mut()) {
+        if cfg.check_content_hash {
+            checksum::check(metadata, key, &content).map_err(locate_keys)?;
+        } else if checksum::update(metadata, key, &content) {
+            pipeline
+                .output
+                .verbose(format_args!("updated {} in {}", key, path));
+        }
+    }
+    provenance.checkpoint(Transform::ContentHash, fixed_metadata.as_ref());
+
+    let alphabetical = KeyOrder::default();
+    let order = cfg
+        .sort_keys
+        .as_ref()
+        .or(cfg.normalize_output.then_some(&alphabetical));
+    if let (Some(order), Some(metadata)) = (order, fixed_metadata.as_mut()) {
+        order.apply(metadata);
+    }
+    if cfg.normalize_output {
+        if let Some(metadata) = fixed_metadata.as_mut() {
+            nfc::nfc_value(metadata).context(format!("couldn't normalize {}", path))?;
+        }
+        let source = renamed_to.clone().unwrap_or_else(|| PathBuf::from(path));
+        let name = source.file_name().and_then(|name| name.to_str());
+        if let (false, Some(Cow::Owned(composed))) = (path == STDIN_PATH, name.map(nfc::nfc)) {
+            // macOS decomposes names when they're created, so a checkout
+            // there has them the other way from everywhere else
+            renamed_to = Some(source.with_file_name(composed));
+        }
+    }
+
+    let format = cfg
+        .to_format
+        .or(cfg.from_format)
+        .or_else(|| frontmatter::detect(document))
+        .unwrap_or(Format::Yaml);
+    let read_format = cfg.from_format.or_else(|| frontmatter::detect(document));
+    let fidelity = cfg.fidelity;
+    let preserving = match (read_format, format) {
+        (Some(Format::Yaml), Format::Yaml)
+            if !cfg.reformat_frontmatter && fidelity != Fidelity::Normalized =>
+        {
+            frontmatter::parse_raw(document).0
+        }
+        _ => None,
+    };
+    let had_frontmatter = frontmatter::detect(document).is_some();
+    let datetimes = frontmatter::Datetimes::find(document, read_format);
+    let fences = frontmatter::Fences::detect(document).filter(|_| fidelity != Fidelity::Normalized);
+    let render = |metadata: Option<&yaml::Value>| -> eyre::Result<Vec<u8>> {
+        let blank_line = cfg.blank_line_after_frontmatter;
+        let output = match preserving {
+            Some(original) => frontmatter::to_bytes_preserving(
+                original, fidelity, blank_line, metadata, &content,
+            )?,
+            None if fidelity == Fidelity::ByteIdentical && had_frontmatter => {
+                return Err(eyre!(
+                    "can't rewrite this frontmatter without reformatting it, see --fidelity"
+                ));
+            }
+            None => frontmatter::to_bytes_with_datetimes(
+                format, blank_line, metadata, &content, &datetimes,
+            )?,
+        };
+        let mut output = provenance.annotate(output);
+        if let Some(fences) = &fences {
+            output = fences.restore(output);
+        }
+        let output = envelope::wrap(envelope, output);
+        Ok(written_style.restore([prefix.as_bytes(), &output].concat()))
+    };
+    let mut output = if fidelity != Fidelity::Normalized
+        && converted.is_none()
+        && read_format.is_none_or(|read_format| read_format == format)
+        && semantically_unchanged(
+            document,
+            cfg.from_format,
+            fixed_metadata.as_ref(),
+            &content,
+            cfg.blank_line_after_frontmatter,
+        ) {
+        // reserializing would only reformat the frontmatter
+        if written_style == style {
+            original.clone().into_bytes()
+        } else {
+            written_style.restore(text.clone().into_bytes())
+        }
+    } else {
+        render(fixed_metadata.as_ref())?
+    };
+    if delete.is_some() {
+        output = Vec::new();
+    }
+    let changed = output != original.as_bytes();
+
+    if changed && delete.is_none() && cfg.version_stamp == Some(StampTarget::Frontmatter) {
+        if let (Some(stamp), Some(metadata)) = (&pipeline.stamp, fixed_metadata.as_mut()) {
+            stamp.apply(metadata);
+            output = render(fixed_metadata.as_ref())?;
+        }
+    }
+
+    let wants_patch = cfg.json_patch_sidecars
+        || cfg
+            .reports
+            .iter()
+            .any(|spec| spec.format == ReportFormat::JsonPatch);
+    let metadata_patch = wants_patch
+        .then(|| {
+            let (original_metadata, _) = frontmatter::parse_as(document, cfg.from_format);
+            let original_metadata = original_metadata.transpose()?;
+            patch::diff(original_metadata.as_ref(), fixed_metadata.as_ref())
+        })
+        .transpose()?;
+
+    if cfg.meta_only {
+        check_meta_only(&original, &output, &cfg.frontmatter_prefix, envelope)?;
+    }
+
+    let destructive = if delete.is_some() {
+        vec![Destructive::Delete]
+    } else if output != original.as_bytes() || renamed_to.is_some() {
+        let (original_metadata, original_content) =
+            frontmatter::parse_as(document, cfg.from_format);
+        let original_metadata = original_metadata.and_then(Result::ok);
+        safety::classify(
+            original_metadata.as_ref(),
+            fixed_metadata.as_ref(),
+            original_content,
+            &content,
+            renamed_to.as_deref(),
+        )
+    } else {
+        Vec::new()
+    };
+    // nothing is lost writing to stdout or another directory, and
+    // --interactive asks about every change
+    let in_place = !cfg.dry_run && path != STDIN_PATH && cfg.output_dir.is_none();
+    if let (true, Some(original), false) = (in_place, delete, cfg.interactive) {
+        return Err(eyre!(
+            "duplicate of {}: pass --interactive to confirm deleting duplicates",
+            original.display()
+        ));
+    }
+    if in_place && !destructive.is_empty() && !cfg.yes && !cfg.interactive {
+        return Err(eyre!(
+            "would {}: pass --yes to allow destructive changes, or --interactive to confirm \
+             each file",
+            safety::describe(&destructive)
+        ));
+    }
+
+    if let Some(confirmer) = &pipeline.confirmer {
+        let renamed = renamed_to.as_ref().map(|path| path.display().to_string());
+        if (output != original.as_bytes() || renamed_to.is_some())
+            && !confirmer.confirm(
+                path,
+                &original,
+                &String::from_utf8_lossy(&output),
+                renamed.as_deref(),
+            )?
+        {
+            return leave_alone(original, Skip::Declined);
+        }
+    }
+
+    if path != STDIN_PATH && delete.is_none() {
+        let source = renamed_to.as_deref().unwrap_or_else(|| Path::new(path));
+        let target = match &cfg.output_dir {
+            Some(output_dir) => output_path(output_dir, &source.to_string_lossy())?,
+            None => links::normalize(source),
+        };
+        pipeline
+            .claims
+            .lock()
+            .expect("claims poisoned")
+            .claim(target, path)?;
+    }
+
+    let mut also_written = Vec::new();
+    let written_to = if cfg.dry_run || path == STDIN_PATH {
+        None
+    } else {
+        if let (None, Some(suffix), None) = (&cfg.output_dir, &cfg.backup_suffix, delete) {
+            if output != original.as_bytes() || renamed_to.is_some() {
+                let mut backup = PathBuf::from(path).into_os_string();
+                backup.push(suffix);
+                modify_file(Path::new(&backup), original.as_bytes())
+                    .context("couldn't write backup")
+                    .map_err(|err| FixError::write(Path::new(&backup), err))?;
+                also_written.push(backup.into());
+            }
+        }
+        match (&cfg.output_dir, &renamed_to) {
+            // deleted once its original has come through
+            _ if delete.is_some() => None,
+            (Some(output_dir), _) => {
+                let source = renamed_to.as_deref().unwrap_or_else(|| Path::new(path));
+                let output_path = output_path(output_dir, &source.to_string_lossy())?;
+                if let Some(parent) = output_path.parent() {
+                    create_dir_all(parent)
+                        .context("couldn't create output directory")
+                        .map_err(|err| FixError::write(&output_path, err))?;
+                }
+                modify_file(&output_path, &output)
+                    .context("couldn't write output file")
+                    .map_err(|err| FixError::write(&output_path, err))?;
+                Some(output_path)
+            }
+            (None, Some(new_path)) => {
+                if let Some(parent) = new_path.parent() {
+                    create_dir_all(parent)
+                        .context("couldn't create directory to move into")
+                        .map_err(|err| FixError::write(new_path, err))?;
+                }
+                atomic::relocate(Path::new(path), new_path, &output, cfg.keep_mtime)
+                    .context(format!("couldn't move file to {}", new_path.display()))
+                    .map_err(|err| FixError::write(new_path, err))?;
+                Some(new_path.clone())
+            }
+            (None, None) => {
+                atomic::replace(Path::new(path), &output, cfg.keep_mtime)
+                    .context("couldn't modify file")
+                    .map_err(|err| FixError::write(Path::new(path), err))?;
+                Some(PathBuf::from(path))
+            }
+        }
+    };
+
+    if let (Some(template), Some(render_dir), Some(template_path)) =
+        (&pipeline.template, &cfg.render_dir, &cfg.render_template)
+    {
+        if !cfg.dry_run && path != STDIN_PATH {
+            let source = renamed_to.as_deref().unwrap_or_else(|| Path::new(path));
+            let meta = serde_json::to_value(&fixed_metadata)
+                .context("frontmatter can't be represented as JSON")?;
+            let context = serde_json::json!({
+                "meta": meta,
+                "content": content,
+                "path": source.to_string_lossy(),
+            });
+            let rendered = template
+                .render(&context)
+                .context("couldn't render template")?;
+            let rendered_path = rendered_path(render_dir, source, template_path)?;
+            if let Some(parent) = rendered_path.parent() {
+                create_dir_all(parent).context("couldn't create render output directory")?;
+            }
+            modify_file(&rendered_path, rendered.as_bytes())
+                .context("couldn't write rendered file")?;
+            also_written.push(rendered_path);
+        }
+    }
+
+    if let (true, Some(written_to), Some(metadata_patch)) =
+        (cfg.json_patch_sidecars, &written_to, &metadata_patch)
+    {
+        if !metadata_patch.is_empty() {
+            let mut sidecar = written_to.clone().into_os_string();
+            sidecar.push(".patch.json");
+            let mut json = serde_json::to_vec_pretty(metadata_patch)?;
+            json.push(b'\n');
+            modify_file(Path::new(&sidecar), &json).context("couldn't write JSON Patch sidecar")?;
+            also_written.push(sidecar.into());
+        }
+    }
+
+    let key_changes = match &pipeline.references {
+        Some(_) => {
+            let (original_metadata, _) = frontmatter::parse_as(document, cfg.from_format);
+            let original_metadata = original_metadata.and_then(Result::ok);
+            snapshot::key_changes(path, original_metadata.as_ref(), fixed_metadata.as_ref())
+        }
+        None => Vec::new(),
+    };
+
+    Ok(Processed {
+        original,
+        output,
+        written_to,
+        renamed_to,
+        also_written,
+        metadata_patch,
+        skipped: None,
+        touched_by: provenance.touched_by(),
+        destructive,
+        key_changes,
+        merged_into: delete.map(Path::to_path_buf),
+    })
+}
+
+/// Whether `original` already has exactly this frontmatter, keys in the
+/// same order, and content, laid out as `blank_line` says, so differs from
+/// them only in formatting.
+fn semantically_unchanged(
+    original: &str,
+    format: Option<Format>,
+    metadata: Option<&yaml::Value>,
+    content: &str,
+    blank_line: BlankLine,
+) -> bool {
+    match frontmatter::parse_as(original, format) {
+        (Some(Ok(original_metadata)), body) => {
+            let keys = |metadata: &yaml::Value| {
+                metadata
+                    .as_mapping()
+                    .map(|mapping| mapping.keys().cloned().collect::<Vec<_>>())
+            };
+            metadata == Some(&original_metadata)
+                && metadata.and_then(keys) == keys(&original_metadata)
+                && blank_line.apply(content) == body
+        }
+        (None, body) => metadata.is_none() && content == body,
+        (Some(Err(_)), _) => false,
+    }
+}
+
+/// Fail before touching anything if two sources would write the same output.
+fn check_output_claims(output_dir: &Path, paths: &[String]) -> eyre::Result<()> {
+    let mut claims = OutputClaims::default();
+    for path in paths.iter().filter(|path| *path != STDIN_PATH) {
+        claims.claim(output_path(output_dir, path)?, path)?;
+    }
+    Ok(())
+}
+
+/// Mirror a source path underneath the output directory.
+fn output_path(output_dir: &Path, path: &str) -> eyre::Result<PathBuf> {
+    let mut output_path = output_dir.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => output_path.push(part),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                return Err(eyre!(
+                    "can't export {} under output directory: path contains '..'",
+                    path
+                ))
+            }
+        }
+    }
+    Ok(output_path)
+}
+
+/// Whether `path` is `root` or under it, both normalized.
+fn stays_within(path: &Path, root: &Path) -> bool {
+    path.strip_prefix(root).is_ok_and(|relative| {
+        relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    })
+}
+
+/// Where to write `path` rendered through the template at `template_path`:
+/// under `render_dir`, with the extension the template has before `.tera`.
+fn rendered_path(render_dir: &Path, path: &Path, template_path: &Path) -> eyre::Result<PathBuf> {
+    let rendered_path = output_path(render_dir, &path.to_string_lossy())?;
+    let template_name = template_path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let template_name = template_name
+        .strip_suffix(".tera")
+        .unwrap_or(&template_name);
+    Ok(match Path::new(template_name).extension() {
+        Some(extension) => rendered_path.with_extension(extension),
+        None => rendered_path,
+    })
+}
+
+/// Check for `--meta-only` that `output` differs from `original` at most in
+/// its frontmatter, comparing everything before and after that byte for
+/// byte.
+fn check_meta_only(
+    original: &str,
+    output: &[u8],
+    prefixes: &[String],
+    envelope: &dyn Envelope,
+) -> eyre::Result<()> {
+    let output = std::str::from_utf8(output).context("fixed file isn't valid UTF-8")?;
+    let outside = |s| outside_frontmatter(s, prefixes, envelope);
+    if outside(original) != outside(output) {
+        return Err(eyre!(
+            "--meta-only: would change content outside the frontmatter"
+        ));
+    }
+    Ok(())
+}
+
+/// The prefix before a document and its content after any frontmatter.
+fn outside_frontmatter<'a>(
+    s: &'a str,
+    prefixes: &[String],
+    envelope: &dyn Envelope,
+) -> (&'a str, &'a str) {
+    let (prefix, document) = frontmatter::split_prefix(s, prefixes);
+    let content = envelope
+        .unwrap(document)
+        .map_or(document, |(_, content)| content);
+    (prefix, content)
+}
+
+fn modify_file(path: &Path, contents: &[u8]) -> eyre::Result<()> {
+    atomic::replace(path, contents, false)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{ffi::OsString, fs::write};
+
+    use super::*;
+    use crate::{envelope::Markdown, project::CONFIG_FILE};
+
+    #[test]
+    fn output_path_mirrors_source_under_output_dir() -> eyre::Result<()> {
+        let out = Path::new("out");
+        assert_eq!(Path::new("out/posts/a.md"), output_path(out, "posts/a.md")?);
+        assert_eq!(
+            Path::new("out/posts/a.md"),
+            output_path(out, "./posts/a.md")?
+        );
+        assert_eq!(Path::new("out/src/a.md"), output_path(out, "/src/a.md")?);
+        Ok(())
+    }
+
+    #[test]
+    fn ordered_output_takes_directories_in_turn() {
+        let paths = ["b/2.md", "a/1.md", "b/1.md", "top.md", "a/2.md"].map(String::from);
+        assert_eq!(vec![vec![0, 2], vec![1, 4], vec![3]], by_directory(&paths));
+    }
+
+    #[test]
+    fn jobs_keep_the_order_of_one_job() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut paths = Vec::new();
+        for i in 0..24 {
+            let path = dir.path().join(format!("{:02}.md", i));
+            match i % 5 {
+                0 => write(&path, "---\ntitle: [Broken\n---\n")?,
+                _ => write(
+                    &path,
+                    format!("---\ntitle: Post {}\n---\n{}\n", i, "x".repeat(i)),
+                )?,
+            }
+            paths.push(path.display().to_string());
+        }
+        let cfg = |jobs: &str| {
+            let report = dir.path().join(format!("report-{}.json", jobs));
+            let mut args = vec![
+                "frontmatter-fixer".to_owned(),
+                "--dry-run".to_owned(),
+                format!("--jobs={}", jobs),
+                "-e".to_owned(),
+                "meta.length = #content".to_owned(),
+                format!("--report=json={}", report.display()),
+            ];
+            args.extend(paths.iter().cloned());
+            Config::try_parse_from(args).map(|cfg| (cfg, report))
+        };
+        let outputs = |cfg: &Config| -> eyre::Result<Vec<Result<Vec<u8>, String>>> {
+            let outcomes = process_all(&Pipeline::new(cfg)?, cfg)?;
+            Ok(outcomes
+                .into_iter()
+                .map(|outcome| {
+                    let (_, result) = outcome.expect("nothing stops the run");
+                    result
+                        .map(|processed| processed.output)
+                        .map_err(|err| format!("{:#}", err))
+                })
+                .collect())
+        };
+
+        let (one, one_report) = cfg("1")?;
+        let (four, four_report) = cfg("4")?;
+        assert_eq!(outputs(&one)?, outputs(&four)?);
+        assert!(run(one).is_err());
+        assert!(run(four).is_err());
+        let report = read_to_string(one_report)?;
+        assert!(report.contains(&paths[23]), "{}", report);
+        assert_eq!(report, read_to_string(four_report)?);
+        Ok(())
+    }
+
+    #[test]
+    fn formatting_alone_is_unchanged() {
+        let original = "---\ntags: [a, b]\ntitle: 'Hello'\n---\nBody\n";
+        let metadata: yaml::Value = yaml::from_str("tags: [a, b]\ntitle: Hello\n").unwrap();
+        let retitled: yaml::Value = yaml::from_str("tags: [a, b]\ntitle: Hi\n").unwrap();
+        let sorted: yaml::Value = yaml::from_str("title: Hello\ntags: [a, b]\n").unwrap();
+        let unchanged = |metadata, content, blank_line| {
+            semantically_unchanged(original, None, metadata, content, blank_line)
+        };
+        assert!(unchanged(Some(&metadata), "Body\n", BlankLine::Preserve));
+        assert!(!unchanged(Some(&metadata), "Body!\n", BlankLine::Preserve));
+        assert!(!unchanged(Some(&metadata), "Body\n", BlankLine::Always));
+        assert!(!unchanged(Some(&retitled), "Body\n", BlankLine::Preserve));
+        assert!(!unchanged(Some(&sorted), "Body\n", BlankLine::Preserve));
+        assert!(semantically_unchanged(
+            "Body\n",
+            None,
+            None,
+            "Body\n",
+            BlankLine::Never
+        ));
+    }
+
+    #[test]
+    fn rendered_path_takes_template_extension() -> eyre::Result<()> {
+        let out = Path::new("feed");
+        let post = Path::new("posts/a.md");
+        assert_eq!(
+            Path::new("feed/posts/a.xml"),
+            rendered_path(out, post, Path::new("templates/item.xml.tera"))?
+        );
+        assert_eq!(
+            Path::new("feed/posts/a.json"),
+            rendered_path(out, post, Path::new("item.json"))?
+        );
+        assert_eq!(
+            Path::new("feed/posts/a.md"),
+            rendered_path(out, post, Path::new("item"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn check_reports_files_that_would_change_without_writing() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let fixed = dir.path().join("fixed.md");
+        let unfixed = dir.path().join("unfixed.md");
+        write(&fixed, "---\ntitle: Hi\ndraft: false\n---\nBody\n")?;
+        write(&unfixed, "---\ntitle: Hi\n---\nBody\n")?;
+        let patch = dir.path().join("patch.json");
+        write(&patch, r#"{"draft": false}"#)?;
+        let args = |paths: &[&Path]| {
+            let mut args = vec!["frontmatter-fixer", "--check", "--apply-patch"];
+            args.push(patch.to_str().unwrap());
+            args.extend(paths.iter().map(|path| path.to_str().unwrap()));
+            Config::try_parse_from(args)
+        };
+
+        let result = run(args(&[&fixed])?);
+        assert_eq!(0, exit_code(&result));
+        let result = run(args(&[&fixed, &unfixed])?);
+        assert_eq!(2, exit_code(&result));
+        let err = result.expect_err("unfixed file should fail check");
+        assert_eq!(
+            1,
+            err.downcast_ref::<WouldChange>()
+                .expect("not WouldChange")
+                .0
+        );
+        assert_eq!("---\ntitle: Hi\n---\nBody\n", read_to_string(&unfixed)?);
+        Ok(())
+    }
+
+    #[test]
+    fn failures_stop_the_run_with_fail_fast() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let paths = ["a.md", "b.md", "c.md"].map(|name| dir.path().join(name));
+        for path in &paths {
+            write(path, "---\ntitle: [Hi\n---\nBody\n")?;
+        }
+        let run_with = |extra: &[&str]| {
+            let mut args = vec!["frontmatter-fixer", "--dry-run", "-e", "meta.seen = true"];
+            args.extend(extra);
+            args.extend(paths.iter().map(|path| path.to_str().unwrap()));
+            run(Config::try_parse_from(args)?)
+        };
+
+        let result = run_with(&[]);
+        assert_eq!(1, exit_code(&result));
+        let err = result.expect_err("frontmatter doesn't parse");
+        assert_eq!("would fail to process 3 files", err.to_string());
+        let err = run_with(&["--fail-fast"]).expect_err("frontmatter doesn't parse");
+        assert_eq!("would fail to process 1 files", err.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn filtering_stdin_keeps_everything_else_off_stdout() -> eyre::Result<()> {
+        for extra in ["--diff", "--report=json"] {
+            let cfg = Config::try_parse_from(["frontmatter-fixer", extra, STDIN_PATH])?;
+            let err = run(cfg).expect_err("stdout carries the document");
+            assert!(err.to_string().contains("to stdout"), "{}", err);
+        }
+        let filtering = Config::try_parse_from(["frontmatter-fixer", STDIN_PATH])?;
+        assert!(filtering.script_options().print_to_stderr);
+        let checking = Config::try_parse_from(["frontmatter-fixer", "--dry-run", STDIN_PATH])?;
+        assert!(!checking.script_options().print_to_stderr);
+        Ok(())
+    }
+
+    #[test]
+    fn destructive_changes_need_yes() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let post = dir.path().join("post.md");
+        write(&post, "---\ntitle: Hi\ndraft: true\n---\nBody\n")?;
+        let args = |extra: &[&str]| {
+            let mut args = vec!["frontmatter-fixer", "-e", "meta.draft = nil"];
+            args.extend(extra);
+            args.push(post.to_str().unwrap());
+            Config::try_parse_from(args)
+        };
+
+        run(args(&["--dry-run"])?)?;
+        let err = run(args(&[])?).expect_err("removing a key needs --yes");
+        assert_eq!("failed to process 1 files", err.to_string());
+        let cfg = args(&[])?;
+        let pipeline = Pipeline::new(&cfg)?;
+        let err = process(&pipeline, &pipeline.fixer()?, post.to_str().unwrap(), &cfg)
+            .err()
+            .expect("removing a key needs --yes");
+        assert!(format!("{:#}", err).contains("pass --yes"), "{:#}", err);
+        assert_eq!(
+            "---\ntitle: Hi\ndraft: true\n---\nBody\n",
+            read_to_string(&post)?
+        );
+        run(args(&["--yes"])?)?;
+        assert_eq!("---\ntitle: Hi\n---\nBody\n", read_to_string(&post)?);
+        Ok(())
+    }
+
+    #[test]
+    fn rewrites_references_like_the_first_pass() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let renamed = dir.path().join("renamed.md");
+        let linking = dir.path().join("linking.md");
+        write(&renamed, "---\nslug: old\n---\nBody\n")?;
+        write(&linking, "---\ntitle: Hi\n---\nSee [[old]].\n")?;
+        let references = dir.path().join("references.lua");
+        write(
+            &references,
+            "function rewrite_references(content, changes, file)\n\
+               return (content:gsub('%[%[old%]%]', '[[new]]'))\n\
+             end\n",
+        )?;
+        let journal = dir.path().join("journal.json");
+        let args = |extra: &[&str]| {
+            let mut args = vec![
+                "frontmatter-fixer",
+                "-e",
+                "if meta.slug then meta.slug = 'new' end",
+                "--rewrite-references",
+                references.to_str().unwrap(),
+                "--backup",
+                "--journal",
+                journal.to_str().unwrap(),
+            ];
+            args.extend(extra);
+            args.extend([renamed.to_str().unwrap(), linking.to_str().unwrap()]);
+            Config::try_parse_from(args)
+        };
+
+        let err = run(args(&[])?).expect_err("editing content needs --yes");
+        assert_eq!("failed to process 1 files", err.to_string());
+        assert_eq!(
+            "---\ntitle: Hi\n---\nSee [[old]].\n",
+            read_to_string(&linking)?
+        );
+        assert!(!dir.path().join("linking.md.bak").exists());
+        write(&renamed, "---\nslug: old\n---\nBody\n")?;
+
+        run(args(&["--yes"])?)?;
+        assert_eq!(
+            "---\ntitle: Hi\n---\nSee [[new]].\n",
+            read_to_string(&linking)?
+        );
+        assert_eq!(
+            "---\ntitle: Hi\n---\nSee [[old]].\n",
+            read_to_string(dir.path().join("linking.md.bak"))?
+        );
+        assert_eq!(2, Journal::load(&journal)?.undo(false)?);
+        assert_eq!(
+            "---\ntitle: Hi\n---\nSee [[old]].\n",
+            read_to_string(&linking)?
+        );
+        assert_eq!("---\nslug: old\n---\nBody\n", read_to_string(&renamed)?);
+
+        // on its own, with nothing changing keys, it has nothing to do
+        let args = [
+            "frontmatter-fixer",
+            "--rewrite-references",
+            references.to_str().unwrap(),
+            linking.to_str().unwrap(),
+        ];
+        run(Config::try_parse_from(args)?)?;
+        assert_eq!(
+            "---\ntitle: Hi\n---\nSee [[old]].\n",
+            read_to_string(&linking)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn claims_outputs_as_files_move() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.md");
+        let b = dir.path().join("b.md");
+        write(&a, "---\ntitle: A\n---\n")?;
+        write(&b, "---\ntitle: B\n---\n")?;
+        let cfg = Config::try_parse_from([
+            "frontmatter-fixer",
+            "--dry-run",
+            "-e",
+            "rename_to('same.md')",
+        ])?;
+        let pipeline = Pipeline::new(&cfg)?;
+        let fixer = pipeline.fixer()?;
+        process(&pipeline, &fixer, a.to_str().unwrap(), &cfg)?;
+        let err = process(&pipeline, &fixer, b.to_str().unwrap(), &cfg)
+            .err()
+            .expect("both would move to same.md");
+        assert_eq!(
+            format!(
+                "{} and {} would both write {}",
+                a.display(),
+                b.display(),
+                dir.path().join("same.md").display()
+            ),
+            err.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_duplicates_whose_original_fails() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let body = "The same post, imported twice from two archives, each time with \
+                    its own metadata, which should end up together on one page rather \
+                    than being lost.";
+        let original = dir.path().join("original.md");
+        let duplicate = dir.path().join("duplicate.md");
+        write(&original, format!("---\ndate: 2023-01-01\n---\n{}\n", body))?;
+        write(
+            &duplicate,
+            format!("---\ndate: 2023-02-01\nauthor: Me\n---\n{}\n", body),
+        )?;
+        let report = dir.path().join("report.json");
+        let outcomes = |script: &str| -> eyre::Result<String> {
+            let report_arg = format!("--report=json={}", report.display());
+            let args = [
+                "frontmatter-fixer",
+                "--dry-run",
+                "--find-duplicates",
+                "--merge-duplicates",
+                "delete",
+                "-e",
+                script,
+                &report_arg,
+                original.to_str().unwrap(),
+                duplicate.to_str().unwrap(),
+            ];
+            let _ = run(Config::try_parse_from(args)?);
+            Ok(read_to_string(&report)?)
+        };
+
+        let merged = outcomes("meta.seen = true")?;
+        assert!(merged.contains(r#""failed": 0"#), "{}", merged);
+        assert!(merged.contains(r#""changed": 2"#), "{}", merged);
+        let kept = outcomes("if meta.author == nil then error('no') end")?;
+        assert!(kept.contains(r#""failed": 1"#), "{}", kept);
+        assert!(kept.contains("original wasn't written"), "{}", kept);
+        Ok(())
+    }
+
+    #[test]
+    fn records_args_with_project_defaults() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        write(
+            dir.path().join(CONFIG_FILE),
+            "eval = \"meta.seen = true\"\n\n[tasks.draft]\neval = \"meta.draft = true\"\n",
+        )?;
+        let post = dir.path().join("post.md");
+        write(&post, "---\ntitle: Hi\n---\nBody\n")?;
+        let bundle = dir.path().join("run.tar.zst");
+        let (bundle_arg, post_arg) = (bundle.to_str().unwrap(), post.to_str().unwrap());
+        let cli = [
+            "frontmatter-fixer",
+            "run",
+            "draft",
+            "--dry-run",
+            "--record",
+            bundle_arg,
+            post_arg,
+        ];
+        run(Config::load_in(
+            dir.path(),
+            cli.map(OsString::from).to_vec(),
+        )?)?;
+
+        let recorded = Bundle::load(&bundle)?;
+        assert_eq!(
+            vec!["meta.seen = true", "meta.draft = true"],
+            recorded.corpora[0].scripts
+        );
+        let replayed = Config::try_parse_from(&recorded.args)?;
+        assert_eq!(
+            vec!["meta.seen = true", "meta.draft = true"],
+            replayed.inline_scripts
+        );
+        assert!(replayed.dry_run);
+        assert_eq!(vec![post_arg], replayed.paths);
+        Ok(())
+    }
+
+    #[test]
+    fn records_every_corpus() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for (name, script) in [("blog", "meta.blog = true"), ("docs", "meta.docs = true")] {
+            let corpus = dir.path().join(name);
+            std::fs::create_dir(&corpus)?;
+            write(corpus.join(corpus::CONFIG_FILE), format!("-e {}\n", script))?;
+            write(corpus.join("page.md"), "---\ntitle: Hi\n---\nBody\n")?;
+        }
+        let bundle = dir.path().join("run.tar.zst");
+        let corpus_arg = |name: &str| format!("{}={}", name, dir.path().join(name).display());
+        let (blog, docs) = (corpus_arg("blog"), corpus_arg("docs"));
+        let args = [
+            "frontmatter-fixer",
+            "--dry-run",
+            "--record",
+            bundle.to_str().unwrap(),
+            "--corpus",
+            &blog,
+            "--corpus",
+            &docs,
+        ];
+        run(Config::try_parse_from(args)?)?;
+
+        let recorded = Bundle::load(&bundle)?;
+        let recorded: Vec<_> = recorded
+            .corpora
+            .iter()
+            .map(|corpus| {
+                (
+                    corpus.name.as_deref(),
+                    &corpus.scripts[..],
+                    corpus.files.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Some("blog"), &["meta.blog = true".to_owned()][..], 1),
+                (Some("docs"), &["meta.docs = true".to_owned()][..], 1),
+            ],
+            recorded
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn replays_into_the_scratch_directory() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let args = [
+            "frontmatter-fixer",
+            "-e",
+            "meta.seen = true",
+            "--commit",
+            "fix",
+            "--journal",
+            "journal.json",
+            "--stats-file",
+            "stats.jsonl",
+            "--report=json=out/report.json",
+            "--render",
+            "item.tera",
+            "--render-out",
+            "feed",
+            "--git-changed",
+        ];
+        let recorded = RecordedCorpus::record(
+            None,
+            None,
+            vec!["meta.seen = true".to_owned()],
+            None,
+            None,
+            &["posts/a.md".to_owned()],
+            false,
+        );
+        let cfg = replay_config(&Config::try_parse_from(args)?, &recorded, dir.path())?;
+        assert_eq!(None, cfg.commit_message);
+        assert!(!cfg.git_changed);
+        for written in [
+            cfg.journal_path.as_deref(),
+            cfg.stats_file.as_deref(),
+            cfg.reports[0].path.as_deref(),
+            cfg.render_dir.as_deref(),
+            cfg.paths.first().map(Path::new),
+        ] {
+            let written = written.expect("still written");
+            assert!(written.starts_with(dir.path()), "{}", written.display());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn replays_sandboxed_from_recorded_inputs() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let lib = dir.path().join("lib");
+        create_dir_all(lib.join("util"))?;
+        write(lib.join("util").join("answer.lua"), "return 42\n")?;
+        let patch = dir.path().join("patch.json");
+        write(&patch, "{\"patched\": true}\n")?;
+        let post = dir.path().join("post.md");
+        write(&post, "---\ntitle: Hi\n---\nBody\n")?;
+        let bundle = dir.path().join("run.tar.zst");
+        let args = [
+            "frontmatter-fixer",
+            "-e",
+            "meta.answer = require('util.answer'); meta.sandboxed = os.getenv == nil",
+            "--no-sandbox",
+            "--lua-path",
+            lib.to_str().unwrap(),
+            "--apply-patch",
+            patch.to_str().unwrap(),
+            "--dry-run",
+            "--record",
+            bundle.to_str().unwrap(),
+            post.to_str().unwrap(),
+        ];
+        run(Config::try_parse_from(args)?)?;
+
+        // replayed from the bundle, not from here
+        std::fs::remove_dir_all(&lib)?;
+        remove_file(&patch)?;
+        let recorded = Bundle::load(&bundle)?;
+        let scratch = dir.path().join("scratch");
+        // as recorded.args would say run from the command line
+        let mut cfg = replay_config(
+            &Config::try_parse_from(args)?,
+            &recorded.corpora[0],
+            &scratch,
+        )?;
+        assert!(!cfg.no_sandbox);
+        cfg.dry_run = false;
+        run(cfg)?;
+        let replayed =
+            read_to_string(output_path(&scratch.join("files"), post.to_str().unwrap())?)?;
+        assert_eq!(
+            "---\ntitle: Hi\nanswer: 42\nsandboxed: true\npatched: true\n---\nBody\n",
+            replayed
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn advances_migration_state_only_when_every_file_is_migrated() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let migrations = dir.path().join("migrations");
+        create_dir_all(&migrations)?;
+        write(migrations.join("001-authors"), "rename author authors\n")?;
+        let state = dir.path().join("state");
+        let (a, b) = (dir.path().join("a.md"), dir.path().join("b.md"));
+        write(&a, "---\nauthor: Ann\ndraft: true\n---\n")?;
+        write(&b, "---\nauthor: Bob\n---\n")?;
+        let args = |extra: &[&str]| {
+            let mut args = vec![
+                "frontmatter-fixer",
+                "--yes",
+                "--migrations",
+                migrations.to_str().unwrap(),
+                "--migration-state",
+                state.to_str().unwrap(),
+            ];
+            args.extend(extra);
+            args.extend([a.to_str().unwrap(), b.to_str().unwrap()]);
+            Config::try_parse_from(args)
+        };
+
+        let err = run(args(&["--where", "draft"])?).unwrap_err();
+        assert!(
+            err.to_string().contains("failed to process 1 files"),
+            "{}",
+            err
+        );
+        assert!(!state.exists());
+        assert_eq!("---\nauthors: Ann\ndraft: true\n---\n", read_to_string(&a)?);
+
+        run(args(&[])?)?;
+        assert_eq!("1\n", read_to_string(&state)?);
+        Ok(())
+    }
+
+    #[test]
+    fn counts_keys_for_typos_as_files_are_parsed() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut paths = Vec::new();
+        for (name, key) in [
+            ("a.md", "categories"),
+            ("b.md", "categories"),
+            ("c.md", "categories"),
+            ("d.md", "catagories"),
+        ] {
+            let path = dir.path().join(name);
+            write(&path, format!("#!/bin/sh\n---\n{}: [x]\n---\n", key))?;
+            paths.push(path.display().to_string());
+        }
+        let args = [
+            "frontmatter-fixer",
+            "--frontmatter-prefix",
+            "#!*",
+            "--fix-key-typos",
+            "--yes",
+        ];
+        run(Config::try_parse_from(
+            args.iter().copied().chain(paths.iter().map(String::as_str)),
+        )?)?;
+        let fixed = read_to_string(&paths[3])?;
+        assert!(
+            fixed.starts_with("#!/bin/sh\n---\ncategories:"),
+            "{}",
+            fixed
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exports_redacted_without_a_script() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let post = dir.path().join("post.md");
+        write(&post, "---\ntitle: Hi\ninternal_notes: secret\n---\nBody\n")?;
+        let out = dir.path().join("out");
+        let args = [
+            "frontmatter-fixer",
+            "--output-dir",
+            out.to_str().unwrap(),
+            "--redact",
+            "keys=internal_*",
+            post.to_str().unwrap(),
+        ];
+        run(Config::try_parse_from(args)?)?;
+        let exported = read_to_string(output_path(&out, post.to_str().unwrap())?)?;
+        assert_eq!("---\ntitle: Hi\n---\nBody\n", exported);
+        Ok(())
+    }
+
+    #[test]
+    fn repair_writes_back_what_scripts_repair() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let post = dir.path().join("post.md");
+        let broken = "---\ntitle: [Hi\ndraft: true\n---\nBody\n";
+        write(&post, broken)?;
+        // fails unless the script sees both the text and why it didn't parse
+        let script = "assert(meta == nil and meta_error:find('line 2'))\n\
+                      meta = yaml_load((meta_raw:gsub('%[', '')))\n\
+                      meta.repaired = true";
+        let args = |extra: &[&str]| {
+            let mut args = vec!["frontmatter-fixer", "-e", script];
+            args.extend(extra);
+            args.push(post.to_str().unwrap());
+            Config::try_parse_from(args)
+        };
+
+        assert!(run(args(&[])?).is_err());
+        assert_eq!(broken, read_to_string(&post)?);
+        run(args(&["--repair"])?)?;
+        // sorted, with no parsed frontmatter to keep the order of
+        assert_eq!(
+            "---\ndraft: true\nrepaired: true\ntitle: Hi\n---\nBody\n",
+            read_to_string(&post)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn converts_html_before_fixing() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let page = dir.path().join("page.html");
+        write(
+            &page,
+            "<html><head><title>Hi</title></head><body><p>Body</p></body></html>\n",
+        )?;
+        let page_path = page.to_str().unwrap();
+        let args = [
+            "frontmatter-fixer",
+            "--convert",
+            "html",
+            "--set",
+            "draft=true",
+            "--yes",
+        ];
+        run(Config::try_parse_from(
+            args.iter().copied().chain([page_path]),
+        )?)?;
+        assert!(!page.exists());
+        assert_eq!(
+            "---\ntitle: Hi\ndraft: true\n---\n\nBody\n",
+            read_to_string(dir.path().join("page.md"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn normalized_output_is_the_same_everywhere() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let decomposed = dir.path().join("cafe\u{301}.md");
+        write(
+            &decomposed,
+            "\u{feff}---\r\ntitle: Cafe\u{301}\r\nauthor: Zoe\u{308}\r\n---\r\nBody\r\n",
+        )?;
+        let args = ["frontmatter-fixer", "--normalize-output", "--yes"];
+        run(Config::try_parse_from(
+            args.iter().copied().chain(decomposed.to_str()),
+        )?)?;
+        assert_eq!(
+            "---\nauthor: Zo\u{eb}\ntitle: Caf\u{e9}\n---\nBody\n",
+            read_to_string(dir.path().join("caf\u{e9}.md"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn flattens_inheritance() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        write(
+            dir.path().join("_base.md"),
+            "---\nlayout: docs\nnav: {order: 1}\n---\n",
+        )?;
+        let page = dir.path().join("page.md");
+        write(
+            &page,
+            "---\ntitle: Hi\nextends: _base.md\nnav: {title: P}\n---\nBody\n",
+        )?;
+        let args = ["frontmatter-fixer", "--flatten-inheritance", "--yes"];
+        run(Config::try_parse_from(
+            args.iter().copied().chain(page.to_str()),
+        )?)?;
+        assert_eq!(
+            "---\ntitle: Hi\nlayout: docs\nnav:\n  title: P\n  order: 1\n---\nBody\n",
+            read_to_string(&page)?
+        );
+        Ok(())
+    }
+
+    /// Documents that are hard to write back faithfully, and what each
+    /// fidelity level makes of them once `draft` is backfilled, `None` if
+    /// it fails them.
+    const FIDELITY_FIXTURES: [(&str, &str, [Option<&str>; 3]); 4] = [
+        (
+            "comments.md",
+            "---\ntitle: 'Hello'  # quoted\ntags: [a, b]\n---\nBody\n",
+            [
+                Some("---\ntitle: 'Hello'  # quoted\ntags: [a, b]\ndraft: true\n---\nBody\n"),
+                Some("---\ntitle: 'Hello'  # quoted\ntags: [a, b]\ndraft: true\n---\nBody\n"),
+                Some("---\ntitle: Hello\ntags:\n- a\n- b\ndraft: true\n---\nBody\n"),
+            ],
+        ),
+        (
+            "unchanged.md",
+            "---\ntitle:   Spaced\ndraft:  true\n---\nBody\n",
+            [
+                Some("---\ntitle:   Spaced\ndraft:  true\n---\nBody\n"),
+                Some("---\ntitle:   Spaced\ndraft:  true\n---\nBody\n"),
+                Some("---\ntitle: Spaced\ndraft: true\n---\nBody\n"),
+            ],
+        ),
+        (
+            "pandoc.md",
+            "---\r\ntitle: Hi # c\r\n...\r\nBody\r\n",
+            [
+                Some("---\r\ntitle: Hi # c\r\ndraft: true\r\n...\r\nBody\r\n"),
+                Some("---\r\ntitle: Hi # c\r\ndraft: true\r\n...\r\nBody\r\n"),
+                Some("---\r\ntitle: Hi\r\ndraft: true\r\n---\r\nBody\r\n"),
+            ],
+        ),
+        (
+            "hugo.md",
+            "+++\ntitle = \"Hi\" # c\n+++\nBody\n",
+            [
+                None,
+                Some("+++\ntitle = \"Hi\"\ndraft = true\n+++\nBody\n"),
+                Some("+++\ntitle = \"Hi\"\ndraft = true\n+++\nBody\n"),
+            ],
+        ),
+    ];
+
+    #[test]
+    fn fidelity_levels_keep_what_they_promise() -> eyre::Result<()> {
+        for (level, fidelity) in ["byte-identical", "semantic", "normalized"]
+            .iter()
+            .enumerate()
+        {
+            let dir = tempfile::tempdir()?;
+            let mut args = vec![
+                "frontmatter-fixer",
+                "--set-missing",
+                "draft=true",
+                "--fidelity",
+            ];
+            args.push(*fidelity);
+            let paths: Vec<String> = FIDELITY_FIXTURES
+                .iter()
+                .map(|(name, original, _)| {
+                    let path = dir.path().join(name);
+                    write(&path, original)?;
+                    Ok(path.to_string_lossy().into_owned())
+                })
+                .collect::<eyre::Result<_>>()?;
+            args.extend(paths.iter().map(String::as_str));
+            let result = run(Config::try_parse_from(args)?);
+
+            let mut failures = 0;
+            for ((name, original, expected), path) in FIDELITY_FIXTURES.iter().zip(&paths) {
+                let expected = expected[level].unwrap_or_else(|| {
+                    failures += 1;
+                    original
+                });
+                assert_eq!(expected, read_to_string(path)?, "{} at {}", name, fidelity);
+            }
+            assert_eq!(failures > 0, result.is_err(), "{}", fidelity);
+        }
+        let strict = [
+            "frontmatter-fixer",
+            "--fidelity",
+            "byte-identical",
+            "--line-endings",
+            "lf",
+        ];
+        assert!(Pipeline::new(&Config::try_parse_from(strict)?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rewrites_frontmatter_without_a_script() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let post = dir.path().join("post.md");
+        let original = "---\ntitle:   'Hi'\n---\nBody\n";
+        let run_with = |flag: &str| -> eyre::Result<String> {
+            write(&post, original)?;
+            run(Config::try_parse_from([
+                "frontmatter-fixer",
+                flag,
+                post.to_str().unwrap(),
+            ])?)?;
+            Ok(read_to_string(&post)?)
+        };
+        assert_eq!(
+            "---\ntitle: Hi\n---\nBody\n",
+            run_with("--fidelity=normalized")?
+        );
+        // which only rewrites frontmatter that changes
+        assert_eq!(original, run_with("--reformat-frontmatter")?);
+        Ok(())
+    }
+
+    #[test]
+    fn meta_only_allows_only_frontmatter_changes() {
+        let original = "---\r\ntitle: Hi\r\n---\r\nBody\r\n";
+        let retitled = "---\r\ntitle: Hello\r\ndraft: true\r\n---\r\nBody\r\n";
+        check_meta_only(original, retitled.as_bytes(), &[], &Markdown).unwrap();
+        check_meta_only("Body\n", b"---\ntitle: Hi\n---\nBody\n", &[], &Markdown).unwrap();
+        for changed in [
+            "---\r\ntitle: Hi\r\n---\r\nBody \r\n",
+            "---\ntitle: Hi\n---\nBody\n",
+        ] {
+            let err = check_meta_only(original, changed.as_bytes(), &[], &Markdown)
+                .expect_err("content changed");
+            assert_eq!(
+                "--meta-only: would change content outside the frontmatter",
+                err.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn sandboxed_scripts_only_move_files_within_their_corpus() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let blog = dir.path().join("blog");
+        create_dir_all(blog.join("posts"))?;
+        let post = blog.join("posts/post.md");
+        let corpus = format!("blog={}", blog.display());
+        let moving_to = |new_path: &str, extra: &[&str]| -> eyre::Result<bool> {
+            write(&post, "---\ntitle: Hi\n---\n")?;
+            let script = format!("rename_to({:?})", new_path);
+            let mut args = vec!["frontmatter-fixer", "--yes", "-e", &script];
+            args.extend(extra);
+            args.extend(["--corpus", &corpus]);
+            Ok(run(Config::try_parse_from(args)?).is_ok())
+        };
+
+        assert!(moving_to("../drafts/post.md", &[])?);
+        std::fs::remove_file(blog.join("drafts/post.md"))?;
+        assert!(!moving_to("../../outside.md", &[])?);
+        assert!(!moving_to(
+            &dir.path().join("abs.md").display().to_string(),
+            &[]
+        )?);
+        assert!(!dir.path().join("outside.md").exists());
+        assert!(moving_to("../../outside.md", &["--no-sandbox"])?);
+        assert!(dir.path().join("outside.md").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn output_path_rejects_parent_dir() {
+        let _ = output_path(Path::new("out"), "../secret.md")
+            .expect_err("shouldn't escape output directory");
+    }
+}
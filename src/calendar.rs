@@ -0,0 +1,385 @@
+use std::{fs::read_to_string, path::Path};
+
+use eyre::{eyre, Context};
+use serde_yaml as yaml;
+
+use crate::{
+    date::{self, Date},
+    feed::{expand_permalink, scalar},
+    frontmatter, helpers,
+    walk::{self, Filter},
+};
+
+/// Which frontmatter keys hold what each event needs, and how to make its
+/// link.
+#[derive(Clone, Debug)]
+pub struct Fields {
+    pub title_key: String,
+    pub start_key: String,
+    pub end_key: String,
+    pub location_key: String,
+    pub description_key: String,
+    /// A URL pattern as for feed permalinks, with `{year}`, `{month}` and
+    /// `{day}` taken from the start.
+    pub url: Option<String>,
+}
+
+/// When an event starts or ends: a whole day, or a time of day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum When {
+    Day(Date),
+    /// A time with no time zone, which calendars take as local time.
+    Floating(Date, u32),
+    /// A time in UTC, converted from whatever offset it was given with.
+    Utc(Date, u32),
+}
+
+impl When {
+    /// Parse a date, or a date and time as `2024-05-01T18:30` or
+    /// `2024-05-01 18:30:00`, optionally followed by `Z` or an offset like
+    /// `+02:00`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (date_part, time_part) = match s.split_once(['T', ' ']) {
+            Some((date_part, time_part)) if date_part.contains(['-', '/', '.']) => {
+                (date_part, Some(time_part.trim()))
+            }
+            _ => (s, None),
+        };
+        let date = Date::parse(date_part)?;
+        let time_part = match time_part {
+            Some(time_part) => time_part,
+            None => return Some(Self::Day(date)),
+        };
+
+        let (time, offset) = if let Some(time) = time_part.strip_suffix(['Z', 'z']) {
+            (time, Some(0))
+        } else if let Some(sign) = time_part.rfind(['+', '-']) {
+            let (time, offset) = time_part.split_at(sign);
+            let (hours, minutes) = offset[1..].split_once(':').unwrap_or((&offset[1..], "0"));
+            let minutes = hours.parse::<i64>().ok()? * 60 + minutes.parse::<i64>().ok()?;
+            (
+                time,
+                Some(if offset.starts_with('-') {
+                    -minutes
+                } else {
+                    minutes
+                }),
+            )
+        } else {
+            (time_part, None)
+        };
+        let mut parts = time.trim().split(':');
+        let hours: u32 = parts.next()?.parse().ok()?;
+        let minutes: u32 = parts.next()?.parse().ok()?;
+        // fractions of a second don't matter to a calendar
+        let seconds: u32 = match parts.next() {
+            Some(seconds) => seconds.split('.').next()?.parse().ok()?,
+            None => 0,
+        };
+        if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+            return None;
+        }
+        let secs_of_day = hours * 3600 + minutes * 60 + seconds;
+        Some(match offset {
+            None => Self::Floating(date, secs_of_day),
+            Some(offset) => {
+                let secs = date.days_since_epoch() * 86400 + i64::from(secs_of_day) - offset * 60;
+                Self::Utc(
+                    Date::from_days_since_epoch(secs.div_euclid(86400)),
+                    secs.rem_euclid(86400) as u32,
+                )
+            }
+        })
+    }
+
+    fn date(&self) -> Date {
+        self.sort_key().0
+    }
+
+    /// The date and seconds into it, with whole days starting at midnight.
+    fn sort_key(&self) -> (Date, u32) {
+        match *self {
+            Self::Day(date) => (date, 0),
+            Self::Floating(date, secs) | Self::Utc(date, secs) => (date, secs),
+        }
+    }
+
+    /// The property for this time, e.g. `DTSTART;VALUE=DATE:20240501`.
+    fn property(&self, name: &str) -> String {
+        match self {
+            Self::Day(date) => format!("{};VALUE=DATE:{}", name, basic_date(*date)),
+            Self::Floating(date, secs) => format!("{}:{}", name, basic_date_time(*date, *secs)),
+            Self::Utc(date, secs) => format!("{}:{}Z", name, basic_date_time(*date, *secs)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub uid: String,
+    pub summary: String,
+    pub start: When,
+    pub end: Option<When>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+}
+
+/// Read the events for a calendar from the files in `paths`, searching any
+/// directories recursively, in order of when they start.
+///
+/// Files without frontmatter or without a start are left out.
+pub fn collect_events(paths: &[String], fields: &Fields) -> eyre::Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for path in walk::expand(paths, &Filter::default())? {
+        let source = read_to_string(&path).context(format!("couldn't read {}", path))?;
+        let metadata = match frontmatter::parse(&source).0 {
+            Some(metadata) => {
+                metadata.context(format!("couldn't parse frontmatter in {}", path))?
+            }
+            None => continue,
+        };
+        if let Some(event) = event(Path::new(&path), &metadata, fields)
+            .context(format!("couldn't make calendar event for {}", path))?
+        {
+            events.push(event);
+        }
+    }
+    events.sort_by(|a, b| {
+        let (a_start, b_start) = (a.start.sort_key(), b.start.sort_key());
+        a_start.cmp(&b_start).then_with(|| a.uid.cmp(&b.uid))
+    });
+    Ok(events)
+}
+
+fn event(path: &Path, metadata: &yaml::Value, fields: &Fields) -> eyre::Result<Option<Event>> {
+    let when = |key: &str| -> eyre::Result<Option<When>> {
+        match metadata.get(key).and_then(scalar) {
+            Some(value) => When::parse(&value)
+                .map(Some)
+                .ok_or_else(|| eyre!("can't understand {} {:?}", key, value)),
+            None => Ok(None),
+        }
+    };
+    let start = match when(&fields.start_key)? {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    let end = when(&fields.end_key)?;
+    if end.is_some_and(|end| end.sort_key() < start.sort_key()) {
+        return Err(eyre!("{} is before {}", fields.end_key, fields.start_key));
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let date = start.date();
+    let url = fields
+        .url
+        .as_ref()
+        .map(|pattern| {
+            expand_permalink(pattern, |placeholder| match placeholder {
+                "stem" => Some(stem.clone()),
+                "year" => Some(format!("{:04}", date.year)),
+                "month" => Some(format!("{:02}", date.month)),
+                "day" => Some(format!("{:02}", date.day)),
+                key => metadata.get(key).and_then(scalar),
+            })
+        })
+        .transpose()?;
+    Ok(Some(Event {
+        // stable across runs, so calendar apps update events in place
+        uid: format!(
+            "{}@frontmatter-fixer",
+            helpers::slugify(&path.to_string_lossy())
+        ),
+        summary: metadata
+            .get(&fields.title_key)
+            .and_then(scalar)
+            .unwrap_or(stem),
+        start,
+        end,
+        location: metadata.get(&fields.location_key).and_then(scalar),
+        description: metadata.get(&fields.description_key).and_then(scalar),
+        url,
+    }))
+}
+
+/// Render an RFC 5545 iCalendar file, with CRLF line endings as it
+/// requires.
+pub fn render(name: Option<&str>, events: &[Event]) -> String {
+    let stamp = date::now_timestamp().replace(['-', ':'], "");
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//frontmatter-fixer//EN".to_owned(),
+        "CALSCALE:GREGORIAN".to_owned(),
+    ];
+    if let Some(name) = name {
+        lines.push(format!("X-WR-CALNAME:{}", escape_text(name)));
+    }
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_owned());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(event.start.property("DTSTART"));
+        match event.end {
+            // an all-day event's end is exclusive, but in frontmatter it's
+            // the last day
+            Some(When::Day(end)) => lines.push(
+                When::Day(Date::from_days_since_epoch(end.days_since_epoch() + 1))
+                    .property("DTEND"),
+            ),
+            Some(end) => lines.push(end.property("DTEND")),
+            None => {}
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        if let Some(location) = &event.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        if let Some(description) = &event.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        if let Some(url) = &event.url {
+            lines.push(format!("URL:{}", url));
+        }
+        lines.push("END:VEVENT".to_owned());
+    }
+    lines.push("END:VCALENDAR".to_owned());
+    lines.iter().map(|line| fold(line) + "\r\n").collect()
+}
+
+fn basic_date(date: Date) -> String {
+    format!("{:04}{:02}{:02}", date.year, date.month, date.day)
+}
+
+fn basic_date_time(date: Date, secs_of_day: u32) -> String {
+    format!(
+        "{}T{:02}{:02}{:02}",
+        basic_date(date),
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+fn escape_text(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line so no line is longer than 75 bytes, continuing
+/// lines with a leading space.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::write;
+
+    use super::*;
+
+    fn fields() -> Fields {
+        Fields {
+            title_key: "title".into(),
+            start_key: "start".into(),
+            end_key: "end".into(),
+            location_key: "location".into(),
+            description_key: "description".into(),
+            url: Some("https://example.com/{year}/{stem}/".into()),
+        }
+    }
+
+    #[test]
+    fn parses_times() {
+        let date = Date::new(2024, 5, 1).unwrap();
+        assert_eq!(Some(When::Day(date)), When::parse("2024-05-01"));
+        assert_eq!(
+            Some(When::Floating(date, 66600)),
+            When::parse("2024-05-01T18:30")
+        );
+        assert_eq!(
+            Some(When::Utc(date, 66615)),
+            When::parse("2024-05-01 18:30:15Z")
+        );
+        assert_eq!(
+            Some(When::Utc(Date::new(2024, 4, 30).unwrap(), 81000)),
+            When::parse("2024-05-01T00:30:00+02:00")
+        );
+        assert_eq!(None, When::parse("2024-05-01T25:00"));
+        assert_eq!(None, When::parse("soon"));
+    }
+
+    #[test]
+    fn exports_events() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for (name, source) in [
+            (
+                "may.md",
+                "---\ntitle: May meetup\nstart: 2024-05-01T18:30\nend: 2024-05-01T21:00\n\
+                 location: The Pub, Main St\n---\n",
+            ),
+            (
+                "camp.md",
+                "---\ntitle: Camp\nstart: 2024-04-12\nend: 2024-04-14\n---\n",
+            ),
+            ("notes.md", "---\ntitle: Not an event\n---\n"),
+        ] {
+            write(dir.path().join(name), source)?;
+        }
+        let root = dir.path().display().to_string();
+        let events = collect_events(&[root], &fields())?;
+        assert_eq!(
+            vec!["Camp", "May meetup"],
+            events
+                .iter()
+                .map(|e| e.summary.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some("https://example.com/2024/may/"),
+            events[1].url.as_deref()
+        );
+
+        let ics = render(Some("Meetups"), &events);
+        let lines: Vec<&str> = ics.split("\r\n").collect();
+        for expected in [
+            "X-WR-CALNAME:Meetups",
+            "DTSTART;VALUE=DATE:20240412",
+            "DTEND;VALUE=DATE:20240415",
+            "DTSTART:20240501T183000",
+            "DTEND:20240501T210000",
+            "LOCATION:The Pub\\, Main St",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        let folded = fold(&format!("DESCRIPTION:{}", "é".repeat(40)));
+        assert!(folded.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(
+            format!("DESCRIPTION:{}", "é".repeat(40)),
+            folded.replace("\r\n ", "")
+        );
+    }
+}
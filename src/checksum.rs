@@ -0,0 +1,72 @@
+use serde_yaml as yaml;
+
+use crate::{
+    hash,
+    report::{Finding, Findings},
+};
+
+/// The hash recorded for a file's body.
+pub fn body_hash(content: &str) -> String {
+    hash::sha256_hex(content.as_bytes())
+}
+
+/// Store the hash of a file's body under `key`, replacing any stale one.
+///
+/// Returns whether the stored hash changed.
+pub fn update(metadata: &mut yaml::Value, key: &str, content: &str) -> bool {
+    let sha256 = body_hash(content);
+    let mapping = match metadata.as_mapping_mut() {
+        Some(mapping) => mapping,
+        None => return false,
+    };
+    if mapping.get(key).and_then(yaml::Value::as_str) == Some(sha256.as_str()) {
+        return false;
+    }
+    mapping.insert(key.into(), sha256.into());
+    true
+}
+
+/// Flag a file whose body no longer matches the hash stored under `key`,
+/// i.e. that was edited without the hash being updated. Files with no
+/// stored hash pass.
+pub fn check(metadata: &yaml::Value, key: &str, content: &str) -> Result<(), Findings> {
+    let stored = metadata.get(key).and_then(yaml::Value::as_str);
+    if stored.is_some_and(|stored| stored != body_hash(content)) {
+        let message = format!("content has changed since {} was recorded", key);
+        return Err(Findings(vec![Finding::for_key(
+            "stale-content-hash",
+            key,
+            message,
+        )]));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn updates_stale_hashes() -> eyre::Result<()> {
+        let mut meta: yaml::Value = yaml::from_str("title: Hello\n")?;
+        assert!(update(&mut meta, "content_sha", "# Hello\n"));
+        assert!(!update(&mut meta, "content_sha", "# Hello\n"));
+        assert!(update(&mut meta, "content_sha", "# Goodbye\n"));
+        assert_eq!(
+            Some(hash::sha256_hex(b"# Goodbye\n").as_str()),
+            meta["content_sha"].as_str()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn flags_out_of_band_edits() -> eyre::Result<()> {
+        let mut meta: yaml::Value = yaml::from_str("title: Hello\n")?;
+        check(&meta, "content_sha", "# Hello\n").expect("no hash recorded yet");
+        update(&mut meta, "content_sha", "# Hello\n");
+        check(&meta, "content_sha", "# Hello\n").expect("hash matches");
+        let findings = check(&meta, "content_sha", "# Hello, edited\n").expect_err("stale");
+        assert_eq!("stale-content-hash", findings.0[0].rule);
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use std::{fmt, str::FromStr};
+
+use serde_yaml as yaml;
+
+use crate::{date::Date, keypath};
+
+/// The type a frontmatter value should be coerced to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    List,
+    Date,
+    Bool,
+    Int,
+    Float,
+    String,
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "list" => Ok(Self::List),
+            "date" => Ok(Self::Date),
+            "bool" => Ok(Self::Bool),
+            "int" => Ok(Self::Int),
+            "float" => Ok(Self::Float),
+            "string" => Ok(Self::String),
+            _ => Err(format!(
+                "unknown type {:?}, expected one of list, date, bool, int, float, string",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::List => "list",
+            Self::Date => "date",
+            Self::Bool => "bool",
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::String => "string",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A `KEY=TYPE` declaration from `--coerce`.
+#[derive(Clone, Debug)]
+pub struct Coercion {
+    pub key: String,
+    pub kind: Kind,
+}
+
+impl FromStr for Coercion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, kind) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=TYPE, got {:?}", s))?;
+        Ok(Self {
+            key: key.to_owned(),
+            kind: kind.parse()?,
+        })
+    }
+}
+
+/// Coerce each declared key present in `metadata` to its declared type.
+///
+/// Returns a description of each coercion actually applied; values already
+/// of the right type are left alone.
+pub fn apply(metadata: &mut yaml::Value, coercions: &[Coercion]) -> eyre::Result<Vec<String>> {
+    let mut applied = Vec::new();
+    for coercion in coercions {
+        let value = match keypath::get_mut(metadata, &coercion.key) {
+            Some(value) => value,
+            None => continue,
+        };
+        let coerced = coerce(value, coercion.kind).map_err(|e| {
+            eyre::eyre!(
+                "couldn't coerce {} to {}: {}",
+                coercion.key,
+                coercion.kind,
+                e
+            )
+        })?;
+        if let Some(coerced) = coerced {
+            applied.push(format!(
+                "{}: {} -> {}",
+                coercion.key,
+                type_name(value),
+                coercion.kind
+            ));
+            *value = coerced;
+        }
+    }
+    Ok(applied)
+}
+
+pub fn type_name(value: &yaml::Value) -> &'static str {
+    match value {
+        yaml::Value::Null => "null",
+        yaml::Value::Bool(_) => "bool",
+        yaml::Value::Number(n) if n.is_f64() => "float",
+        yaml::Value::Number(_) => "int",
+        yaml::Value::String(_) => "string",
+        yaml::Value::Sequence(_) => "list",
+        yaml::Value::Mapping(_) => "mapping",
+        yaml::Value::Tagged(_) => "tagged",
+    }
+}
+
+/// Returns `Ok(None)` if the value already has the requested type.
+pub(crate) fn coerce(value: &yaml::Value, kind: Kind) -> Result<Option<yaml::Value>, String> {
+    use yaml::Value;
+
+    let coerced = match (kind, value) {
+        (Kind::List, Value::Sequence(_)) => return Ok(None),
+        (Kind::List, Value::Null) => Value::Sequence(Vec::new()),
+        (Kind::List, Value::String(s)) => Value::Sequence(
+            s.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(Value::from)
+                .collect(),
+        ),
+        (Kind::List, Value::Mapping(_)) => return Err("mappings can't become lists".into()),
+        (Kind::List, scalar) => Value::Sequence(vec![scalar.clone()]),
+
+        (Kind::Date, Value::String(s)) => {
+            let date = Date::parse(s).ok_or_else(|| format!("{:?} isn't a date", s))?;
+            let formatted = date.to_string();
+            if &formatted == s {
+                return Ok(None);
+            }
+            Value::from(formatted)
+        }
+
+        (Kind::Bool, Value::Bool(_)) => return Ok(None),
+        (Kind::Bool, Value::String(s)) => match s.trim().to_lowercase().as_str() {
+            "yes" | "y" | "true" | "on" | "1" => Value::from(true),
+            "no" | "n" | "false" | "off" | "0" => Value::from(false),
+            _ => return Err(format!("{:?} isn't a boolean", s)),
+        },
+        (Kind::Bool, Value::Number(n)) => match n.as_i64() {
+            Some(1) => Value::from(true),
+            Some(0) => Value::from(false),
+            _ => return Err(format!("{} isn't a boolean", n)),
+        },
+
+        (Kind::Int, Value::Number(n)) if n.is_f64() => match n.as_f64() {
+            Some(f) if f.fract() == 0.0 => Value::from(f as i64),
+            _ => return Err(format!("{} isn't a whole number", n)),
+        },
+        (Kind::Int, Value::Number(_)) => return Ok(None),
+        (Kind::Int, Value::String(s)) => Value::from(
+            s.trim()
+                .parse::<i64>()
+                .map_err(|_| format!("{:?} isn't an integer", s))?,
+        ),
+
+        (Kind::Float, Value::Number(n)) if n.is_f64() => return Ok(None),
+        (Kind::Float, Value::Number(n)) => Value::from(n.as_f64().unwrap_or_default()),
+        (Kind::Float, Value::String(s)) => Value::from(
+            s.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("{:?} isn't a number", s))?,
+        ),
+
+        (Kind::String, Value::String(_)) => return Ok(None),
+        (Kind::String, Value::Bool(b)) => Value::from(b.to_string()),
+        (Kind::String, Value::Number(n)) => Value::from(n.to_string()),
+
+        (_, other) => return Err(format!("can't convert a {}", type_name(other))),
+    };
+    Ok(Some(coerced))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn coerce_all(yfm: &str, coercions: &[&str]) -> (yaml::Value, Vec<String>) {
+        let mut meta: yaml::Value = yaml::from_str(yfm).unwrap();
+        let coercions: Vec<Coercion> = coercions.iter().map(|c| c.parse().unwrap()).collect();
+        let applied = apply(&mut meta, &coercions).unwrap();
+        (meta, applied)
+    }
+
+    #[test]
+    fn splits_comma_strings_into_lists() {
+        let (meta, applied) = coerce_all("tags: rust, lua,\n", &["tags=list"]);
+        assert_eq!("tags:\n- rust\n- lua\n", yaml::to_string(&meta).unwrap());
+        assert_eq!(vec!["tags: string -> list"], applied);
+    }
+
+    #[test]
+    fn maps_yes_no_to_booleans() {
+        let (meta, _) = coerce_all(
+            "draft: yes\npublished: \"no\"\n",
+            &["draft=bool", "published=bool"],
+        );
+        assert_eq!(
+            "draft: true\npublished: false\n",
+            yaml::to_string(&meta).unwrap()
+        );
+    }
+
+    #[test]
+    fn normalizes_dates() {
+        let (meta, applied) = coerce_all("date: January 31, 2023\n", &["date=date"]);
+        assert_eq!(Some("2023-01-31"), meta["date"].as_str());
+        assert_eq!(vec!["date: string -> date"], applied);
+    }
+
+    #[test]
+    fn leaves_correct_types_alone() {
+        let (_, applied) = coerce_all(
+            "tags: [a]\ndraft: false\ndate: 2023-01-31\n",
+            &["tags=list", "draft=bool", "date=date", "missing=int"],
+        );
+        assert!(applied.is_empty());
+    }
+
+    #[test]
+    fn fails_on_unconvertible_values() {
+        let mut meta: yaml::Value = yaml::from_str("draft: maybe\n").unwrap();
+        let _ =
+            apply(&mut meta, &["draft=bool".parse().unwrap()]).expect_err("maybe isn't a boolean");
+    }
+}
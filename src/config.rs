@@ -0,0 +1,1388 @@
+use std::{
+    collections::BTreeSet,
+    env,
+    ffi::OsString,
+    fs::{read_to_string, write},
+    io::{self, Read, Write},
+    iter,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{CommandFactory, Parser, Subcommand};
+use eyre::{eyre, Context};
+
+use crate::{
+    batch, calendar,
+    coerce::Coercion,
+    convert::Converter,
+    corpus::CorpusSpec,
+    duplicates::Merge,
+    edit::Edits,
+    envelope::{self, ForExtension},
+    feed::{self, Channel, FeedFormat, Fields},
+    fixer::{Limits, Script, ScriptOptions},
+    frontmatter::{BlankLine, Fidelity, Format, LineEndings},
+    history,
+    import::{self, DataFormat, Layout},
+    infer,
+    journal::Journal,
+    library,
+    links::LinkStyle,
+    migrate::{Migration, MIGRATIONS_DIR},
+    order::KeyOrder,
+    permalink::{Pattern, Permalink},
+    project::{ProjectConfig, CONFIG_FILE},
+    provenance::Transform,
+    publish::Archive,
+    query::{Aggregation, Condition},
+    recipes::Recipe,
+    redact::Redaction,
+    render::Template,
+    report::ReportSpec,
+    search::{self, FieldMapping, IndexFormat},
+    select::{QueryFormat, Select},
+    snapshot::Snapshot,
+    stamp::StampTarget,
+    title::Dedupe,
+    why,
+};
+
+/// The program's name, when the command line doesn't give it.
+const PROGRAM: &str = "frontmatter-fixer";
+
+/// Run a Lua script to fix your frontmatter
+#[derive(Clone, Debug, Default, Parser)]
+#[command(args_conflicts_with_subcommands = true, args_override_self = true)]
+pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Pass a short Lua script to run, or - to read the script from stdin;
+    /// may be repeated, running each in turn after any script files
+    #[arg(short = 'e', long = "eval")]
+    pub inline_scripts: Vec<String>,
+    /// Read a Lua script from a file; may be repeated, running each in turn
+    /// on what the one before left
+    #[arg(short = 'f', long = "script", id = "SCRIPT_FILE")]
+    pub script_paths: Vec<String>,
+    /// Run a built-in script for a common fix before any others; may be
+    /// repeated. Recipes: add-missing-date, add-missing-title,
+    /// add-missing-slug, tags-to-list, lowercase-tags, dedupe-tags,
+    /// sort-tags, lowercase-keys, trim-strings, remove-empty
+    #[arg(long = "recipe", id = "RECIPE")]
+    pub recipes: Vec<Recipe>,
+    /// Run a Lua REPL on each file in turn (enter :help for its commands),
+    /// with line editing and history kept in ~/.frontmatter-fixer_history
+    /// at a terminal
+    #[arg(short = 'r', long = "repl")]
+    pub repl: bool,
+    /// Look for modules scripts require in this directory, after those of
+    /// any script files, e.g. with --lua-path lib, require 'dates' loads
+    /// lib/dates.lua (may be repeated)
+    #[arg(long = "lua-path", id = "LUA_DIR")]
+    pub lua_paths: Vec<PathBuf>,
+    /// Give scripts the whole Lua standard library, including io and os,
+    /// and no limit on the time or memory they use: only for scripts you
+    /// trust
+    #[arg(long = "no-sandbox")]
+    pub no_sandbox: bool,
+    /// Fail a file if scripts run on it for longer than this many seconds
+    #[arg(long = "script-timeout", id = "SECONDS", value_parser = parse_seconds)]
+    pub script_timeout: Option<Duration>,
+    /// Fail a file if the Lua state grows past this many megabytes while
+    /// scripts run on it (default 512 when sandboxed)
+    #[arg(long = "script-memory", id = "MB")]
+    pub script_memory: Option<usize>,
+    /// Fail a file if scripts run more than this many Lua instructions on
+    /// it (default 1000000000 when sandboxed)
+    #[arg(long = "script-instructions", id = "INSTRUCTIONS")]
+    pub script_instructions: Option<u64>,
+    /// Run scripts on files whose frontmatter doesn't parse rather than
+    /// failing them, with meta nil, the error in meta_error and the text in
+    /// meta_raw, for the script to set meta (e.g. with yaml_load)
+    #[arg(long = "repair")]
+    pub repair: bool,
+    /// Run the script in two passes: its collect(meta, file) function over
+    /// every file, then its fix() function over each file to fix it, with
+    /// whatever collect gathered in the global table state, and
+    /// corpus.values(key) and corpus.count(key, value) to tell how many
+    /// files have each value
+    #[arg(long = "aggregate", conflicts_with = "repl")]
+    pub aggregate: bool,
+    /// After fixing every file, run this script's rewrite_references(content,
+    /// changes, file) function over each file, for it to update references
+    /// to keys that changed, such as renamed tags or slugs: changes lists
+    /// each {path, key, old, new}, and it returns the file's new text, or
+    /// nil to leave it alone
+    #[arg(long = "rewrite-references", id = "REFERENCES_SCRIPT")]
+    pub rewrite_references: Option<String>,
+    /// Don't modify any files, just run script and show what would be done
+    #[arg(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+    /// Show what would change in each file and ask whether to write it
+    #[arg(
+        short = 'i',
+        long = "interactive",
+        conflicts_with_all = ["dry_run", "check", "repl", "jobs", "watch"]
+    )]
+    pub interactive: bool,
+    /// Before writing anything, try a sample of the files to estimate how
+    /// many would change and by which transforms, and ask to go on if that's
+    /// more than this many
+    #[arg(
+        long = "confirm-over",
+        id = "CONFIRM_THRESHOLD",
+        conflicts_with_all = ["dry_run", "check", "repl", "watch"]
+    )]
+    pub confirm_over: Option<usize>,
+    /// Don't modify any files, but list those that would change and exit
+    /// with status 2 if there are any, e.g. in CI or a pre-commit hook
+    /// (failures still exit with status 1)
+    #[arg(long = "check")]
+    pub check: bool,
+    /// Make destructive changes without asking: removing keys, stripping
+    /// frontmatter, moving files and editing content, which otherwise need
+    /// --interactive to confirm them (--dry-run lists them)
+    #[arg(short = 'y', long = "yes")]
+    pub yes: bool,
+    /// Fail any file whose content, outside its frontmatter, would change,
+    /// to guarantee a run touches metadata only
+    #[arg(long = "meta-only")]
+    pub meta_only: bool,
+    /// Stop at the first file that fails to process
+    #[arg(long = "fail-fast")]
+    pub fail_fast: bool,
+    /// Print the name of each file being processed and its outcome
+    #[arg(short = 'v', long = "verbose")]
+    pub verbose: bool,
+    /// Print nothing but errors: no progress bar, notices or summary
+    #[arg(short = 'q', long = "quiet", conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Print out the processed result of each file
+    #[arg(short = 'p', long = "print")]
+    pub print_result: bool,
+    /// Print a unified diff of the changes to each file
+    #[arg(long = "diff")]
+    pub diff: bool,
+    /// Look for frontmatter after any leading lines matching these globs,
+    /// e.g. '#!*' for a shebang, writing those lines back unchanged
+    #[arg(long = "frontmatter-prefix")]
+    pub frontmatter_prefix: Vec<String>,
+    /// How frontmatter is embedded in files: markdown (bare rules), html (in
+    /// an `<!-- -->` comment), template (in `{# #}`), hash (in `#` comment
+    /// lines), slashes (in `//` lines), line:PREFIX or block:OPEN,CLOSE
+    /// (default: going by each file's extension, e.g. html for HTML, hash
+    /// for YAML and Python, slashes for JavaScript and TypeScript, markdown
+    /// for anything unknown)
+    #[arg(long = "envelope")]
+    pub envelope: Option<envelope::Kind>,
+    /// The envelope for files with an extension, e.g. ts=line:// or
+    /// tpl=block:{{!,}} (may be repeated)
+    #[arg(long = "envelope-for", id = "EXT=ENVELOPE")]
+    pub envelope_for: Vec<ForExtension>,
+    /// Convert files in another format to Markdown as they're read, moving
+    /// them to a .md file: html (.html and .htm files, with the title and
+    /// meta tags as frontmatter) (may be repeated)
+    #[arg(long = "convert", value_name = "FORMAT")]
+    pub convert: Vec<Converter>,
+    /// Parse frontmatter as yaml or toml, whatever its delimiters say
+    #[arg(long = "from")]
+    pub from_format: Option<Format>,
+    /// Write frontmatter as yaml or toml, converting if it was read as the
+    /// other (default: the format it was read in)
+    #[arg(long = "to")]
+    pub to_format: Option<Format>,
+    /// Whether a blank line separates frontmatter from the content: always,
+    /// never, or preserve whatever the content starts with
+    #[arg(long = "blank-line-after-frontmatter", default_value = "preserve")]
+    pub blank_line_after_frontmatter: BlankLine,
+    /// Line endings to write files with: lf, crlf, native to the platform,
+    /// or preserve whatever each file had
+    #[arg(long = "line-endings", default_value = "preserve")]
+    pub line_endings: LineEndings,
+    /// Rewrite all of any YAML frontmatter that changes, rather than keeping
+    /// the lines of keys that didn't change as they were, comments, anchors
+    /// and quoting included
+    #[arg(long = "reformat-frontmatter")]
+    pub reformat_frontmatter: bool,
+    /// How faithfully files are kept: byte-identical (everything that isn't
+    /// changed stays as it was, failing files that can't be edited that
+    /// way), semantic (values are kept, formatting where possible) or
+    /// normalized (every file's frontmatter is written afresh)
+    #[arg(long = "fidelity", default_value = "semantic")]
+    pub fidelity: Fidelity,
+    /// Write files the same however they were checked out, e.g. in CI, so
+    /// macOS, Linux and Windows don't disagree: Unicode NFC file names and
+    /// frontmatter, LF line endings, no byte order mark, and keys in the
+    /// --sort-keys order, alphabetical if not given
+    #[arg(long = "normalize-output", conflicts_with = "line_endings")]
+    pub normalize_output: bool,
+    /// Remove whitespace from the ends of lines in the body, except for
+    /// Markdown hard line breaks
+    #[arg(long = "trim-trailing-ws")]
+    pub trim_trailing_ws: bool,
+    /// Add a newline to the end of bodies missing one
+    #[arg(long = "ensure-final-newline")]
+    pub ensure_final_newline: bool,
+    /// Write fixed files under this directory instead of modifying them in place
+    #[arg(short = 'o', long = "output-dir", id = "OUTPUT_DIR")]
+    pub output_dir: Option<PathBuf>,
+    /// Before modifying or moving a file, copy the original alongside it
+    /// with this suffix added to its name (.bak if just --backup)
+    #[arg(
+        long = "backup",
+        id = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".bak"
+    )]
+    pub backup_suffix: Option<String>,
+    /// Keep each modified file's modification time, so tools that go by it
+    /// don't see metadata fixes as edits
+    #[arg(long = "keep-mtime")]
+    pub keep_mtime: bool,
+    /// Record the original contents of every file modified or moved in this
+    /// journal, so `frontmatter-fixer undo JOURNAL_FILE` can restore them
+    #[arg(long = "journal", id = "JOURNAL_FILE", conflicts_with_all = ["dry_run", "check"])]
+    pub journal_path: Option<PathBuf>,
+    /// Render each fixed file's frontmatter, body and path through this
+    /// Tera template, e.g. to export feed items, escaping what it renders
+    /// if it's named for HTML or XML (item.html.tera); see --render-out
+    #[arg(long = "render", id = "TEMPLATE_FILE", requires = "RENDER_DIR")]
+    pub render_template: Option<PathBuf>,
+    /// Write --render output under this directory, mirroring the files'
+    /// paths, with the template's extension (item.xml.tera makes .xml files)
+    #[arg(long = "render-out", id = "RENDER_DIR", requires = "TEMPLATE_FILE")]
+    pub render_dir: Option<PathBuf>,
+    /// Remove keys matching these globs over dotted paths when exporting to
+    /// an output directory, e.g. keys=internal_*,reviewer
+    #[arg(long = "redact", requires = "OUTPUT_DIR")]
+    pub redact: Option<Redaction>,
+    /// Convert values to a declared type, e.g. tags=list,date=date,draft=bool
+    /// (types: list, date, bool, int, float, string)
+    #[arg(long = "coerce", value_delimiter = ',')]
+    pub coerce: Vec<Coercion>,
+    /// Write frontmatter's keys in a canonical order: any listed first, in
+    /// the order given, then the rest alphabetically, e.g. title,date,tags
+    /// (with no list, all alphabetically)
+    #[arg(long = "sort-keys", id = "KEYS", num_args = 0..=1, default_missing_value = "")]
+    pub sort_keys: Option<KeyOrder>,
+    #[command(flatten)]
+    pub edits: Edits,
+    /// Migrate keys without a script, applying each of these operations in
+    /// turn: rename OLD NEW, move FROM.PATH TO.PATH, split KEY SEP, join KEY
+    /// SEP, map-values KEY FROM=TO..., default KEY VALUE, drop KEY, unnest
+    /// KEY [PREFIX], nest PREFIX KEY, add-prefix PREFIX [except KEY...] or
+    /// strip-prefix PREFIX, each optionally followed by `if` and --where
+    /// conditions joined by `and`, e.g. "default layout post if !layout and
+    /// draft"
+    #[arg(long = "migrate", id = "MIGRATION")]
+    pub migrations: Vec<Migration>,
+    /// Apply the numbered migration files in this directory that each file
+    /// hasn't had yet, going by its `migration` key, which records the
+    /// latest applied (see `frontmatter-fixer migrate`)
+    #[arg(long = "migrations", id = "MIGRATIONS_DIR")]
+    pub migrations_dir: Option<PathBuf>,
+    /// Record the latest migration applied to the whole corpus in this file
+    /// instead of in each file's frontmatter, advancing it only when a run
+    /// migrates every file
+    #[arg(
+        long = "migration-state",
+        id = "MIGRATION_STATE",
+        requires = "MIGRATIONS_DIR"
+    )]
+    pub migration_state: Option<PathBuf>,
+    /// Write what each file inherits through an `extends: BASE` key, a path
+    /// relative to it, into its own frontmatter in place of `extends`: the
+    /// base's keys it doesn't have, and theirs within mappings both have
+    #[arg(long = "flatten-inheritance")]
+    pub flatten_inheritance: bool,
+    /// When the body starts with a heading repeating the title, remove
+    /// either the heading or the title key
+    #[arg(long = "dedupe-title")]
+    pub dedupe_title: Option<Dedupe>,
+    /// Move a level 1 heading starting the body into the title (unless the
+    /// title differs), demoting the body's other headings one level
+    #[arg(long = "promote-title", conflicts_with = "dedupe_title")]
+    pub promote_title: bool,
+    /// Publish drafts whose time has come: set `draft: true` to false where
+    /// `publish_at` (or failing that `date`) is now or earlier, reporting
+    /// each file promoted. Times without an offset are taken as UTC
+    #[arg(long = "publish-scheduled")]
+    pub publish_scheduled: bool,
+    /// Archive content whose `expires` (or failing that `sunset`) time has
+    /// passed, reporting each file: "mark" sets `archived: true`, "move"
+    /// moves it into an archive directory alongside it, and banner=TEXT
+    /// sets the `banner` key to TEXT
+    #[arg(long = "archive-expired", id = "ARCHIVE_MODE")]
+    pub archive_expired: Option<Archive>,
+    /// Convert links between the files being fixed to markdown
+    /// ([label](path.md)) or wiki ([[Target]]) links, resolving wiki link
+    /// targets by file name, title or aliases
+    #[arg(long = "convert-links")]
+    pub convert_links: Option<LinkStyle>,
+    /// Apply a patch to every file's frontmatter from this JSON file: a JSON
+    /// Patch (RFC 6902, an array of operations) or JSON Merge Patch (RFC
+    /// 7396, an object)
+    #[arg(long = "apply-patch", id = "PATCH_FILE")]
+    pub patch_path: Option<PathBuf>,
+    /// Apply patches from this JSON file, an object mapping file paths to
+    /// the JSON Patch or JSON Merge Patch for that file
+    #[arg(
+        long = "apply-patch-map",
+        id = "PATCH_MAP_FILE",
+        conflicts_with = "PATCH_FILE"
+    )]
+    pub patch_map_path: Option<PathBuf>,
+    /// Run a transform compiled to a WASI module, sandboxed, after any Lua
+    /// scripts; may be repeated. It reads {"path", "metadata", "content"} as
+    /// JSON on stdin and writes the fixed metadata as JSON to stdout, with
+    /// no access to files, the environment or the network
+    #[arg(long = "wasm", id = "WASM_MODULE")]
+    pub wasm_modules: Vec<PathBuf>,
+    /// Validate frontmatter against a schema file declaring the expected
+    /// keys, e.g. `date: date, required` or `tags: list of strings`, failing
+    /// files that break it
+    #[arg(long = "schema", id = "SCHEMA_FILE")]
+    pub schema_path: Option<PathBuf>,
+    /// Fail files containing keys not declared in the schema
+    #[arg(long = "deny-unknown-keys", requires = "SCHEMA_FILE")]
+    pub deny_unknown_keys: bool,
+    /// Rename keys that look like misspellings of a schema key or of a key
+    /// used much more often across the files being fixed
+    #[arg(long = "fix-key-typos")]
+    pub fix_key_typos: bool,
+    /// Store a SHA-256 hash of each file's body under this frontmatter key,
+    /// e.g. content_sha
+    #[arg(long = "content-hash", id = "CONTENT_HASH_KEY")]
+    pub content_hash_key: Option<String>,
+    /// Instead of updating --content-hash, fail files whose body no longer
+    /// matches the stored hash
+    #[arg(long = "check-content-hash", requires = "CONTENT_HASH_KEY")]
+    pub check_content_hash: bool,
+    /// Fail translations whose source has changed: files in sibling
+    /// directories of this one (e.g. content/de next to content/en) that
+    /// record an out-of-date hash of their source's body
+    #[arg(long = "translation-source", id = "TRANSLATION_SOURCE")]
+    pub translation_source: Option<PathBuf>,
+    /// Frontmatter key translations record their source's body hash under
+    /// (default: source_hash)
+    #[arg(long = "translation-hash-key", requires = "TRANSLATION_SOURCE")]
+    pub translation_hash_key: Option<String>,
+    /// Treat files as one page in these locales, e.g. en,fr: in locale
+    /// directories (content/en/a.md, content/fr/a.md) or with the locale
+    /// before the extension (a.md, a.fr.md, files without one being in the
+    /// first locale). Scripts see the other locales' variants in
+    /// file.variants
+    #[arg(long = "locales", id = "LOCALES", value_delimiter = ',')]
+    pub locales: Vec<String>,
+    /// Fail files where any of these keys differ from the page's variants
+    /// in other --locales, e.g. slug,translationKey
+    #[arg(
+        long = "consistent-across-locales",
+        requires = "LOCALES",
+        value_delimiter = ','
+    )]
+    pub locale_keys: Vec<String>,
+    /// The site's permalink pattern, e.g. /:section/:year/:slug/, for
+    /// scripts' permalink() to compute each file's URL with (tokens: :year,
+    /// :month, :day, :slug, :title, :filename, :section, :sections)
+    #[arg(long = "permalink", id = "PERMALINK")]
+    pub permalink: Option<Pattern>,
+    /// The directory --permalink sections are under, e.g. content
+    /// (default: the one paths are relative to)
+    #[arg(long = "content-root", requires = "PERMALINK")]
+    pub content_root: Option<PathBuf>,
+    /// Fail files whose --permalink URL another file also has
+    #[arg(long = "unique-permalinks", requires = "PERMALINK")]
+    pub unique_permalinks: bool,
+    /// Fail files whose body, frontmatter aside, is the same as or nearly
+    /// the same as an earlier-dated file's
+    #[arg(long = "find-duplicates", id = "FIND_DUPLICATES")]
+    pub find_duplicates: bool,
+    /// How many of the 64 bits of bodies' fingerprints may differ for
+    /// --find-duplicates to call them near duplicates (0 for exact duplicates
+    /// only)
+    #[arg(
+        long = "duplicate-distance",
+        requires = "FIND_DUPLICATES",
+        default_value_t = 3
+    )]
+    pub duplicate_distance: u32,
+    /// Rather than fail --find-duplicates' duplicates, merge their
+    /// frontmatter into the original's and delete them once it's written
+    /// (delete), adding their URLs to the original's aliases too (alias).
+    /// Deleting needs --interactive to confirm each one
+    #[arg(long = "merge-duplicates", requires = "FIND_DUPLICATES")]
+    pub merge_duplicates: Option<Merge>,
+    /// Mark keys added or modified by these transforms with a trailing
+    /// comment naming the tool and date (transforms: script, inherit, wasm,
+    /// patch, set, migrate, key-typos, coerce, publish, archive, content-hash)
+    #[arg(long = "provenance-comments", value_delimiter = ',')]
+    pub provenance_comments: Vec<Transform>,
+    /// Record the tool version, script hash and time in files this run
+    /// modifies: either "frontmatter" (a `fixer:` block) or manifest=PATH
+    #[arg(long = "version-stamp", id = "STAMP_TARGET")]
+    pub version_stamp: Option<StampTarget>,
+    /// Write a JSON manifest of inputs and outputs with their hashes, for
+    /// build systems to use as a dependency snapshot
+    #[arg(long = "manifest", id = "MANIFEST_FILE")]
+    pub manifest_path: Option<PathBuf>,
+    /// Record the arguments, scripts, schema and input files of this run,
+    /// for every corpus, into a bundle (a .tar.zst archive) that --replay can
+    /// rerun elsewhere
+    #[arg(long = "record", id = "RECORD_BUNDLE")]
+    pub record: Option<PathBuf>,
+    /// Leave file bodies out of the recorded bundle, keeping only frontmatter
+    #[arg(long = "record-redact-content", requires = "RECORD_BUNDLE")]
+    pub record_redact_content: bool,
+    /// The arguments this was parsed from, program name first, after adding
+    /// the project's defaults and expanding any task, so a recorded run
+    /// replays the same without the project's frontmatter-fixer.toml.
+    #[arg(skip)]
+    pub resolved_args: Vec<String>,
+    /// The directory of the `--corpus` being fixed, which sandboxed scripts
+    /// can only move its files within; for files given on the command line,
+    /// each file's own directory.
+    #[arg(skip)]
+    pub corpus_root: Option<PathBuf>,
+    /// Rerun a recorded bundle against copies of its files in a scratch
+    /// directory, printing the results; whatever else the run would write
+    /// goes there too, and --commit is left out
+    #[arg(
+        long = "replay",
+        id = "REPLAY_BUNDLE",
+        conflicts_with = "RECORD_BUNDLE"
+    )]
+    pub replay: Option<PathBuf>,
+    /// Append this run's totals (files processed, changed, failed, timings)
+    /// as a JSON line to a local stats file
+    #[arg(long = "stats-file", id = "STATS_FILE")]
+    pub stats_file: Option<PathBuf>,
+    /// After a successful run, commit exactly the files that changed with
+    /// this message (refuses if anything else is already staged)
+    #[arg(
+        long = "commit",
+        visible_alias = "git-commit",
+        id = "COMMIT_MESSAGE",
+        conflicts_with_all = ["dry_run", "check"]
+    )]
+    pub commit_message: Option<String>,
+    /// GPG-sign the commit made by --commit
+    #[arg(long = "commit-sign", requires = "COMMIT_MESSAGE")]
+    pub commit_sign: bool,
+    /// Report results in a machine-readable format: github (workflow
+    /// annotations), gitlab (code quality JSON), junit (XML test cases),
+    /// sarif, json (each file's outcome and whether it changed), or
+    /// json-patch (each changed file's frontmatter changes as an RFC 6902
+    /// JSON Patch), optionally =PATH to write to a file instead of stdout
+    #[arg(long = "report", id = "REPORT")]
+    pub reports: Vec<ReportSpec>,
+    /// Write each changed file's frontmatter changes as an RFC 6902 JSON
+    /// Patch to a sidecar file alongside it, named like post.md.patch.json
+    #[arg(long = "json-patch-sidecars")]
+    pub json_patch_sidecars: bool,
+    /// Process files on this many threads, each running its own copy of the
+    /// script (default 1, or 0 for one per CPU)
+    #[arg(short = 'j', long = "jobs", conflicts_with = "repl")]
+    pub jobs: Option<usize>,
+    /// With --jobs, write each directory's files one at a time in the order
+    /// given, so tools watching directories see one change after another
+    #[arg(long = "ordered-output", requires = "jobs")]
+    pub ordered_output: bool,
+    /// Fix this directory's files recursively as a separately summarised
+    /// corpus, with the options in its .frontmatter-fixer file if it has one
+    /// (may be repeated, e.g. --corpus blog=content/blog)
+    #[arg(long = "corpus", id = "CORPUS", conflicts_with = "FILES")]
+    pub corpora: Vec<CorpusSpec>,
+    /// Keep running, fixing files again whenever they change: the files
+    /// given, and those in any directories given and their subdirectories
+    #[arg(
+        short = 'w',
+        long = "watch",
+        conflicts_with_all = ["repl", "CORPUS", "RECORD_BUNDLE", "REPLAY_BUNDLE"]
+    )]
+    pub watch: bool,
+    /// Process the files in any directories given, and their subdirectories
+    #[arg(short = 'R', long = "recursive")]
+    pub recursive: bool,
+    /// Only process files git reports as modified, added or untracked: those
+    /// among the files and directories given, or under the current
+    /// directory if none are, e.g. from an editor's save hook
+    #[arg(
+        long = "git-changed",
+        visible_alias = "only-dirty",
+        conflicts_with = "watch"
+    )]
+    pub git_changed: bool,
+    /// With --recursive, only process files matching these globs, relative
+    /// to the directory given, e.g. '**/*.md'
+    #[arg(long = "include", requires = "recursive")]
+    pub include: Vec<String>,
+    /// With --recursive, skip files matching these globs, e.g. 'drafts/**'
+    #[arg(long = "exclude", requires = "recursive")]
+    pub exclude: Vec<String>,
+    /// Only process files whose frontmatter meets this condition: KEY (set
+    /// and not false or empty), !KEY, or KEY OP VALUE with =, !=, <, <=, >
+    /// or >=, e.g. 'date<2020', or 'KEY=nil' for missing or null (may be
+    /// repeated; all must hold)
+    #[arg(long = "where", id = "CONDITION")]
+    pub conditions: Vec<Condition>,
+    /// Don't run anything, just print the paths of the files that --where
+    /// (and --schema, if given) select, for other tools to work on
+    #[arg(long = "list", conflicts_with_all = ["repl", "watch", "check"])]
+    pub list: bool,
+    /// Don't run anything, just print how many files --where (and
+    /// --schema, if given) select
+    #[arg(long = "count", conflicts_with_all = ["list", "repl", "watch", "check"])]
+    pub count: bool,
+    /// With --count, count the selected files having each value of this key
+    /// (or of each element, for lists), e.g. --group-by tags
+    #[arg(long = "group-by", id = "GROUP_KEY", requires = "count")]
+    pub group_by: Option<String>,
+    /// With --count, also compute sum(KEY), avg(KEY), min(KEY) or max(KEY)
+    /// over the selected files (in each group, with --group-by), e.g.
+    /// --agg 'sum(reading_time)'; may be repeated
+    #[arg(long = "agg", id = "AGGREGATION", requires = "count")]
+    pub aggregations: Vec<Aggregation>,
+
+    /// Supply the files to fix as positional arguments, or - to fix stdin
+    /// and write the result to stdout (scripts then print to stderr)
+    #[arg(id = "FILES")]
+    pub paths: Vec<String>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Manage shared scripts vendored into this project
+    #[command(subcommand)]
+    Script(ScriptCommand),
+    /// Record every file's frontmatter and a hash of its body, or report
+    /// what's changed since, to audit a run without git
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Run a task from the project's frontmatter-fixer.toml: its options on
+    /// top of the file's defaults, then any given here
+    Run {
+        task: String,
+        /// More options and files, overriding the task's
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Apply the numbered migration files in ./migrations (or --migrations
+    /// DIR) that haven't been applied yet, like a database migration tool:
+    /// a run with --migrations, taking the project's defaults and any other
+    /// options and files given here
+    Migrate {
+        /// Options and files for the run
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Restore the files a run modified or moved, from its --journal
+    Undo {
+        /// The journal written by the run to undo
+        journal: PathBuf,
+        /// Restore files even if they've changed since the run
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Show each commit that changed a frontmatter key in the files given,
+    /// following them through renames: its date, commit, author, and the
+    /// old and new values
+    History {
+        /// The key to trace, a dotted path like author.name
+        key: String,
+        /// Files, or directories to search recursively
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Show the commit, author and date that last changed each of a file's
+    /// frontmatter keys, going by their values rather than lines so that
+    /// reordering or reformatting keys doesn't count
+    Blame {
+        /// The file to blame
+        path: String,
+    },
+    /// Generate an RSS, Atom or JSON Feed from the frontmatter of the files
+    /// given, newest first by date
+    Feed {
+        /// Files, or directories to search recursively
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// rss, atom or json
+        #[arg(long = "format", default_value = "rss")]
+        format: FeedFormat,
+        /// The feed's title
+        #[arg(long = "title")]
+        title: String,
+        /// The site's URL
+        #[arg(long = "link")]
+        link: String,
+        /// What the feed is about
+        #[arg(long = "description", default_value = "")]
+        description: String,
+        /// Who writes the feed (Atom feeds fall back on its title)
+        #[arg(long = "author")]
+        author: Option<String>,
+        /// Each item's URL, with {KEY} replaced by the frontmatter's value for
+        /// KEY, {stem} by the file name without extension, and {year},
+        /// {month} and {day} by the date, e.g. https://example.com/{slug}/
+        #[arg(long = "permalink")]
+        permalink: String,
+        /// Frontmatter key holding each item's title (defaults to the file
+        /// name)
+        #[arg(long = "title-key", default_value = "title")]
+        title_key: String,
+        /// Frontmatter key holding each item's date; files without one are
+        /// left out
+        #[arg(long = "date-key", default_value = "date")]
+        date_key: String,
+        /// Frontmatter key holding each item's description
+        #[arg(long = "description-key", default_value = "description")]
+        description_key: String,
+        /// Only include this many of the newest items
+        #[arg(long = "limit")]
+        limit: Option<usize>,
+        /// Write the feed to this file instead of stdout
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Generate an iCalendar (.ics) file of events from the frontmatter of
+    /// the files given, in order of when they start
+    Calendar {
+        /// Files, or directories to search recursively
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// The calendar's name, as calendar apps show it
+        #[arg(long = "name")]
+        name: Option<String>,
+        /// Each event's URL, with {KEY} replaced by the frontmatter's value
+        /// for KEY, {stem} by the file name without extension, and {year},
+        /// {month} and {day} by the start date
+        #[arg(long = "url")]
+        url: Option<String>,
+        /// Frontmatter key holding each event's title (defaults to the file
+        /// name)
+        #[arg(long = "title-key", default_value = "title")]
+        title_key: String,
+        /// Frontmatter key holding when each event starts, as a date or a
+        /// date and time; files without one are left out
+        #[arg(long = "start-key", default_value = "start")]
+        start_key: String,
+        /// Frontmatter key holding when each event ends (for a date, the
+        /// last day of the event)
+        #[arg(long = "end-key", default_value = "end")]
+        end_key: String,
+        /// Frontmatter key holding where each event is
+        #[arg(long = "location-key", default_value = "location")]
+        location_key: String,
+        /// Frontmatter key holding each event's description
+        #[arg(long = "description-key", default_value = "description")]
+        description_key: String,
+        /// Write the calendar to this file instead of stdout
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Run a SELECT over the frontmatter of the files given, as a table
+    /// files with a path column and a column for each key, e.g. SELECT
+    /// path, title FROM files WHERE date > '2023-01-01' ORDER BY date
+    ///
+    /// This is a limited query language that borrows SQL's syntax, not SQL:
+    /// SELECT columns or *, each optionally renamed with AS; FROM files;
+    /// WHERE conditions combined with AND, OR, NOT and parentheses; ORDER BY
+    /// columns with ASC or DESC; and LIMIT. A condition compares a column to
+    /// a literal with =, !=, <>, <, <=, > or >=, as --where does, so a list
+    /// matches if any of its elements does; tests for a value with IS NULL
+    /// or IS NOT NULL; or matches text with LIKE or NOT LIKE, where % and _
+    /// are wildcards and case is ignored. There are no joins, grouping,
+    /// functions or subqueries.
+    Query {
+        /// The SELECT to run
+        select: Select,
+        /// Files, or directories to search recursively
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// table, tsv, csv or json
+        #[arg(long = "format", default_value = "table")]
+        format: QueryFormat,
+    },
+    /// Print the values of frontmatter keys, dotted paths like author.name,
+    /// for each of the files given, e.g. get title date -- posts
+    Get {
+        /// The keys to print
+        #[arg(required = true)]
+        keys: Vec<String>,
+        /// Files, or directories to search recursively, after --
+        #[arg(last = true, required = true)]
+        paths: Vec<String>,
+        /// tsv, csv, json or table
+        #[arg(long = "format", default_value = "tsv")]
+        format: QueryFormat,
+    },
+    /// Create a Markdown file for each record in a CSV file, with a header
+    /// row naming the keys, or a JSON array of objects, with the record as
+    /// its frontmatter
+    Import {
+        /// The CSV or JSON file
+        data: PathBuf,
+        /// Each file's path, with {KEY} replaced by the record's value for
+        /// KEY, slugified, e.g. posts/{date}-{title}.md
+        #[arg(long = "path")]
+        path: String,
+        /// A Tera template for each file's body, as --render takes, with
+        /// the record as meta
+        #[arg(long = "body")]
+        body: Option<PathBuf>,
+        /// csv or json (defaults to the data file's extension)
+        #[arg(long = "format")]
+        format: Option<DataFormat>,
+        /// Overwrite files that already exist
+        #[arg(long = "force")]
+        force: bool,
+        /// List the files that would be created without creating them
+        #[arg(short = 'n', long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Work out the migration that makes the same edits as were made by
+    /// hand to a few example files, renaming, moving, splitting, mapping,
+    /// defaulting and dropping keys, and print it for review, with what it
+    /// can't tell left for a script
+    InferFix {
+        /// Example files in pairs, each as it was before editing then
+        /// after, or with --git, files edited since the last commit
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Compare each file with its last committed version
+        #[arg(long = "git")]
+        git: bool,
+        /// Write the migration to this file instead of stdout, e.g. into
+        /// migrations/ for `frontmatter-fixer migrate`
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Export a search index document for each of the files given, with
+    /// fields from their frontmatter and their body as plain text
+    Index {
+        /// Files, or directories to search recursively
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// lunr or meilisearch (JSON arrays), or elasticsearch (bulk NDJSON)
+        #[arg(long = "format", default_value = "lunr")]
+        format: IndexFormat,
+        /// Copy frontmatter key KEY to document field NAME, or to a field
+        /// of the same name without NAME= (may be repeated; default title)
+        #[arg(long = "field", id = "NAME=KEY")]
+        fields: Vec<FieldMapping>,
+        /// Document field holding the body as plain text
+        #[arg(long = "content-field", default_value = "body")]
+        content_field: String,
+        /// Elasticsearch index to add documents to
+        #[arg(long = "index-name", default_value = "documents")]
+        index_name: String,
+        /// Write the index to this file instead of stdout
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Explain what fixing one file does, step by step, to find out why it
+    /// fails: reading it, finding and parsing its frontmatter, then a dry
+    /// run of its scripts and checks, taking the project's defaults and any
+    /// other options given here
+    Why {
+        /// List each line of Lua scripts run on the file
+        #[arg(long = "trace")]
+        trace: bool,
+        /// Options for the run, and the file
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ScriptCommand {
+    /// Fetch a shared script (gh:ORG/REPO/PATH[@REF], a URL or a local path)
+    /// into the script directory, pinning its hash, and add it to the
+    /// scripts in the project's frontmatter-fixer.toml if it has one
+    Add {
+        source: String,
+        /// Name to save the script as (defaults to its file name)
+        #[arg(long = "name")]
+        name: Option<String>,
+        /// Fail unless the fetched script has this SHA-256 hash
+        #[arg(long = "sha256")]
+        sha256: Option<String>,
+        /// Directory holding vendored scripts and their lock file
+        #[arg(long = "dir", default_value = "scripts")]
+        dir: PathBuf,
+    },
+    /// Check vendored scripts still match their pinned hashes
+    Verify {
+        /// Directory holding vendored scripts and their lock file
+        #[arg(long = "dir", default_value = "scripts")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum SnapshotCommand {
+    /// Write a snapshot of the files given to SNAPSHOT
+    Create {
+        snapshot: PathBuf,
+        /// Files, or directories to search recursively
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Show each frontmatter key that's changed since SNAPSHOT was taken,
+    /// and which files' bodies have changed or which were added or removed
+    Diff {
+        snapshot: PathBuf,
+        /// Files, or directories to search recursively (defaults to those
+        /// the snapshot was taken of)
+        paths: Vec<String>,
+    },
+}
+
+impl Command {
+    pub fn run(&self) -> eyre::Result<()> {
+        match self {
+            Self::Script(ScriptCommand::Add {
+                source,
+                name,
+                sha256,
+                dir,
+            }) => {
+                let project = ProjectConfig::find(&env::current_dir()?)?;
+                let path = library::add(
+                    dir,
+                    source,
+                    name.as_deref(),
+                    sha256.as_deref(),
+                    project.as_ref(),
+                )?;
+                eprintln!("added {} from {}", path.display(), source);
+                if let Some(project) = &project {
+                    eprintln!("running it by default in {}", project.path.display());
+                }
+                Ok(())
+            }
+            // with a config file, Config::load has already turned the task
+            // into options
+            Self::Run { task, .. } => Err(eyre!(
+                "can't run task {}: no {} here or in any parent directory",
+                task,
+                CONFIG_FILE
+            )),
+            Self::Migrate { args } => {
+                let args = migrate_args(args.iter().map(OsString::from).collect());
+                batch::run(Config::parse_resolved(
+                    iter::once(OsString::from(PROGRAM)).chain(args),
+                ))
+            }
+            Self::Undo { journal, force } => {
+                let restored = Journal::load(journal)?.undo(*force)?;
+                eprintln!("restored {} files", restored);
+                Ok(())
+            }
+            Self::History { key, paths } => {
+                let changes = history::changes(key, paths)?;
+                let mut stdout = io::stdout().lock();
+                for change in &changes {
+                    writeln!(stdout, "{}", change)?;
+                }
+                eprintln!("found {} changes to {}", changes.len(), key);
+                Ok(())
+            }
+            Self::Blame { path } => {
+                let mut stdout = io::stdout().lock();
+                for attribution in history::blame(path)? {
+                    writeln!(stdout, "{}", attribution)?;
+                }
+                Ok(())
+            }
+            Self::Feed {
+                paths,
+                format,
+                title,
+                link,
+                description,
+                author,
+                permalink,
+                title_key,
+                date_key,
+                description_key,
+                limit,
+                output,
+            } => {
+                let fields = Fields {
+                    title_key: title_key.clone(),
+                    date_key: date_key.clone(),
+                    description_key: description_key.clone(),
+                    permalink: permalink.clone(),
+                };
+                let channel = Channel {
+                    title: title.clone(),
+                    link: link.clone(),
+                    description: description.clone(),
+                    author: author.clone(),
+                };
+                let items = feed::collect_items(paths, &fields, *limit)?;
+                let rendered = feed::render(*format, &channel, &items)?;
+                match output {
+                    Some(output) => write(output, rendered)
+                        .context(format!("couldn't write feed to {}", output.display()))?,
+                    None => print!("{}", rendered),
+                }
+                eprintln!("generated feed of {} items", items.len());
+                Ok(())
+            }
+            Self::Calendar {
+                paths,
+                name,
+                url,
+                title_key,
+                start_key,
+                end_key,
+                location_key,
+                description_key,
+                output,
+            } => {
+                let fields = calendar::Fields {
+                    title_key: title_key.clone(),
+                    start_key: start_key.clone(),
+                    end_key: end_key.clone(),
+                    location_key: location_key.clone(),
+                    description_key: description_key.clone(),
+                    url: url.clone(),
+                };
+                let events = calendar::collect_events(paths, &fields)?;
+                let rendered = calendar::render(name.as_deref(), &events);
+                match output {
+                    Some(output) => write(output, rendered)
+                        .context(format!("couldn't write calendar to {}", output.display()))?,
+                    None => print!("{}", rendered),
+                }
+                eprintln!("generated calendar of {} events", events.len());
+                Ok(())
+            }
+            Self::Index {
+                paths,
+                format,
+                fields,
+                content_field,
+                index_name,
+                output,
+            } => {
+                let default_fields = [FieldMapping {
+                    name: "title".into(),
+                    key: "title".into(),
+                }];
+                let fields = if fields.is_empty() {
+                    &default_fields[..]
+                } else {
+                    &fields[..]
+                };
+                let documents = search::documents(paths, fields, content_field)?;
+                let count = documents.len();
+                let rendered = search::render(*format, index_name, documents)?;
+                match output {
+                    Some(output) => write(output, rendered)
+                        .context(format!("couldn't write index to {}", output.display()))?,
+                    None => print!("{}", rendered),
+                }
+                eprintln!("exported {} documents", count);
+                Ok(())
+            }
+            Self::Why { trace, args } => {
+                let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+                let args = match ProjectConfig::find(&env::current_dir()?)? {
+                    Some(project) => project.args(None, &args)?,
+                    None => args,
+                };
+                let cfg = Config::parse_resolved(iter::once(OsString::from(PROGRAM)).chain(args));
+                let account = why::explain(&cfg, *trace)?;
+                print!("{}", account);
+                if account.failed {
+                    return Err(eyre!("fixing {} fails", cfg.paths.join(" ")));
+                }
+                Ok(())
+            }
+            Self::Import {
+                data,
+                path,
+                body,
+                format,
+                force,
+                dry_run,
+            } => {
+                let format = match format {
+                    Some(format) => *format,
+                    None => DataFormat::for_path(data)?,
+                };
+                let records = import::read_records(data, format)?;
+                let layout = Layout {
+                    path: path.clone(),
+                    body: body.as_deref().map(Template::load).transpose()?,
+                };
+                let created = import::import(&records, &layout, *force, *dry_run)?;
+                let mut stdout = io::stdout().lock();
+                for path in &created {
+                    writeln!(stdout, "{}", path.display())?;
+                }
+                let verb = if *dry_run { "would create" } else { "created" };
+                eprintln!("{} {} files from {}", verb, created.len(), data.display());
+                Ok(())
+            }
+            Self::InferFix { paths, git, output } => {
+                let inference = infer::infer(&infer::read_examples(paths, *git)?);
+                let migration = inference.migration();
+                match output {
+                    Some(output) => write(output, migration)
+                        .context(format!("couldn't write migration to {}", output.display()))?,
+                    None => print!("{}", migration),
+                }
+                eprintln!(
+                    "inferred {} edits, {} left for a script",
+                    inference.edits.len(),
+                    inference.unsure.len()
+                );
+                Ok(())
+            }
+            Self::Query {
+                select,
+                paths,
+                format,
+            } => {
+                let rows = select.run(paths)?;
+                rows.write(&mut io::stdout().lock(), *format)?;
+                eprintln!("selected {} files", rows.rows.len());
+                Ok(())
+            }
+            Self::Get {
+                keys,
+                paths,
+                format,
+            } => Select::keys(keys)
+                .run(paths)?
+                .write(&mut io::stdout().lock(), *format),
+            Self::Snapshot(SnapshotCommand::Create { snapshot, paths }) => {
+                let taken = Snapshot::create(paths)?;
+                taken.save(snapshot)?;
+                eprintln!("took snapshot of {} files", taken.len());
+                Ok(())
+            }
+            Self::Snapshot(SnapshotCommand::Diff { snapshot, paths }) => {
+                let changes = Snapshot::load(snapshot)?.diff(paths)?;
+                let mut stdout = io::stdout().lock();
+                for change in &changes {
+                    writeln!(stdout, "{}", change)?;
+                }
+                let files: BTreeSet<&str> = changes.iter().map(|change| change.path()).collect();
+                eprintln!("{} files changed since {}", files.len(), snapshot.display());
+                Ok(())
+            }
+            Self::Script(ScriptCommand::Verify { dir }) => {
+                let drifted = library::verify(dir)?;
+                for drift in &drifted {
+                    eprintln!("{}", drift);
+                }
+                if drifted.is_empty() {
+                    Ok(())
+                } else {
+                    Err(eyre!("{} vendored scripts have drifted", drifted.len()))
+                }
+            }
+        }
+    }
+}
+
+impl Config {
+    /// The Lua scripts to run, recipes first, then script files, then
+    /// inline scripts: `None` means run a REPL instead.
+    pub fn scripts(&self) -> eyre::Result<Option<Vec<Script>>> {
+        let has_scripts = !self.recipes.is_empty()
+            || !self.script_paths.is_empty()
+            || !self.inline_scripts.is_empty();
+        if self.repl {
+            return if has_scripts {
+                Err(eyre!("can't run scripts and a REPL"))
+            } else {
+                Ok(None)
+            };
+        }
+        if !has_scripts {
+            return if self.has_builtin_operations() {
+                Ok(Some(Vec::new()))
+            } else {
+                Err(eyre!(
+                    "must specify one of inline script, a script file, REPL, or a built-in \
+                     operation"
+                ))
+            };
+        }
+        if self.inline_scripts.iter().filter(|s| *s == "-").count() > 1 {
+            return Err(eyre!("can only read a script from stdin once"));
+        }
+        let mut scripts: Vec<Script> = self
+            .recipes
+            .iter()
+            .map(|recipe| Script {
+                name: format!("recipe {}", recipe.name),
+                source: recipe.source.to_owned(),
+            })
+            .collect();
+        for path in &self.script_paths {
+            let source =
+                read_to_string(path).context(format!("couldn't read script file {}", path))?;
+            scripts.push(Script {
+                name: path.clone(),
+                source,
+            });
+        }
+        for (index, inline_script) in self.inline_scripts.iter().enumerate() {
+            let source = if inline_script == "-" {
+                let mut source = String::new();
+                io::stdin()
+                    .read_to_string(&mut source)
+                    .context("couldn't read script from stdin")?;
+                source
+            } else {
+                inline_script.clone()
+            };
+            scripts.push(Script {
+                name: format!("inline script {}", index + 1),
+                source,
+            });
+        }
+        // a lone script needs no name to tell it apart
+        if let [script] = &mut scripts[..] {
+            script.name = String::new();
+        }
+        Ok(Some(scripts))
+    }
+
+    /// How to run the scripts: sandboxed unless `--no-sandbox`, requiring
+    /// modules from each script file's directory, then `--lua-path`,
+    /// repairing frontmatter if `--repair`, seeing other `--locales`, and
+    /// computing URLs with `--permalink`.
+    pub fn script_options(&self) -> ScriptOptions {
+        let mut lua_path: Vec<PathBuf> = Vec::new();
+        for path in &self.script_paths {
+            let dir = match Path::new(path).parent() {
+                Some(dir) if dir != Path::new("") => dir.to_owned(),
+                _ => PathBuf::from("."),
+            };
+            if !lua_path.contains(&dir) {
+                lua_path.push(dir);
+            }
+        }
+        lua_path.extend(self.lua_paths.iter().cloned());
+        ScriptOptions {
+            sandbox: !self.no_sandbox,
+            lua_path,
+            repair: self.repair,
+            locales: self.locales.clone(),
+            permalink: self.permalink(),
+            limits: self.limits(),
+            // only `why --trace` traces, through its pipeline
+            trace: false,
+            print_to_stderr: !self.dry_run
+                && self.paths.iter().any(|path| path == batch::STDIN_PATH),
+        }
+    }
+
+    /// What scripts may use on each file: the sandbox's limits unless
+    /// --no-sandbox, with any given here instead.
+    fn limits(&self) -> Limits {
+        let defaults = if self.no_sandbox {
+            Limits::NONE
+        } else {
+            Limits::SANDBOX
+        };
+        Limits {
+            timeout: self.script_timeout.or(defaults.timeout),
+            memory: self
+                .script_memory
+                .map(|mb| mb.saturating_mul(1024 * 1024))
+                .or(defaults.memory),
+            instructions: self.script_instructions.or(defaults.instructions),
+        }
+    }
+
+    /// How frontmatter is embedded in the file at `path`.
+    pub fn envelope(&self, path: &str) -> envelope::Kind {
+        match &self.envelope {
+            Some(envelope) => envelope.clone(),
+            None => envelope::Kind::for_path(path, &self.envelope_for),
+        }
+    }
+
+    /// How to compute files' URLs, if `--permalink` says.
+    pub fn permalink(&self) -> Option<Permalink> {
+        let pattern = self.permalink.clone()?;
+        Some(Permalink::new(pattern, self.content_root.clone()))
+    }
+
+    /// Parse the command line on top of the defaults in the project's
+    /// frontmatter-fixer.toml, if it has one, expanding `run TASK` into the
+    /// task's options and `migrate` into a run with `--migrations`. Other
+    /// subcommands don't take the defaults.
+    pub fn load() -> eyre::Result<Self> {
+        Self::load_in(&env::current_dir()?, env::args_os().collect())
+    }
+
+    /// Parse `cli`, a command line starting with the program name, as if
+    /// run in `dir`.
+    pub(crate) fn load_in(dir: &Path, mut cli: Vec<OsString>) -> eyre::Result<Self> {
+        let program = if cli.is_empty() {
+            OsString::from(PROGRAM)
+        } else {
+            cli.remove(0)
+        };
+        let project = match ProjectConfig::find(dir)? {
+            Some(project) => project,
+            None => return Ok(Self::parse_resolved(iter::once(program).chain(cli))),
+        };
+        let args = match cli.first().and_then(|arg| arg.to_str()) {
+            Some("run") => match cli.get(1).and_then(|arg| arg.to_str()) {
+                Some(task) => project.args(Some(task), &cli[2..])?,
+                None => cli,
+            },
+            Some("migrate") => project.args(None, &migrate_args(cli[1..].to_vec()))?,
+            Some(name) if name == "help" || Self::command().find_subcommand(name).is_some() => cli,
+            _ => project.args(None, &cli)?,
+        };
+        Ok(Self::parse_resolved(iter::once(program).chain(args)))
+    }
+
+    /// Parse `args`, which need no more resolving, remembering them.
+    fn parse_resolved(args: impl IntoIterator<Item = OsString>) -> Self {
+        let args: Vec<OsString> = args.into_iter().collect();
+        Self {
+            resolved_args: args
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            ..Self::parse_from(&args)
+        }
+    }
+
+    /// Take the options that apply to the whole run, rather than to how each
+    /// file is fixed, from `run`.
+    pub fn inherit_run_options(&mut self, run: &Config) {
+        self.dry_run = run.dry_run;
+        self.check = run.check;
+        self.meta_only = run.meta_only;
+        self.interactive = run.interactive;
+        self.yes = run.yes;
+        self.confirm_over = run.confirm_over;
+        self.no_sandbox |= run.no_sandbox;
+        self.script_timeout = run.script_timeout.or(self.script_timeout);
+        self.script_memory = run.script_memory.or(self.script_memory);
+        self.script_instructions = run.script_instructions.or(self.script_instructions);
+        self.fail_fast = run.fail_fast;
+        self.conditions.extend(run.conditions.iter().cloned());
+        self.verbose = run.verbose;
+        self.quiet = run.quiet;
+        self.print_result = run.print_result;
+        self.diff = run.diff;
+        self.output_dir = run.output_dir.clone();
+        self.backup_suffix = run.backup_suffix.clone();
+        self.keep_mtime = run.keep_mtime;
+        self.journal_path = run.journal_path.clone();
+        self.version_stamp = run.version_stamp.clone();
+        self.manifest_path = run.manifest_path.clone();
+        self.record = None;
+        self.replay = None;
+        self.stats_file = run.stats_file.clone();
+        self.commit_message = run.commit_message.clone();
+        self.git_changed = run.git_changed;
+        self.commit_sign = run.commit_sign;
+        self.reports = run.reports.clone();
+        self.json_patch_sidecars = run.json_patch_sidecars;
+        self.jobs = run.jobs;
+        self.ordered_output = run.ordered_output;
+    }
+
+    /// Whether any transforms or checks that run without a Lua script were
+    /// requested.
+    fn has_builtin_operations(&self) -> bool {
+        !self.coerce.is_empty()
+            || !self.convert.is_empty()
+            || !self.edits.is_empty()
+            || self.sort_keys.is_some()
+            || self.normalize_output
+            || self.flatten_inheritance
+            || !self.migrations.is_empty()
+            || self.migrations_dir.is_some()
+            || self.render_template.is_some()
+            || self.redact.is_some()
+            || self.rewrite_references.is_some()
+            || self.patch_path.is_some()
+            || self.patch_map_path.is_some()
+            || !self.wasm_modules.is_empty()
+            || self.schema_path.is_some()
+            || self.fix_key_typos
+            || self.to_format.is_some()
+            || self.content_hash_key.is_some()
+            || self.translation_source.is_some()
+            || !self.locale_keys.is_empty()
+            || self.unique_permalinks
+            || self.find_duplicates
+            || self.dedupe_title.is_some()
+            || self.promote_title
+            || self.publish_scheduled
+            || self.archive_expired.is_some()
+            || self.convert_links.is_some()
+            || self.blank_line_after_frontmatter != BlankLine::Preserve
+            || self.line_endings != LineEndings::Preserve
+            || self.trim_trailing_ws
+            || self.ensure_final_newline
+            || self.reformat_frontmatter
+            || self.fidelity != Fidelity::default()
+    }
+}
+
+/// Options that loosen what scripts may do, so can only come from the
+/// command line, not from a config or options file checked in to a
+/// repository someone else controls.
+const COMMAND_LINE_ONLY: &[&str] = &[
+    "no-sandbox",
+    "script-memory",
+    "script-instructions",
+    "script-timeout",
+    "lua-path",
+];
+
+/// Fail if `args`, read from the file at `path`, give any option that only
+/// the command line can.
+pub fn reject_command_line_only(args: &[String], path: &Path) -> eyre::Result<()> {
+    for arg in args {
+        let name = match arg.strip_prefix("--") {
+            Some(option) => option.split('=').next().unwrap_or(option),
+            None => continue,
+        };
+        if COMMAND_LINE_ONLY.contains(&name) {
+            return Err(eyre!(
+                "--{} can only be given on the command line, not in {}",
+                name,
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a number of seconds, which may have a fraction, as a duration.
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s
+        .parse()
+        .map_err(|_| format!("expected a number of seconds, got {:?}", s))?;
+    Duration::try_from_secs_f64(seconds).map_err(|err| format!("{:?}: {}", s, err))
+}
+
+/// The arguments `migrate` runs with: `--migrations` for the default
+/// directory, then `args`, which can override it.
+fn migrate_args(args: Vec<OsString>) -> Vec<OsString> {
+    let default_dir = OsString::from(format!("--migrations={}", MIGRATIONS_DIR));
+    iter::once(default_dir).chain(args).collect()
+}
@@ -0,0 +1,129 @@
+use std::{
+    io::{self, BufRead, Write},
+    sync::Mutex,
+};
+
+use eyre::{eyre, Context};
+
+use crate::{diff, output};
+
+/// What was answered when asked whether to write a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    /// Write this file and every one after it without asking.
+    All,
+    /// Write neither this file nor any after it.
+    Quit,
+}
+
+/// Asks on the terminal before each changed file is written, for
+/// `--interactive`, showing what would change.
+#[derive(Debug, Default)]
+pub struct Confirmer {
+    /// The standing answer for every file after an `all` or `quit`.
+    standing: Mutex<Option<Answer>>,
+}
+
+impl Confirmer {
+    /// Whether to write the file at `path`, which would change from
+    /// `original` to `output`, moving it to `renamed_to` if given.
+    pub fn confirm(
+        &self,
+        path: &str,
+        original: &str,
+        output: &str,
+        renamed_to: Option<&str>,
+    ) -> eyre::Result<bool> {
+        let mut standing = self.standing.lock().expect("prompt panicked");
+        match *standing {
+            Some(Answer::All) => return Ok(true),
+            Some(Answer::Quit) => return Ok(false),
+            _ => {}
+        }
+
+        let mut stderr = io::stderr().lock();
+        if let Some(diff) = diff::unified(original, output, path) {
+            let diff = if output::supports_ansi(&io::stderr()) {
+                diff::colorize(&diff)
+            } else {
+                diff
+            };
+            stderr.write_all(diff.as_bytes())?;
+        }
+        if let Some(renamed_to) = renamed_to {
+            writeln!(stderr, "move {} -> {}", path, renamed_to)?;
+        }
+        let stdin = io::stdin();
+        let answer = loop {
+            write!(stderr, "write {}? [y]es / [n]o / [a]ll / [q]uit ", path)?;
+            stderr.flush()?;
+            let mut line = String::new();
+            let read = stdin
+                .lock()
+                .read_line(&mut line)
+                .context("couldn't read answer")?;
+            if read == 0 {
+                return Err(eyre!("no answer, as stdin was closed"));
+            }
+            match parse_answer(&line) {
+                Some(answer) => break answer,
+                None => writeln!(stderr, "please answer y, n, a or q")?,
+            }
+        };
+        if let Answer::All | Answer::Quit = answer {
+            *standing = Some(answer);
+        }
+        Ok(matches!(answer, Answer::Yes | Answer::All))
+    }
+}
+
+/// Ask a yes or no `question` on the terminal, taking anything but yes as
+/// no.
+pub fn ask(question: &str) -> eyre::Result<bool> {
+    let mut stderr = io::stderr().lock();
+    write!(stderr, "{} [y/N] ", question)?;
+    stderr.flush()?;
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("couldn't read answer")?;
+    Ok(parse_answer(&line) == Some(Answer::Yes))
+}
+
+fn parse_answer(line: &str) -> Option<Answer> {
+    match line.trim().to_lowercase().as_str() {
+        "y" | "yes" => Some(Answer::Yes),
+        "n" | "no" => Some(Answer::No),
+        "a" | "all" => Some(Answer::All),
+        "q" | "quit" => Some(Answer::Quit),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_answers() {
+        assert_eq!(Some(Answer::Yes), parse_answer("y\n"));
+        assert_eq!(Some(Answer::No), parse_answer(" No \n"));
+        assert_eq!(Some(Answer::All), parse_answer("a\n"));
+        assert_eq!(Some(Answer::Quit), parse_answer("quit\n"));
+        assert_eq!(None, parse_answer("\n"));
+        assert_eq!(None, parse_answer("maybe\n"));
+    }
+
+    #[test]
+    fn standing_answers_skip_the_prompt() -> eyre::Result<()> {
+        let confirmer = Confirmer::default();
+        *confirmer.standing.lock().unwrap() = Some(Answer::Quit);
+        assert!(!confirmer.confirm("post.md", "a\n", "b\n", None)?);
+        *confirmer.standing.lock().unwrap() = Some(Answer::All);
+        assert!(confirmer.confirm("post.md", "a\n", "b\n", None)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,493 @@
+use std::{path::Path, str::FromStr};
+
+use serde_yaml as yaml;
+
+use crate::frontmatter::{self, BlankLine, Format};
+
+/// Converters from other formats to Markdown with frontmatter, run on files
+/// as they're read, so the rest of the pipeline only ever sees Markdown.
+/// Converted files are moved to the same name with a `.md` extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Converter {
+    /// HTML pages, such as WordPress or Blogger exports: the `<title>` and
+    /// `<meta name=... content=...>` tags become frontmatter, with
+    /// `keywords` split into `tags`, and the body becomes Markdown.
+    Html,
+}
+
+impl FromStr for Converter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(Converter::Html),
+            _ => Err(format!("unknown converter {:?}, expected html", s)),
+        }
+    }
+}
+
+impl Converter {
+    /// Which of `converters` applies to the file at `path`, going by its
+    /// extension.
+    pub fn for_path(converters: &[Converter], path: &Path) -> Option<Converter> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        converters
+            .iter()
+            .copied()
+            .find(|converter| converter.extensions().contains(&extension.as_str()))
+    }
+
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Converter::Html => &["html", "htm"],
+        }
+    }
+
+    /// Convert a file's contents to a Markdown document.
+    pub fn convert(self, source: &str) -> eyre::Result<String> {
+        let (metadata, content) = match self {
+            Converter::Html => html_to_markdown(source),
+        };
+        let metadata = (!metadata.is_empty()).then_some(yaml::Value::Mapping(metadata));
+        let output =
+            frontmatter::to_bytes(Format::Yaml, BlankLine::Always, metadata.as_ref(), &content)?;
+        Ok(String::from_utf8(output)?)
+    }
+}
+
+/// An element being converted, with the Markdown for what's inside it so far.
+struct Frame {
+    tag: String,
+    href: Option<String>,
+    text: String,
+    /// The number of the next item, for `<ol>`.
+    items: usize,
+}
+
+/// Elements whose text isn't part of the page's content.
+const HIDDEN: [&str; 6] = ["head", "script", "style", "template", "noscript", "svg"];
+
+/// Elements that separate paragraphs.
+const BLOCKS: [&str; 10] = [
+    "p", "div", "section", "article", "main", "header", "footer", "aside", "figure", "table",
+];
+
+/// Elements with no end tag.
+const VOID: [&str; 8] = ["br", "hr", "img", "meta", "link", "input", "source", "wbr"];
+
+fn html_to_markdown(html: &str) -> (yaml::Mapping, String) {
+    let mut metadata = yaml::Mapping::new();
+    let mut stack = vec![Frame::new("", None)];
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(open) = rest.find('<') else {
+            push_text(&mut stack, rest);
+            break;
+        };
+        push_text(&mut stack, &rest[..open]);
+        rest = &rest[open..];
+        let skip_to = |rest: &str, end: &str| rest.find(end).map_or(rest.len(), |i| i + end.len());
+        if rest.starts_with("<!--") {
+            rest = &rest[skip_to(rest, "-->")..];
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            // doctype, CDATA or processing instruction
+            rest = &rest[skip_to(rest, ">")..];
+            continue;
+        }
+        let end = skip_to(rest, ">");
+        let Some(tag) = Tag::parse(rest[1..end].trim_end_matches('>')) else {
+            push_text(&mut stack, "<");
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[end..];
+        if tag.closing {
+            if let Some(depth) = stack.iter().rposition(|frame| frame.tag == tag.name) {
+                while stack.len() > depth.max(1) {
+                    close(&mut stack, &mut metadata);
+                }
+            }
+            continue;
+        }
+        match tag.name.as_str() {
+            "meta" => {
+                if let (Some(name), Some(content)) = (tag.attr("name"), tag.attr("content")) {
+                    add_meta(&mut metadata, name, content);
+                }
+            }
+            "br" => stack.last_mut().unwrap().text.push_str("  \n"),
+            "hr" => push_block(&mut stack, "* * *"),
+            "img" => {
+                let alt = tag.attr("alt").unwrap_or_default();
+                let src = tag.attr("src").unwrap_or_default();
+                push_inline(&mut stack, &format!("![{}]({})", alt, src));
+            }
+            name if VOID.contains(&name) || tag.self_closing => {}
+            name => {
+                // paragraphs and list items end where the next begins
+                if matches!(name, "p" | "li") && stack.last().unwrap().tag == name {
+                    close(&mut stack, &mut metadata);
+                }
+                stack.push(Frame::new(name, tag.attr("href")));
+                if matches!(name, "script" | "style") {
+                    // raw text, which may hold anything but its end tag
+                    let end = format!("</{}", name);
+                    let raw_end = rest.to_ascii_lowercase().find(&end).unwrap_or(rest.len());
+                    rest = &rest[raw_end..];
+                }
+            }
+        }
+    }
+    while stack.len() > 1 {
+        close(&mut stack, &mut metadata);
+    }
+    let content = stack.pop().unwrap().text.trim().to_owned();
+    (
+        metadata,
+        if content.is_empty() {
+            content
+        } else {
+            content + "\n"
+        },
+    )
+}
+
+impl Frame {
+    fn new(tag: &str, href: Option<&str>) -> Self {
+        Self {
+            tag: tag.to_owned(),
+            href: href.map(str::to_owned),
+            text: String::new(),
+            items: 1,
+        }
+    }
+}
+
+/// Finish the innermost element, adding its Markdown to its parent's.
+fn close(stack: &mut Vec<Frame>, metadata: &mut yaml::Mapping) {
+    let frame = stack.pop().unwrap();
+    let text = frame.text.trim();
+    let in_pre = stack.iter().any(|frame| frame.tag == "pre");
+    match frame.tag.as_str() {
+        "title" => {
+            if !in_head_or_top(stack) {
+                return;
+            }
+            let title = collapse(text);
+            if !title.is_empty() {
+                metadata.insert("title".into(), title.into());
+            }
+        }
+        tag if HIDDEN.contains(&tag) => {}
+        tag if BLOCKS.contains(&tag) => push_block(stack, text),
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = frame.tag[1..].parse().unwrap_or(1);
+            push_block(stack, &format!("{} {}", "#".repeat(level), collapse(text)));
+        }
+        "blockquote" => {
+            let quoted: Vec<String> = text
+                .lines()
+                .map(|line| match line {
+                    "" => ">".to_owned(),
+                    line => format!("> {}", line),
+                })
+                .collect();
+            push_block(stack, &quoted.join("\n"));
+        }
+        "pre" => push_block(
+            stack,
+            &format!("```\n{}\n```", frame.text.trim_matches('\n')),
+        ),
+        "code" if in_pre => stack.last_mut().unwrap().text.push_str(&frame.text),
+        "code" if !text.is_empty() => push_inline(stack, &format!("`{}`", text)),
+        "strong" | "b" if !text.is_empty() => push_inline(stack, &format!("**{}**", text)),
+        "em" | "i" if !text.is_empty() => push_inline(stack, &format!("*{}*", text)),
+        "a" => match frame.href {
+            Some(href) if !text.is_empty() => push_inline(stack, &format!("[{}]({})", text, href)),
+            _ => push_inline(stack, text),
+        },
+        "ul" | "ol" => push_block(stack, text),
+        "li" => {
+            let list = stack.last_mut().unwrap();
+            let marker = if list.tag == "ol" {
+                list.items += 1;
+                format!("{}. ", list.items - 1)
+            } else {
+                "- ".to_owned()
+            };
+            let indent = " ".repeat(marker.len());
+            let mut item = String::new();
+            for (i, line) in text.lines().enumerate() {
+                let prefix = if i == 0 {
+                    marker.as_str()
+                } else {
+                    indent.as_str()
+                };
+                if !line.is_empty() {
+                    item += prefix;
+                    item += line;
+                }
+                item.push('\n');
+            }
+            let list = &mut list.text;
+            if !list.is_empty() && !list.ends_with('\n') {
+                list.push('\n');
+            }
+            list.push_str(&item);
+        }
+        "td" | "th" => push_inline(stack, &format!("{} ", text)),
+        "tr" => push_inline(stack, &format!("{}  \n", text)),
+        _ => push_inline(stack, &frame.text),
+    }
+}
+
+fn in_head_or_top(stack: &[Frame]) -> bool {
+    !stack
+        .iter()
+        .any(|frame| frame.tag == "body" || frame.tag == "svg")
+}
+
+/// Add text to the innermost element, collapsing whitespace outside `<pre>`.
+fn push_text(stack: &mut [Frame], text: &str) {
+    let text = decode_entities(text);
+    let in_pre = stack.iter().any(|frame| frame.tag == "pre");
+    let frame = stack.last_mut().unwrap();
+    if in_pre {
+        frame.text.push_str(&text);
+        return;
+    }
+    let at_line_start = frame.text.is_empty() || frame.text.ends_with(['\n', ' ']);
+    let mut collapsed = collapse(&text);
+    if text.starts_with(char::is_whitespace) && !at_line_start && !collapsed.is_empty() {
+        collapsed.insert(0, ' ');
+    }
+    if text.ends_with(char::is_whitespace) && !collapsed.is_empty() {
+        collapsed.push(' ');
+    }
+    if collapsed.is_empty() && !at_line_start && !text.is_empty() {
+        collapsed.push(' ');
+    }
+    frame.text.push_str(&collapsed);
+}
+
+fn push_inline(stack: &mut [Frame], markdown: &str) {
+    stack.last_mut().unwrap().text.push_str(markdown);
+}
+
+/// Add a paragraph to the innermost element, separated by a blank line
+/// from what's before it.
+fn push_block(stack: &mut [Frame], markdown: &str) {
+    if markdown.is_empty() {
+        return;
+    }
+    let text = &mut stack.last_mut().unwrap().text;
+    let trimmed = text.trim_end_matches([' ', '\n']).len();
+    text.truncate(trimmed);
+    if !text.is_empty() {
+        text.push_str("\n\n");
+    }
+    text.push_str(markdown);
+    text.push_str("\n\n");
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn add_meta(metadata: &mut yaml::Mapping, name: &str, content: &str) {
+    let name = name.trim().to_ascii_lowercase();
+    let content = decode_entities(content.trim());
+    if name.is_empty() || content.is_empty() {
+        return;
+    }
+    if name == "keywords" {
+        let tags: Vec<yaml::Value> = content
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(Into::into)
+            .collect();
+        metadata.insert("tags".into(), tags.into());
+    } else {
+        metadata.insert(name.into(), content.into());
+    }
+}
+
+/// A start or end tag, with its attributes.
+struct Tag {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    attrs: Vec<(String, String)>,
+}
+
+impl Tag {
+    /// Parse what's between `<` and `>`, or nothing if it isn't a tag, such
+    /// as a `<` in text.
+    fn parse(s: &str) -> Option<Self> {
+        let (closing, s) = match s.strip_prefix('/') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let self_closing = s.ends_with('/');
+        let s = s.trim_end_matches('/');
+        let name_end = s.find(|c: char| c.is_whitespace()).unwrap_or(s.len());
+        let name = s[..name_end].to_ascii_lowercase();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        Some(Self {
+            name,
+            closing,
+            self_closing,
+            attrs: parse_attrs(&s[name_end..]),
+        })
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(attr, _)| attr == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn parse_attrs(mut s: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    loop {
+        s = s.trim_start();
+        let name_end = s
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(s.len());
+        if name_end == 0 {
+            return attrs;
+        }
+        let name = s[..name_end].to_ascii_lowercase();
+        s = s[name_end..].trim_start();
+        let value = match s.strip_prefix('=') {
+            Some(rest) => {
+                let rest = rest.trim_start();
+                let (value, after) = match rest.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let rest = &rest[1..];
+                        let end = rest.find(quote).unwrap_or(rest.len());
+                        (&rest[..end], rest.get(end + 1..).unwrap_or(""))
+                    }
+                    _ => {
+                        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                        (&rest[..end], &rest[end..])
+                    }
+                };
+                s = after;
+                decode_entities(value)
+            }
+            None => String::new(),
+        };
+        attrs.push((name, value));
+    }
+}
+
+/// Replace character references: the common named ones and numeric ones.
+fn decode_entities(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let reference = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end]);
+        let c = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            _ => {
+                let number = reference.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (c, reference) {
+            (Some(c), Some(reference)) => {
+                decoded.push(c);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_html_exports() -> eyre::Result<()> {
+        let html = r#"<!DOCTYPE html>
+<html>
+<head>
+  <title>Hello &amp; welcome</title>
+  <meta charset="utf-8">
+  <meta name="author" content="Sam">
+  <meta name="keywords" content="rust, blogging">
+  <style>p { color: red; }</style>
+</head>
+<body>
+  <h1>Hello</h1>
+  <p>Some <strong>bold</strong> and <a href="/about">a link</a>.
+  <p>A list:</p>
+  <ul>
+    <li>one
+    <li>two, with <em>stress</em></li>
+  </ul>
+  <ol><li>first</li><li>second</li></ol>
+  <blockquote><p>Quoted<br>twice</p></blockquote>
+  <pre><code>let x = 1 &lt; 2;
+  indented</code></pre>
+  <img src="cat.png" alt="A cat">
+  <!-- <p>commented out</p> -->
+</body>
+</html>
+"#;
+        assert_eq!(
+            "---\ntitle: Hello & welcome\nauthor: Sam\ntags:\n- rust\n- blogging\n---\n\n\
+             # Hello\n\n\
+             Some **bold** and [a link](/about).\n\n\
+             A list:\n\n\
+             - one\n- two, with *stress*\n\n\
+             1. first\n2. second\n\n\
+             > Quoted  \n> twice\n\n\
+             ```\nlet x = 1 < 2;\n  indented\n```\n\n\
+             ![A cat](cat.png)\n",
+            Converter::Html.convert(html)?
+        );
+        let html_only = [Converter::Html];
+        assert_eq!(
+            Some(Converter::Html),
+            Converter::for_path(&html_only, Path::new("a.HTM"))
+        );
+        assert_eq!(None, Converter::for_path(&html_only, Path::new("a.md")));
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, read_to_string},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::Parser;
+use eyre::{eyre, Context};
+
+use crate::{
+    config::{self, Config},
+    git,
+    walk::{self, Filter},
+};
+
+/// The file in a corpus directory holding that corpus's own options.
+pub const CONFIG_FILE: &str = ".frontmatter-fixer";
+
+/// A `--corpus NAME=DIR` request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorpusSpec {
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+impl FromStr for CorpusSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, dir)) if !name.is_empty() && !dir.is_empty() => Ok(Self {
+                name: name.to_owned(),
+                dir: PathBuf::from(dir),
+            }),
+            _ => Err(format!("expected NAME=DIR, got {:?}", s)),
+        }
+    }
+}
+
+/// A set of files fixed the same way.
+#[derive(Debug)]
+pub struct Corpus {
+    /// `None` for the files given on the command line.
+    pub name: Option<String>,
+    /// The options from its `.frontmatter-fixer` file, if it has one, for
+    /// recording; otherwise it's fixed as the command line says.
+    pub options: Option<Vec<String>>,
+    /// How to fix this corpus's files, whose paths are in `cfg.paths`.
+    pub cfg: Config,
+}
+
+/// The corpora `cfg` asks for, each with the files it contains.
+///
+/// Without `--corpus`, that's the files given on the command line, fixed
+/// as the command line says. Each `--corpus` is a directory fixed
+/// recursively, with the fixing options in its `.frontmatter-fixer` file if
+/// it has one, and the command line's otherwise.
+pub fn load(cfg: &Config) -> eyre::Result<Vec<Corpus>> {
+    if cfg.corpora.is_empty() {
+        let mut cfg = cfg.clone();
+        expand_paths(&mut cfg)?;
+        return Ok(vec![Corpus {
+            name: None,
+            options: None,
+            cfg,
+        }]);
+    }
+
+    let mut names = BTreeSet::new();
+    let mut corpora = Vec::with_capacity(cfg.corpora.len());
+    for spec in &cfg.corpora {
+        if !names.insert(spec.name.as_str()) {
+            return Err(eyre!("corpus {} given more than once", spec.name));
+        }
+        let options = read_options(&spec.dir)?;
+        let mut corpus_cfg = match &options {
+            Some(options) => {
+                let args = std::iter::once("frontmatter-fixer".to_owned()).chain(options.clone());
+                let mut corpus_cfg = Config::try_parse_from(args)
+                    .context(format!("couldn't parse options for corpus {}", spec.name))?;
+                corpus_cfg.inherit_run_options(cfg);
+                corpus_cfg
+            }
+            None => cfg.clone(),
+        };
+        corpus_cfg.corpora = Vec::new();
+        corpus_cfg.paths = vec![spec.dir.display().to_string()];
+        corpus_cfg.recursive = true;
+        corpus_cfg.corpus_root = Some(spec.dir.clone());
+        expand_paths(&mut corpus_cfg)?;
+        corpora.push(Corpus {
+            name: Some(spec.name.clone()),
+            options,
+            cfg: corpus_cfg,
+        });
+    }
+    Ok(corpora)
+}
+
+fn expand_paths(cfg: &mut Config) -> eyre::Result<()> {
+    if cfg.git_changed {
+        let changed = git::changed_files()?;
+        select_changed(cfg, &changed)?;
+    } else if cfg.recursive {
+        let filter = Filter::new(cfg.include.clone(), cfg.exclude.clone());
+        cfg.paths = walk::expand(&cfg.paths, &filter)?;
+    }
+    Ok(())
+}
+
+/// For `--git-changed`, narrow the files to those in `changed`, looking
+/// through any directories given, or the current directory if no paths
+/// were. Both are compared canonicalized, since git reports paths relative
+/// to the current directory but the directories can be given any way, like
+/// absolutely or through `..`.
+fn select_changed(cfg: &mut Config, changed: &[PathBuf]) -> eyre::Result<()> {
+    // both only ever hold files that exist
+    let canonical = |path: &Path| fs::canonicalize(path).ok();
+    let changed: BTreeSet<PathBuf> = changed.iter().filter_map(|path| canonical(path)).collect();
+    if cfg.paths.is_empty() {
+        cfg.paths = vec![".".to_owned()];
+    }
+    let filter = Filter::new(cfg.include.clone(), cfg.exclude.clone());
+    cfg.paths = walk::expand(&cfg.paths, &filter)?
+        .into_iter()
+        .filter(|path| canonical(Path::new(path)).is_some_and(|path| changed.contains(&path)))
+        .collect();
+    Ok(())
+}
+
+/// Read a corpus's options file: one option per line, with any value after
+/// the first space, e.g. `--script blog.lua`. Blank lines and lines starting
+/// with `#` are ignored.
+fn read_options(dir: &Path) -> eyre::Result<Option<Vec<String>>> {
+    let path = dir.join(CONFIG_FILE);
+    let text = match read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(format!("couldn't read {}", path.display())),
+    };
+    let options = parse_options(&text);
+    config::reject_command_line_only(&options, &path)?;
+    Ok(Some(options))
+}
+
+fn parse_options(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(' ') {
+            Some((option, value)) if option.starts_with('-') => {
+                args.push(option.to_owned());
+                args.push(value.trim_start().to_owned());
+            }
+            _ => args.push(line.to_owned()),
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        fs::{create_dir_all, write},
+    };
+
+    use super::*;
+
+    #[test]
+    fn parses_corpus_specs() {
+        assert_eq!(
+            Ok(CorpusSpec {
+                name: "blog".into(),
+                dir: "content/blog".into()
+            }),
+            "blog=content/blog".parse()
+        );
+        assert!("content/blog".parse::<CorpusSpec>().is_err());
+    }
+
+    #[test]
+    fn parses_options_one_per_line() {
+        let text = "# blog posts\n--script blog.lua\n\n-e meta.x = 1\n--dry-run\n";
+        assert_eq!(
+            vec!["--script", "blog.lua", "-e", "meta.x = 1", "--dry-run"],
+            parse_options(text)
+        );
+    }
+
+    #[test]
+    fn selects_changed_files() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for file in ["posts/a.md", "posts/b.md", "c.md"] {
+            let path = dir.path().join(file);
+            create_dir_all(path.parent().unwrap())?;
+            write(path, "")?;
+        }
+        let root = dir.path().display().to_string();
+        let mut cfg = Config::try_parse_from([
+            "frontmatter-fixer".to_owned(),
+            "--git-changed".to_owned(),
+            format!("{}/posts", root),
+            format!("{}/c.md", root),
+        ])?;
+        let changed = [dir.path().join("posts/./b.md"), dir.path().join("c.md")];
+        select_changed(&mut cfg, &changed)?;
+        assert_eq!(
+            vec![format!("{}/posts/b.md", root), format!("{}/c.md", root)],
+            cfg.paths
+        );
+
+        // as git reports them, relative to the current directory
+        let up: PathBuf = env::current_dir()?
+            .components()
+            .skip(1)
+            .map(|_| "..")
+            .collect();
+        let relative = |file: &str| up.join(dir.path().strip_prefix("/").unwrap()).join(file);
+        let mut cfg = Config::try_parse_from([
+            "frontmatter-fixer".to_owned(),
+            "--git-changed".to_owned(),
+            format!("{}/posts/../posts", root),
+        ])?;
+        select_changed(&mut cfg, &[relative("posts/a.md"), relative("c.md")])?;
+        assert_eq!(vec![format!("{}/posts/../posts/a.md", root)], cfg.paths);
+        Ok(())
+    }
+
+    #[test]
+    fn loads_corpora_with_their_own_options() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for file in ["blog/a.md", "docs/b.md"] {
+            let path = dir.path().join(file);
+            create_dir_all(path.parent().unwrap())?;
+            write(path, "")?;
+        }
+        write(dir.path().join("blog").join(CONFIG_FILE), "--to toml\n")?;
+        let root = dir.path().display().to_string();
+        let cfg = Config::try_parse_from([
+            "frontmatter-fixer".to_owned(),
+            "--dry-run".to_owned(),
+            format!("--corpus=blog={}/blog", root),
+            format!("--corpus=docs={}/docs", root),
+        ])?;
+
+        let corpora = load(&cfg)?;
+        assert_eq!(Some("blog"), corpora[0].name.as_deref());
+        assert_eq!(vec![format!("{}/blog/a.md", root)], corpora[0].cfg.paths);
+        assert!(corpora[0].cfg.to_format.is_some());
+        assert!(corpora[0].cfg.dry_run);
+        assert_eq!(vec![format!("{}/docs/b.md", root)], corpora[1].cfg.paths);
+        assert!(corpora[1].cfg.to_format.is_none());
+
+        write(dir.path().join("docs").join(CONFIG_FILE), "--no-sandbox\n")?;
+        let err = load(&cfg).expect_err("options files can't turn off the sandbox");
+        assert!(format!("{:#}", err).contains("--no-sandbox can only be given"));
+        Ok(())
+    }
+}
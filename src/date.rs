@@ -0,0 +1,358 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+const MONTH_NAMES: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// A calendar date, as found in frontmatter `date:` fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// Today's date in UTC.
+    pub fn today() -> Self {
+        Self::from_days_since_epoch(unix_now().div_euclid(86400))
+    }
+
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        if (1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day) {
+            Some(Self { year, month, day })
+        } else {
+            None
+        }
+    }
+
+    /// Parse the date formats commonly found in hand-written frontmatter.
+    ///
+    /// Accepts `2023-01-31`, `2023/01/31` and `2023.01.31` (optionally
+    /// followed by a time, which is discarded), `January 31, 2023`,
+    /// `Jan 31 2023` and `31 January 2023`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        Self::parse_numeric(s).or_else(|| Self::parse_named_month(s))
+    }
+
+    fn parse_numeric(s: &str) -> Option<Self> {
+        let date_part = s.split(['T', ' ']).next()?;
+        let sep = date_part.chars().find(|c| matches!(*c, '-' | '/' | '.'))?;
+        let mut parts = date_part.split(sep);
+        let year = parts.next()?;
+        let month = parts.next()?;
+        let day = parts.next()?;
+        if parts.next().is_some() || year.len() != 4 {
+            return None;
+        }
+        Self::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+    }
+
+    fn parse_named_month(s: &str) -> Option<Self> {
+        let words: Vec<&str> = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|w| !w.is_empty())
+            .collect();
+        match words.as_slice() {
+            [month, day, year] if month_number(month).is_some() => {
+                Self::new(year.parse().ok()?, month_number(month)?, day.parse().ok()?)
+            }
+            [day, month, year] => {
+                Self::new(year.parse().ok()?, month_number(month)?, day.parse().ok()?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Days since 1970-01-01 (negative for earlier dates).
+    pub fn days_since_epoch(&self) -> i64 {
+        // Howard Hinnant's days_from_civil
+        let y = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = (if y >= 0 { y } else { y - 399 }) / 400;
+        let yoe = y - era * 400;
+        let m = i64::from(self.month);
+        let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    /// Inverse of [`Date::days_since_epoch`].
+    pub fn from_days_since_epoch(days: i64) -> Self {
+        let z = days + 719468;
+        let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+        let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+        Self { year, month, day }
+    }
+
+    /// Format with strftime-style directives: `%Y`, `%y`, `%m`, `%d`, `%e`,
+    /// `%B`, `%b`, `%A`, `%a`, `%j` and `%%`.
+    pub fn format(&self, format: &str) -> Result<String, String> {
+        let month = MONTH_NAMES[self.month as usize - 1];
+        // 1970-01-01 was a Thursday
+        let weekday = WEEKDAY_NAMES[(self.days_since_epoch() + 3).rem_euclid(7) as usize];
+        let new_year = Self {
+            year: self.year,
+            month: 1,
+            day: 1,
+        };
+        let day_of_year = self.days_since_epoch() - new_year.days_since_epoch() + 1;
+        let mut formatted = String::with_capacity(format.len());
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                formatted.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => formatted.push_str(&format!("{:04}", self.year)),
+                Some('y') => formatted.push_str(&format!("{:02}", self.year.rem_euclid(100))),
+                Some('m') => formatted.push_str(&format!("{:02}", self.month)),
+                Some('d') => formatted.push_str(&format!("{:02}", self.day)),
+                Some('e') => formatted.push_str(&format!("{:2}", self.day)),
+                Some('B') => formatted.push_str(&capitalize(month)),
+                Some('b') => formatted.push_str(&capitalize(&month[..3])),
+                Some('A') => formatted.push_str(&capitalize(weekday)),
+                Some('a') => formatted.push_str(&capitalize(&weekday[..3])),
+                Some('j') => formatted.push_str(&format!("{:03}", day_of_year)),
+                Some('%') => formatted.push('%'),
+                Some(other) => return Err(format!("unsupported date format %{}", other)),
+                None => return Err("date format ends with %".to_owned()),
+            }
+        }
+        Ok(formatted)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp.
+pub fn format_timestamp(secs: i64) -> String {
+    let date = Date::from_days_since_epoch(secs.div_euclid(86400));
+    let secs_of_day = secs.rem_euclid(86400);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        date,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parse a date or date and time into seconds since the Unix epoch.
+///
+/// Accepts the date formats of [`Date::parse`], optionally followed (after
+/// `T` or a space) by `HH:MM` or `HH:MM:SS` with any fraction of a second,
+/// and a `Z` or `+HH:MM` style offset. A time without an offset is taken
+/// as UTC, and a date without a time as midnight UTC.
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let split = s
+        .find('T')
+        .or_else(|| s.find(' ').filter(|_| Date::parse_numeric(s).is_some()));
+    let (date, time) = match split {
+        Some(i) => (&s[..i], Some(s[i + 1..].trim())),
+        None => (s, None),
+    };
+    let days = Date::parse(date)?.days_since_epoch();
+    let secs_of_day = match time {
+        Some(time) => parse_time(time)?,
+        None => 0,
+    };
+    Some(days * 86400 + secs_of_day)
+}
+
+/// Seconds into the day of a `HH:MM[:SS[.fff]][Z|±HH:MM|±HHMM]` time,
+/// adjusted to UTC (so possibly negative or past a day).
+fn parse_time(s: &str) -> Option<i64> {
+    let (clock, offset) = match s.find(['Z', 'z', '+', '-']) {
+        Some(i) => (s[..i].trim_end(), Some(&s[i..])),
+        None => (s, None),
+    };
+    let mut parts = clock.split(':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: i64 = match parts.next() {
+        Some(seconds) => seconds.split('.').next()?.parse().ok()?,
+        None => 0,
+    };
+    if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let offset = match offset {
+        None | Some("Z") | Some("z") => 0,
+        Some(offset) => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let digits = offset[1..].replace(':', "");
+            if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let hours: i64 = digits[..2].parse().ok()?;
+            let minutes: i64 = digits[2..].parse().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+    Some(hours * 3600 + minutes * 60 + seconds - offset)
+}
+
+/// Seconds since the Unix epoch by the system clock.
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+pub fn now_timestamp() -> String {
+    system_timestamp(SystemTime::now())
+}
+
+/// Format a time from the system clock or filesystem as an RFC 3339 UTC
+/// timestamp.
+pub fn system_timestamp(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    format_timestamp(secs)
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    let name = name.trim_end_matches('.').to_lowercase();
+    if name.len() < 3 {
+        return None;
+    }
+    MONTH_NAMES
+        .iter()
+        .position(|month| month.starts_with(&name))
+        .map(|i| i as u32 + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_iso_dates() {
+        assert_eq!(Date::new(2023, 1, 31), Date::parse("2023-01-31"));
+        assert_eq!(Date::new(2023, 1, 31), Date::parse("2023/1/31"));
+        assert_eq!(Date::new(2023, 1, 31), Date::parse("2023-01-31T10:00:00Z"));
+        assert_eq!(Date::new(2023, 1, 31), Date::parse("2023-01-31 10:00"));
+    }
+
+    #[test]
+    fn parses_named_months() {
+        assert_eq!(Date::new(2023, 1, 31), Date::parse("January 31, 2023"));
+        assert_eq!(Date::new(2023, 1, 31), Date::parse("Jan 31 2023"));
+        assert_eq!(Date::new(2023, 1, 31), Date::parse("31 Jan. 2023"));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert_eq!(None, Date::parse("2023-02-29"));
+        assert_eq!(None, Date::parse("23-01-31"));
+        assert_eq!(None, Date::parse("yesterday"));
+        assert_eq!(Date::new(2024, 2, 29), Date::parse("2024-02-29"));
+    }
+
+    #[test]
+    fn round_trips_epoch_days() {
+        let date = Date::new(2023, 1, 31).unwrap();
+        assert_eq!(0, Date::new(1970, 1, 1).unwrap().days_since_epoch());
+        assert_eq!(19388, date.days_since_epoch());
+        assert_eq!(date, Date::from_days_since_epoch(date.days_since_epoch()));
+    }
+
+    #[test]
+    fn formats_with_directives() {
+        let date = Date::new(2023, 3, 5).unwrap();
+        assert_eq!(Ok("2023-03-05".to_owned()), date.format("%Y-%m-%d"));
+        assert_eq!(
+            Ok("Sunday,  5 March 23".to_owned()),
+            date.format("%A, %e %B %y")
+        );
+        assert_eq!(
+            Ok("Sun Mar 064 100%".to_owned()),
+            date.format("%a %b %j 100%%")
+        );
+        assert!(date.format("%Q").is_err());
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!("1970-01-01T00:00:00Z", format_timestamp(0));
+        assert_eq!("2023-01-31T10:11:12Z", format_timestamp(1675159872));
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(Some(1675159872), parse_timestamp("2023-01-31T10:11:12Z"));
+        assert_eq!(
+            Some(1675159872),
+            parse_timestamp("2023-01-31 12:11:12.5+02:00")
+        );
+        assert_eq!(
+            Some(1675159872),
+            parse_timestamp("2023-01-31T05:11:12-0500")
+        );
+        assert_eq!(Some(1675159860), parse_timestamp("2023-01-31 10:11"));
+        assert_eq!(Some(1675123200), parse_timestamp("January 31, 2023"));
+        assert_eq!(None, parse_timestamp("2023-01-31T25:00"));
+        assert_eq!(None, parse_timestamp("2023-01-31T10:00+2"));
+    }
+}
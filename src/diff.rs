@@ -0,0 +1,246 @@
+/// Lines of unchanged context shown around each change.
+const CONTEXT: usize = 3;
+
+/// Above this many cells, the changed middle of a file is too big to diff
+/// line by line and is shown as wholly replaced instead.
+const MAX_TABLE_SIZE: usize = 4_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A unified diff of `old` against `new`, labelled with `path`, or `None`
+/// if they're the same.
+pub fn unified(old: &str, new: &str, path: &str) -> Option<String> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = edits(&old_lines, &new_lines);
+    let hunks = hunks(&edits);
+    if hunks.is_empty() {
+        return None;
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    // line numbers before each edit, in old and new
+    let mut old_line = 0;
+    let mut new_line = 0;
+    let mut positions = Vec::with_capacity(edits.len());
+    for (kind, _) in &edits {
+        positions.push((old_line, new_line));
+        match kind {
+            Kind::Equal => {
+                old_line += 1;
+                new_line += 1;
+            }
+            Kind::Delete => old_line += 1,
+            Kind::Insert => new_line += 1,
+        }
+    }
+
+    for (start, end) in hunks {
+        let hunk = &edits[start..end];
+        let old_count = hunk
+            .iter()
+            .filter(|(kind, _)| *kind != Kind::Insert)
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|(kind, _)| *kind != Kind::Delete)
+            .count();
+        let (old_start, new_start) = positions[start];
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_count > 0),
+            old_count,
+            new_start + usize::from(new_count > 0),
+            new_count
+        ));
+        for (kind, line) in hunk {
+            out.push(match kind {
+                Kind::Equal => ' ',
+                Kind::Delete => '-',
+                Kind::Insert => '+',
+            });
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    Some(out)
+}
+
+/// `diff`, a unified diff, with ANSI colours for a terminal: removed lines
+/// red, added lines green and hunk headers cyan.
+pub fn colorize(diff: &str) -> String {
+    diff.split_inclusive('\n')
+        .map(|line| {
+            let colour = if line.starts_with("---") || line.starts_with("+++") {
+                "1"
+            } else if line.starts_with('-') {
+                "31"
+            } else if line.starts_with('+') {
+                "32"
+            } else if line.starts_with("@@") {
+                "36"
+            } else {
+                return line.to_owned();
+            };
+            let (text, newline) = match line.strip_suffix('\n') {
+                Some(text) => (text, "\n"),
+                None => (line, ""),
+            };
+            format!("\x1b[{}m{}\x1b[0m{}", colour, text, newline)
+        })
+        .collect()
+}
+
+/// The shortest edit script turning `old` into `new`.
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Kind, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<(Kind, &str)> = old[..prefix]
+        .iter()
+        .map(|&line| (Kind::Equal, line))
+        .collect();
+    if old_middle.len() * new_middle.len() > MAX_TABLE_SIZE {
+        edits.extend(old_middle.iter().map(|&line| (Kind::Delete, line)));
+        edits.extend(new_middle.iter().map(|&line| (Kind::Insert, line)));
+    } else {
+        edits.extend(longest_common_subsequence(old_middle, new_middle));
+    }
+    edits.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|&line| (Kind::Equal, line)),
+    );
+    edits
+}
+
+fn longest_common_subsequence<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Kind, &'a str)> {
+    // lengths[i][j] is the LCS length of old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut lengths = vec![0usize; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            edits.push((Kind::Equal, old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            edits.push((Kind::Delete, old[i]));
+            i += 1;
+        } else {
+            edits.push((Kind::Insert, new[j]));
+            j += 1;
+        }
+    }
+    edits.extend(old[i..].iter().map(|&line| (Kind::Delete, line)));
+    edits.extend(new[j..].iter().map(|&line| (Kind::Insert, line)));
+    edits
+}
+
+/// Ranges of edits to show, each change with its surrounding context,
+/// merging changes whose context would overlap.
+fn hunks(edits: &[(Kind, &str)]) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (index, _) in edits
+        .iter()
+        .enumerate()
+        .filter(|(_, (kind, _))| *kind != Kind::Equal)
+    {
+        match hunks.last_mut() {
+            Some((_, end)) if index <= *end + 2 * CONTEXT => *end = index + 1,
+            _ => hunks.push((index, index + 1)),
+        }
+    }
+    hunks
+        .into_iter()
+        .map(|(start, end)| {
+            (
+                start.saturating_sub(CONTEXT),
+                (end + CONTEXT).min(edits.len()),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_files_have_no_diff() {
+        assert_eq!(None, unified("a\nb\n", "a\nb\n", "a.md"));
+    }
+
+    #[test]
+    fn diffs_changed_lines_with_context() {
+        let old = "---\ntitle: Hello\ntags: a\n---\n1\n2\n3\n4\n5\n6\n";
+        let new = "---\ntitle: Hello\ntags:\n- a\n---\n1\n2\n3\n4\n5\n6\n";
+        let expected = [
+            "--- a/post.md",
+            "+++ b/post.md",
+            "@@ -1,6 +1,7 @@",
+            " ---",
+            " title: Hello",
+            "-tags: a",
+            "+tags:",
+            "+- a",
+            " ---",
+            " 1",
+            " 2\n",
+        ];
+        assert_eq!(Some(expected.join("\n")), unified(old, new, "post.md"));
+    }
+
+    #[test]
+    fn separates_distant_changes_into_hunks() {
+        let old: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 19\n", "line nineteen\n");
+        let diff = unified(&old, &new, "n").unwrap();
+        assert_eq!(2, diff.matches("@@ -").count());
+        assert!(diff.contains("@@ -1,5 +1,5 @@\n"));
+        assert!(diff.contains("@@ -16,5 +16,5 @@\n"));
+    }
+
+    #[test]
+    fn marks_missing_newline_at_end() {
+        let diff = unified("a", "b\n", "a").unwrap();
+        assert!(diff.ends_with("@@ -1,1 +1,1 @@\n-a\n\\ No newline at end of file\n+b\n"));
+    }
+
+    #[test]
+    fn colorizes_changes() {
+        let diff = unified("a\nb\n", "a\nc\n", "post.md").unwrap();
+        let colored = colorize(&diff);
+        assert!(colored.contains("\x1b[31m-b\x1b[0m\n"));
+        assert!(colored.contains("\x1b[32m+c\x1b[0m\n"));
+        assert!(colored.contains("\x1b[1m--- a/post.md\x1b[0m\n"));
+        assert!(colored.contains("\n a\n"));
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_yaml as yaml;
+
+use crate::{
+    date::Date,
+    feed::scalar,
+    frontmatter::{self, TextStyle},
+    hash, links,
+    report::{Finding, Findings},
+};
+
+/// Bodies with fewer words share too few for their fingerprints to say
+/// whether they're nearly the same.
+const MIN_WORDS: usize = 20;
+
+/// Words per shingle, the overlapping runs of words fingerprints are made
+/// from.
+const SHINGLE: usize = 3;
+
+/// Every file's body, frontmatter aside, to find files whose content is the
+/// same as or nearly the same as another's, as when an imported archive has
+/// the same post several times with different metadata.
+///
+/// Of each set of duplicates, the file with the earliest `date` is taken to
+/// be the original, or the first given if their dates don't say; the others
+/// are flagged as duplicates of it.
+#[derive(Debug, Default)]
+pub struct Index {
+    bodies: Vec<Body>,
+    /// How many of the 64 fingerprint bits may differ between near
+    /// duplicates; 0 for exact duplicates only.
+    max_distance: u32,
+}
+
+/// What `--merge-duplicates` does with duplicates once their frontmatter
+/// is merged into the original's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Merge {
+    Delete,
+    /// Delete them, adding their URLs to the original's `aliases` so the
+    /// site redirects them there.
+    Alias,
+}
+
+impl FromStr for Merge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delete" => Ok(Merge::Delete),
+            "alias" => Ok(Merge::Alias),
+            _ => Err(format!("expected delete or alias, got {:?}", s)),
+        }
+    }
+}
+
+/// A file duplicating another, as it was when the corpus was read.
+#[derive(Debug)]
+pub struct Duplicate<'a> {
+    pub path: &'a Path,
+    pub meta: Option<&'a yaml::Value>,
+}
+
+#[derive(Debug)]
+struct Body {
+    path: PathBuf,
+    meta: Option<yaml::Value>,
+    date: Option<Date>,
+    digest: String,
+    /// `None` for bodies too short to fingerprint.
+    simhash: Option<u64>,
+}
+
+impl Index {
+    /// Read the body of every file in `paths`. Files that can't be read, or
+    /// whose bodies are empty, are left out.
+    pub fn build(paths: &[String], max_distance: u32) -> Self {
+        let bodies = paths
+            .iter()
+            .filter_map(|path| {
+                let text = read_to_string(path).ok()?;
+                let text = TextStyle::detect(&text).normalize(&text).into_owned();
+                let (_, content) = frontmatter::parse_raw(&text);
+                let body = normalize(content);
+                if body.is_empty() {
+                    return None;
+                }
+                let meta = frontmatter::parse(&text).0.and_then(Result::ok);
+                let date = meta
+                    .as_ref()
+                    .and_then(|meta| scalar(meta.get("date")?))
+                    .and_then(|date| Date::parse(&date));
+                Some(Body {
+                    path: links::normalize(Path::new(path)),
+                    meta,
+                    date,
+                    digest: hash::sha256_hex(body.as_bytes()),
+                    simhash: simhash(&body),
+                })
+            })
+            .collect();
+        Self {
+            bodies,
+            max_distance,
+        }
+    }
+
+    /// Fail if the file at `path` duplicates, or nearly duplicates, a file
+    /// taken to be the original.
+    pub fn check(&self, path: &Path) -> Result<(), Findings> {
+        let (mut exact, mut near) = (Vec::new(), Vec::new());
+        for (other, distance) in self.originals(&links::normalize(path)) {
+            match distance {
+                0 => exact.push(other.path.display().to_string()),
+                _ => near.push(format!(
+                    "{} ({} bits apart)",
+                    other.path.display(),
+                    distance
+                )),
+            }
+        }
+
+        let mut findings = Vec::new();
+        if !exact.is_empty() {
+            findings.push(Finding::new(
+                "duplicate-content",
+                format!("same content as {}", exact.join(", ")),
+            ));
+        }
+        if !near.is_empty() {
+            findings.push(Finding::new(
+                "near-duplicate-content",
+                format!("nearly the same content as {}", near.join(", ")),
+            ));
+        }
+        if findings.is_empty() {
+            Ok(())
+        } else {
+            Err(Findings(findings))
+        }
+    }
+
+    /// The original the file at `path` duplicates, if it's a duplicate,
+    /// following a duplicate of a duplicate back to the file that isn't one.
+    pub fn original_of(&self, path: &Path) -> Option<&Path> {
+        let mut original = self.direct_original_of(&links::normalize(path))?;
+        // each step is to a file taken to come earlier, so this ends
+        while let Some(earlier) = self.direct_original_of(original) {
+            original = earlier;
+        }
+        Some(original)
+    }
+
+    /// The likeliest original of the file at the normalized `path`, which
+    /// may itself be a duplicate.
+    fn direct_original_of(&self, path: &Path) -> Option<&Path> {
+        let (original, _) = self.originals(path).into_iter().next()?;
+        Some(&original.path)
+    }
+
+    /// The files whose original is the file at `path`, directly or through
+    /// other duplicates.
+    pub fn duplicates_of(&self, path: &Path) -> Vec<Duplicate<'_>> {
+        let path = links::normalize(path);
+        self.bodies
+            .iter()
+            .filter(|body| self.original_of(&body.path) == Some(path.as_path()))
+            .map(|body| Duplicate {
+                path: &body.path,
+                meta: body.meta.as_ref(),
+            })
+            .collect()
+    }
+
+    /// The files the one at `path` duplicates or nearly duplicates that are
+    /// taken to come before it, the likeliest original first, with how many
+    /// fingerprint bits apart they are, 0 for exact duplicates.
+    fn originals(&self, path: &Path) -> Vec<(&Body, u32)> {
+        let Some((index, body)) = self.bodies.iter().enumerate().find(|(_, b)| b.path == path)
+        else {
+            return Vec::new();
+        };
+        let key = |i: usize, b: &Body| (b.date.is_none(), b.date, i);
+        let mut originals: Vec<_> = self
+            .bodies
+            .iter()
+            .enumerate()
+            .filter(|(i, other)| key(*i, other) < key(index, body))
+            .filter_map(|(i, other)| {
+                if other.digest == body.digest {
+                    return Some((key(i, other), other, 0));
+                }
+                let distance = (body.simhash? ^ other.simhash?).count_ones();
+                (distance <= self.max_distance).then_some((key(i, other), other, distance))
+            })
+            .collect();
+        originals.sort_by_key(|(key, _, _)| *key);
+        originals
+            .into_iter()
+            .map(|(_, other, distance)| (other, distance))
+            .collect()
+    }
+}
+
+/// Merge a duplicate's frontmatter `from` into the original's `into`:
+/// lists get the items they're missing, `date` becomes the earlier of the
+/// two, and keys that are missing or empty take the duplicate's value.
+pub fn merge(into: &mut yaml::Value, from: &yaml::Value) {
+    let (Some(into), Some(from)) = (into.as_mapping_mut(), from.as_mapping()) else {
+        return;
+    };
+    for (key, theirs) in from {
+        let Some(ours) = into.get_mut(key) else {
+            into.insert(key.clone(), theirs.clone());
+            continue;
+        };
+        if is_empty(ours) {
+            *ours = theirs.clone();
+        } else if let (Some(ours), Some(theirs)) = (ours.as_sequence_mut(), theirs.as_sequence()) {
+            for item in theirs {
+                if !ours.contains(item) {
+                    ours.push(item.clone());
+                }
+            }
+        } else if key.as_str() == Some("date") {
+            let date = |value: &yaml::Value| scalar(value).as_deref().and_then(Date::parse);
+            if let (Some(a), Some(b)) = (date(ours), date(theirs)) {
+                if b < a {
+                    *ours = theirs.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Add `url` to `metadata`'s `aliases`, the URLs a site redirects to the
+/// page, unless it's already there.
+pub fn add_alias(metadata: &mut yaml::Value, url: String) {
+    let Some(metadata) = metadata.as_mapping_mut() else {
+        return;
+    };
+    let aliases = metadata
+        .entry("aliases".into())
+        .or_insert_with(|| yaml::Value::Sequence(Vec::new()));
+    if let yaml::Value::String(alias) = aliases {
+        *aliases = yaml::Value::Sequence(vec![alias.clone().into()]);
+    }
+    if let Some(aliases) = aliases.as_sequence_mut() {
+        if !aliases.iter().any(|alias| alias.as_str() == Some(&url)) {
+            aliases.push(url.into());
+        }
+    }
+}
+
+fn is_empty(value: &yaml::Value) -> bool {
+    match value {
+        yaml::Value::Null => true,
+        yaml::Value::String(s) => s.trim().is_empty(),
+        yaml::Value::Sequence(items) => items.is_empty(),
+        yaml::Value::Mapping(mapping) => mapping.is_empty(),
+        _ => false,
+    }
+}
+
+/// A body without trailing whitespace on its lines or blank lines around
+/// it, which don't make it a different post.
+fn normalize(content: &str) -> String {
+    let lines: Vec<_> = content.lines().map(str::trim_end).collect();
+    lines.join("\n").trim().to_owned()
+}
+
+/// A fingerprint of `body` where similar bodies have fingerprints that
+/// differ in few bits, made from its lowercased words in overlapping runs.
+fn simhash(body: &str) -> Option<u64> {
+    let words: Vec<String> = body
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+    let mut weights = [0i64; 64];
+    for shingle in words.windows(SHINGLE) {
+        let hash = fnv1a(shingle.join(" ").as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if (hash >> bit) & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    let fingerprint = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |fingerprint, (bit, _)| fingerprint | (1 << bit));
+    Some(fingerprint)
+}
+
+/// 64-bit FNV-1a, which is stable across builds, unlike std's hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::write;
+
+    use super::*;
+
+    const POST: &str = "Imported archives are full of duplicated posts, each with \
+        slightly different metadata, because every export tool had its own idea \
+        of what a post should look like and nobody cleaned up afterwards.";
+
+    #[test]
+    fn finds_duplicate_bodies() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = |name: &str| dir.path().join(name);
+        write(
+            path("copy.md"),
+            format!("---\ndate: 2023-02-01\n---\n{}\n", POST),
+        )?;
+        write(
+            path("original.md"),
+            format!("---\ndate: 2023-01-01\n---\n\n{}  \n", POST),
+        )?;
+        let edited = POST.replace("nobody", "no one ever");
+        write(
+            path("edited.md"),
+            format!("---\ntitle: Edited\n---\n{}\n", edited),
+        )?;
+        write(path("other.md"), "---\n---\nSomething else entirely.\n")?;
+        let paths: Vec<_> = ["copy.md", "original.md", "edited.md", "other.md"]
+            .iter()
+            .map(|name| path(name).display().to_string())
+            .collect();
+        let index = Index::build(&paths, 12);
+
+        index
+            .check(&path("original.md"))
+            .expect("earliest is the original");
+        index
+            .check(&path("other.md"))
+            .expect("nothing else is like it");
+        let findings = index.check(&path("copy.md")).expect_err("copy of original");
+        assert_eq!("duplicate-content", findings.0[0].rule);
+        assert!(findings.0[0].message.contains("original.md"));
+        let findings = index
+            .check(&path("edited.md"))
+            .expect_err("edit of original");
+        assert_eq!("near-duplicate-content", findings.0[0].rule);
+
+        let exact_only = Index::build(&paths, 0);
+        exact_only
+            .check(&path("edited.md"))
+            .expect("not exactly the same");
+
+        let original = links::normalize(&path("original.md"));
+        assert_eq!(
+            Some(original.as_path()),
+            index.original_of(&path("edited.md"))
+        );
+        let duplicates: Vec<_> = index
+            .duplicates_of(&original)
+            .iter()
+            .map(|d| d.path)
+            .collect();
+        assert_eq!(
+            vec![
+                links::normalize(&path("copy.md")),
+                links::normalize(&path("edited.md"))
+            ],
+            duplicates
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn follows_near_duplicates_back_to_the_original() {
+        let body = |name: &str, simhash| Body {
+            path: PathBuf::from(name),
+            meta: None,
+            date: None,
+            digest: name.to_owned(),
+            simhash: Some(simhash),
+        };
+        // b is near a, and c near b but not a
+        let index = Index {
+            bodies: vec![body("a.md", 0), body("b.md", 0xf), body("c.md", 0xff)],
+            max_distance: 4,
+        };
+        assert_eq!(
+            Some(Path::new("a.md")),
+            index.original_of(Path::new("c.md"))
+        );
+        let duplicates: Vec<_> = index
+            .duplicates_of(Path::new("a.md"))
+            .iter()
+            .map(|d| d.path)
+            .collect();
+        assert_eq!(vec![Path::new("b.md"), Path::new("c.md")], duplicates);
+        assert!(index.duplicates_of(Path::new("b.md")).is_empty());
+    }
+
+    #[test]
+    fn merges_frontmatter() {
+        let mut original: yaml::Value = yaml::from_str(
+            "title: Hi
+date: 2023-02-01
+tags: [a]
+summary: ''
+",
+        )
+        .unwrap();
+        let duplicate: yaml::Value = yaml::from_str(
+            "title: Other
+date: 2023-01-01
+tags: [b, a]
+summary: Hello
+author: Me
+",
+        )
+        .unwrap();
+        merge(&mut original, &duplicate);
+        add_alias(&mut original, "/old/".to_owned());
+        add_alias(&mut original, "/old/".to_owned());
+        assert_eq!(
+            "title: Hi\ndate: 2023-01-01\ntags:\n- a\n- b\nsummary: Hello\nauthor: Me\n\
+             aliases:\n- /old/\n",
+            yaml::to_string(&original).unwrap()
+        );
+        assert_eq!(Ok(Merge::Alias), "alias".parse());
+    }
+
+    #[test]
+    fn fingerprints_similar_bodies_alike() {
+        let (a, b) = (
+            simhash(POST).unwrap(),
+            simhash(&POST.replace("tool", "program")),
+        );
+        let unrelated = simhash(&POST.split(' ').rev().collect::<Vec<_>>().join(" "));
+        assert!((a ^ b.unwrap()).count_ones() < (a ^ unrelated.unwrap()).count_ones());
+        assert_eq!(None, simhash("too short"));
+    }
+}
@@ -0,0 +1,318 @@
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Args, Command, FromArgMatches};
+use eyre::eyre;
+use serde_yaml as yaml;
+
+use crate::{
+    generate::{Assignment, Generators},
+    migrate::Operation,
+    query::{self, Condition},
+};
+
+/// Scriptless edits from `--set`, `--set-missing`, `--unset` and
+/// `--rename-key`, in the order given, guarded by `--if` conditions.
+///
+/// Each `--if` applies to the edits after it, up to the next `--if` that
+/// follows an edit, which starts a new group; consecutive `--if`s must all
+/// hold. Edits before the first `--if` apply to every file. So
+///
+/// ```text
+/// --if 'meta.draft == nil' --set draft=false --if 'draft' --unset published
+/// ```
+///
+/// sets `draft` where it's missing, and then unsets `published` on drafts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Edits {
+    groups: Vec<Group>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Group {
+    conditions: Vec<Condition>,
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Set(Assignment),
+    SetMissing(Assignment),
+    Unset(String),
+    RenameKey(String, String),
+}
+
+/// `--rename-key`'s argument, `OLD=NEW`.
+#[derive(Clone, Debug, PartialEq)]
+struct Renaming(String, String);
+
+impl std::str::FromStr for Renaming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => {
+                Ok(Self(from.trim().to_owned(), to.trim().to_owned()))
+            }
+            _ => Err(format!("expected OLD=NEW, got {:?}", s)),
+        }
+    }
+}
+
+/// `--if`'s argument: a `--where` condition, with the key optionally
+/// written `meta.KEY` as in scripts.
+fn guard(s: &str) -> Result<Condition, String> {
+    let s = s.trim();
+    s.strip_prefix("!meta.")
+        .map(|rest| format!("!{}", rest))
+        .unwrap_or_else(|| s.strip_prefix("meta.").unwrap_or(s).to_owned())
+        .parse()
+}
+
+impl Edits {
+    pub fn is_empty(&self) -> bool {
+        self.groups.iter().all(|group| group.steps.is_empty())
+    }
+
+    /// Edits from command line arguments, as `--if`, `--set` and the rest
+    /// would give them.
+    pub fn parse<I, T>(args: I) -> eyre::Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let cmd = Self::augment_args(Command::new("edits").no_binary_name(true));
+        let matches = cmd.try_get_matches_from(args)?;
+        Self::from_arg_matches(&matches).map_err(|e| eyre!(e))
+    }
+
+    /// Apply the edits to the frontmatter of the file at `path`, checking
+    /// each group's conditions against the frontmatter as the edits before
+    /// it left it, and returning a description of each edit made.
+    pub fn apply(
+        &self,
+        metadata: &mut Option<yaml::Value>,
+        generators: &Generators,
+        path: &str,
+    ) -> eyre::Result<Vec<String>> {
+        let mut applied = Vec::new();
+        for group in &self.groups {
+            if !query::matches_all(&group.conditions, metadata.as_ref()) {
+                continue;
+            }
+            for step in &group.steps {
+                if let Some(description) = step.apply(metadata, generators, path)? {
+                    applied.push(description);
+                }
+            }
+        }
+        Ok(applied)
+    }
+
+    fn push(&mut self, step: Step) {
+        match self.groups.last_mut() {
+            Some(group) => group.steps.push(step),
+            None => self.groups.push(Group {
+                conditions: Vec::new(),
+                steps: vec![step],
+            }),
+        }
+    }
+
+    fn guard(&mut self, condition: Condition) {
+        match self.groups.last_mut() {
+            Some(group) if group.steps.is_empty() => group.conditions.push(condition),
+            _ => self.groups.push(Group {
+                conditions: vec![condition],
+                steps: Vec::new(),
+            }),
+        }
+    }
+}
+
+impl Step {
+    fn apply(
+        &self,
+        metadata: &mut Option<yaml::Value>,
+        generators: &Generators,
+        path: &str,
+    ) -> eyre::Result<Option<String>> {
+        match self {
+            Self::Set(assignment) => assignment.apply(metadata, false, generators, path),
+            Self::SetMissing(assignment) => assignment.apply(metadata, true, generators, path),
+            Self::Unset(key) => {
+                let operation = Operation::Drop { key: key.clone() };
+                Ok(apply(metadata, &operation)?.then(|| format!("unset {}", key)))
+            }
+            Self::RenameKey(from, to) => {
+                let (from, to) = (from.clone(), to.clone());
+                let description = format!("{} -> {}", from, to);
+                let operation = if from.contains('.') || to.contains('.') {
+                    Operation::Move { from, to }
+                } else {
+                    Operation::Rename { from, to }
+                };
+                Ok(apply(metadata, &operation)?.then_some(description))
+            }
+        }
+    }
+}
+
+fn apply(metadata: &mut Option<yaml::Value>, operation: &Operation) -> eyre::Result<bool> {
+    match metadata {
+        Some(metadata) => operation.apply(metadata),
+        None => Ok(false),
+    }
+}
+
+const IF: &str = "if";
+const SET: &str = "set";
+const SET_MISSING: &str = "set-missing";
+const UNSET: &str = "unset";
+const RENAME_KEY: &str = "rename-key";
+
+impl Args for Edits {
+    fn augment_args(cmd: Command) -> Command {
+        cmd.arg(
+            Arg::new(IF)
+                .long(IF)
+                .value_name("CONDITION")
+                .action(ArgAction::Append)
+                .value_parser(guard)
+                .help(
+                    "Only make the --set, --set-missing, --unset and --rename-key edits that \
+                     follow for files meeting this --where condition, e.g. 'meta.draft == nil' \
+                     (repeat for all of several conditions; an --if after an edit starts a new \
+                     group)",
+                ),
+        )
+        .arg(
+            Arg::new(SET)
+                .long(SET)
+                .value_name("KEY=VALUE")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(Assignment))
+                .help(
+                    "Set a key, a dotted path, to a value that may hold generator expressions, \
+                     evaluated for each file: e.g. id='{{uuid}}', date='{{now:%Y-%m-%d}}' or \
+                     slug='{{slugify(meta.title)}}' (may be repeated; generators: uuid, now, \
+                     slugify, lower, upper, trim, default)",
+                ),
+        )
+        .arg(
+            Arg::new(SET_MISSING)
+                .long(SET_MISSING)
+                .value_name("KEY=VALUE")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(Assignment))
+                .help(
+                    "Like --set, but only for files where the key is missing or null, to \
+                     backfill it",
+                ),
+        )
+        .arg(
+            Arg::new(UNSET)
+                .long(UNSET)
+                .value_name("KEY")
+                .action(ArgAction::Append)
+                .help("Remove a key, a dotted path (may be repeated)"),
+        )
+        .arg(
+            Arg::new(RENAME_KEY)
+                .long(RENAME_KEY)
+                .value_name("OLD=NEW")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(Renaming))
+                .help(
+                    "Rename a key, keeping its place, or with dotted paths move its value \
+                     (may be repeated)",
+                ),
+        )
+    }
+
+    fn augment_args_for_update(cmd: Command) -> Command {
+        Self::augment_args(cmd)
+    }
+}
+
+impl FromArgMatches for Edits {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        let mut edits = Self::default();
+        edits.update_from_arg_matches(matches)?;
+        Ok(edits)
+    }
+
+    /// Rebuild the order the flags were given in from their indices, since
+    /// that's what decides which `--if`s guard which edits.
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        enum Flag {
+            If(Condition),
+            Step(Step),
+        }
+        fn indexed<T: Clone + Send + Sync + 'static>(
+            matches: &ArgMatches,
+            id: &str,
+            flag: impl Fn(T) -> Flag,
+        ) -> Vec<(usize, Flag)> {
+            match (matches.indices_of(id), matches.get_many::<T>(id)) {
+                (Some(indices), Some(values)) => indices.zip(values.cloned().map(flag)).collect(),
+                _ => Vec::new(),
+            }
+        }
+        let mut flags = indexed(matches, IF, Flag::If);
+        flags.extend(indexed(matches, SET, |a| Flag::Step(Step::Set(a))));
+        flags.extend(indexed(matches, SET_MISSING, |a| {
+            Flag::Step(Step::SetMissing(a))
+        }));
+        flags.extend(indexed(matches, UNSET, |key| Flag::Step(Step::Unset(key))));
+        flags.extend(indexed(matches, RENAME_KEY, |Renaming(from, to)| {
+            Flag::Step(Step::RenameKey(from, to))
+        }));
+        flags.sort_by_key(|(index, _)| *index);
+        for (_, flag) in flags {
+            match flag {
+                Flag::If(condition) => self.guard(condition),
+                Flag::Step(step) => self.push(step),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn edit(args: &[&str], metadata: &str) -> eyre::Result<String> {
+        let mut metadata = Some(yaml::from_str(metadata)?);
+        Edits::parse(args)?.apply(&mut metadata, &Generators::default(), "a.md")?;
+        Ok(yaml::to_string(&metadata)?)
+    }
+
+    #[test]
+    fn guards_edits_with_conditions() -> eyre::Result<()> {
+        #[rustfmt::skip]
+        let args = [
+            "--set", "checked=true",
+            "--rename-key", "author=authors",
+            "--if", "meta.draft == nil", "--set", "draft=false",
+            "--if", "draft", "--if", "!meta.published", "--unset", "date",
+        ];
+        assert_eq!(
+            "title: A\nauthors: me\nchecked: true\ndraft: false\n",
+            edit(&args, "title: A\nauthor: me\n")?
+        );
+        assert_eq!(
+            "title: B\ndraft: true\nauthors: me\nchecked: true\n",
+            edit(&args, "title: B\ndate: today\ndraft: true\nauthor: me\n")?
+        );
+        assert_eq!(
+            "title: C\ndate: today\ndraft: true\npublished: true\nauthors: me\n\
+             checked: true\n",
+            edit(
+                &args,
+                "title: C\ndate: today\ndraft: true\npublished: true\nauthor: me\n"
+            )?
+        );
+        assert!(Edits::parse(["--if", "draft"])?.is_empty());
+        assert!(Edits::parse(["--rename-key", "author"]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+use std::{borrow::Cow, path::Path, str::FromStr};
+
+use crate::frontmatter;
+
+/// How frontmatter is embedded in a kind of file: Markdown's bare rules, or
+/// the rules wrapped in a comment, so that files other than Markdown can
+/// carry metadata without breaking.
+///
+/// An envelope converts between its own form and Markdown's, so everything
+/// else only ever deals in frontmatter between plain rules.
+pub trait Envelope: Send + Sync {
+    /// Split off the frontmatter at the start of `s` as Markdown would have
+    /// it, rules included, returning it and the content after the envelope,
+    /// or `None` if there's no frontmatter.
+    fn unwrap<'a>(&self, s: &'a str) -> Option<(Cow<'a, str>, &'a str)>;
+
+    /// Wrap frontmatter written as Markdown would have it, rules included.
+    fn wrap(&self, frontmatter: &str) -> String;
+}
+
+/// Frontmatter between bare rules, as Markdown has it.
+pub struct Markdown;
+
+/// Frontmatter in a block comment, as in HTML:
+///
+/// ```text
+/// <!--
+/// ---
+/// title: Hello
+/// ---
+/// -->
+/// ```
+///
+/// or with the rules on the same lines as the comment's delimiters, as in
+/// `<!-- ---` and `--- -->`, or `{#---` and `---#}` in a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockComment {
+    open: String,
+    close: String,
+}
+
+/// Frontmatter in a header of line comments, as in YAML, Python or shell
+/// files with `#`, or JavaScript with `//`:
+///
+/// ```text
+/// # ---
+/// # title: Hello
+/// # ---
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineComment {
+    prefix: String,
+}
+
+impl Envelope for Markdown {
+    fn unwrap<'a>(&self, s: &'a str) -> Option<(Cow<'a, str>, &'a str)> {
+        let (raw, content) = frontmatter::parse_raw(s);
+        raw.map(|_| (Cow::Borrowed(&s[..s.len() - content.len()]), content))
+    }
+
+    fn wrap(&self, frontmatter: &str) -> String {
+        frontmatter.to_owned()
+    }
+}
+
+impl BlockComment {
+    pub fn new(open: &str, close: &str) -> Self {
+        Self {
+            open: open.to_owned(),
+            close: close.to_owned(),
+        }
+    }
+
+    /// `<!--` and `-->`.
+    pub fn html() -> Self {
+        Self::new("<!--", "-->")
+    }
+
+    /// `{#` and `#}`, as in Jinja, Twig and Nunjucks templates.
+    pub fn template() -> Self {
+        Self::new("{#", "#}")
+    }
+}
+
+impl Envelope for BlockComment {
+    fn unwrap<'a>(&self, s: &'a str) -> Option<(Cow<'a, str>, &'a str)> {
+        let mut lines = s.split_inclusive('\n');
+        let first = lines.next()?;
+        let mut frontmatter = match first.trim_end().strip_prefix(self.open.as_str())?.trim() {
+            "" => String::new(),
+            rule @ ("---" | "+++") => format!("{}\n", rule),
+            _ => return None,
+        };
+        let mut end = first.len();
+        for line in lines {
+            end += line.len();
+            let trimmed = line.trim_end();
+            if let Some(rule) = trimmed.strip_suffix(self.close.as_str()) {
+                match rule.trim() {
+                    "" => {}
+                    rule @ ("---" | "+++" | "...") => frontmatter.push_str(&format!("{}\n", rule)),
+                    _ => return None,
+                }
+                let (raw, rest) = frontmatter::parse_raw(&frontmatter);
+                return (raw.is_some() && rest.trim().is_empty())
+                    .then(|| (Cow::Owned(frontmatter), &s[end..]));
+            }
+            frontmatter.push_str(line);
+        }
+        // otherwise the comment never closed
+        None
+    }
+
+    fn wrap(&self, frontmatter: &str) -> String {
+        format!("{}\n{}{}\n", self.open, frontmatter, self.close)
+    }
+}
+
+impl LineComment {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_owned(),
+        }
+    }
+}
+
+impl Envelope for LineComment {
+    fn unwrap<'a>(&self, s: &'a str) -> Option<(Cow<'a, str>, &'a str)> {
+        let mut frontmatter = String::new();
+        let mut end = 0;
+        for line in s.split_inclusive('\n') {
+            let Some(uncommented) = line.strip_prefix(self.prefix.as_str()) else {
+                break;
+            };
+            frontmatter.push_str(uncommented.strip_prefix(' ').unwrap_or(uncommented));
+            end += line.len();
+            let (raw, rest) = frontmatter::parse_raw(&frontmatter);
+            if raw.is_some() && rest.is_empty() {
+                return Some((Cow::Owned(frontmatter), &s[end..]));
+            }
+        }
+        None
+    }
+
+    fn wrap(&self, frontmatter: &str) -> String {
+        frontmatter
+            .split_inclusive('\n')
+            .map(|line| match line.trim_end_matches(['\r', '\n']) {
+                "" => format!("{}{}", self.prefix, line),
+                _ => format!("{} {}", self.prefix, line),
+            })
+            .collect()
+    }
+}
+
+/// An envelope as `--envelope` names it: `markdown`, `html` (`<!-- -->`),
+/// `template` (`{# #}`), `hash` (`#` lines), `slashes` (`//` lines), or any
+/// other comment syntax as `line:PREFIX` or `block:OPEN,CLOSE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Markdown,
+    Block(BlockComment),
+    Line(LineComment),
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(prefix) = s.strip_prefix("line:") {
+            return match prefix.trim() {
+                "" => Err("line: needs a comment prefix, e.g. line://".to_owned()),
+                prefix => Ok(Kind::Line(LineComment::new(prefix))),
+            };
+        }
+        if let Some(delimiters) = s.strip_prefix("block:") {
+            return match delimiters.split_once(',') {
+                Some((open, close)) if !open.trim().is_empty() && !close.trim().is_empty() => {
+                    Ok(Kind::Block(BlockComment::new(open.trim(), close.trim())))
+                }
+                _ => Err(format!("expected block:OPEN,CLOSE, got {:?}", s)),
+            };
+        }
+        match s {
+            "markdown" => Ok(Kind::Markdown),
+            "html" => Ok(Kind::Block(BlockComment::html())),
+            "template" => Ok(Kind::Block(BlockComment::template())),
+            "hash" => Ok(Kind::Line(LineComment::new("#"))),
+            "slashes" => Ok(Kind::Line(LineComment::new("//"))),
+            _ => Err(format!(
+                "unknown envelope {:?}, expected markdown, html, template, hash, slashes, \
+                 line:PREFIX or block:OPEN,CLOSE",
+                s
+            )),
+        }
+    }
+}
+
+impl Kind {
+    /// The envelope for a file, going by its extension: any given for it in
+    /// `overrides`, and otherwise HTML comments for HTML, `{# #}` for
+    /// templates, `#` lines for YAML, Python, Ruby, shell and TOML, `//`
+    /// lines for JavaScript, TypeScript and C-like languages, `--` lines for
+    /// SQL and Lua, and bare rules for anything else.
+    pub fn for_path(path: &str, overrides: &[ForExtension]) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let Some(extension) = extension else {
+            return Kind::Markdown;
+        };
+        if let Some(found) = overrides.iter().find(|o| o.extension == extension) {
+            return found.kind.clone();
+        }
+        match extension.as_str() {
+            "html" | "htm" | "xhtml" | "vue" | "svelte" => Kind::Block(BlockComment::html()),
+            "j2" | "jinja" | "jinja2" | "twig" | "njk" => Kind::Block(BlockComment::template()),
+            "yaml" | "yml" | "py" | "rb" | "sh" | "toml" => Kind::Line(LineComment::new("#")),
+            "js" | "jsx" | "mjs" | "ts" | "tsx" | "rs" | "go" | "java" | "kt" | "swift" | "c"
+            | "h" | "cpp" | "cs" => Kind::Line(LineComment::new("//")),
+            "sql" | "lua" => Kind::Line(LineComment::new("--")),
+            _ => Kind::Markdown,
+        }
+    }
+}
+
+impl Envelope for Kind {
+    fn unwrap<'a>(&self, s: &'a str) -> Option<(Cow<'a, str>, &'a str)> {
+        match self {
+            Kind::Markdown => Markdown.unwrap(s),
+            Kind::Block(block) => block.unwrap(s),
+            Kind::Line(line) => line.unwrap(s),
+        }
+    }
+
+    fn wrap(&self, frontmatter: &str) -> String {
+        match self {
+            Kind::Markdown => Markdown.wrap(frontmatter),
+            Kind::Block(block) => block.wrap(frontmatter),
+            Kind::Line(line) => line.wrap(frontmatter),
+        }
+    }
+}
+
+/// `--envelope-for`'s argument, `EXT=ENVELOPE`: the envelope files with an
+/// extension use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForExtension {
+    extension: String,
+    kind: Kind,
+}
+
+impl FromStr for ForExtension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (extension, kind) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected EXT=ENVELOPE, got {:?}", s))?;
+        Ok(Self {
+            extension: extension
+                .trim()
+                .trim_start_matches('.')
+                .to_ascii_lowercase(),
+            kind: kind.trim().parse()?,
+        })
+    }
+}
+
+/// A document as Markdown would have it, with its frontmatter, if any, out
+/// of `envelope`.
+pub fn unwrap<'a>(envelope: &dyn Envelope, s: &'a str) -> Cow<'a, str> {
+    match envelope.unwrap(s) {
+        Some((Cow::Borrowed(_), _)) | None => Cow::Borrowed(s),
+        Some((Cow::Owned(frontmatter), content)) => Cow::Owned(frontmatter + content),
+    }
+}
+
+/// A document written as Markdown would have it, with its frontmatter, if
+/// any, put in `envelope`.
+pub fn wrap(envelope: &dyn Envelope, written: Vec<u8>) -> Vec<u8> {
+    let Ok(s) = std::str::from_utf8(&written) else {
+        return written;
+    };
+    match Markdown.unwrap(s) {
+        Some((frontmatter, content)) => [envelope.wrap(&frontmatter).as_str(), content]
+            .concat()
+            .into_bytes(),
+        None => written,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_envelopes() -> Result<(), String> {
+        let markdown = "---\ntitle: Hello\n\nlist: [a]\n---\nBody\n";
+        for (kind, wrapped) in [
+            ("markdown", markdown),
+            (
+                "html",
+                "<!--\n---\ntitle: Hello\n\nlist: [a]\n---\n-->\nBody\n",
+            ),
+            (
+                "hash",
+                "# ---\n# title: Hello\n#\n# list: [a]\n# ---\nBody\n",
+            ),
+            (
+                "line://",
+                "// ---\n// title: Hello\n//\n// list: [a]\n// ---\nBody\n",
+            ),
+            (
+                "block:{#,#}",
+                "{#\n---\ntitle: Hello\n\nlist: [a]\n---\n#}\nBody\n",
+            ),
+        ] {
+            let envelope: Kind = kind.parse()?;
+            assert_eq!(markdown, unwrap(&envelope, wrapped), "{}", kind);
+            assert_eq!(
+                wrapped.as_bytes(),
+                wrap(&envelope, markdown.into()),
+                "{}",
+                kind
+            );
+        }
+        for (envelope, wrapped) in [
+            (
+                BlockComment::html(),
+                "<!-- ---\ntitle: Hello\n\nlist: [a]\n--- -->\nBody\n",
+            ),
+            (
+                BlockComment::template(),
+                "{#---\ntitle: Hello\n\nlist: [a]\n---#}\nBody\n",
+            ),
+        ] {
+            assert_eq!(markdown, unwrap(&envelope, wrapped));
+        }
+        // comments that aren't frontmatter are left alone
+        for (envelope, s) in [
+            ("html", "<!-- note -->\nBody\n"),
+            ("hash", "#!/bin/sh\n# ---\n"),
+            ("hash", "# ---\n# title: Unclosed\n"),
+        ] {
+            assert!(envelope.parse::<Kind>()?.unwrap(s).is_none(), "{:?}", s);
+        }
+        assert!("line:".parse::<Kind>().is_err());
+        assert!("block:{#".parse::<Kind>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn chooses_envelope_by_extension() -> Result<(), String> {
+        let overrides: Vec<ForExtension> = vec![".md=html".parse()?, "txt=line:;".parse()?];
+        let for_path = |path| Kind::for_path(path, &overrides);
+        assert_eq!(
+            Kind::Line(LineComment::new("#")),
+            for_path("config/site.YML")
+        );
+        assert_eq!(
+            Kind::Line(LineComment::new("//")),
+            for_path("routes/index.ts")
+        );
+        assert_eq!(Kind::Block(BlockComment::template()), for_path("base.njk"));
+        assert_eq!(Kind::Block(BlockComment::html()), for_path("post.md"));
+        assert_eq!(Kind::Line(LineComment::new(";")), for_path("notes.txt"));
+        assert_eq!(Kind::Markdown, for_path("README"));
+        assert_eq!(Kind::Markdown, for_path("post.markdown"));
+        Ok(())
+    }
+}
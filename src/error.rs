@@ -0,0 +1,152 @@
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::frontmatter::SyntaxError;
+
+/// Why a file couldn't be fixed, for embedding applications and reports to
+/// tell kinds of failure apart. [`process`](crate::process) fails with one
+/// where it can, and like [`Findings`](crate::report::Findings) it travels
+/// through `eyre`, so recover it with `report.downcast_ref::<FixError>()`.
+///
+/// Each keeps the whole account of the failure `eyre` would have given as
+/// its message.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FixError {
+    /// Frontmatter didn't parse, at `line` and `column` (1-based) if known.
+    Parse {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// A script failed on the file; `traceback` is Lua's account of it,
+    /// with a stack traceback where there is one.
+    Script {
+        path: PathBuf,
+        traceback: String,
+        message: String,
+    },
+    /// The fixed file, or its backup, couldn't be written to `path`.
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl FixError {
+    /// The file that failed.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Parse { path, .. } | Self::Script { path, .. } | Self::Write { path, .. } => path,
+        }
+    }
+
+    /// Turn a failure fixing the file at `path` into a `FixError` if it's
+    /// one of those kinds, or leave it be.
+    pub(crate) fn classify(path: &Path, err: eyre::Report) -> eyre::Report {
+        if err.downcast_ref::<Self>().is_some() {
+            return err;
+        }
+        let path = path.to_owned();
+        let message = format!("{:#}", err);
+        // a script can fail on bad frontmatter it read, so scripts first
+        if let Some(lua) = err.chain().find_map(|e| e.downcast_ref::<mlua::Error>()) {
+            return Self::Script {
+                path,
+                traceback: lua.to_string(),
+                message,
+            }
+            .into();
+        }
+        match err.chain().find_map(|e| e.downcast_ref::<SyntaxError>()) {
+            Some(syntax) => Self::Parse {
+                path,
+                line: syntax.line,
+                column: syntax.column,
+                message,
+            }
+            .into(),
+            None => err,
+        }
+    }
+
+    /// Turn a failure writing to `path` into a `FixError::Write`.
+    pub(crate) fn write(path: &Path, err: eyre::Report) -> eyre::Report {
+        let kind = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<io::Error>())
+            .map_or(io::ErrorKind::Other, io::Error::kind);
+        Self::Write {
+            path: path.to_owned(),
+            source: io::Error::new(kind, format!("{:#}", err)),
+        }
+        .into()
+    }
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse { message, .. } | Self::Script { message, .. } => f.write_str(message),
+            Self::Write { path, .. } => write!(f, "couldn't write {}", path.display()),
+        }
+    }
+}
+
+impl Error for FixError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Write { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use eyre::Context;
+
+    use super::*;
+    use crate::frontmatter;
+
+    #[test]
+    fn classifies_failures() {
+        let path = Path::new("a.md");
+        let (frontmatter, _) = frontmatter::parse("---\ntitle: [\n---\n");
+        let err = frontmatter
+            .unwrap()
+            .context("couldn't parse frontmatter")
+            .unwrap_err();
+        match FixError::classify(path, err).downcast_ref::<FixError>() {
+            Some(FixError::Parse { line, message, .. }) => {
+                assert_eq!(Some(3), *line);
+                assert!(
+                    message.starts_with("couldn't parse frontmatter: "),
+                    "{}",
+                    message
+                );
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let lua = mlua::Lua::new();
+        let err = eyre::Report::new(lua.load("error('oops')").exec().unwrap_err());
+        let err = FixError::classify(path, err.wrap_err("error in Lua script"));
+        assert!(matches!(
+            err.downcast_ref::<FixError>(),
+            Some(FixError::Script { traceback, .. }) if traceback.contains("oops")
+        ));
+
+        let err = eyre::Report::new(io::Error::from(io::ErrorKind::PermissionDenied));
+        match FixError::write(path, err.wrap_err("couldn't modify file")).downcast_ref() {
+            Some(FixError::Write { source, .. }) => {
+                assert_eq!(io::ErrorKind::PermissionDenied, source.kind())
+            }
+            other => panic!("expected a write error, got {:?}", other),
+        }
+
+        let other = FixError::classify(path, eyre::eyre!("would delete file"));
+        assert!(other.downcast_ref::<FixError>().is_none());
+    }
+}
@@ -0,0 +1,113 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{batch::Processed, provenance::Transform};
+
+/// How many files to try before a `--confirm-over` run.
+pub const SAMPLE_SIZE: usize = 50;
+
+/// What a run would do, going by what it did to a sample of the files, so
+/// `--confirm-over` can ask before a run changes more files than expected.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Estimate {
+    /// Every file the run would process.
+    pub matched: usize,
+    pub sampled: usize,
+    /// Sampled files that would change.
+    pub changed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Sampled files each transform would change keys in.
+    pub by_transform: BTreeMap<Transform, usize>,
+}
+
+impl Estimate {
+    /// Count the outcome of processing a sampled file.
+    pub fn record(&mut self, result: &eyre::Result<Processed>) {
+        self.sampled += 1;
+        match result {
+            Ok(processed) if processed.skipped.is_some() => self.skipped += 1,
+            Ok(processed) => {
+                if processed.changed() {
+                    self.changed += 1;
+                }
+                for transform in &processed.touched_by {
+                    *self.by_transform.entry(*transform).or_default() += 1;
+                }
+            }
+            Err(_) => self.failed += 1,
+        }
+    }
+
+    /// How many files would change, scaling up from the sample.
+    pub fn changes(&self) -> usize {
+        self.scale(self.changed)
+    }
+
+    /// A count of sampled files scaled up to every file, rounding up so
+    /// that a change seen at all isn't estimated as none.
+    fn scale(&self, count: usize) -> usize {
+        if self.sampled == 0 {
+            return 0;
+        }
+        (count * self.matched).div_ceil(self.sampled)
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} files matched; of {} tried, {} would change, {} be skipped and {} fail",
+            self.matched, self.sampled, self.changed, self.skipped, self.failed
+        )?;
+        for (transform, count) in &self.by_transform {
+            writeln!(
+                f,
+                "  {}: about {} files ({} tried)",
+                transform,
+                self.scale(*count),
+                count
+            )?;
+        }
+        write!(f, "estimated {} files would change", self.changes())
+    }
+}
+
+/// Up to `size` of `items`, spread evenly through them.
+pub fn sample<T>(items: &[T], size: usize) -> Vec<&T> {
+    if items.len() <= size {
+        return items.iter().collect();
+    }
+    (0..size).map(|i| &items[i * items.len() / size]).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn samples_evenly() {
+        let items: Vec<usize> = (0..10).collect();
+        assert_eq!(vec![&0, &2, &4, &6, &8], sample(&items, 5));
+        assert_eq!(3, sample(&items[..3], 5).len());
+    }
+
+    #[test]
+    fn scales_up_sample() {
+        let estimate = Estimate {
+            matched: 1000,
+            sampled: 50,
+            changed: 20,
+            skipped: 1,
+            failed: 0,
+            by_transform: [(Transform::Script, 20), (Transform::Coerce, 1)].into(),
+        };
+        assert_eq!(400, estimate.changes());
+        assert_eq!(
+            "1000 files matched; of 50 tried, 20 would change, 1 be skipped and 0 fail\n  \
+             script: about 400 files (20 tried)\n  coerce: about 20 files (1 tried)\n\
+             estimated 400 files would change",
+            estimate.to_string()
+        );
+    }
+}
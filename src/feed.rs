@@ -0,0 +1,402 @@
+use std::{fs::read_to_string, path::Path, str::FromStr};
+
+use eyre::{eyre, Context};
+use serde::Serialize;
+use serde_yaml as yaml;
+
+use crate::{
+    date::Date,
+    frontmatter,
+    report::escape_xml,
+    walk::{self, Filter},
+};
+
+/// Formats a feed can be generated in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FromStr for FeedFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rss" => Ok(Self::Rss),
+            "atom" => Ok(Self::Atom),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown feed format {:?}, expected rss, atom or json",
+                s
+            )),
+        }
+    }
+}
+
+/// The feed as a whole.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub title: String,
+    /// The site's URL.
+    pub link: String,
+    pub description: String,
+    pub author: Option<String>,
+}
+
+/// Which frontmatter keys hold what each item needs, and how to make its
+/// link.
+#[derive(Clone, Debug)]
+pub struct Fields {
+    pub title_key: String,
+    pub date_key: String,
+    pub description_key: String,
+    /// A URL with `{KEY}` placeholders for frontmatter values, as well as
+    /// `{stem}` for the file name without its extension and `{year}`,
+    /// `{month}` and `{day}` from the date, e.g.
+    /// `https://example.com/{year}/{month}/{slug}/`.
+    pub permalink: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    pub title: String,
+    pub link: String,
+    pub date: Date,
+    pub description: Option<String>,
+}
+
+/// Read the items for a feed from the files in `paths`, searching any
+/// directories recursively, newest first and at most `limit` of them.
+///
+/// Files without frontmatter or without a date are left out.
+pub fn collect_items(
+    paths: &[String],
+    fields: &Fields,
+    limit: Option<usize>,
+) -> eyre::Result<Vec<Item>> {
+    let mut items = Vec::new();
+    for path in walk::expand(paths, &Filter::default())? {
+        let source = read_to_string(&path).context(format!("couldn't read {}", path))?;
+        let metadata = match frontmatter::parse(&source).0 {
+            Some(metadata) => {
+                metadata.context(format!("couldn't parse frontmatter in {}", path))?
+            }
+            None => continue,
+        };
+        if let Some(item) = item(Path::new(&path), &metadata, fields)
+            .context(format!("couldn't make feed item for {}", path))?
+        {
+            items.push(item);
+        }
+    }
+    items.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+    if let Some(limit) = limit {
+        items.truncate(limit);
+    }
+    Ok(items)
+}
+
+fn item(path: &Path, metadata: &yaml::Value, fields: &Fields) -> eyre::Result<Option<Item>> {
+    let date = match metadata
+        .get(&fields.date_key)
+        .and_then(yaml::Value::as_str)
+        .and_then(Date::parse)
+    {
+        Some(date) => date,
+        None => return Ok(None),
+    };
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let title = metadata
+        .get(&fields.title_key)
+        .and_then(scalar)
+        .unwrap_or_else(|| stem.clone());
+    let description = metadata.get(&fields.description_key).and_then(scalar);
+    let link = expand_permalink(&fields.permalink, |placeholder| match placeholder {
+        "stem" => Some(stem.clone()),
+        "year" => Some(format!("{:04}", date.year)),
+        "month" => Some(format!("{:02}", date.month)),
+        "day" => Some(format!("{:02}", date.day)),
+        key => metadata.get(key).and_then(scalar),
+    })?;
+    Ok(Some(Item {
+        title,
+        link,
+        date,
+        description,
+    }))
+}
+
+/// A scalar as a string, or `None` for anything else.
+pub(crate) fn scalar(value: &yaml::Value) -> Option<String> {
+    match value {
+        yaml::Value::String(s) => Some(s.clone()),
+        yaml::Value::Number(n) => Some(n.to_string()),
+        yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+pub(crate) fn expand_permalink(
+    pattern: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> eyre::Result<String> {
+    let mut link = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        link.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| eyre!("unclosed {{ in permalink {:?}", pattern))?;
+        let placeholder = &rest[start + 1..start + end];
+        let value = lookup(placeholder).ok_or_else(|| eyre!("no value for {{{}}}", placeholder))?;
+        link.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    link.push_str(rest);
+    Ok(link)
+}
+
+/// Render the feed.
+pub fn render(format: FeedFormat, channel: &Channel, items: &[Item]) -> eyre::Result<String> {
+    match format {
+        FeedFormat::Rss => Ok(render_rss(channel, items)),
+        FeedFormat::Atom => Ok(render_atom(channel, items)),
+        FeedFormat::Json => render_json(channel, items),
+    }
+}
+
+fn render_rss(channel: &Channel, items: &[Item]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<rss version=\"2.0\">\n  <channel>\n");
+    out.push_str(&format!(
+        "    <title>{}</title>\n",
+        escape_xml(&channel.title)
+    ));
+    out.push_str(&format!("    <link>{}</link>\n", escape_xml(&channel.link)));
+    out.push_str(&format!(
+        "    <description>{}</description>\n",
+        escape_xml(&channel.description)
+    ));
+    for item in items {
+        let link = escape_xml(&item.link);
+        out.push_str("    <item>\n");
+        out.push_str(&format!(
+            "      <title>{}</title>\n",
+            escape_xml(&item.title)
+        ));
+        out.push_str(&format!("      <link>{}</link>\n", link));
+        out.push_str(&format!(
+            "      <guid isPermaLink=\"true\">{}</guid>\n",
+            link
+        ));
+        out.push_str(&format!("      <pubDate>{}</pubDate>\n", rfc822(item.date)));
+        if let Some(description) = &item.description {
+            out.push_str(&format!(
+                "      <description>{}</description>\n",
+                escape_xml(description)
+            ));
+        }
+        out.push_str("    </item>\n");
+    }
+    out.push_str("  </channel>\n</rss>\n");
+    out
+}
+
+fn render_atom(channel: &Channel, items: &[Item]) -> String {
+    let updated = items
+        .iter()
+        .map(|item| item.date)
+        .max()
+        .unwrap_or_else(Date::today);
+    // Atom requires an author, so fall back on the feed's title
+    let author = channel.author.as_deref().unwrap_or(&channel.title);
+    let link = escape_xml(&channel.link);
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str(&format!(
+        "  <title>{}</title>\n",
+        escape_xml(&channel.title)
+    ));
+    if !channel.description.is_empty() {
+        out.push_str(&format!(
+            "  <subtitle>{}</subtitle>\n",
+            escape_xml(&channel.description)
+        ));
+    }
+    out.push_str(&format!("  <link href=\"{}\"/>\n", link));
+    out.push_str(&format!("  <id>{}</id>\n", link));
+    out.push_str(&format!("  <updated>{}</updated>\n", rfc3339(updated)));
+    out.push_str(&format!(
+        "  <author>\n    <name>{}</name>\n  </author>\n",
+        escape_xml(author)
+    ));
+    for item in items {
+        let link = escape_xml(&item.link);
+        out.push_str("  <entry>\n");
+        out.push_str(&format!("    <title>{}</title>\n", escape_xml(&item.title)));
+        out.push_str(&format!("    <link href=\"{}\"/>\n", link));
+        out.push_str(&format!("    <id>{}</id>\n", link));
+        out.push_str(&format!("    <updated>{}</updated>\n", rfc3339(item.date)));
+        if let Some(description) = &item.description {
+            out.push_str(&format!(
+                "    <summary>{}</summary>\n",
+                escape_xml(description)
+            ));
+        }
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+fn render_json(channel: &Channel, items: &[Item]) -> eyre::Result<String> {
+    #[derive(Serialize)]
+    struct Feed<'a> {
+        version: &'static str,
+        title: &'a str,
+        home_page_url: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<&'a str>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        authors: Vec<Author<'a>>,
+        items: Vec<FeedItem<'a>>,
+    }
+    #[derive(Serialize)]
+    struct Author<'a> {
+        name: &'a str,
+    }
+    #[derive(Serialize)]
+    struct FeedItem<'a> {
+        id: &'a str,
+        url: &'a str,
+        title: &'a str,
+        content_text: &'a str,
+        date_published: String,
+    }
+
+    let feed = Feed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: &channel.title,
+        home_page_url: &channel.link,
+        description: Some(channel.description.as_str()).filter(|d| !d.is_empty()),
+        authors: channel.author.iter().map(|name| Author { name }).collect(),
+        items: items
+            .iter()
+            .map(|item| FeedItem {
+                id: &item.link,
+                url: &item.link,
+                title: &item.title,
+                content_text: item.description.as_deref().unwrap_or_default(),
+                date_published: rfc3339(item.date),
+            })
+            .collect(),
+    };
+    let mut json = serde_json::to_string_pretty(&feed)?;
+    json.push('\n');
+    Ok(json)
+}
+
+fn rfc822(date: Date) -> String {
+    date.format("%a, %d %b %Y 00:00:00 +0000")
+        .expect("format should be valid")
+}
+
+fn rfc3339(date: Date) -> String {
+    date.format("%Y-%m-%dT00:00:00Z")
+        .expect("format should be valid")
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+
+    fn fields() -> Fields {
+        Fields {
+            title_key: "title".into(),
+            date_key: "date".into(),
+            description_key: "description".into(),
+            permalink: "https://example.com/{year}/{slug}/".into(),
+        }
+    }
+
+    #[test]
+    fn collects_newest_items_first() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        create_dir_all(dir.path().join("posts"))?;
+        for (name, source) in [
+            (
+                "old.md",
+                "---\ntitle: Old\ndate: 2022-01-02\nslug: old\n---\n",
+            ),
+            (
+                "new.md",
+                "---\ntitle: New & Shiny\ndate: 2023-03-05\nslug: new\n---\n",
+            ),
+            (
+                "newer.md",
+                "---\ntitle: Newer\ndate: 2023-04-01\nslug: newer\n---\n",
+            ),
+            ("undated.md", "---\ntitle: Undated\n---\n"),
+            ("plain.md", "no frontmatter\n"),
+        ] {
+            write(dir.path().join("posts").join(name), source)?;
+        }
+        let root = dir.path().display().to_string();
+        let items = collect_items(&[root], &fields(), Some(2))?;
+        assert_eq!(
+            vec![
+                "https://example.com/2023/newer/",
+                "https://example.com/2023/new/"
+            ],
+            items
+                .iter()
+                .map(|item| item.link.as_str())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn renders_feeds() -> eyre::Result<()> {
+        let channel = Channel {
+            title: "Blog".into(),
+            link: "https://example.com/".into(),
+            description: "Posts".into(),
+            author: None,
+        };
+        let items = [Item {
+            title: "New & Shiny".into(),
+            link: "https://example.com/2023/new/".into(),
+            date: Date::new(2023, 3, 5).unwrap(),
+            description: Some("All about it".into()),
+        }];
+
+        let rss = render(FeedFormat::Rss, &channel, &items)?;
+        assert!(rss.contains("<title>New &amp; Shiny</title>"));
+        assert!(rss.contains("<pubDate>Sun, 05 Mar 2023 00:00:00 +0000</pubDate>"));
+
+        let atom = render(FeedFormat::Atom, &channel, &items)?;
+        assert!(atom.contains("<updated>2023-03-05T00:00:00Z</updated>"));
+        assert!(atom.contains("<name>Blog</name>"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(FeedFormat::Json, &channel, &items)?)?;
+        assert_eq!("https://example.com/2023/new/", json["items"][0]["id"]);
+        assert_eq!("2023-03-05T00:00:00Z", json["items"][0]["date_published"]);
+        Ok(())
+    }
+
+    #[test]
+    fn requires_permalink_values() {
+        let metadata: yaml::Value = yaml::from_str("date: 2023-03-05\n").unwrap();
+        assert!(item(Path::new("a.md"), &metadata, &fields()).is_err());
+    }
+}
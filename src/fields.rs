@@ -0,0 +1,220 @@
+use chrono::NaiveDate;
+use eyre::eyre;
+use serde::{Deserialize, Serialize};
+use serde_yaml as yaml;
+
+use crate::{date::Date, feed::scalar, frontmatter, keypath};
+
+/// A document's frontmatter, a mapping, with typed accessors for the keys
+/// most sites use, so programs needn't walk [`yaml::Value`]s by hand.
+///
+/// Keys are dotted paths into nested mappings, as in scripts and queries,
+/// and setting a key keeps the others in their order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "yaml::Value", into = "yaml::Value")]
+pub struct Frontmatter(yaml::Value);
+
+impl Frontmatter {
+    /// Empty frontmatter.
+    pub fn new() -> Self {
+        Self(yaml::Value::Mapping(yaml::Mapping::new()))
+    }
+
+    /// Parse a document's frontmatter, returning it, `None` if it has none,
+    /// and the content after it.
+    pub fn parse(document: &str) -> eyre::Result<(Option<Self>, &str)> {
+        let (metadata, content) = frontmatter::parse(document);
+        let metadata = metadata.transpose()?.map(Self::try_from).transpose()?;
+        Ok((metadata, content))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&yaml::Value> {
+        keypath::get(&self.0, key)
+    }
+
+    /// The value at `key` if it's a string.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(yaml::Value::as_str)
+    }
+
+    /// Set the value at `key`, creating mappings along the way; fails if
+    /// part of the path is something other than a mapping.
+    pub fn set(&mut self, key: &str, value: impl Into<yaml::Value>) -> eyre::Result<()> {
+        keypath::set(&mut self.0, key, value.into())
+            .map_err(|_| eyre!("can't set {}: part of the path isn't a mapping", key))
+    }
+
+    /// Remove the value at `key`, returning it.
+    pub fn remove(&mut self, key: &str) -> Option<yaml::Value> {
+        keypath::remove(&mut self.0, key)
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get_str("title")
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.set_top("title", title.into());
+    }
+
+    /// The `date`, in any of the formats [`Date::parse`] accepts, ignoring
+    /// any time of day.
+    pub fn date(&self) -> Option<NaiveDate> {
+        let date = self.get_str("date").and_then(Date::parse)?;
+        NaiveDate::from_ymd_opt(date.year, date.month, date.day)
+    }
+
+    /// Set the `date`, as `YYYY-MM-DD`.
+    pub fn set_date(&mut self, date: NaiveDate) {
+        self.set_top("date", date.format("%Y-%m-%d").to_string().into());
+    }
+
+    /// The `tags`, from a list, or from a string of comma-separated tags as
+    /// hand-written frontmatter often has them. Numbers and booleans count
+    /// as tags too.
+    pub fn tags(&self) -> Vec<String> {
+        match self.get("tags") {
+            Some(yaml::Value::Sequence(tags)) => tags.iter().filter_map(scalar).collect(),
+            Some(yaml::Value::String(tags)) => tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            Some(other) => scalar(other).into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Set the `tags`, as a list.
+    pub fn set_tags<S: Into<String>>(&mut self, tags: impl IntoIterator<Item = S>) {
+        let tags = tags.into_iter().map(|tag| yaml::Value::String(tag.into()));
+        self.set_top("tags", yaml::Value::Sequence(tags.collect()));
+    }
+
+    /// Add `tag` to the `tags` unless it's there already, returning whether
+    /// it was added. Tags given as a string become a list.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        let mut tags = self.tags();
+        if tags.iter().any(|existing| existing == tag) {
+            return false;
+        }
+        tags.push(tag.to_owned());
+        self.set_tags(tags);
+        true
+    }
+
+    /// Remove `tag` from the `tags`, returning whether it was there.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let mut tags = self.tags();
+        let before = tags.len();
+        tags.retain(|existing| existing != tag);
+        if tags.len() == before {
+            return false;
+        }
+        self.set_tags(tags);
+        true
+    }
+
+    /// Whether `draft` is set, as a boolean or the string `"true"`.
+    pub fn draft(&self) -> bool {
+        match self.get("draft") {
+            Some(yaml::Value::Bool(draft)) => *draft,
+            Some(yaml::Value::String(draft)) => draft.trim().eq_ignore_ascii_case("true"),
+            _ => false,
+        }
+    }
+
+    pub fn set_draft(&mut self, draft: bool) {
+        self.set_top("draft", draft.into());
+    }
+
+    pub fn slug(&self) -> Option<&str> {
+        self.get_str("slug")
+    }
+
+    pub fn set_slug(&mut self, slug: &str) {
+        self.set_top("slug", slug.into());
+    }
+
+    pub fn as_value(&self) -> &yaml::Value {
+        &self.0
+    }
+
+    pub fn into_value(self) -> yaml::Value {
+        self.0
+    }
+
+    /// Set a top-level key, which can't fail since the frontmatter is
+    /// always a mapping.
+    fn set_top(&mut self, key: &str, value: yaml::Value) {
+        if let Some(mapping) = self.0.as_mapping_mut() {
+            mapping.insert(key.into(), value);
+        }
+    }
+}
+
+impl Default for Frontmatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TryFrom<yaml::Value> for Frontmatter {
+    type Error = eyre::Report;
+
+    /// Frontmatter from a mapping, or `null`, which is empty.
+    fn try_from(value: yaml::Value) -> eyre::Result<Self> {
+        match value {
+            yaml::Value::Mapping(_) => Ok(Self(value)),
+            yaml::Value::Null => Ok(Self::new()),
+            other => Err(eyre!(
+                "expected frontmatter to be a mapping, got {:?}",
+                other
+            )),
+        }
+    }
+}
+
+impl From<Frontmatter> for yaml::Value {
+    fn from(frontmatter: Frontmatter) -> Self {
+        frontmatter.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_common_keys() -> eyre::Result<()> {
+        let (frontmatter, content) = Frontmatter::parse(
+            "---\ntitle: Hello\ndate: January 31, 2023\ntags: rust, lua\ndraft: 'true'\n---\n\
+             Body\n",
+        )?;
+        let mut frontmatter = frontmatter.unwrap();
+        assert_eq!("Body\n", content);
+        assert_eq!(Some("Hello"), frontmatter.title());
+        assert_eq!(NaiveDate::from_ymd_opt(2023, 1, 31), frontmatter.date());
+        assert_eq!(vec!["rust", "lua"], frontmatter.tags());
+        assert!(frontmatter.draft());
+
+        frontmatter.set_title("Hi");
+        frontmatter.set_date(NaiveDate::from_ymd_opt(2023, 2, 1).unwrap());
+        assert!(frontmatter.add_tag("wasm"));
+        assert!(!frontmatter.add_tag("rust"));
+        assert!(frontmatter.remove_tag("lua"));
+        frontmatter.set_draft(false);
+        frontmatter.set("author.name", "Me")?;
+        assert!(frontmatter.set("title.sub", "x").is_err());
+        assert_eq!(
+            "title: Hi\ndate: 2023-02-01\ntags:\n- rust\n- wasm\ndraft: false\n\
+             author:\n  name: Me\n",
+            yaml::to_string(&frontmatter)?
+        );
+        let round_tripped: Frontmatter = yaml::from_str(&yaml::to_string(&frontmatter)?)?;
+        assert_eq!(frontmatter, round_tripped);
+        assert!(yaml::from_str::<Frontmatter>("[a, b]").is_err());
+        Ok(())
+    }
+}
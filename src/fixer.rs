@@ -0,0 +1,1803 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use eyre::{eyre, Context};
+use mlua::{Debug, DebugEvent, Function, HookTriggers, Lua, LuaOptions, RegistryKey, StdLib};
+use serde_yaml as yaml;
+
+use crate::{
+    date,
+    frontmatter::{self, Format},
+    helpers,
+    inherit::Inheritance,
+    locales::Locales,
+    lua_yaml,
+    permalink::Permalink,
+    repl::{Repl, Step},
+    snapshot::Change,
+    tally::Tally,
+};
+
+/// The registry slot `set_content` stashes replacement content in.
+const NEW_CONTENT: &str = "frontmatter_fixer_new_content";
+
+/// The registry slot `skip` flags the current document in.
+const SKIP: &str = "frontmatter_fixer_skip";
+
+/// The registry slot `rename_to` stashes the current document's new path in.
+const RENAME_TO: &str = "frontmatter_fixer_rename_to";
+
+/// Lua instructions a sandboxed script may run on each document, unless
+/// told otherwise.
+const INSTRUCTION_LIMIT: u64 = 1_000_000_000;
+
+/// How many instructions a script runs between checks of its instruction
+/// count and running time.
+const INSTRUCTION_CHECK_INTERVAL: u32 = 100_000;
+
+/// Bytes of memory a sandboxed Lua state may use, unless told otherwise.
+const MEMORY_LIMIT: usize = 512 * MB;
+
+const MB: usize = 1024 * 1024;
+
+/// Globals a sandboxed script can't use, since they reach outside it.
+const UNSAFE_GLOBALS: [&str; 4] = ["dofile", "loadfile", "load", "require"];
+
+/// Functions a sandboxed script can't use from the `os` table; it keeps
+/// `clock`, `date`, `difftime` and `time`.
+const UNSAFE_OS_FUNCTIONS: [&str; 7] = [
+    "execute",
+    "exit",
+    "getenv",
+    "remove",
+    "rename",
+    "setlocale",
+    "tmpname",
+];
+
+/// The registry slot `require` caches the modules it's loaded in.
+const LOADED_MODULES: &str = "frontmatter_fixer_loaded_modules";
+
+/// Runs a Lua script over each document's frontmatter.
+///
+/// Scripts see the document body as `content`, and its lines as the array
+/// `content_lines`, both read-only; to rewrite the body they must call
+/// `set_content(new_content)` or `replace_lines(from, to, new_lines)`. A
+/// script calls `skip()` to leave the document exactly as it was, and can
+/// use the helpers in the `fm` table (see `helpers::register`). When
+/// fixing a file, scripts also see a `file` table with its `path`,
+/// `basename` and `mtime`, `ctime` and `birthtime` timestamps where the
+/// platform has them, and can call `rename_to(new_path)` to move it, when
+/// sandboxed only to a relative path within its corpus. With
+/// `ScriptOptions::locales`, the table also has the file's `locale` and its
+/// `variants` in other locales, each a table of `path` and `meta` keyed by
+/// locale. With `ScriptOptions::permalink`, `permalink([path[, meta]])`
+/// computes a file's URL, by default the current file's, and likewise
+/// `resolve_extends([path[, meta]])` gives a file's frontmatter with what
+/// it inherits through `extends` filled in (see `Inheritance`).
+///
+/// The frontmatter's text, as written, is in `meta_raw`, and `yaml_load`
+/// is the inverse of `yaml_dump`. A document whose frontmatter doesn't
+/// parse fails, unless `ScriptOptions::repair` is set: then scripts run
+/// with `meta` nil and the parse error in `meta_error`, and the document
+/// only fails if they leave `meta` nil.
+///
+/// An aggregating script (see `Fixer::aggregating`) instead defines
+/// functions, `collect(meta, file)` called on every document first, and
+/// `fix()` called on each document as a plain script would be run, with
+/// whatever `collect` gathered into the global `state` table, and with a
+/// `corpus` table for asking how often keys and values turn up across all
+/// the files (see `Fixer::set_corpus`). A references
+/// script (see `Fixer::rewriting`) defines `rewrite_references(content,
+/// changes, file)`, which gets a whole file's text to update for how other
+/// files' frontmatter changed.
+///
+/// Scripts can `require` modules from the directories in their
+/// `ScriptOptions::lua_path`, so `require 'lib.dates'` loads
+/// `lib/dates.lua`.
+///
+/// Unless told otherwise, scripts run sandboxed: without the `io` library,
+/// `os` functions beyond telling the time, or the means to load other
+/// code, and stopped if they run too long on a document or use too much
+/// memory. `ScriptOptions::limits` changes how much is too much, and
+/// `Fixer::usage` says how much they used on the last document.
+pub struct Fixer {
+    lua: Lua,
+    /// Each compiled script, with its name.
+    scripts: Option<Vec<(String, RegistryKey)>>,
+    collect: Option<RegistryKey>,
+    /// A references script's `rewrite_references` function.
+    rewrite: Option<RegistryKey>,
+    /// What scripts have used on the current document.
+    meter: Arc<Meter>,
+    /// The REPL run instead of scripts, if there are none.
+    repl: Option<Repl>,
+    /// Whether scripts get to repair frontmatter that doesn't parse.
+    repair: bool,
+    locales: Option<Locales>,
+}
+
+/// How scripts run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptOptions {
+    /// Whether scripts run sandboxed, as they do by default.
+    pub sandbox: bool,
+    /// Directories `require` looks for modules in, in order.
+    pub lua_path: Vec<PathBuf>,
+    /// Whether to run scripts on documents whose frontmatter doesn't parse,
+    /// for them to repair it.
+    pub repair: bool,
+    /// Locales whose variants of each file scripts see; see [`Locales`].
+    pub locales: Vec<String>,
+    /// How scripts' `permalink()` computes URLs.
+    pub permalink: Option<Permalink>,
+    /// What scripts may use on each document.
+    pub limits: Limits,
+    /// Whether to record each line scripts run, for [`Fixer::trace`].
+    pub trace: bool,
+    /// Whether `print` and `yaml_dump` write to stderr, as they must while
+    /// stdout carries a filtered document.
+    pub print_to_stderr: bool,
+}
+
+/// What scripts may use on each document before it fails, each limit off
+/// if `None`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub timeout: Option<Duration>,
+    /// Bytes of memory the Lua state may grow to.
+    pub memory: Option<usize>,
+    pub instructions: Option<u64>,
+}
+
+impl Limits {
+    /// The limits sandboxed scripts run under unless told otherwise.
+    pub const SANDBOX: Self = Self {
+        timeout: None,
+        memory: Some(MEMORY_LIMIT),
+        instructions: Some(INSTRUCTION_LIMIT),
+    };
+
+    pub const NONE: Self = Self {
+        timeout: None,
+        memory: None,
+        instructions: None,
+    };
+}
+
+/// What scripts used on the last document they ran on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Lua instructions run, counted in steps of 100,000.
+    pub instructions: u64,
+    pub elapsed: Duration,
+    /// Bytes of memory the Lua state held afterwards.
+    pub memory: usize,
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{} instructions in {:.1?}, {:.1} MB of memory",
+            if self.instructions > 0 {
+                "about "
+            } else {
+                "under "
+            },
+            self.instructions.max(INSTRUCTION_CHECK_INTERVAL.into()),
+            self.elapsed,
+            self.memory as f64 / MB as f64
+        )
+    }
+}
+
+/// Counts what scripts use on each document, stopping them at their limits.
+#[derive(Debug)]
+struct Meter {
+    limits: Limits,
+    instructions: AtomicU64,
+    started: Mutex<Instant>,
+    elapsed: Mutex<Duration>,
+    /// The lines run on the current document, if tracing.
+    trace: Option<Mutex<Vec<String>>>,
+}
+
+impl Meter {
+    fn new(limits: Limits, trace: bool) -> Self {
+        Self {
+            limits,
+            instructions: AtomicU64::new(0),
+            started: Mutex::new(Instant::now()),
+            elapsed: Mutex::default(),
+            trace: trace.then(Mutex::default),
+        }
+    }
+
+    fn start(&self) {
+        self.instructions.store(0, Ordering::Relaxed);
+        *self.started.lock().expect("meter poisoned") = Instant::now();
+        if let Some(trace) = &self.trace {
+            trace.lock().expect("meter poisoned").clear();
+        }
+    }
+
+    /// Note the line a script is about to run, if tracing.
+    fn trace(&self, debug: &Debug) {
+        if let Some(trace) = &self.trace {
+            let source = debug.source();
+            let script = String::from_utf8_lossy(source.short_src.unwrap_or(b"?"));
+            let line = format!("{}:{}", script, debug.curr_line());
+            trace.lock().expect("meter poisoned").push(line);
+        }
+    }
+
+    fn finish(&self) {
+        *self.elapsed.lock().expect("meter poisoned") =
+            self.started.lock().expect("meter poisoned").elapsed();
+    }
+
+    /// Count another `INSTRUCTION_CHECK_INTERVAL` instructions, failing if
+    /// that's too many or they've taken too long.
+    fn check(&self) -> mlua::Result<()> {
+        let run = self
+            .instructions
+            .fetch_add(INSTRUCTION_CHECK_INTERVAL.into(), Ordering::Relaxed);
+        if let Some(limit) = self.limits.instructions.filter(|limit| run >= *limit) {
+            return Err(mlua::Error::external(format!(
+                "script ran over {} instructions on one document (see --script-instructions)",
+                limit
+            )));
+        }
+        let elapsed = self.started.lock().expect("meter poisoned").elapsed();
+        if let Some(limit) = self.limits.timeout.filter(|limit| elapsed >= *limit) {
+            return Err(mlua::Error::external(format!(
+                "script ran over {:?} on one document (see --script-timeout)",
+                limit
+            )));
+        }
+        Ok(())
+    }
+
+    /// Say which limit a script ran into if it ran out of memory, which Lua
+    /// doesn't.
+    fn explain(&self, err: mlua::Error) -> eyre::Report {
+        match (&err, self.limits.memory) {
+            (mlua::Error::MemoryError(_), Some(limit)) => eyre!(
+                "script used over {} MB of memory on one document (see --script-memory)",
+                limit / MB
+            ),
+            _ => err.into(),
+        }
+    }
+}
+
+impl Default for ScriptOptions {
+    fn default() -> Self {
+        Self {
+            sandbox: true,
+            lua_path: Vec::new(),
+            repair: false,
+            locales: Vec::new(),
+            permalink: None,
+            limits: Limits::SANDBOX,
+            trace: false,
+            print_to_stderr: false,
+        }
+    }
+}
+
+/// A Lua script to run, with the name errors in it are reported under, such
+/// as the file it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Script {
+    pub name: String,
+    pub source: String,
+}
+
+impl Fixer {
+    /// Compile `script` to run sandboxed, or with `None` read Lua from stdin
+    /// as a REPL for each document.
+    pub fn new(script: Option<&str>) -> eyre::Result<Self> {
+        match script {
+            Some(source) => Self::chained(
+                &[Script {
+                    name: String::new(),
+                    source: source.to_owned(),
+                }],
+                &ScriptOptions::default(),
+            ),
+            None => Self::repl(&ScriptOptions::default()),
+        }
+    }
+
+    /// Read Lua from stdin as a REPL for each document, going on to the next
+    /// when told to (see `repl::Repl`).
+    pub fn repl(options: &ScriptOptions) -> eyre::Result<Self> {
+        let (lua, meter) = setup_lua(options)?;
+        Ok(Self {
+            lua,
+            scripts: None,
+            collect: None,
+            rewrite: None,
+            meter,
+            repl: Some(Repl::default()),
+            repair: options.repair,
+            locales: locales(options),
+        })
+    }
+
+    /// Compile `scripts` to run one after another over each document, each
+    /// seeing the `meta` and content the one before left. Once one calls
+    /// `skip()`, the rest don't run.
+    pub fn chained(scripts: &[Script], options: &ScriptOptions) -> eyre::Result<Self> {
+        let (lua, meter) = setup_lua(options)?;
+        let scripts = scripts
+            .iter()
+            .map(|script| {
+                let chunk = lua.load(&script.source);
+                // so traced lines say which script they're in
+                let chunk = if options.trace {
+                    let name = match script.name.as_str() {
+                        "" => "script",
+                        name => name,
+                    };
+                    chunk.set_name(format!("={}", name))?
+                } else {
+                    chunk
+                };
+                let fun = chunk.into_function().context("lua script didn't compile");
+                let fun = in_script(&script.name, fun)?;
+                let key = lua
+                    .create_registry_value(fun)
+                    .expect("couldn't save precompiled script");
+                Ok((script.name.clone(), key))
+            })
+            .collect::<eyre::Result<_>>()?;
+        Ok(Self {
+            lua,
+            scripts: Some(scripts),
+            collect: None,
+            rewrite: None,
+            meter,
+            repl: None,
+            repair: options.repair,
+            locales: locales(options),
+        })
+    }
+
+    /// Compile an aggregating `script`, running it once to define its
+    /// `collect` and `fix` functions, with an empty `state`.
+    pub fn aggregating(script: &str, options: &ScriptOptions) -> eyre::Result<Self> {
+        let script = Script {
+            name: String::new(),
+            source: script.to_owned(),
+        };
+        let mut fixer = Self::chained(&[script], options)?;
+        let globals = fixer.lua.globals();
+        for (_, script) in fixer.scripts.iter().flatten() {
+            let script_fun: Function = fixer
+                .lua
+                .registry_value(script)
+                .expect("couldn't retrieve precompiled script");
+            script_fun
+                .call::<_, ()>(())
+                .map_err(|err| fixer.meter.explain(err))
+                .context("error in Lua script")?;
+        }
+        let collect: Function = globals
+            .get("collect")
+            .context("aggregating script must define a collect(meta, file) function")?;
+        let fix: Function = globals
+            .get("fix")
+            .context("aggregating script must define a fix() function")?;
+        globals
+            .set("state", fixer.lua.create_table()?)
+            .context("couldn't create Lua state")?;
+        fixer.collect = Some(fixer.lua.create_registry_value(collect)?);
+        let fix = fixer.lua.create_registry_value(fix)?;
+        // globals borrows the Lua state being returned
+        drop(globals);
+        fixer.scripts = Some(vec![(String::new(), fix)]);
+        Ok(fixer)
+    }
+
+    /// Compile a references `script`, running it once to define its
+    /// `rewrite_references(content, changes, file)` function.
+    pub fn rewriting(script: &Script, options: &ScriptOptions) -> eyre::Result<Self> {
+        let mut fixer = Self::chained(std::slice::from_ref(script), options)?;
+        for (name, script) in fixer.scripts.iter().flatten() {
+            let script_fun: Function = fixer
+                .lua
+                .registry_value(script)
+                .expect("couldn't retrieve precompiled script");
+            let called = script_fun
+                .call::<_, ()>(())
+                .map_err(|err| fixer.meter.explain(err))
+                .context("error in Lua script");
+            in_script(name, called)?;
+        }
+        let rewrite: Function = fixer.lua.globals().get("rewrite_references").context(
+            "references script must define a rewrite_references(content, changes, file) function",
+        )?;
+        fixer.rewrite = Some(fixer.lua.create_registry_value(rewrite)?);
+        fixer.scripts = None;
+        Ok(fixer)
+    }
+
+    /// Have a references script's `rewrite_references` function update the
+    /// whole of the file at `path`, `content`, for how other files'
+    /// frontmatter changed, returning the new contents if it changed them.
+    pub fn rewrite_references(
+        &self,
+        path: &Path,
+        content: &str,
+        changes: &[Change],
+    ) -> eyre::Result<Option<String>> {
+        let rewrite = match &self.rewrite {
+            Some(rewrite) => rewrite,
+            None => return Ok(None),
+        };
+        let to_lua = |value: &Option<yaml::Value>| {
+            value
+                .as_ref()
+                .map(|value| lua_yaml::to_lua(&self.lua, value))
+                .transpose()
+        };
+        let lua_changes = self.lua.create_table()?;
+        for change in changes {
+            if let Change::Key {
+                path,
+                key,
+                old,
+                new,
+            } = change
+            {
+                let entry = self.lua.create_table()?;
+                entry.set("path", path.as_str())?;
+                entry.set("key", key.as_str())?;
+                entry.set("old", to_lua(old)?)?;
+                entry.set("new", to_lua(new)?)?;
+                lua_changes.push(entry)?;
+            }
+        }
+        let rewrite_fun: Function = self
+            .lua
+            .registry_value(rewrite)
+            .expect("couldn't retrieve rewrite_references function");
+        self.meter.start();
+        let rewritten = rewrite_fun
+            .call::<_, Option<String>>((content, lua_changes, self.file_table(path)?))
+            .map_err(|err| self.meter.explain(err))
+            .context("error in Lua rewrite_references function");
+        self.meter.finish();
+        Ok(rewritten?.filter(|rewritten| rewritten != content))
+    }
+
+    /// Show a document from `path` to an aggregating script's `collect`
+    /// function.
+    pub fn collect(&self, path: &Path, content: &str, format: Option<Format>) -> eyre::Result<()> {
+        let collect = match &self.collect {
+            Some(collect) => collect,
+            None => return Ok(()),
+        };
+        let (metadata, _) = frontmatter::parse_as(content, format);
+        let metadata = metadata.transpose().context("couldn't parse frontmatter")?;
+        let lua_metadata = metadata
+            .as_ref()
+            .map(|metadata| lua_yaml::to_lua(&self.lua, metadata))
+            .transpose()
+            .context("couldn't convert metadata to Lua representation")?;
+        let collect_fun: Function = self
+            .lua
+            .registry_value(collect)
+            .expect("couldn't retrieve collect function");
+        self.meter.start();
+        let collected = collect_fun
+            .call::<_, ()>((lua_metadata, self.file_table(path)?))
+            .map_err(|err| self.meter.explain(err))
+            .context("error in Lua collect function");
+        self.meter.finish();
+        collected
+    }
+
+    /// What an aggregating script's `collect` function gathered.
+    pub fn state(&self) -> eyre::Result<yaml::Value> {
+        let state: mlua::Value = self
+            .lua
+            .globals()
+            .get("state")
+            .context("couldn't retrieve state from Lua")?;
+        lua_yaml::from_lua(&self.lua, state)
+            .context("couldn't convert state back from Lua representation")
+    }
+
+    /// Give an aggregating script's `fix` function the `state` gathered by
+    /// another `Fixer`.
+    pub fn set_state(&self, state: &yaml::Value) -> eyre::Result<()> {
+        let lua_state = lua_yaml::to_lua(&self.lua, state)
+            .context("couldn't convert state to Lua representation")?;
+        self.lua
+            .globals()
+            .set("state", lua_state)
+            .context("couldn't send state to Lua")
+    }
+
+    /// Give an aggregating script's `fix` function the global `corpus`
+    /// table, for asking how often keys (which may be dotted paths) and
+    /// values turn up across the files `tally` gathered:
+    ///
+    /// - `corpus.values(key)`: a table of how many files have each value,
+    ///   counting each item of a list, e.g. `corpus.values('tags').rust`
+    /// - `corpus.count(key, value)`: how many files have `value` for `key`,
+    ///   or a list with it, or with `value` omitted, any value
+    pub fn set_corpus(&self, tally: Arc<Tally>) -> eyre::Result<()> {
+        let corpus = self.lua.create_table()?;
+        // a script asks for the same key for each file, so count once
+        let counted: RefCell<HashMap<String, yaml::Mapping>> = RefCell::default();
+        let values_tally = Arc::clone(&tally);
+        let values = self.lua.create_function(move |lua, key: String| {
+            let mut counted = counted.borrow_mut();
+            let values = counted
+                .entry(key)
+                .or_insert_with_key(|key| values_tally.values(key));
+            lua_yaml::to_lua(lua, &yaml::Value::Mapping(values.clone()))
+        })?;
+        let count =
+            self.lua
+                .create_function(move |lua, (key, value): (String, Option<mlua::Value>)| {
+                    let value = value
+                        .map(|value| lua_yaml::from_lua(lua, value))
+                        .transpose()?;
+                    Ok(tally.count(&key, value.as_ref()))
+                })?;
+        corpus.set("values", values)?;
+        corpus.set("count", count)?;
+        self.lua
+            .globals()
+            .set("corpus", corpus)
+            .context("couldn't send corpus to Lua")
+    }
+
+    /// Run the script over a document, returning its altered frontmatter
+    /// and its content, which is only altered if the script called
+    /// `set_content`. `format` overrides the frontmatter's delimiters.
+    pub fn fix<'doc>(
+        &self,
+        content: &'doc str,
+        format: Option<Format>,
+    ) -> eyre::Result<(Option<yaml::Value>, Cow<'doc, str>)> {
+        let raw_metadata = frontmatter::parse_raw(content).0;
+        let (metadata, content) = frontmatter::parse_as(content, format);
+
+        let (metadata, parse_error) = match metadata.transpose() {
+            Ok(metadata) => (metadata, None),
+            // left for the scripts to repair
+            Err(err) if self.repair => (None, Some(err)),
+            Err(err) => return Err(err).context("couldn't parse frontmatter"),
+        };
+
+        let globals = self.lua.globals();
+        globals
+            .set("meta_raw", raw_metadata)
+            .context("couldn't send raw metadata to Lua")?;
+        globals
+            .set(
+                "meta_error",
+                parse_error.as_ref().map(|err| format!("{:#}", err)),
+            )
+            .context("couldn't send metadata error to Lua")?;
+        if let Some(metadata) = &metadata {
+            let lua_metadata = lua_yaml::to_lua(&self.lua, metadata)
+                .context("couldn't convert metadata to Lua representation")?;
+            globals
+                .set("meta", lua_metadata)
+                .context("couldn't send metadata to Lua")?;
+        } else {
+            // clear out previous file's meta
+            globals
+                .raw_remove("meta")
+                .context("couldn't clear Lua metadata")?;
+        }
+        send_content(&self.lua, content).context("couldn't send content to Lua")?;
+        self.lua
+            .unset_named_registry_value(NEW_CONTENT)
+            .context("couldn't clear previous file's content")?;
+        self.lua
+            .unset_named_registry_value(SKIP)
+            .context("couldn't clear previous file's skip")?;
+        self.lua
+            .unset_named_registry_value(RENAME_TO)
+            .context("couldn't clear previous file's rename")?;
+        self.lua
+            .unset_named_registry_value(helpers::KEY_ORDER)
+            .context("couldn't clear previous file's key order")?;
+
+        if let Some(scripts) = &self.scripts {
+            self.meter.start();
+            let ran = self.run_scripts(scripts);
+            self.meter.finish();
+            ran?;
+        } else {
+            let repl = self
+                .repl
+                .as_ref()
+                .expect("a fixer without scripts has a REPL");
+            let step = if repl.quit() {
+                Step::Skip
+            } else {
+                let name = globals
+                    .get::<_, Option<mlua::Table>>("file")?
+                    .and_then(|file| file.get::<_, Option<String>>("path").ok().flatten())
+                    .unwrap_or_else(|| "lua".to_owned());
+                repl.interact(&self.lua, &name)?
+            };
+            if step == Step::Skip {
+                self.lua
+                    .set_named_registry_value(SKIP, true)
+                    .context("couldn't skip")?;
+            }
+        }
+
+        let altered_lua_metadata = globals
+            .get("meta")
+            .context("couldn't retrieve metadata from Lua")?;
+        let altered_metadata = lua_yaml::from_lua_option(&self.lua, altered_lua_metadata)
+            .context("couldn't convert metadata back from Lua representation")?;
+        let mut altered_metadata = match (&metadata, altered_metadata) {
+            (Some(metadata), Some(altered_metadata)) => {
+                Some(restore_key_order(metadata, altered_metadata))
+            }
+            (_, None) if !self.skipped()? => {
+                if let Some(err) = parse_error {
+                    return Err(err).context("couldn't parse frontmatter");
+                }
+                None
+            }
+            (_, altered_metadata) => altered_metadata,
+        };
+        let key_order = helpers::key_order(&self.lua).context("couldn't retrieve key order")?;
+        if let (Some(order), Some(metadata)) = (key_order, altered_metadata.as_mut()) {
+            order.apply(metadata);
+        }
+        let altered_content: Option<String> = self
+            .lua
+            .named_registry_value(NEW_CONTENT)
+            .context("couldn't retrieve content from Lua")?;
+
+        let content = match altered_content {
+            Some(altered_content) => Cow::Owned(altered_content),
+            None => Cow::Borrowed(content),
+        };
+        Ok((altered_metadata, content))
+    }
+
+    fn run_scripts(&self, scripts: &[(String, RegistryKey)]) -> eyre::Result<()> {
+        for (name, script) in scripts {
+            let script_fun: Function = self
+                .lua
+                .registry_value(script)
+                .expect("couldn't retrieve precompiled script");
+            let called = script_fun
+                .call::<_, ()>(())
+                .map_err(|err| self.meter.explain(err))
+                .context("error in Lua script");
+            in_script(name, called)?;
+            if self.skipped()? {
+                break;
+            }
+            // so the next script sees the content this one left
+            let new_content: Option<String> = self
+                .lua
+                .named_registry_value(NEW_CONTENT)
+                .context("couldn't retrieve content from Lua")?;
+            if let Some(new_content) = new_content {
+                send_content(&self.lua, &new_content).context("couldn't send content to Lua")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `fix`, but also tells the script about the file the document
+    /// was read from.
+    pub fn fix_file<'doc>(
+        &self,
+        path: &Path,
+        content: &'doc str,
+        format: Option<Format>,
+    ) -> eyre::Result<(Option<yaml::Value>, Cow<'doc, str>)> {
+        let globals = self.lua.globals();
+        globals
+            .set("file", self.file_table(path)?)
+            .context("couldn't send file metadata to Lua")?;
+        let fixed = self.fix(content, format);
+        globals
+            .raw_remove("file")
+            .context("couldn't clear Lua file metadata")?;
+        fixed
+    }
+
+    /// Whether the script called `skip()` on the last document it fixed,
+    /// meaning whatever it returned should be thrown away.
+    pub fn skipped(&self) -> eyre::Result<bool> {
+        let skipped: Option<bool> = self
+            .lua
+            .named_registry_value(SKIP)
+            .context("couldn't retrieve skip from Lua")?;
+        Ok(skipped.unwrap_or(false))
+    }
+
+    /// Where the script asked to move the last document it fixed, as it
+    /// gave the path: relative paths are relative to the document's
+    /// directory.
+    pub fn renamed_to(&self) -> eyre::Result<Option<PathBuf>> {
+        let renamed_to: Option<String> = self
+            .lua
+            .named_registry_value(RENAME_TO)
+            .context("couldn't retrieve rename from Lua")?;
+        Ok(renamed_to.map(PathBuf::from))
+    }
+
+    /// Each line scripts ran on the last document, in order, as
+    /// `SCRIPT:LINE`, if `ScriptOptions::trace` asked for them.
+    pub fn trace(&self) -> Vec<String> {
+        match &self.meter.trace {
+            Some(trace) => trace.lock().expect("meter poisoned").clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// What scripts used on the last document they ran on.
+    pub fn usage(&self) -> Usage {
+        Usage {
+            instructions: self.meter.instructions.load(Ordering::Relaxed),
+            elapsed: *self.meter.elapsed.lock().expect("meter poisoned"),
+            memory: self.lua.used_memory(),
+        }
+    }
+
+    fn file_table(&self, path: &Path) -> eyre::Result<mlua::Table<'_>> {
+        let metadata = fs::metadata(path).context("couldn't read file metadata")?;
+        let file = self.lua.create_table()?;
+        file.set("path", path.to_string_lossy().as_ref())?;
+        if let Some(basename) = path.file_name() {
+            file.set("basename", basename.to_string_lossy().as_ref())?;
+        }
+        if let Ok(modified) = metadata.modified() {
+            file.set("mtime", date::system_timestamp(modified))?;
+        }
+        if let Ok(created) = metadata.created() {
+            file.set("birthtime", date::system_timestamp(created))?;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            file.set("ctime", date::format_timestamp(metadata.ctime()))?;
+        }
+        if let Some(locales) = &self.locales {
+            file.set("locale", locales.locale(path))?;
+            let variants = self.lua.create_table()?;
+            for variant in locales.variants(path) {
+                let entry = self.lua.create_table()?;
+                entry.set("path", variant.path.to_string_lossy().as_ref())?;
+                if let Some(meta) = &variant.meta {
+                    entry.set("meta", lua_yaml::to_lua(&self.lua, meta)?)?;
+                }
+                variants.set(variant.locale, entry)?;
+            }
+            file.set("variants", variants)?;
+        }
+        Ok(file)
+    }
+}
+
+fn locales(options: &ScriptOptions) -> Option<Locales> {
+    (!options.locales.is_empty()).then(|| Locales::new(options.locales.clone()))
+}
+
+/// Put keys back in the order they had before the script ran, since Lua
+/// tables don't keep any order. Keys the script added follow them, sorted.
+pub(crate) fn restore_key_order(original: &yaml::Value, altered: yaml::Value) -> yaml::Value {
+    match (original, altered) {
+        (yaml::Value::Mapping(original), yaml::Value::Mapping(mut altered)) => {
+            let mut ordered = yaml::Mapping::with_capacity(altered.len());
+            for (key, original_value) in original {
+                if let Some(value) = altered.remove(key) {
+                    ordered.insert(key.clone(), restore_key_order(original_value, value));
+                }
+            }
+            let mut added: Vec<_> = altered.into_iter().collect();
+            added.sort_by(|(a, _), (b, _)| a.as_str().cmp(&b.as_str()));
+            ordered.extend(added);
+            yaml::Value::Mapping(ordered)
+        }
+        (yaml::Value::Sequence(original), yaml::Value::Sequence(altered)) => {
+            let ordered = altered
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| match original.get(i) {
+                    Some(original_value) => restore_key_order(original_value, value),
+                    None => value,
+                })
+                .collect();
+            yaml::Value::Sequence(ordered)
+        }
+        (_, altered) => altered,
+    }
+}
+
+fn yaml_dump(v: &yaml::Value, to_stderr: bool) -> eyre::Result<()> {
+    let yaml = yaml::to_string(v)?;
+    if to_stderr {
+        eprintln!("{}", &yaml);
+    } else {
+        println!("{}", &yaml);
+    }
+    Ok(())
+}
+
+/// Set the `content` and `content_lines` globals.
+fn send_content(lua: &Lua, content: &str) -> mlua::Result<()> {
+    let lines = lua.create_sequence_from(split_lines(content))?;
+    let len = lines.raw_len();
+    let metatable = lua.create_table()?;
+    metatable.set("__index", lines)?;
+    metatable.set("__len", lua.create_function(move |_, ()| Ok(len))?)?;
+    metatable.set(
+        "__newindex",
+        lua.create_function(|_, ()| -> mlua::Result<()> {
+            Err(mlua::Error::external(
+                "content_lines is read-only, use replace_lines to edit content",
+            ))
+        })?,
+    )?;
+    let content_lines = lua.create_table()?;
+    content_lines.set_metatable(Some(metatable));
+
+    let globals = lua.globals();
+    globals.set("content", content)?;
+    globals.set("content_lines", content_lines)
+}
+
+fn lua_permalink(
+    lua: &Lua,
+    permalink: &Permalink,
+    args: (Option<String>, Option<mlua::Value>),
+) -> mlua::Result<Option<String>> {
+    let (path, meta) = path_and_meta(lua, "permalink", args)?;
+    Ok(permalink.url(Path::new(&path), meta.as_ref()))
+}
+
+fn lua_resolve_extends<'lua>(
+    lua: &'lua Lua,
+    inheritance: &Inheritance,
+    args: (Option<String>, Option<mlua::Value<'lua>>),
+) -> mlua::Result<mlua::Value<'lua>> {
+    let (path, meta) = path_and_meta(lua, "resolve_extends", args)?;
+    let Some(meta) = meta else {
+        return Ok(mlua::Value::Nil);
+    };
+    let resolved = inheritance
+        .resolve(Path::new(&path), &meta)
+        .map_err(|err| mlua::Error::external(format!("{:#}", err)))?;
+    lua_yaml::to_lua(lua, resolved.as_ref().unwrap_or(&meta))
+}
+
+/// The path and frontmatter a `function([path[, meta]])` was called with,
+/// by default the current file's.
+fn path_and_meta(
+    lua: &Lua,
+    function: &str,
+    (path, meta): (Option<String>, Option<mlua::Value>),
+) -> mlua::Result<(String, Option<yaml::Value>)> {
+    let globals = lua.globals();
+    let path = match path {
+        Some(path) => path,
+        None => globals
+            .get::<_, Option<mlua::Table>>("file")?
+            .map(|file| file.get::<_, String>("path"))
+            .transpose()?
+            .ok_or_else(|| {
+                mlua::Error::external(format!("{}() needs a path outside a file", function))
+            })?,
+    };
+    let meta: Option<yaml::Value> = match meta {
+        Some(meta) => lua_yaml::from_lua_option(lua, meta)?,
+        None => lua_yaml::from_lua_option(lua, globals.get("meta")?)?,
+    };
+    Ok((path, meta))
+}
+
+fn lua_set_content(lua: &Lua, new_content: String) -> mlua::Result<()> {
+    send_content(lua, &new_content)?;
+    lua.set_named_registry_value(NEW_CONTENT, new_content)
+}
+
+fn lua_replace_lines(
+    lua: &Lua,
+    (from, to, new_lines): (usize, usize, Vec<String>),
+) -> mlua::Result<()> {
+    let content: String = lua.globals().get("content")?;
+    let new_content =
+        replace_lines(&content, from, to, &new_lines).map_err(mlua::Error::external)?;
+    lua_set_content(lua, new_content)
+}
+
+/// Lines without their terminators.
+fn split_lines(content: &str) -> Vec<&str> {
+    content
+        .split_inclusive('\n')
+        .map(|line| line.strip_suffix('\n').unwrap_or(line))
+        .collect()
+}
+
+/// A Lua state with the globals and helpers every script can use, held to
+/// `options.limits` by the returned meter, which traces it if
+/// `options.trace`.
+fn setup_lua(options: &ScriptOptions) -> eyre::Result<(Lua, Arc<Meter>)> {
+    let lua = if options.sandbox {
+        sandboxed_lua()?
+    } else {
+        Lua::new()
+    };
+    let meter = limit(&lua, options.limits, options.trace)?;
+    let to_stderr = options.print_to_stderr;
+    let dump_fun = lua
+        .create_function(move |lua, v| lua_yaml_dump(lua, v, to_stderr))
+        .context("couldn't create yaml_dump function")?;
+    lua.globals()
+        .set("yaml_dump", dump_fun)
+        .context("couldn't register yaml_dump function")?;
+    if to_stderr {
+        let print_fun = lua
+            .create_function(lua_eprint)
+            .context("couldn't create print function")?;
+        lua.globals()
+            .set("print", print_fun)
+            .context("couldn't register print function")?;
+    }
+
+    let load_fun = lua
+        .create_function(lua_yaml_load)
+        .context("couldn't create yaml_load function")?;
+    lua.globals()
+        .set("yaml_load", load_fun)
+        .context("couldn't register yaml_load function")?;
+
+    let set_content_fun = lua
+        .create_function(lua_set_content)
+        .context("couldn't create set_content function")?;
+    lua.globals()
+        .set("set_content", set_content_fun)
+        .context("couldn't register set_content function")?;
+
+    let replace_lines_fun = lua
+        .create_function(lua_replace_lines)
+        .context("couldn't create replace_lines function")?;
+    lua.globals()
+        .set("replace_lines", replace_lines_fun)
+        .context("couldn't register replace_lines function")?;
+
+    if let Some(permalink) = options.permalink.clone() {
+        let permalink_fun = lua
+            .create_function(move |lua, args| lua_permalink(lua, &permalink, args))
+            .context("couldn't create permalink function")?;
+        lua.globals()
+            .set("permalink", permalink_fun)
+            .context("couldn't register permalink function")?;
+    }
+
+    let inheritance = Inheritance::default();
+    let resolve_extends_fun = lua
+        .create_function(move |lua, args| lua_resolve_extends(lua, &inheritance, args))
+        .context("couldn't create resolve_extends function")?;
+    lua.globals()
+        .set("resolve_extends", resolve_extends_fun)
+        .context("couldn't register resolve_extends function")?;
+
+    let skip_fun = lua
+        .create_function(|lua, ()| lua.set_named_registry_value(SKIP, true))
+        .context("couldn't create skip function")?;
+    lua.globals()
+        .set("skip", skip_fun)
+        .context("couldn't register skip function")?;
+
+    let sandbox = options.sandbox;
+    let rename_to_fun = lua
+        .create_function(move |lua, new_path: String| {
+            if new_path.is_empty() {
+                return Err(mlua::Error::external("can't rename to an empty path"));
+            }
+            if sandbox && Path::new(&new_path).has_root() {
+                return Err(mlua::Error::external(format!(
+                    "can't rename to absolute path {} when sandboxed",
+                    new_path
+                )));
+            }
+            lua.set_named_registry_value(RENAME_TO, new_path)
+        })
+        .context("couldn't create rename_to function")?;
+    lua.globals()
+        .set("rename_to", rename_to_fun)
+        .context("couldn't register rename_to function")?;
+
+    helpers::register(&lua).context("couldn't register fm helpers")?;
+    register_require(&lua, options.lua_path.clone()).context("couldn't register require")?;
+
+    Ok((lua, meter))
+}
+
+/// Replace `require` with one that loads modules from `lua_path`, falling
+/// back on Lua's own `require` (absent when sandboxed) for the rest.
+fn register_require(lua: &Lua, lua_path: Vec<PathBuf>) -> eyre::Result<()> {
+    let fallback = lua
+        .globals()
+        .get::<_, Option<Function>>("require")?
+        .map(|require| lua.create_registry_value(require))
+        .transpose()?;
+    lua.set_named_registry_value(LOADED_MODULES, lua.create_table()?)?;
+    let require = lua.create_function(move |lua, name: String| {
+        let loaded: mlua::Table = lua.named_registry_value(LOADED_MODULES)?;
+        let module: mlua::Value = loaded.get(name.as_str())?;
+        if module != mlua::Value::Nil {
+            return Ok(module);
+        }
+        let file = format!("{}.lua", name.replace('.', "/"));
+        let path = match lua_path
+            .iter()
+            .map(|dir| dir.join(&file))
+            .find(|path| path.is_file())
+        {
+            Some(path) => path,
+            None => {
+                return match &fallback {
+                    Some(fallback) => lua.registry_value::<Function>(fallback)?.call(name),
+                    None => Err(mlua::Error::external(format!(
+                        "module {} not found: no {} in the Lua path",
+                        name, file
+                    ))),
+                }
+            }
+        };
+        let source = fs::read_to_string(&path).map_err(mlua::Error::external)?;
+        let module: mlua::Value = lua
+            .load(&source)
+            .set_name(path.to_string_lossy())?
+            .call(name.as_str())?;
+        // as with Lua's require, a module returning nothing is still loaded
+        let module = match module {
+            mlua::Value::Nil => mlua::Value::Boolean(true),
+            module => module,
+        };
+        loaded.set(name.as_str(), module.clone())?;
+        Ok(module)
+    })?;
+    lua.globals().set("require", require)?;
+    Ok(())
+}
+
+/// A Lua state without the libraries and globals that reach outside it.
+fn sandboxed_lua() -> eyre::Result<Lua> {
+    let libs = StdLib::COROUTINE
+        | StdLib::TABLE
+        | StdLib::OS
+        | StdLib::STRING
+        | StdLib::UTF8
+        | StdLib::MATH;
+    let lua = Lua::new_with(libs, LuaOptions::default()).context("couldn't create Lua state")?;
+    {
+        let globals = lua.globals();
+        for name in UNSAFE_GLOBALS {
+            globals.raw_remove(name)?;
+        }
+        let os: mlua::Table = globals.get("os")?;
+        for name in UNSAFE_OS_FUNCTIONS {
+            os.raw_remove(name)?;
+        }
+    }
+    Ok(lua)
+}
+
+/// Hold `lua` to `limits`, through a meter that also counts what scripts
+/// use when they aren't limited, and notes each line they run if `trace`.
+fn limit(lua: &Lua, limits: Limits, trace: bool) -> eyre::Result<Arc<Meter>> {
+    if let Some(memory) = limits.memory {
+        lua.set_memory_limit(memory)
+            .context("couldn't limit Lua memory")?;
+    }
+    let meter = Arc::new(Meter::new(limits, trace));
+    let checked = Arc::clone(&meter);
+    // Lua has one hook, so the same one traces
+    let triggers = HookTriggers {
+        every_nth_instruction: Some(INSTRUCTION_CHECK_INTERVAL),
+        every_line: trace,
+        ..HookTriggers::default()
+    };
+    lua.set_hook(triggers, move |_, debug| match debug.event() {
+        DebugEvent::Line => {
+            checked.trace(&debug);
+            Ok(())
+        }
+        _ => checked.check(),
+    })
+    .context("couldn't limit Lua instructions")?;
+    Ok(meter)
+}
+
+/// Say which of several scripts went wrong, if it has a name.
+fn in_script<T>(name: &str, result: eyre::Result<T>) -> eyre::Result<T> {
+    if name.is_empty() {
+        result
+    } else {
+        result.context(format!("in {}", name))
+    }
+}
+
+/// Replace lines `from` to `to` (1-based, inclusive) with `new_lines`. With
+/// `to` one before `from`, the new lines are inserted before `from`.
+fn replace_lines(
+    content: &str,
+    from: usize,
+    to: usize,
+    new_lines: &[String],
+) -> Result<String, String> {
+    let mut lines = split_lines(content);
+    if from == 0 || from > to + 1 || to > lines.len() {
+        return Err(format!(
+            "can't replace lines {} to {} of {}",
+            from,
+            to,
+            lines.len()
+        ));
+    }
+    lines.splice(from - 1..to, new_lines.iter().map(String::as_str));
+    let mut replaced = lines.join("\n");
+    if !lines.is_empty() && (content.ends_with('\n') || content.is_empty()) {
+        replaced.push('\n');
+    }
+    Ok(replaced)
+}
+
+fn lua_yaml_dump(lua: &Lua, v: mlua::Value, to_stderr: bool) -> mlua::Result<()> {
+    let yaml_v = lua_yaml::from_lua(lua, v)?;
+    yaml_dump(&yaml_v, to_stderr)
+        .map_err(|e| mlua::Error::external(format!("couldn't format value as YAML: {:?}", e)))?;
+    Ok(())
+}
+
+/// Lua's `print`, but to stderr.
+fn lua_eprint(lua: &Lua, args: mlua::Variadic<mlua::Value>) -> mlua::Result<()> {
+    let tostring: Function = lua.globals().get("tostring")?;
+    let strings = args
+        .into_iter()
+        .map(|arg| tostring.call::<_, String>(arg))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    eprintln!("{}", strings.join("\t"));
+    Ok(())
+}
+
+fn lua_yaml_load(lua: &Lua, s: String) -> mlua::Result<mlua::Value<'_>> {
+    let yaml_v: yaml::Value = yaml::from_str(&s)
+        .map_err(|e| mlua::Error::external(format!("couldn't parse YAML: {}", e)))?;
+    lua_yaml::to_lua(lua, &yaml_v)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: &'_ str = "---\nhello: world\n---\n# Title\n";
+
+    const EXAMPLE_EMPTY_YFM: &'_ str = "---\n---\n# Title\n";
+
+    const EXAMPLE_NO_YFM: &'_ str = "# Title\n";
+
+    #[test]
+    fn empty_script_returns_frontmatter() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(""))?;
+        let (yfm, _) = processor.fix(EXAMPLE, None)?;
+        assert_eq!("hello: world\n", yaml::to_string(&yfm)?);
+        Ok(())
+    }
+
+    #[test]
+    fn chains_scripts_until_skipped() -> eyre::Result<()> {
+        let script = |name: &str, source: &str| Script {
+            name: name.into(),
+            source: source.into(),
+        };
+        let scripts = [
+            script(
+                "first.lua",
+                "meta.hello = meta.hello .. 'fish'; set_content('# Fish\\n')",
+            ),
+            script(
+                "second.lua",
+                "meta.content = content; if meta.stop then skip() end",
+            ),
+            script("third.lua", "meta.third = true"),
+        ];
+        let processor = Fixer::chained(&scripts, &ScriptOptions::default())?;
+        let (yfm, content) = processor.fix(EXAMPLE, None)?;
+        assert_eq!(
+            "hello: worldfish\ncontent: |\n  # Fish\nthird: true\n",
+            yaml::to_string(&yfm)?
+        );
+        assert_eq!("# Fish\n", content);
+
+        processor.fix("---\nhello: x\nstop: true\n---\n", None)?;
+        assert!(processor.skipped()?);
+
+        let scripts = [script("ok.lua", ""), script("bad.lua", "error('oops')")];
+        let processor = Fixer::chained(&scripts, &ScriptOptions::default())?;
+        let err = processor.fix(EXAMPLE, None).unwrap_err();
+        assert!(format!("{:?}", err).contains("in bad.lua"));
+        Ok(())
+    }
+
+    #[test]
+    fn passes_through_content() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(""))?;
+        let (_, content) = processor.fix(EXAMPLE, None)?;
+        assert_eq!("# Title", content.trim());
+        Ok(())
+    }
+
+    #[test]
+    fn script_can_access_and_modify_frontmatter() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(
+            r#"
+            meta.hello = meta.hello .. 'fish'
+        "#,
+        ))?;
+        let (fixed, _) = processor.fix(EXAMPLE, None)?;
+        assert_eq!("hello: worldfish\n", yaml::to_string(&fixed)?);
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_key_order() -> eyre::Result<()> {
+        let processor = Fixer::new(Some("meta.z = 1; meta.m = 2; meta.c.y = nil"))?;
+        let document = "---\nb: 1\na: [{y: 1, x: 2}]\nc: {y: 1, x: 2}\n---\n";
+        let (fixed, _) = processor.fix(document, None)?;
+        assert_eq!(
+            "b: 1\na:\n- y: 1\n  x: 2\nc:\n  x: 2\nm: 2\nz: 1\n",
+            yaml::to_string(&fixed)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn script_can_access_content() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(
+            r#"
+            meta.hello = string.match(content, '# ([^%c]*)')
+        "#,
+        ))?;
+        let (fixed, _) = processor.fix(EXAMPLE, None)?;
+        assert_eq!("hello: Title\n", yaml::to_string(&fixed)?);
+        Ok(())
+    }
+
+    #[test]
+    fn script_cannot_modify_content() {
+        let processor =
+            Fixer::new(Some("content.fudge = 'vanilla'")).expect("script is valid, but...");
+        let _ = processor
+            .fix(EXAMPLE, None)
+            .expect_err("content shouldn't be mutable");
+    }
+
+    #[test]
+    fn script_cannot_replace_content() -> eyre::Result<()> {
+        let processor = Fixer::new(Some("content = 'vanilla'"))?;
+        let (_, content) = processor.fix(EXAMPLE, None)?;
+        assert_eq!("# Title", content.trim());
+        Ok(())
+    }
+
+    #[test]
+    fn script_can_set_content() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(
+            r#"
+            meta.title, body = string.match(content, '^# ([^\n]*)\n(.*)$')
+            set_content(body)
+            meta.length = #content
+        "#,
+        ))?;
+        let (fixed, content) = processor.fix(
+            "---
+hello: world
+---
+# Title
+Body
+",
+            None,
+        )?;
+        assert_eq!(
+            "hello: world
+length: 5
+title: Title
+",
+            yaml::to_string(&fixed)?
+        );
+        assert_eq!(
+            "Body
+",
+            content
+        );
+
+        let (_, content) = Fixer::new(Some(""))?.fix(EXAMPLE, None)?;
+        assert!(matches!(content, Cow::Borrowed(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn script_sees_file_metadata() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("post.md");
+        std::fs::write(&path, EXAMPLE)?;
+        let processor = Fixer::new(Some(
+            r#"
+            if file then
+                meta.name = file.basename
+                meta.date = file.mtime
+            end
+        "#,
+        ))?;
+        let (fixed, _) = processor.fix_file(&path, EXAMPLE, None)?;
+        let fixed = fixed.expect("should have frontmatter");
+        assert_eq!(Some("post.md"), fixed["name"].as_str());
+        assert!(fixed["date"]
+            .as_str()
+            .is_some_and(|date| date.ends_with('Z')));
+
+        let (fixed, _) = processor.fix(EXAMPLE, None)?;
+        assert!(fixed.expect("should have frontmatter")["name"].is_null());
+        Ok(())
+    }
+
+    #[test]
+    fn script_can_replace_lines() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(
+            r#"
+            meta.lines = #content_lines
+            if content_lines[1] == '# ' .. meta.title then
+                replace_lines(1, 2, {})
+            end
+            meta.first = content_lines[1]
+        "#,
+        ))?;
+        let (fixed, content) = processor.fix("---\ntitle: Hi\n---\n# Hi\n\nBody\n", None)?;
+        let fixed = fixed.expect("should have frontmatter");
+        assert_eq!(Some(3), fixed["lines"].as_i64());
+        assert_eq!(Some("Body"), fixed["first"].as_str());
+        assert_eq!("Body\n", content);
+        Ok(())
+    }
+
+    #[test]
+    fn content_lines_are_read_only() {
+        let processor = Fixer::new(Some("content_lines[1] = 'x'")).unwrap();
+        let _ = processor
+            .fix("# Title\n", None)
+            .expect_err("content_lines shouldn't be mutable");
+    }
+
+    #[test]
+    fn repairs_broken_frontmatter() -> eyre::Result<()> {
+        let broken = "---\ntitle: [unclosed\n---\nBody\n";
+        let repair = |source: &str| {
+            let script = Script {
+                name: String::new(),
+                source: source.to_owned(),
+            };
+            let options = ScriptOptions {
+                repair: true,
+                ..ScriptOptions::default()
+            };
+            Fixer::chained(&[script], &options)
+        };
+        let processor = repair(
+            "if meta_error then meta = yaml_load((meta_raw:gsub('%[', ''))) else meta.ok = true end",
+        )?;
+        let (metadata, _) = processor.fix(broken, None)?;
+        let expected: yaml::Value = yaml::from_str("title: unclosed")?;
+        assert_eq!(Some(expected), metadata);
+        let (metadata, _) = processor.fix("---\ntitle: [ok]\n---\n", None)?;
+        let expected: yaml::Value = yaml::from_str("{title: [ok], ok: true}")?;
+        assert_eq!(Some(expected), metadata);
+
+        let processor = repair("assert(meta == nil and meta_raw)")?;
+        let _ = processor
+            .fix(broken, None)
+            .expect_err("frontmatter that's still broken should fail");
+        let processor = Fixer::new(Some("meta = {}"))?;
+        let _ = processor
+            .fix(broken, None)
+            .expect_err("frontmatter shouldn't be repaired without asking");
+        Ok(())
+    }
+
+    #[test]
+    fn replaces_and_inserts_lines() {
+        let new_lines =
+            |lines: &[&str]| -> Vec<String> { lines.iter().map(|&line| line.to_owned()).collect() };
+        assert_eq!(
+            Ok("a\nx\ny\nc\n".to_owned()),
+            replace_lines("a\nb\nc\n", 2, 2, &new_lines(&["x", "y"]))
+        );
+        assert_eq!(
+            Ok("x\na\n".to_owned()),
+            replace_lines("a\n", 1, 0, &new_lines(&["x"]))
+        );
+        assert_eq!(Ok("a".to_owned()), replace_lines("a\nb", 2, 2, &[]));
+        assert!(replace_lines("a\n", 2, 2, &[]).is_err());
+    }
+
+    #[test]
+    fn script_can_skip_documents() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(
+            r#"
+            if meta.draft then skip() end
+            meta.fixed = true
+        "#,
+        ))?;
+        processor.fix("---\ndraft: true\n---\n", None)?;
+        assert!(processor.skipped()?);
+        processor.fix("---\ndraft: false\n---\n", None)?;
+        assert!(!processor.skipped()?);
+        Ok(())
+    }
+
+    #[test]
+    fn script_can_rename_documents() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(
+            r#"
+            if meta.slug then rename_to(meta.slug .. ".md") end
+        "#,
+        ))?;
+        processor.fix("---\nslug: hello\n---\n", None)?;
+        assert_eq!(Some(PathBuf::from("hello.md")), processor.renamed_to()?);
+        processor.fix("---\ntitle: Hello\n---\n", None)?;
+        assert_eq!(None, processor.renamed_to()?);
+        Ok(())
+    }
+
+    #[test]
+    fn aggregating_script_sees_collected_state() -> eyre::Result<()> {
+        let script = r#"
+            function collect(meta, file)
+                state.count = (state.count or 0) + 1
+                state.tags = state.tags or {}
+                for _, tag in ipairs(meta.tags) do state.tags[tag] = true end
+            end
+            function fix()
+                meta.posts = state.count
+                meta.known_tag = state.tags.rust == true
+            end
+        "#;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("a.md");
+        fs::write(&path, "")?;
+        let collector = Fixer::aggregating(script, &ScriptOptions::default())?;
+        collector.collect(&path, "---\ntags: [rust]\n---\n", None)?;
+        collector.collect(&path, "---\ntags: [lua]\n---\n", None)?;
+
+        let processor = Fixer::aggregating(script, &ScriptOptions::default())?;
+        processor.set_state(&collector.state()?)?;
+        let (yfm, _) = processor.fix("---\ntitle: a\n---\n", None)?;
+        assert_eq!(
+            yaml::from_str::<yaml::Value>("title: a\nknown_tag: true\nposts: 2\n")?,
+            yfm.unwrap()
+        );
+        assert!(Fixer::aggregating("function fix() end", &ScriptOptions::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn aggregating_script_asks_corpus() -> eyre::Result<()> {
+        let script = r#"
+            function collect(meta, file) end
+            function fix()
+                local used = corpus.values('tags')
+                local tags = {}
+                for _, tag in ipairs(meta.tags) do
+                    if (used[tag] or 0) >= 2 then tags[#tags + 1] = tag end
+                end
+                meta.tags = fm.array(tags)
+                meta.news = corpus.count('category', 'news')
+                meta.categorized = corpus.count('category')
+            end
+        "#;
+        let mut tally = Tally::default();
+        for doc in [
+            "tags: [rust, lua]
+category: news
+",
+            "tags: [rust]
+",
+        ] {
+            tally.record(yaml::from_str(doc)?);
+        }
+        let processor = Fixer::aggregating(script, &ScriptOptions::default())?;
+        processor.set_corpus(Arc::new(tally))?;
+        let (yfm, _) = processor.fix(
+            "---
+tags: [rust, lua]
+---
+",
+            None,
+        )?;
+        assert_eq!(
+            yaml::from_str::<yaml::Value>(
+                "tags: [rust]
+news: 1
+categorized: 1
+"
+            )?,
+            yfm.unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn passes_through_content_if_no_frontmatter() -> eyre::Result<()> {
+        let processor = Fixer::new(Some("")).unwrap();
+        let (yfm, content) = processor.fix(EXAMPLE_NO_YFM, None)?;
+        assert_eq!(None, yfm);
+        assert_eq!("# Title", content.trim());
+        Ok(())
+    }
+
+    #[test]
+    fn blows_up_if_empty_frontmatter() {
+        let processor = Fixer::new(Some("")).unwrap();
+        let _ = processor
+            .fix(EXAMPLE_EMPTY_YFM, None)
+            .expect_err("malformed frontmatter should fail");
+    }
+
+    #[test]
+    fn can_create_frontmatter_if_none() -> eyre::Result<()> {
+        let processor = Fixer::new(Some("meta = { hello = 'world' }")).unwrap();
+        let (yfm, _) = processor.fix(EXAMPLE_NO_YFM, None)?;
+        assert_eq!("hello: world\n", yaml::to_string(&yfm)?);
+        Ok(())
+    }
+
+    #[test]
+    fn script_sees_toml_frontmatter() -> eyre::Result<()> {
+        let processor = Fixer::new(Some("meta.hello = meta.hello .. 'fish'"))?;
+        let (fixed, content) = processor.fix("+++\nhello = \"world\"\n+++\n# Title\n", None)?;
+        assert_eq!("hello: worldfish\n", yaml::to_string(&fixed)?);
+        assert_eq!("# Title", content.trim());
+        Ok(())
+    }
+
+    #[test]
+    fn sandbox_limits_scripts() -> eyre::Result<()> {
+        let run = |script: &str| Fixer::new(Some(script))?.fix(EXAMPLE, None).map(|_| ());
+        assert!(run("os.execute('true')").is_err());
+        assert!(run("io.open('/etc/passwd')").is_err());
+        assert!(run("require('os')").is_err());
+        run("meta.now = os.time()")?;
+
+        let script = Script {
+            name: String::new(),
+            source: "meta.home = os.getenv('HOME') ~= nil".into(),
+        };
+        let options = ScriptOptions {
+            sandbox: false,
+            ..ScriptOptions::default()
+        };
+        Fixer::chained(&[script], &options)?.fix(EXAMPLE, None)?;
+        Ok(())
+    }
+
+    #[test]
+    fn limits_runaway_scripts() -> eyre::Result<()> {
+        let script = Script {
+            name: String::new(),
+            source: "if meta.big then local t = {} for i = 1, 1e8 do t[i] = i end end\n\
+                     while meta.forever do end"
+                .into(),
+        };
+        let fixer = |limits| {
+            let options = ScriptOptions {
+                sandbox: false,
+                limits,
+                ..ScriptOptions::default()
+            };
+            Fixer::chained(std::slice::from_ref(&script), &options)
+        };
+        let forever = "---\nforever: true\n---\n";
+        let big = "---\nbig: true\n---\n";
+        let error =
+            |fixer: &Fixer, document| format!("{:#}", fixer.fix(document, None).unwrap_err());
+
+        let limited = fixer(Limits {
+            instructions: Some(1_000_000),
+            ..Limits::NONE
+        })?;
+        assert!(error(&limited, forever).contains("ran over 1000000 instructions"));
+        limited.fix(EXAMPLE, None)?;
+        assert!(limited.usage().instructions < 1_000_000);
+
+        let limited = fixer(Limits {
+            timeout: Some(Duration::from_millis(50)),
+            ..Limits::NONE
+        })?;
+        assert!(error(&limited, forever).contains("ran over 50ms"));
+
+        let limited = fixer(Limits {
+            memory: Some(64 * MB),
+            ..Limits::NONE
+        })?;
+        assert!(error(&limited, big).contains("over 64 MB of memory"));
+        limited.fix(EXAMPLE, None)?;
+        Ok(())
+    }
+
+    #[test]
+    fn traces_lines_run() -> eyre::Result<()> {
+        let script = Script {
+            name: "fix.lua".into(),
+            source: "if meta.draft then\n  meta.draft = nil\nend\nmeta.seen = true\n".into(),
+        };
+        let options = ScriptOptions {
+            trace: true,
+            ..ScriptOptions::default()
+        };
+        let fixer = Fixer::chained(&[script], &options)?;
+        let ran = |document| -> eyre::Result<Vec<String>> {
+            fixer.fix(document, None)?;
+            let mut trace = fixer.trace();
+            // just the script's lines, each once per visit
+            trace.retain(|line| line.starts_with("fix.lua:"));
+            trace.dedup();
+            Ok(trace)
+        };
+        assert_eq!(
+            vec!["fix.lua:1", "fix.lua:4"],
+            ran("---\ndraft: false\n---\n")?
+        );
+        assert_eq!(
+            vec!["fix.lua:1", "fix.lua:2", "fix.lua:4"],
+            ran("---\ndraft: true\n---\n")?
+        );
+        assert!(Fixer::new(Some("meta.seen = true"))?.trace().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn requires_modules_from_lua_path() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("lib"))?;
+        fs::write(
+            dir.path().join("lib/greet.lua"),
+            "loads = (loads or 0) + 1\nreturn { hello = function(s) return 'hello ' .. s end }\n",
+        )?;
+        let script = Script {
+            name: String::new(),
+            source: "local greet = require 'lib.greet'\nrequire 'lib.greet'\n\
+                     meta.hello = greet.hello(meta.hello)\nmeta.loads = loads"
+                .into(),
+        };
+        let options = ScriptOptions {
+            lua_path: vec![dir.path().to_owned()],
+            ..ScriptOptions::default()
+        };
+        let (yfm, _) = Fixer::chained(&[script], &options)?.fix(EXAMPLE, None)?;
+        assert_eq!("hello: hello world\nloads: 1\n", yaml::to_string(&yfm)?);
+        assert!(Fixer::new(Some("require 'lib.greet'"))?
+            .fix(EXAMPLE, None)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn computes_permalinks() -> eyre::Result<()> {
+        let script = Script {
+            name: String::new(),
+            source: "meta.url = permalink('content/posts/a.md')\n\
+                     meta.other = permalink('content/b.md', { slug = 'bee' })"
+                .into(),
+        };
+        let options = ScriptOptions {
+            permalink: Some(Permalink::new("/:section/:slug/".parse().unwrap(), None)),
+            ..ScriptOptions::default()
+        };
+        let (yfm, _) = Fixer::chained(&[script], &options)?.fix(EXAMPLE, None)?;
+        let yfm = yfm.unwrap();
+        assert_eq!(Some("/content/a/"), yfm["url"].as_str());
+        assert_eq!(Some("/content/bee/"), yfm["other"].as_str());
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_extends() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("_base.md"), "---\nlayout: docs\n---\n")?;
+        let path = dir.path().join("page.md");
+        let document = "---\nextends: _base.md\ntitle: Hi\n---\n";
+        std::fs::write(&path, document)?;
+        let processor = Fixer::new(Some("meta.inherited = resolve_extends().layout"))?;
+        let (fixed, _) = processor.fix_file(&path, document, None)?;
+        let fixed = fixed.expect("should have frontmatter");
+        assert_eq!(Some("docs"), fixed["inherited"].as_str());
+        assert!(fixed["layout"].is_null());
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_keys_on_request() -> eyre::Result<()> {
+        let processor = Fixer::new(Some(
+            "meta.zebra = 1\nmeta.title = 'Hi'\n\
+             if meta.hello then fm.sort_keys(meta, { 'title' }) end",
+        ))?;
+        let (yfm, _) = processor.fix(EXAMPLE, None)?;
+        assert_eq!(
+            "title: Hi\nhello: world\nzebra: 1\n",
+            yaml::to_string(&yfm)?
+        );
+        // only for the document it was called on
+        let (yfm, _) = processor.fix("---\nworld: hello\n---\n", None)?;
+        assert_eq!(
+            "world: hello\ntitle: Hi\nzebra: 1\n",
+            yaml::to_string(&yfm)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rewrites_references() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("a.md");
+        fs::write(&path, "")?;
+        let script = Script {
+            name: String::new(),
+            source: "function rewrite_references(content, changes, file)\n\
+                       for _, change in ipairs(changes) do\n\
+                         if change.key == 'slug' then\n\
+                           content = content:gsub('%[%[' .. change.old .. '%]%]', \
+                                                  '[[' .. change.new .. ']]')\n\
+                         end\n\
+                       end\n\
+                       return content\n\
+                     end"
+            .into(),
+        };
+        let fixer = Fixer::rewriting(&script, &ScriptOptions::default())?;
+        let changes = [Change::Key {
+            path: "b.md".into(),
+            key: "slug".into(),
+            old: Some("old".into()),
+            new: Some("new".into()),
+        }];
+        assert_eq!(
+            Some("See [[new]].\n".to_owned()),
+            fixer.rewrite_references(&path, "See [[old]].\n", &changes)?
+        );
+        assert_eq!(
+            None,
+            fixer.rewrite_references(&path, "See [[other]].\n", &changes)?
+        );
+        let script = Script {
+            name: String::new(),
+            source: "function fix() end".into(),
+        };
+        assert!(Fixer::rewriting(&script, &ScriptOptions::default()).is_err());
+        Ok(())
+    }
+}
@@ -1,46 +1,746 @@
-use eyre::Context;
-use std::io::Write;
+use eyre::{eyre, Context};
+use std::{borrow::Cow, collections::HashSet, error::Error, fmt, io::Write, str::FromStr};
 
-const RULE_LENGTH: usize = "---\n".len();
+use crate::{glob, preserve};
 
-pub fn parse(s: &str) -> (Option<serde_yaml::Result<serde_yaml::Value>>, &str) {
-    let (raw_frontmatter, content) = parse_raw(s);
-    let frontmatter = raw_frontmatter.map(serde_yaml::from_str);
-    (frontmatter, content)
+/// The languages frontmatter can be written in, told apart by their
+/// delimiters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// YAML between `---` rules.
+    Yaml,
+    /// TOML between `+++` rules, as used by Hugo.
+    Toml,
 }
 
+impl Format {
+    pub fn rule(self) -> &'static str {
+        match self {
+            Format::Yaml => "---\n",
+            Format::Toml => "+++\n",
+        }
+    }
+
+    /// Parse `raw` frontmatter, found in `document` at byte offset `start`.
+    fn deserialize(
+        self,
+        document: &str,
+        start: usize,
+        raw: &str,
+    ) -> eyre::Result<serde_yaml::Value> {
+        match self {
+            Format::Yaml => serde_yaml::from_str(raw)
+                .map_err(|err| yaml_error(document, start, raw, err).into()),
+            Format::Toml => match raw.parse() {
+                Ok(value) => Ok(toml_to_yaml(value)),
+                Err(err) => Err(toml_error(document, start, err).into()),
+            },
+        }
+    }
+}
+
+/// Frontmatter that doesn't parse, with where it went wrong, 1-based within
+/// the whole document, if known.
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for SyntaxError {}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            _ => Err(format!(
+                "unknown frontmatter format {:?}, expected yaml or toml",
+                s
+            )),
+        }
+    }
+}
+
+/// Whether a blank line separates the closing rule from the content.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlankLine {
+    /// Exactly one blank line.
+    Always,
+    /// No blank lines.
+    Never,
+    /// Whatever blank lines the content starts with.
+    #[default]
+    Preserve,
+}
+
+impl BlankLine {
+    /// Lay out the start of content that follows frontmatter.
+    pub fn apply(self, content: &str) -> Cow<'_, str> {
+        if self == BlankLine::Preserve {
+            return Cow::Borrowed(content);
+        }
+        let mut start = 0;
+        for line in content.split_inclusive('\n') {
+            if !line.trim().is_empty() {
+                break;
+            }
+            start += line.len();
+        }
+        match self {
+            BlankLine::Always if start < content.len() => {
+                Cow::Owned(format!("\n{}", &content[start..]))
+            }
+            _ => Cow::Borrowed(&content[start..]),
+        }
+    }
+}
+
+impl FromStr for BlankLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(BlankLine::Always),
+            "never" => Ok(BlankLine::Never),
+            "preserve" => Ok(BlankLine::Preserve),
+            _ => Err(format!("expected always, never or preserve, got {:?}", s)),
+        }
+    }
+}
+
+/// What line endings files are written with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEndings {
+    Lf,
+    Crlf,
+    /// CRLF on Windows, LF elsewhere.
+    Native,
+    /// Whatever each file had.
+    #[default]
+    Preserve,
+}
+
+impl LineEndings {
+    /// How to write a document read in `style`.
+    pub fn apply(self, style: TextStyle) -> TextStyle {
+        let crlf = match self {
+            LineEndings::Lf => false,
+            LineEndings::Crlf => true,
+            LineEndings::Native => cfg!(windows),
+            LineEndings::Preserve => style.crlf,
+        };
+        TextStyle { crlf, ..style }
+    }
+}
+
+impl FromStr for LineEndings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lf" => Ok(LineEndings::Lf),
+            "crlf" => Ok(LineEndings::Crlf),
+            "native" => Ok(LineEndings::Native),
+            "preserve" => Ok(LineEndings::Preserve),
+            _ => Err(format!(
+                "expected lf, crlf, native or preserve, got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// How faithfully documents are kept when they're written back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fidelity {
+    /// Everything that isn't changed stays byte for byte as it was: the
+    /// lines of keys whose values didn't change, with their comments,
+    /// anchors and quoting, the rules, and the content. Frontmatter that
+    /// can't be edited that way, such as TOML, fails rather than being
+    /// written afresh.
+    ByteIdentical,
+    /// Values are kept, and so are unchanged keys' lines where that can be
+    /// done faithfully, with the frontmatter written afresh otherwise.
+    /// Documents whose frontmatter and content didn't change aren't
+    /// rewritten.
+    #[default]
+    Semantic,
+    /// Every document's frontmatter is written afresh, laid out the same
+    /// way between `---` or `+++` rules, whether or not it changed.
+    Normalized,
+}
+
+impl FromStr for Fidelity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "byte-identical" => Ok(Fidelity::ByteIdentical),
+            "semantic" => Ok(Fidelity::Semantic),
+            "normalized" => Ok(Fidelity::Normalized),
+            _ => Err(format!(
+                "expected byte-identical, semantic or normalized, got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+const BOM: char = '\u{feff}';
+
+/// How a document's text is laid out beyond its content: whether it starts
+/// with a UTF-8 byte order mark and whether its lines end with CRLF, going
+/// by its first line.
+///
+/// Documents are fixed with the BOM removed and LF line endings, then
+/// written back in their original style.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    pub bom: bool,
+    pub crlf: bool,
+}
+
+impl TextStyle {
+    pub fn detect(s: &str) -> Self {
+        Self {
+            bom: s.starts_with(BOM),
+            crlf: s
+                .find('\n')
+                .is_some_and(|newline| s[..newline].ends_with('\r')),
+        }
+    }
+
+    /// The document without a BOM and with LF line endings.
+    pub fn normalize(self, s: &str) -> Cow<'_, str> {
+        let s = s.strip_prefix(BOM).unwrap_or(s);
+        if self.crlf {
+            Cow::Owned(s.replace("\r\n", "\n"))
+        } else {
+            Cow::Borrowed(s)
+        }
+    }
+
+    /// Put back the BOM and line endings taken out by
+    /// [`TextStyle::normalize`].
+    pub fn restore(self, normalized: Vec<u8>) -> Vec<u8> {
+        if self == Self::default() {
+            return normalized;
+        }
+        let mut restored = Vec::with_capacity(normalized.len() + 3);
+        if self.bom {
+            restored.extend_from_slice("\u{feff}".as_bytes());
+        }
+        for &byte in &normalized {
+            if byte == b'\n' && self.crlf {
+                restored.push(b'\r');
+            }
+            restored.push(byte);
+        }
+        restored
+    }
+}
+
+/// How a document's frontmatter rules are written, which can differ from
+/// the usual `---` or `+++` lines: YAML closed Pandoc-style with `...`,
+/// whitespace after a rule, or no newline after a closing rule that ends
+/// the document.
+///
+/// Frontmatter is written with the usual rules, then they're put back the
+/// way the document had them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fences {
+    format: Format,
+    /// The opening rule, without its line ending.
+    open: String,
+    /// The closing rule, without its line ending.
+    close: String,
+    /// Whether a line ending follows the closing rule.
+    newline_after: bool,
+}
+
+impl Fences {
+    pub fn detect(s: &str) -> Option<Self> {
+        let found = find(s)?;
+        let rule = |line: &str| line.trim_end_matches(['\r', '\n']).to_owned();
+        Some(Self {
+            format: found.format,
+            open: rule(&s[found.open..found.start]),
+            close: rule(&s[found.close..found.end]),
+            newline_after: s[..found.end].ends_with('\n'),
+        })
+    }
+
+    /// Put back the rules taken out by writing `written`, which should
+    /// have LF line endings, if its frontmatter is in the same format.
+    pub fn restore(&self, written: Vec<u8>) -> Vec<u8> {
+        let Ok(s) = std::str::from_utf8(&written) else {
+            return written;
+        };
+        let found = match find(s) {
+            Some(found) if found.format == self.format => found,
+            _ => return written,
+        };
+        let content = &s[found.end..];
+        let newline = if content.is_empty() && !self.newline_after {
+            ""
+        } else {
+            "\n"
+        };
+        let frontmatter = &s[found.start..found.close];
+        [
+            &s[..found.open],
+            &self.open,
+            "\n",
+            frontmatter,
+            &self.close,
+            newline,
+            content,
+        ]
+        .concat()
+        .into_bytes()
+    }
+}
+
+/// The format of a document's frontmatter, going by its delimiters.
+pub fn detect(s: &str) -> Option<Format> {
+    split(s).0.map(|(format, _)| format)
+}
+
+pub fn parse(s: &str) -> (Option<eyre::Result<serde_yaml::Value>>, &str) {
+    parse_as(s, None)
+}
+
+/// Parse frontmatter as `format`, or as whatever its delimiters say if `None`.
+pub fn parse_as(
+    s: &str,
+    format: Option<Format>,
+) -> (Option<eyre::Result<serde_yaml::Value>>, &str) {
+    let Some(found) = find(s) else {
+        return (None, s);
+    };
+    let raw = &s[found.start..found.close];
+    let frontmatter = format
+        .unwrap_or(found.format)
+        .deserialize(s, found.start, raw);
+    (Some(frontmatter), &s[found.end..])
+}
+
+/// Explain why YAML frontmatter found in `document` at byte offset `start`
+/// didn't parse, with positions counted from the top of the document rather
+/// than of the frontmatter, and the lines the error points at, so it can be
+/// fixed without opening the file to look.
+fn yaml_error(document: &str, start: usize, raw: &str, err: serde_yaml::Error) -> SyntaxError {
+    // parsing again after as many blank lines as precede the frontmatter,
+    // so all the error's positions, context included, match the document
+    let lines_before = document[..start].matches('\n').count();
+    let padded = ["\n".repeat(lines_before).as_str(), raw].concat();
+    let err = serde_yaml::from_str::<serde_yaml::Value>(&padded)
+        .err()
+        .unwrap_or(err);
+    match err.location() {
+        Some(at) => SyntaxError {
+            line: Some(at.line()),
+            column: Some(at.column()),
+            message: format!("{}\n{}", err, excerpt(document, at.line(), at.column())),
+        },
+        None => SyntaxError {
+            line: None,
+            column: None,
+            message: err.to_string(),
+        },
+    }
+}
+
+/// TOML's errors come with an excerpt already, but positioned within the
+/// frontmatter rather than the document.
+fn toml_error(document: &str, start: usize, err: toml::de::Error) -> SyntaxError {
+    let (line, column) = match err.span() {
+        Some(span) => {
+            let before = &document[..(start + span.start).min(document.len())];
+            let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+            let column = before[line_start..].chars().count() + 1;
+            (Some(before.matches('\n').count() + 1), Some(column))
+        }
+        None => (None, None),
+    };
+    SyntaxError {
+        line,
+        column,
+        message: err.to_string(),
+    }
+}
+
+/// The 1-based `line` of `document` and the one before it, numbered, with a
+/// caret under the 1-based `column`.
+fn excerpt(document: &str, line: usize, column: usize) -> String {
+    let lines: Vec<&str> = document.lines().collect();
+    let width = line.to_string().len();
+    let mut excerpt = String::new();
+    for number in line.saturating_sub(1).max(1)..=line {
+        let text = lines.get(number - 1).copied().unwrap_or("");
+        excerpt += &format!("{:>width$} | {}\n", number, text.trim_start_matches(BOM));
+    }
+    excerpt += &format!("{:width$} | {:>column$}", "", "^");
+    excerpt
+}
+
+/// Write a document, separating any frontmatter from the content as
+/// `blank_line` says.
 pub fn write<W: Write>(
+    writer: W,
+    format: Format,
+    blank_line: BlankLine,
+    frontmatter: Option<&serde_yaml::Value>,
+    content: &str,
+) -> eyre::Result<()> {
+    write_with_datetimes(
+        writer,
+        format,
+        blank_line,
+        frontmatter,
+        content,
+        &Datetimes::default(),
+    )
+}
+
+/// Like [`write`], but writing TOML with the strings where `datetimes` says
+/// there were datetimes as datetimes again.
+fn write_with_datetimes<W: Write>(
     mut writer: W,
+    format: Format,
+    blank_line: BlankLine,
     frontmatter: Option<&serde_yaml::Value>,
     content: &str,
+    datetimes: &Datetimes,
 ) -> eyre::Result<()> {
+    let content = match frontmatter {
+        Some(_) => blank_line.apply(content),
+        None => Cow::Borrowed(content),
+    };
     if let Some(frontmatter) = frontmatter {
-        writer.write_all(b"---\n")?;
-        serde_yaml::to_writer(&mut writer, frontmatter)
-            .context("couldn't serialize frontmatter")?;
-        writer.write_all(b"---\n")?;
+        writer.write_all(format.rule().as_bytes())?;
+        match format {
+            Format::Yaml => serde_yaml::to_writer(&mut writer, frontmatter)
+                .context("couldn't serialize frontmatter")?,
+            Format::Toml => {
+                let toml = match yaml_to_toml(frontmatter, datetimes, &mut Vec::new())? {
+                    table @ toml::Value::Table(_) => {
+                        toml::to_string(&table).context("couldn't serialize frontmatter as TOML")?
+                    }
+                    _ => return Err(eyre!("TOML frontmatter must be a table")),
+                };
+                writer.write_all(toml.as_bytes())?;
+            }
+        }
+        writer.write_all(format.rule().as_bytes())?;
     }
     writer.write_all(content.as_bytes())?;
     Ok(())
 }
 
+pub fn to_bytes(
+    format: Format,
+    blank_line: BlankLine,
+    frontmatter: Option<&serde_yaml::Value>,
+    content: &str,
+) -> eyre::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    write(&mut output, format, blank_line, frontmatter, content)?;
+    Ok(output)
+}
+
+/// Like [`to_bytes`], but writing TOML with the strings where `datetimes`
+/// says the original had datetimes as datetimes again.
+pub fn to_bytes_with_datetimes(
+    format: Format,
+    blank_line: BlankLine,
+    frontmatter: Option<&serde_yaml::Value>,
+    content: &str,
+    datetimes: &Datetimes,
+) -> eyre::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    write_with_datetimes(
+        &mut output,
+        format,
+        blank_line,
+        frontmatter,
+        content,
+        datetimes,
+    )?;
+    Ok(output)
+}
+
+/// Where TOML frontmatter has datetimes, each as the keys (and array
+/// indices) leading to it. They're read as strings, so writing TOML back
+/// needs this to tell them from strings that only look like dates.
+#[derive(Debug, Default)]
+pub struct Datetimes(HashSet<Vec<String>>);
+
+impl Datetimes {
+    /// The datetimes in `document`'s frontmatter, read as `format` if
+    /// given; none unless it's TOML.
+    pub fn find(document: &str, format: Option<Format>) -> Self {
+        let mut datetimes = Self::default();
+        if let (Some((detected, raw)), _) = split(document) {
+            if format.unwrap_or(detected) == Format::Toml {
+                if let Ok(value) = raw.parse() {
+                    datetimes.collect(&value, &mut Vec::new());
+                }
+            }
+        }
+        datetimes
+    }
+
+    fn collect(&mut self, value: &toml::Value, path: &mut Vec<String>) {
+        let mut descend = |key: String, value| {
+            path.push(key);
+            self.collect(value, path);
+            path.pop();
+        };
+        match value {
+            toml::Value::Datetime(_) => {
+                self.0.insert(path.clone());
+            }
+            toml::Value::Array(array) => {
+                for (i, item) in array.iter().enumerate() {
+                    descend(i.to_string(), item);
+                }
+            }
+            toml::Value::Table(table) => {
+                for (key, value) in table {
+                    descend(key.clone(), value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Like [`to_bytes`] for YAML, but keeping the lines of the `original` YAML
+/// frontmatter for keys whose values haven't changed, with their comments,
+/// anchors and quoting, unless that can't be done faithfully, in which case
+/// it's written afresh, or with [`Fidelity::ByteIdentical`] not at all.
+pub fn to_bytes_preserving(
+    original: &str,
+    fidelity: Fidelity,
+    blank_line: BlankLine,
+    frontmatter: Option<&serde_yaml::Value>,
+    content: &str,
+) -> eyre::Result<Vec<u8>> {
+    let edited = frontmatter.and_then(|frontmatter| preserve::edit(original, frontmatter));
+    let Some(edited) = edited else {
+        if fidelity == Fidelity::ByteIdentical && frontmatter.is_some() {
+            return Err(eyre!(
+                "can't keep the frontmatter's unchanged lines byte-identical, \
+                 see --fidelity"
+            ));
+        }
+        return to_bytes(Format::Yaml, blank_line, frontmatter, content);
+    };
+    let rule = Format::Yaml.rule();
+    Ok([rule, &edited, rule, &*blank_line.apply(content)]
+        .concat()
+        .into_bytes())
+}
+
+/// The 1-based line number of a top-level key within a file's frontmatter.
+pub fn key_line(s: &str, key: &str) -> Option<usize> {
+    let (raw_frontmatter, _) = parse_raw(s);
+    let index = raw_frontmatter?.lines().position(|line| {
+        line.strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with([':', '=']))
+    })?;
+    // frontmatter starts on the line after the opening rule
+    Some(index + 2)
+}
+
+/// Whether a document opts out of being fixed, with `fixer: ignore` in its
+/// frontmatter or a `<!-- frontmatter-fixer: ignore -->` comment on a line
+/// of its own.
+pub fn is_ignored(s: &str, format: Option<Format>) -> bool {
+    let (frontmatter, content) = parse_as(s, format);
+    let marked = frontmatter
+        .and_then(Result::ok)
+        .and_then(|metadata| metadata.get("fixer").cloned())
+        .is_some_and(|fixer| fixer.as_str() == Some("ignore"));
+    marked
+        || content.lines().any(|line| {
+            line.trim()
+                .strip_prefix("<!--")
+                .and_then(|comment| comment.strip_suffix("-->"))
+                .is_some_and(|comment| comment.trim() == "frontmatter-fixer: ignore")
+        })
+}
+
+/// Split off leading lines matching any of the globs in `patterns`, such as
+/// a shebang, which come before the frontmatter and are kept as they are.
+pub fn split_prefix<'a>(s: &'a str, patterns: &[String]) -> (&'a str, &'a str) {
+    let mut end = 0;
+    for line in s.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\r', '\n']);
+        if !patterns
+            .iter()
+            .any(|pattern| glob::matches(pattern, text, '\n'))
+        {
+            break;
+        }
+        end += line.len();
+    }
+    s.split_at(end)
+}
+
 pub fn parse_raw(s: &str) -> (Option<&str>, &str) {
-    // first line must begin frontmatter if present
-    let mut rules = s.match_indices("---\n");
-    if let Some((0, _)) = rules.next() {
-        let start = RULE_LENGTH;
-        if let Some((close, _)) = rules.next() {
-            assert!(start <= close);
+    let (raw_frontmatter, content) = split(s);
+    (raw_frontmatter.map(|(_, raw)| raw), content)
+}
+
+/// Split frontmatter from content.
+fn split(s: &str) -> (Option<(Format, &str)>, &str) {
+    match find(s) {
+        Some(found) => (
+            Some((found.format, &s[found.start..found.close])),
+            &s[found.end..],
+        ),
+        None => (None, s),
+    }
+}
 
-            let content_start = close + RULE_LENGTH;
-            assert!(content_start <= s.len());
+/// Where frontmatter was found in a document, as byte offsets.
+struct Found {
+    format: Format,
+    /// The start of the opening rule.
+    open: usize,
+    /// The start of the frontmatter, after the opening rule.
+    start: usize,
+    /// The start of the closing rule.
+    close: usize,
+    /// The end of the closing rule, where content starts.
+    end: usize,
+}
 
-            return (Some(&s[start..close]), &s[content_start..]);
+/// Find frontmatter, allowing for a byte order mark before the opening
+/// rule, CRLF line endings, whitespace after the rules, YAML closed with
+/// `...`, and a closing rule that ends the document without a newline.
+fn find(s: &str) -> Option<Found> {
+    let bom = if s.starts_with(BOM) {
+        BOM.len_utf8()
+    } else {
+        0
+    };
+    let is_rule = |line: &str, rule: &str| {
+        line.strip_prefix(rule)
+            .is_some_and(|rest| rest.trim_end_matches([' ', '\t', '\r', '\n']).is_empty())
+    };
+    for format in [Format::Yaml, Format::Toml] {
+        let rule = format.rule().trim_end();
+        let mut lines = s[bom..].split_inclusive('\n');
+        // first line must begin frontmatter if present
+        let start = match lines.next() {
+            Some(line) if line.ends_with('\n') && is_rule(line, rule) => bom + line.len(),
+            _ => continue,
+        };
+        let mut close = start;
+        for line in lines {
+            if is_rule(line, rule) || (format == Format::Yaml && is_rule(line, "...")) {
+                let end = close + line.len();
+                return Some(Found {
+                    format,
+                    open: bom,
+                    start,
+                    close,
+                    end,
+                });
+            }
+            close += line.len();
         }
         // otherwise frontmatter never closed
     }
     // otherwise frontmatter never started
-    (None, s)
+    None
+}
+
+/// TOML datetimes have no YAML equivalent, so they become strings; see
+/// [`Datetimes`] for the way back.
+fn toml_to_yaml(value: toml::Value) -> serde_yaml::Value {
+    use serde_yaml::Value;
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Sequence(array.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Value::Mapping(
+            table
+                .into_iter()
+                .map(|(key, value)| (Value::String(key), toml_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Strings at `path` or under it are written back as TOML datetimes where
+/// `datetimes` says they were ones, so datetimes survive a round trip
+/// through YAML; any other string stays a string, however much it looks
+/// like a date.
+fn yaml_to_toml(
+    value: &serde_yaml::Value,
+    datetimes: &Datetimes,
+    path: &mut Vec<String>,
+) -> eyre::Result<toml::Value> {
+    use serde_yaml::Value;
+    let mut descend = |key: String, value| {
+        path.push(key);
+        let value = yaml_to_toml(value, datetimes, path);
+        path.pop();
+        value
+    };
+    Ok(match value {
+        Value::Null => return Err(eyre!("TOML has no null value")),
+        Value::Bool(b) => toml::Value::Boolean(*b),
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => toml::Value::Integer(i),
+            (None, Some(f)) if !n.is_u64() => toml::Value::Float(f),
+            _ => return Err(eyre!("{} is too large for a TOML integer", n)),
+        },
+        Value::String(s) => match s.parse::<toml::value::Datetime>() {
+            Ok(datetime) if datetimes.0.contains(path) => toml::Value::Datetime(datetime),
+            _ => toml::Value::String(s.clone()),
+        },
+        Value::Sequence(sequence) => toml::Value::Array(
+            sequence
+                .iter()
+                .enumerate()
+                .map(|(i, item)| descend(i.to_string(), item))
+                .collect::<eyre::Result<_>>()?,
+        ),
+        Value::Mapping(mapping) => toml::Value::Table(
+            mapping
+                .iter()
+                .map(|(key, value)| {
+                    let key = key
+                        .as_str()
+                        .ok_or_else(|| eyre!("TOML keys must be strings, got {:?}", key))?;
+                    let value =
+                        descend(key.to_owned(), value).context(format!("in key {}", key))?;
+                    Ok((key.to_owned(), value))
+                })
+                .collect::<eyre::Result<_>>()?,
+        ),
+        Value::Tagged(tagged) => {
+            return Err(eyre!("TOML has no tags, so can't write {}", tagged.tag))
+        }
+    })
 }
 
 #[cfg(test)]
@@ -68,6 +768,15 @@ hello: world
 
     const EXAMPLE_NO_YFM: &'_ str = "";
 
+    const EXAMPLE_TOML: &'_ str = "\
++++
+title = \"Hello\"
+date = 2020-01-02T03:04:05Z
+tags = [\"a\"]
++++
+# Title
+";
+
     #[test]
     fn parses_example_raw() {
         let (yfm, content) = parse_raw(EXAMPLE);
@@ -131,6 +840,250 @@ hello: world
         assert_eq!("", content);
     }
 
+    #[test]
+    fn finds_key_lines() {
+        assert_eq!(Some(2), key_line(EXAMPLE, "hello"));
+        assert_eq!(Some(3), key_line("+++\na = 1\nhello = 2\n+++\n", "hello"));
+        assert_eq!(None, key_line(EXAMPLE, "title"));
+        assert_eq!(None, key_line(EXAMPLE_NO_YFM, "hello"));
+    }
+
+    #[test]
+    fn recognizes_ignore_markers() {
+        assert!(is_ignored("---\nfixer: ignore\n---\nhi\n", None));
+        assert!(is_ignored("+++\nfixer = \"ignore\"\n+++\n", None));
+        assert!(is_ignored(
+            "hi\n  <!-- frontmatter-fixer: ignore -->\n",
+            None
+        ));
+        assert!(!is_ignored("---\nfixer: keep\n---\nhi\n", None));
+        assert!(!is_ignored(
+            "see <!-- frontmatter-fixer: ignore --> here\n",
+            None
+        ));
+    }
+
+    #[test]
+    fn splits_prefix_lines() {
+        let patterns = vec!["#!*".to_owned(), "<!--*-->".to_owned()];
+        let document = "#!/usr/bin/env lit\n<!-- generated -->\n---\na: 1\n---\n";
+        assert_eq!(
+            (
+                "#!/usr/bin/env lit\n<!-- generated -->\n",
+                "---\na: 1\n---\n"
+            ),
+            split_prefix(document, &patterns)
+        );
+        assert_eq!(("", EXAMPLE), split_prefix(EXAMPLE, &patterns));
+        assert_eq!(("", EXAMPLE), split_prefix(EXAMPLE, &[]));
+    }
+
+    #[test]
+    fn round_trips_toml() -> eyre::Result<()> {
+        assert_eq!(Some(Format::Toml), detect(EXAMPLE_TOML));
+
+        let (frontmatter, content) = parse(EXAMPLE_TOML);
+        let frontmatter = frontmatter.expect("should be present")?;
+        assert_eq!(Some("Hello"), frontmatter["title"].as_str());
+        assert_eq!(Some("2020-01-02T03:04:05Z"), frontmatter["date"].as_str());
+
+        let output = to_bytes_with_datetimes(
+            Format::Toml,
+            BlankLine::Preserve,
+            Some(&frontmatter),
+            content,
+            &Datetimes::find(EXAMPLE_TOML, None),
+        )?;
+        assert_eq!(EXAMPLE_TOML, String::from_utf8(output)?);
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_strings_that_look_like_dates_as_strings() -> eyre::Result<()> {
+        let (frontmatter, _) = parse("---\nversion: 2020-01-02\n---\n");
+        let output = to_bytes(
+            Format::Toml,
+            BlankLine::Preserve,
+            Some(&frontmatter.unwrap()?),
+            "",
+        )?;
+        assert_eq!(
+            "+++\nversion = \"2020-01-02\"\n+++\n",
+            String::from_utf8(output)?
+        );
+
+        let (frontmatter, _) = parse("---\nsecret: !vault abc\n---\n");
+        let err = to_bytes(
+            Format::Toml,
+            BlankLine::Preserve,
+            Some(&frontmatter.unwrap()?),
+            "",
+        )
+        .expect_err("TOML has no tags");
+        assert_eq!(
+            "in key secret: TOML has no tags, so can't write !vault",
+            format!("{:#}", err)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn converts_between_formats() -> eyre::Result<()> {
+        let (frontmatter, content) = parse(EXAMPLE);
+        let frontmatter = frontmatter.unwrap()?;
+        let output = to_bytes(
+            Format::Toml,
+            BlankLine::Preserve,
+            Some(&frontmatter),
+            content,
+        )?;
+        assert_eq!(
+            "+++\nhello = \"world\"\n+++\n# Title\n",
+            String::from_utf8(output)?
+        );
+
+        let (frontmatter, _) = parse_as("+++\nhello: world\n+++\n", Some(Format::Yaml));
+        assert_eq!(Some("world"), frontmatter.unwrap()?["hello"].as_str());
+        Ok(())
+    }
+
+    #[test]
+    fn shows_where_yaml_went_wrong() {
+        let (frontmatter, _) = parse("---\ntitle: Hi\n  bad: indent\n---\nBody\n");
+        let err = frontmatter.unwrap().unwrap_err().to_string();
+        assert!(err.contains("at line 3 column 6"), "{}", err);
+        assert!(
+            err.ends_with("\n2 | title: Hi\n3 |   bad: indent\n  |      ^"),
+            "{}",
+            err
+        );
+
+        let (frontmatter, _) = parse("+++\ntitle = \"Hi\"\ndraft = tru\n+++\nBody\n");
+        let err = frontmatter.unwrap().unwrap_err();
+        let err = err
+            .downcast_ref::<SyntaxError>()
+            .expect("not a SyntaxError");
+        assert_eq!((Some(3), Some(9)), (err.line, err.column));
+    }
+
+    #[test]
+    fn enforces_blank_line_policy() -> eyre::Result<()> {
+        let frontmatter: serde_yaml::Value = serde_yaml::from_str("a: 1\n")?;
+        let write = |blank_line, content| -> eyre::Result<String> {
+            let output = to_bytes(Format::Yaml, blank_line, Some(&frontmatter), content)?;
+            Ok(String::from_utf8(output)?)
+        };
+        assert_eq!(
+            "---\na: 1\n---\n\n# Title\n",
+            write(BlankLine::Always, "# Title\n")?
+        );
+        assert_eq!(
+            "---\na: 1\n---\n\n# Title\n",
+            write(BlankLine::Always, "\n \n# Title\n")?
+        );
+        assert_eq!(
+            "---\na: 1\n---\n# Title\n",
+            write(BlankLine::Never, "\n\n# Title\n")?
+        );
+        assert_eq!("---\na: 1\n---\n", write(BlankLine::Always, "\n")?);
+        assert_eq!(
+            "---\na: 1\n---\n\n\nx",
+            write(BlankLine::Preserve, "\n\nx")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn toml_has_no_null() {
+        let frontmatter: serde_yaml::Value = serde_yaml::from_str("draft: ~\n").unwrap();
+        let _ = to_bytes(Format::Toml, BlankLine::Preserve, Some(&frontmatter), "")
+            .expect_err("null isn't TOML");
+    }
+
+    #[test]
+    fn parses_crlf_and_bom() -> eyre::Result<()> {
+        let windows = "\u{feff}---\r\nhello: world\r\n---\r\n# Title\r\n";
+        let (yfm, content) = parse_raw(windows);
+        assert_eq!(Some("hello: world\r\n"), yfm);
+        assert_eq!("# Title\r\n", content);
+        assert_eq!(Some("world"), parse(windows).0.unwrap()?["hello"].as_str());
+        assert_eq!(Some(2), key_line(windows, "hello"));
+        assert_eq!(None, parse_raw("---\na: 1\nb: 2---\n").0);
+        Ok(())
+    }
+
+    #[test]
+    fn preserves_text_style() {
+        let windows = "\u{feff}---\r\nhello: world\r\n---\r\n# Title\r\n";
+        let style = TextStyle::detect(windows);
+        assert_eq!(
+            TextStyle {
+                bom: true,
+                crlf: true
+            },
+            style
+        );
+        let normalized = style.normalize(windows);
+        assert_eq!(EXAMPLE, normalized);
+        assert_eq!(
+            windows.as_bytes(),
+            style.restore(normalized.as_bytes().to_vec())
+        );
+
+        let style = TextStyle::detect(EXAMPLE);
+        assert_eq!(TextStyle::default(), style);
+        assert_eq!(EXAMPLE, style.normalize(EXAMPLE));
+
+        let crlf = LineEndings::Crlf.apply(style);
+        assert_eq!(b"a\r\nb\r\n".to_vec(), crlf.restore(b"a\nb\n".to_vec()));
+        assert_eq!(TextStyle::default(), LineEndings::Lf.apply(crlf));
+        assert_eq!(crlf, LineEndings::Preserve.apply(crlf));
+    }
+
+    #[test]
+    fn parses_other_fences() {
+        assert_eq!(
+            (Some("a: 1\n"), "Body\n"),
+            parse_raw("---\na: 1\n...\nBody\n")
+        );
+        assert_eq!(
+            (Some("a: 1\n"), "Body\n"),
+            parse_raw("--- \na: 1\n---\t\nBody\n")
+        );
+        assert_eq!((Some("a: 1\n"), ""), parse_raw("---\na: 1\n---"));
+        assert_eq!(None, parse_raw("+++\na = 1\n...\n").0);
+        assert_eq!(None, parse_raw("---").0);
+    }
+
+    #[test]
+    fn restores_fences() -> eyre::Result<()> {
+        let frontmatter: serde_yaml::Value = serde_yaml::from_str("a: 2\n")?;
+        let rewrite = |original: &str, content: &str| -> eyre::Result<String> {
+            let fences = Fences::detect(original).expect("should have fences");
+            let output = to_bytes(
+                Format::Yaml,
+                BlankLine::Preserve,
+                Some(&frontmatter),
+                content,
+            )?;
+            Ok(String::from_utf8(fences.restore(output))?)
+        };
+        assert_eq!(
+            "--- \na: 2\n...\nBody\n",
+            rewrite("--- \na: 1\n...\nBody\n", "Body\n")?
+        );
+        assert_eq!("---\na: 2\n---", rewrite("---\na: 1\n---", "")?);
+        assert_eq!(
+            "---\na: 2\n---\nBody\n",
+            rewrite("---\na: 1\n---", "Body\n")?
+        );
+
+        let fences = Fences::detect("---\na: 1\n...\n").unwrap();
+        let toml = to_bytes(Format::Toml, BlankLine::Preserve, Some(&frontmatter), "")?;
+        assert_eq!(toml, fences.restore(toml.clone()));
+        Ok(())
+    }
+
     #[test]
     fn parses_no_yfm() {
         let (yfm, content) = parse(EXAMPLE_NO_YFM);
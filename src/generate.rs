@@ -0,0 +1,440 @@
+use std::{collections::BTreeMap, fmt, path::Path, str::FromStr, sync::Arc};
+
+use eyre::eyre;
+use serde_yaml as yaml;
+
+use crate::{
+    date::{self, Date},
+    helpers, keypath,
+};
+
+/// A generator: given its arguments, evaluated, and any raw argument after
+/// a colon, as in `now:%Y`, the value it makes for the file in `context`.
+pub type Generator =
+    Arc<dyn Fn(&[yaml::Value], Option<&str>, &Context) -> eyre::Result<yaml::Value> + Send + Sync>;
+
+/// The file a value is generated for.
+#[derive(Clone, Copy, Debug)]
+pub struct Context<'a> {
+    pub path: &'a str,
+    /// The frontmatter as it is so far.
+    pub metadata: Option<&'a yaml::Value>,
+}
+
+/// The generators `{{ }}` expressions can call, by name.
+///
+/// The built-in ones are:
+///
+/// - `uuid`: a random version 4 UUID
+/// - `now`: the time as an RFC 3339 UTC timestamp, or with a format,
+///   `now:%Y-%m-%d`, today's date in it
+/// - `slugify(s)`, `lower(s)`, `upper(s)` and `trim(s)`, as in scripts
+/// - `default(value, fallback)`: `value`, unless it's missing or null
+#[derive(Clone)]
+pub struct Generators {
+    generators: BTreeMap<String, Generator>,
+}
+
+impl fmt::Debug for Generators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.generators.keys()).finish()
+    }
+}
+
+impl Default for Generators {
+    fn default() -> Self {
+        let mut generators = Self {
+            generators: BTreeMap::new(),
+        };
+        generators.register("uuid", |_, _, _| {
+            Ok(uuid::Uuid::new_v4().to_string().into())
+        });
+        generators.register("now", |_, format, _| match format {
+            Some(format) => Date::today()
+                .format(format)
+                .map(Into::into)
+                .map_err(|e| eyre!(e)),
+            None => Ok(date::now_timestamp().into()),
+        });
+        generators.register("slugify", |args, _, _| {
+            Ok(helpers::slugify(&text(one(args)?)).into())
+        });
+        generators.register("lower", |args, _, _| {
+            Ok(text(one(args)?).to_lowercase().into())
+        });
+        generators.register("upper", |args, _, _| {
+            Ok(text(one(args)?).to_uppercase().into())
+        });
+        generators.register("trim", |args, _, _| Ok(text(one(args)?).trim().into()));
+        generators.register("default", |args, _, _| match args {
+            [yaml::Value::Null, fallback] => Ok(fallback.clone()),
+            [value, _] => Ok(value.clone()),
+            _ => Err(eyre!("expected 2 arguments, got {}", args.len())),
+        });
+        generators
+    }
+}
+
+impl Generators {
+    /// Add a generator, or replace the one of the same name.
+    pub fn register(
+        &mut self,
+        name: &str,
+        generator: impl Fn(&[yaml::Value], Option<&str>, &Context) -> eyre::Result<yaml::Value>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        self.generators.insert(name.to_owned(), Arc::new(generator));
+    }
+
+    fn call(
+        &self,
+        name: &str,
+        args: &[yaml::Value],
+        raw: Option<&str>,
+        context: &Context,
+    ) -> eyre::Result<yaml::Value> {
+        let generator = self
+            .generators
+            .get(name)
+            .ok_or_else(|| eyre!("unknown generator {}", name))?;
+        generator(args, raw, context).map_err(|e| eyre!("{}: {}", name, e))
+    }
+}
+
+/// A value to set, text with generator expressions in `{{ }}`, e.g.
+/// `{{uuid}}`, `{{now:%Y-%m-%d}}` or `post-{{slugify(meta.title)}}`, or
+/// without any, a YAML value.
+///
+/// Expressions are a generator's name, alone, with a raw argument after a
+/// colon, or with expressions as arguments in parentheses; a variable,
+/// `meta.KEY` (a dotted path) or `file.path`, `file.name` or `file.stem`;
+/// or a string in single quotes. A value that's nothing but one expression
+/// keeps that expression's type, so `{{meta.tags}}` copies a list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Literal(String),
+    Meta(String),
+    File(FileField),
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        raw: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FileField {
+    Path,
+    Name,
+    Stem,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| format!("unclosed {{{{ in {:?}", s))?;
+            let expr = rest[start + 2..start + end].trim();
+            parts.push(Part::Expr(parse_expr(expr)?));
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    /// Generate the value for the file in `context`.
+    pub fn evaluate(
+        &self,
+        generators: &Generators,
+        context: &Context,
+    ) -> eyre::Result<yaml::Value> {
+        match self.parts.as_slice() {
+            [Part::Expr(expr)] => evaluate(expr, generators, context),
+            // a plain value is YAML, so draft=false sets a boolean
+            [Part::Text(text)] => Ok(yaml::from_str(text).unwrap_or_else(|_| text.as_str().into())),
+            parts => {
+                let mut out = String::new();
+                for part in parts {
+                    match part {
+                        Part::Text(text) => out.push_str(text),
+                        Part::Expr(expr) => {
+                            out.push_str(&text(&evaluate(expr, generators, context)?))
+                        }
+                    }
+                }
+                Ok(out.into())
+            }
+        }
+    }
+}
+
+/// `--set`'s argument: a key, a dotted path, and the value to give it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assignment {
+    pub key: String,
+    pub value: Template,
+}
+
+impl FromStr for Assignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got {:?}", s))?;
+        Ok(Self {
+            key: key.trim().to_owned(),
+            value: value.parse()?,
+        })
+    }
+}
+
+impl Assignment {
+    /// Set the key to its value generated for the file at `path`, or with
+    /// `missing_only` only if it's missing or null, returning a description
+    /// of what was set.
+    pub fn apply(
+        &self,
+        metadata: &mut Option<yaml::Value>,
+        missing_only: bool,
+        generators: &Generators,
+        path: &str,
+    ) -> eyre::Result<Option<String>> {
+        let existing = metadata
+            .as_ref()
+            .and_then(|metadata| keypath::get(metadata, &self.key));
+        if missing_only && !matches!(existing, None | Some(yaml::Value::Null)) {
+            return Ok(None);
+        }
+        let context = Context {
+            path,
+            metadata: metadata.as_ref(),
+        };
+        let value = self
+            .value
+            .evaluate(generators, &context)
+            .map_err(|e| eyre!("couldn't generate {}: {}", self.key, e))?;
+        let described = format!("{} = {}", self.key, text(&value));
+        let metadata = metadata.get_or_insert_with(|| yaml::Value::Mapping(yaml::Mapping::new()));
+        keypath::set(metadata, &self.key, value)
+            .map_err(|_| eyre!("can't set {}: part of the path isn't a mapping", self.key))?;
+        Ok(Some(described))
+    }
+}
+
+fn parse_expr(expr: &str) -> Result<Expr, String> {
+    if let Some(literal) = expr.strip_prefix('\'') {
+        return literal
+            .strip_suffix('\'')
+            .map(|literal| Expr::Literal(literal.to_owned()))
+            .ok_or_else(|| format!("unclosed string in {{{{{}}}}}", expr));
+    }
+    if let Some(key) = expr.strip_prefix("meta.") {
+        return Ok(Expr::Meta(key.to_owned()));
+    }
+    if let Some(field) = expr.strip_prefix("file.") {
+        return match field {
+            "path" => Ok(Expr::File(FileField::Path)),
+            "name" => Ok(Expr::File(FileField::Name)),
+            "stem" => Ok(Expr::File(FileField::Stem)),
+            _ => Err(format!(
+                "unknown file field {}, expected path, name or stem",
+                field
+            )),
+        };
+    }
+    if let Some((name, args)) = expr.split_once('(') {
+        let args = args
+            .strip_suffix(')')
+            .ok_or_else(|| format!("unclosed ( in {{{{{}}}}}", expr))?;
+        let args = split_args(args)
+            .into_iter()
+            .map(parse_expr)
+            .collect::<Result<_, _>>()?;
+        return Ok(Expr::Call {
+            name: ident(name.trim())?,
+            args,
+            raw: None,
+        });
+    }
+    let (name, raw) = match expr.split_once(':') {
+        Some((name, raw)) => (name, Some(raw.to_owned())),
+        None => (expr, None),
+    };
+    Ok(Expr::Call {
+        name: ident(name.trim())?,
+        args: Vec::new(),
+        raw,
+    })
+}
+
+/// Split arguments at top-level commas, outside quotes and parentheses.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let (mut depth, mut quoted, mut start) = (0, false, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                split.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = args[start..].trim();
+    if !last.is_empty() || !split.is_empty() {
+        split.push(last);
+    }
+    split
+}
+
+fn ident(name: &str) -> Result<String, String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Ok(name.to_owned())
+    } else {
+        Err(format!("expected a generator name, got {:?}", name))
+    }
+}
+
+fn evaluate(expr: &Expr, generators: &Generators, context: &Context) -> eyre::Result<yaml::Value> {
+    Ok(match expr {
+        Expr::Literal(literal) => literal.clone().into(),
+        Expr::Meta(key) => context
+            .metadata
+            .and_then(|metadata| keypath::get(metadata, key))
+            .cloned()
+            .unwrap_or(yaml::Value::Null),
+        Expr::File(field) => {
+            let path = Path::new(context.path);
+            let part = match field {
+                FileField::Path => Some(path.as_os_str()),
+                FileField::Name => path.file_name(),
+                FileField::Stem => path.file_stem(),
+            };
+            part.unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+                .into()
+        }
+        Expr::Call { name, args, raw } => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate(arg, generators, context))
+                .collect::<eyre::Result<Vec<_>>>()?;
+            generators.call(name, &args, raw.as_deref(), context)?
+        }
+    })
+}
+
+fn one(args: &[yaml::Value]) -> eyre::Result<&yaml::Value> {
+    match args {
+        [arg] => Ok(arg),
+        _ => Err(eyre!("expected 1 argument, got {}", args.len())),
+    }
+}
+
+/// A value as text, as it would be interpolated.
+fn text(value: &yaml::Value) -> String {
+    match value {
+        yaml::Value::Null => String::new(),
+        yaml::Value::String(s) => s.clone(),
+        yaml::Value::Bool(b) => b.to_string(),
+        yaml::Value::Number(n) => n.to_string(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn generate(template: &str, metadata: &str) -> eyre::Result<yaml::Value> {
+        let template: Template = template.parse().map_err(|e: String| eyre!(e))?;
+        let metadata: yaml::Value = yaml::from_str(metadata)?;
+        let context = Context {
+            path: "posts/hello-world.md",
+            metadata: Some(&metadata),
+        };
+        template.evaluate(&Generators::default(), &context)
+    }
+
+    #[test]
+    fn generates_values() -> eyre::Result<()> {
+        let meta = "title: Hello, World!\ntags: [a, b]\n";
+        assert_eq!(
+            yaml::Value::from("/hello-world/"),
+            generate("/{{ slugify(meta.title) }}/", meta)?
+        );
+        assert_eq!(
+            generate("{{meta.tags}}", meta)?,
+            yaml::from_str::<yaml::Value>("[a, b]")?
+        );
+        assert_eq!(
+            yaml::Value::from("hello-world.md by anon"),
+            generate("{{file.name}} by {{default(meta.author, 'anon')}}", meta)?
+        );
+        assert_eq!(
+            yaml::Value::from(Date::today().to_string()),
+            generate("{{now:%Y-%m-%d}}", meta)?
+        );
+        let uuid = generate("{{uuid}}", meta)?;
+        let uuid = uuid.as_str().unwrap();
+        assert_eq!((36, Some('4')), (uuid.len(), uuid.chars().nth(14)));
+        assert_ne!(generate("{{uuid}}", meta)?, generate("{{uuid}}", meta)?);
+
+        assert!(generate("{{nope}}", meta).is_err());
+        assert!("{{uuid".parse::<Template>().is_err());
+        assert!("{{file.size}}".parse::<Template>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sets_missing_keys() -> eyre::Result<()> {
+        let set = |assignment: &str, metadata: &mut Option<yaml::Value>| {
+            let assignment: Assignment = assignment.parse().map_err(|e: String| eyre!(e))?;
+            assignment.apply(metadata, true, &Generators::default(), "a.md")
+        };
+        let mut metadata = Some(yaml::from_str("title: Hi There\ndraft: no\n")?);
+        assert_eq!(
+            Some("slug = hi-there".to_owned()),
+            set("slug={{slugify(meta.title)}}", &mut metadata)?
+        );
+        assert_eq!(None, set("draft=true", &mut metadata)?);
+        let mut empty = None;
+        set("draft=false", &mut empty)?;
+        assert_eq!(
+            "title: Hi There\ndraft: no\nslug: hi-there\n",
+            yaml::to_string(&metadata)?
+        );
+        assert_eq!("draft: false\n", yaml::to_string(&empty)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use std::{ffi::OsStr, path::PathBuf, process::Command};
+
+use eyre::{eyre, Context};
+
+/// Run git with the given arguments, returning its stdout.
+fn git<I, S>(args: I) -> eyre::Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("couldn't run git")?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).context("git output wasn't UTF-8")
+}
+
+/// Check we're in a git work tree with nothing already staged, so a commit
+/// of the files we change won't sweep up anything unrelated.
+pub fn ensure_clean_index() -> eyre::Result<()> {
+    git(["rev-parse", "--is-inside-work-tree"]).context("not inside a git work tree")?;
+    let staged = git(["diff", "--cached", "--name-only"])?;
+    if !staged.trim().is_empty() {
+        return Err(eyre!(
+            "refusing to commit: unrelated changes are already staged:\n{}",
+            staged.trim_end()
+        ));
+    }
+    Ok(())
+}
+
+/// Stage exactly `paths` and commit them.
+pub fn commit(paths: &[PathBuf], message: &str, sign: bool) -> eyre::Result<()> {
+    let mut add_args: Vec<&OsStr> = vec!["add".as_ref(), "--".as_ref()];
+    add_args.extend(paths.iter().map(|path| path.as_os_str()));
+    git(add_args).context("couldn't stage changed files")?;
+
+    let mut commit_args = vec!["commit", "--quiet", "-m", message];
+    if sign {
+        commit_args.push("--gpg-sign");
+    }
+    git(commit_args).context("couldn't commit changed files")?;
+    Ok(())
+}
+
+/// The files `git status` reports as modified, added or untracked, as
+/// paths relative to the current directory. Deleted files are left out.
+pub fn changed_files() -> eyre::Result<Vec<PathBuf>> {
+    let status = git(["status", "--porcelain", "-z", "--untracked-files=all"])
+        .context("couldn't list changed files")?;
+    // porcelain paths are relative to the top of the work tree
+    let prefix = git(["rev-parse", "--show-prefix"])?;
+    let cdup = git(["rev-parse", "--show-cdup"])?;
+    Ok(parse_status(&status)
+        .into_iter()
+        .map(|path| match path.strip_prefix(prefix.trim_end()) {
+            Some(relative) => PathBuf::from(relative),
+            None => PathBuf::from(cdup.trim_end()).join(path),
+        })
+        .collect())
+}
+
+/// The paths in `git status --porcelain -z` output that still exist.
+fn parse_status(status: &str) -> Vec<&str> {
+    let mut paths = Vec::new();
+    let mut entries = status.split('\0').filter(|entry| !entry.is_empty());
+    while let Some(entry) = entries.next() {
+        let (Some(code), Some(path)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+        if code.starts_with(['R', 'C']) {
+            // the path it was renamed or copied from
+            entries.next();
+        }
+        if !code.contains('D') {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// A commit that touched a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Revision {
+    pub commit: String,
+    pub author: String,
+    /// The commit's author date, as YYYY-MM-DD.
+    pub date: String,
+    /// The file's path in the commit, relative to the top of the work tree,
+    /// which differs from its path now if it's since been renamed.
+    pub path: String,
+}
+
+/// The commits that touched the file at `path`, oldest first, following it
+/// through renames.
+pub fn file_history(path: &str) -> eyre::Result<Vec<Revision>> {
+    let log = git([
+        "log",
+        "--follow",
+        "--format=%x1e%H%x1f%an%x1f%ad",
+        "--date=short",
+        "--name-only",
+        "--",
+        path,
+    ])
+    .context(format!("couldn't read history of {}", path))?;
+    let mut revisions: Vec<Revision> = log
+        .split('\x1e')
+        .filter(|record| !record.trim().is_empty())
+        .map(|record| {
+            let mut lines = record.lines().filter(|line| !line.is_empty());
+            let header = lines.next().unwrap_or_default();
+            let mut fields = header.split('\x1f').map(str::to_owned);
+            let mut field = || fields.next().unwrap_or_default();
+            Revision {
+                commit: field(),
+                author: field(),
+                date: field(),
+                path: lines.next().unwrap_or(path).to_owned(),
+            }
+        })
+        .collect();
+    revisions.reverse();
+    Ok(revisions)
+}
+
+/// What the file at `path` (relative to the top of the work tree) held in
+/// `commit`, or `None` if it didn't exist there, as after a deletion.
+pub fn show(commit: &str, path: &str) -> Option<String> {
+    git(["show", &format!("{}:{}", commit, path)]).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_porcelain_status() {
+        let status = " M a.md\0?? new/b.md\0 D gone.md\0R  c.md\0old/c.md\0A  d.md\0";
+        assert_eq!(
+            vec!["a.md", "new/b.md", "c.md", "d.md"],
+            parse_status(status)
+        );
+    }
+}
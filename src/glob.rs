@@ -0,0 +1,79 @@
+/// Match `text` against a shell-style glob `pattern`.
+///
+/// `*` matches any run of characters within a single segment, `**` matches
+/// across segments, and `?` matches one non-separator character. Segments
+/// are delimited by `sep`, which is `/` for file paths and `.` for dotted
+/// key paths.
+pub fn matches(pattern: &str, text: &str, sep: char) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_chars(&pattern, &text, sep)
+}
+
+fn matches_chars(pattern: &[char], text: &[char], sep: char) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may also match zero segments
+            if let [first, after_sep @ ..] = rest {
+                if *first == sep && matches_chars(after_sep, text, sep) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| matches_chars(rest, &text[i..], sep))
+        }
+        ['*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if matches_chars(rest, &text[i..], sep) {
+                    return true;
+                }
+                if i < text.len() && text[i] == sep {
+                    break;
+                }
+            }
+            false
+        }
+        ['?', rest @ ..] => match text {
+            [c, text_rest @ ..] if *c != sep => matches_chars(rest, text_rest, sep),
+            _ => false,
+        },
+        [p, rest @ ..] => match text {
+            [c, text_rest @ ..] if c == p => matches_chars(rest, text_rest, sep),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_literals() {
+        assert!(matches("reviewer", "reviewer", '.'));
+        assert!(!matches("reviewer", "reviewers", '.'));
+    }
+
+    #[test]
+    fn star_stays_within_segment() {
+        assert!(matches("internal_*", "internal_notes", '.'));
+        assert!(!matches("internal_*", "internal_notes.author", '.'));
+        assert!(matches("*.md", "post.md", '/'));
+        assert!(!matches("*.md", "drafts/post.md", '/'));
+    }
+
+    #[test]
+    fn double_star_crosses_segments() {
+        assert!(matches("**/*.md", "post.md", '/'));
+        assert!(matches("**/*.md", "a/b/post.md", '/'));
+        assert!(matches("drafts/**", "drafts/a/b.md", '/'));
+        assert!(!matches("drafts/**", "posts/a.md", '/'));
+    }
+
+    #[test]
+    fn question_mark_matches_one_char() {
+        assert!(matches("v?", "v1", '.'));
+        assert!(!matches("v?", "v12", '.'));
+        assert!(!matches("a?b", "a.b", '.'));
+    }
+}
@@ -0,0 +1,181 @@
+use mlua::{Lua, LuaSerdeExt, Table};
+
+use crate::{date::Date, lua_yaml::map_metatable, order::KeyOrder};
+
+/// The registry slot `fm.sort_keys` stashes the order the current
+/// document's keys are to be written in.
+pub(crate) const KEY_ORDER: &str = "frontmatter_fixer_key_order";
+
+/// Register the `fm` table of helper functions scripts can call:
+///
+/// - `fm.slugify(s)`: lowercase, with runs of anything but letters and
+///   digits replaced by single hyphens
+/// - `fm.parse_date(s)`: a `{year, month, day}` table, or nil if `s` isn't
+///   a date in one of the formats `--coerce` understands
+/// - `fm.format_date(date, format)`: format a date table or string with
+///   strftime-style directives (default `%Y-%m-%d`)
+/// - `fm.trim(s)`, `fm.titlecase(s)`, `fm.split(s, sep)`,
+///   `fm.starts_with(s, prefix)` and `fm.ends_with(s, suffix)`, taking
+///   plain strings rather than Lua patterns
+/// - `fm.array(t)` and `fm.map(t)`: mark a table (default a new empty one)
+///   to be written as a YAML list or mapping, e.g. `fm.array{}` for an empty
+///   list or `fm.map{"a", "b"}` for a mapping with keys 1 and 2, where Lua
+///   would otherwise have to guess
+/// - `fm.sort_keys(meta, order)`: write the document's frontmatter with the
+///   keys listed in `order` first, in that order, then the rest sorted,
+///   since the order of a Lua table's keys is lost; returns `meta`
+pub(crate) fn register(lua: &Lua) -> mlua::Result<()> {
+    let fm = lua.create_table()?;
+    fm.set(
+        "slugify",
+        lua.create_function(|_, s: String| Ok(slugify(&s)))?,
+    )?;
+    fm.set("parse_date", lua.create_function(lua_parse_date)?)?;
+    fm.set("format_date", lua.create_function(lua_format_date)?)?;
+    fm.set(
+        "trim",
+        lua.create_function(|_, s: String| Ok(s.trim().to_owned()))?,
+    )?;
+    fm.set(
+        "titlecase",
+        lua.create_function(|_, s: String| Ok(titlecase(&s)))?,
+    )?;
+    fm.set(
+        "split",
+        lua.create_function(|_, (s, sep): (String, String)| {
+            if sep.is_empty() {
+                return Err(mlua::Error::external("can't split on an empty separator"));
+            }
+            Ok(s.split(sep.as_str()).map(str::to_owned).collect::<Vec<_>>())
+        })?,
+    )?;
+    fm.set(
+        "starts_with",
+        lua.create_function(|_, (s, prefix): (String, String)| Ok(s.starts_with(&prefix)))?,
+    )?;
+    fm.set(
+        "ends_with",
+        lua.create_function(|_, (s, suffix): (String, String)| Ok(s.ends_with(&suffix)))?,
+    )?;
+    fm.set(
+        "array",
+        lua.create_function(|lua, t: Option<Table>| {
+            let t = t.map_or_else(|| lua.create_table(), Ok)?;
+            t.set_metatable(Some(lua.array_metatable()));
+            Ok(t)
+        })?,
+    )?;
+    fm.set(
+        "map",
+        lua.create_function(|lua, t: Option<Table>| {
+            let t = t.map_or_else(|| lua.create_table(), Ok)?;
+            t.set_metatable(Some(map_metatable(lua)?));
+            Ok(t)
+        })?,
+    )?;
+    fm.set(
+        "sort_keys",
+        lua.create_function(|lua, (meta, order): (mlua::Value, Option<Vec<String>>)| {
+            lua.set_named_registry_value(KEY_ORDER, order.unwrap_or_default())?;
+            Ok(meta)
+        })?,
+    )?;
+    lua.globals().set("fm", fm)
+}
+
+/// The order `fm.sort_keys` asked for the current document's keys to be
+/// written in, if it was called.
+pub(crate) fn key_order(lua: &Lua) -> mlua::Result<Option<KeyOrder>> {
+    let order: Option<Vec<String>> = lua.named_registry_value(KEY_ORDER)?;
+    Ok(order.map(KeyOrder::new))
+}
+
+fn lua_parse_date(lua: &Lua, s: String) -> mlua::Result<Option<Table<'_>>> {
+    let date = match Date::parse(&s) {
+        Some(date) => date,
+        None => return Ok(None),
+    };
+    let table = lua.create_table()?;
+    table.set("year", date.year)?;
+    table.set("month", date.month)?;
+    table.set("day", date.day)?;
+    Ok(Some(table))
+}
+
+fn lua_format_date(_: &Lua, (date, format): (mlua::Value, Option<String>)) -> mlua::Result<String> {
+    let date = match date {
+        mlua::Value::String(s) => Date::parse(s.to_str()?),
+        mlua::Value::Table(table) => {
+            Date::new(table.get("year")?, table.get("month")?, table.get("day")?)
+        }
+        _ => return Err(mlua::Error::external("expected a date table or string")),
+    };
+    let date = date.ok_or_else(|| mlua::Error::external("not a valid date"))?;
+    date.format(format.as_deref().unwrap_or("%Y-%m-%d"))
+        .map_err(mlua::Error::external)
+}
+
+pub(crate) fn slugify(s: &str) -> String {
+    let mut slug = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(slug.trim_end_matches('-').len());
+    slug
+}
+
+/// Capitalise the first letter of each word, leaving the rest alone so
+/// acronyms survive.
+fn titlecase(s: &str) -> String {
+    let mut titled = String::with_capacity(s.len());
+    let mut word_start = true;
+    for c in s.chars() {
+        if word_start {
+            titled.extend(c.to_uppercase());
+        } else {
+            titled.push(c);
+        }
+        word_start = c.is_whitespace();
+    }
+    titled
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slugifies() {
+        assert_eq!("hello-world", slugify("  Hello, World!  "));
+        assert_eq!("c-and-rust-2023", slugify("C++ and Rust (2023)"));
+        assert_eq!("café", slugify("Café"));
+    }
+
+    #[test]
+    fn titlecases() {
+        assert_eq!("Using HTTP In Rust", titlecase("using HTTP in rust"));
+    }
+
+    #[test]
+    fn scripts_can_call_helpers() -> mlua::Result<()> {
+        let lua = Lua::new();
+        register(&lua)?;
+        let result: String = lua
+            .load(
+                r#"
+                local date = fm.parse_date("March 5, 2023")
+                local parts = fm.split("a, b", ", ")
+                return fm.format_date(date, "%d %b %Y") .. " " .. fm.format_date("2023-03-05")
+                    .. " " .. fm.slugify(fm.trim(" Hi There ")) .. " " .. parts[2]
+                    .. " " .. tostring(fm.parse_date("soon"))
+            "#,
+            )
+            .eval()?;
+        assert_eq!("05 Mar 2023 2023-03-05 hi-there b nil", result);
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use std::{fmt, fs::read_to_string};
+
+use eyre::Context;
+use serde_yaml as yaml;
+
+use crate::{
+    feed::scalar,
+    frontmatter,
+    git::{self, Revision},
+    keypath,
+    walk::{self, Filter},
+};
+
+/// How a change shows a file not having the key.
+const NO_VALUE: &str = "(none)";
+
+/// How many characters of a commit hash to show.
+const SHORT_COMMIT_LEN: usize = 10;
+
+/// A commit that changed the value of a frontmatter key in a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub path: String,
+    pub revision: Revision,
+    /// `None` if the key wasn't set before.
+    pub old: Option<String>,
+    /// `None` if the commit removed the key, or the file.
+    pub new: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} {} {}: {} -> {}",
+            self.path,
+            self.revision.date,
+            short(&self.revision.commit),
+            self.revision.author,
+            self.old.as_deref().unwrap_or(NO_VALUE),
+            self.new.as_deref().unwrap_or(NO_VALUE)
+        )
+    }
+}
+
+/// Every commit that changed `key` in the files given, and those in any
+/// directories given and their subdirectories, oldest first for each file.
+///
+/// Only the key's value counts, so commits that just reformat the
+/// frontmatter or edit the body aren't changes. Versions whose frontmatter
+/// doesn't parse are passed over.
+pub fn changes(key: &str, paths: &[String]) -> eyre::Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    for path in walk::expand(paths, &Filter::default())? {
+        let versions = git::file_history(&path)?.into_iter().map(|revision| {
+            let source = git::show(&revision.commit, &revision.path);
+            (revision, source)
+        });
+        changes.extend(key_changes(&path, key, versions));
+    }
+    Ok(changes)
+}
+
+/// The changes to `key` across versions of the file at `path`, each the
+/// revision and what the file held then (`None` if it was deleted).
+fn key_changes(
+    path: &str,
+    key: &str,
+    versions: impl Iterator<Item = (Revision, Option<String>)>,
+) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut previous: Option<String> = None;
+    for (revision, source) in versions {
+        let value = match &source {
+            Some(source) => match frontmatter::parse(source).0 {
+                Some(Ok(metadata)) => keypath::get(&metadata, key).and_then(display),
+                Some(Err(_)) => continue,
+                None => None,
+            },
+            None => None,
+        };
+        if value != previous {
+            changes.push(Change {
+                path: path.to_owned(),
+                revision,
+                old: previous,
+                new: value.clone(),
+            });
+            previous = value;
+        }
+    }
+    changes
+}
+
+/// The commit that last changed one of a file's frontmatter keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribution {
+    pub key: String,
+    pub value: String,
+    /// `None` if the key's current value hasn't been committed.
+    pub revision: Option<Revision>,
+}
+
+impl fmt::Display for Attribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.revision {
+            Some(revision) => write!(
+                f,
+                "{} ({} {})",
+                short(&revision.commit),
+                revision.author,
+                revision.date
+            )?,
+            None => write!(f, "{} (not committed yet)", "0".repeat(SHORT_COMMIT_LEN))?,
+        }
+        write!(f, " {}: {}", self.key, self.value)
+    }
+}
+
+/// Which commit last changed each of the top-level frontmatter keys of the
+/// file at `path`, in the order the file has them.
+///
+/// As with `changes`, this goes by the keys' values rather than lines, so
+/// reordering or reformatting keys doesn't take the credit.
+pub fn blame(path: &str) -> eyre::Result<Vec<Attribution>> {
+    let source = read_to_string(path).context(format!("couldn't read {}", path))?;
+    let current = frontmatter::parse(&source)
+        .0
+        .transpose()
+        .context(format!("couldn't parse frontmatter in {}", path))?;
+    let versions = git::file_history(path)?.into_iter().map(|revision| {
+        let source = git::show(&revision.commit, &revision.path);
+        (revision, source)
+    });
+    Ok(attribute(current.as_ref(), versions))
+}
+
+/// Attribute each key of `current` to the last of the versions, oldest
+/// first, to change it to its current value.
+fn attribute(
+    current: Option<&yaml::Value>,
+    versions: impl Iterator<Item = (Revision, Option<String>)>,
+) -> Vec<Attribution> {
+    let mapping = match current {
+        Some(yaml::Value::Mapping(mapping)) => mapping,
+        _ => return Vec::new(),
+    };
+    let keys: Vec<String> = mapping.keys().filter_map(scalar).collect();
+    // the revision each key last changed in, and its value then
+    let mut last_changes: Vec<Option<(Revision, Option<String>)>> = vec![None; keys.len()];
+    for (revision, source) in versions {
+        let metadata = match source.as_deref().map(|source| frontmatter::parse(source).0) {
+            Some(Some(Ok(metadata))) => Some(metadata),
+            Some(Some(Err(_))) => continue,
+            _ => None,
+        };
+        for (key, last_change) in keys.iter().zip(&mut last_changes) {
+            let value = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get(key.as_str()))
+                .and_then(display);
+            let previous = last_change.as_ref().and_then(|(_, value)| value.clone());
+            if last_change.is_none() || value != previous {
+                *last_change = Some((revision.clone(), value));
+            }
+        }
+    }
+    keys.into_iter()
+        .zip(last_changes)
+        .map(|(key, last_change)| {
+            let value = mapping.get(key.as_str()).and_then(display);
+            let revision = match last_change {
+                Some((revision, committed)) if committed == value => Some(revision),
+                _ => None,
+            };
+            Attribution {
+                key,
+                value: value.unwrap_or_default(),
+                revision,
+            }
+        })
+        .collect()
+}
+
+fn short(commit: &str) -> &str {
+    commit.get(..SHORT_COMMIT_LEN).unwrap_or(commit)
+}
+
+fn display(value: &yaml::Value) -> Option<String> {
+    match value {
+        yaml::Value::Null => None,
+        yaml::Value::Sequence(_) | yaml::Value::Mapping(_) => serde_json::to_string(value).ok(),
+        value => scalar(value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn revision(commit: &str) -> Revision {
+        Revision {
+            commit: commit.into(),
+            author: "Ann".into(),
+            date: "2023-01-01".into(),
+            path: "post.md".into(),
+        }
+    }
+
+    #[test]
+    fn finds_changes_to_key() {
+        let versions = [
+            ("a", Some("---\ntitle: Post\n---\nHello\n")),
+            (
+                "b",
+                Some("---\ntitle: Post\ncanonical_url: https://a.example\n---\nHello\n"),
+            ),
+            (
+                "c",
+                Some("---\ncanonical_url: \"https://a.example\"\ntitle: Post\n---\nHi\n"),
+            ),
+            ("d", Some("---\ncanonical_url: [unclosed\n---\n")),
+            (
+                "e",
+                Some("---\ncanonical_url: https://b.example\n---\nHi\n"),
+            ),
+            ("f", None),
+        ];
+        let versions = versions
+            .iter()
+            .map(|(commit, source)| (revision(commit), source.map(str::to_owned)));
+        let changes: Vec<(String, Option<String>, Option<String>)> =
+            key_changes("post.md", "canonical_url", versions)
+                .into_iter()
+                .map(|change| (change.revision.commit, change.old, change.new))
+                .collect();
+        let url = |host: &str| Some(format!("https://{}.example", host));
+        assert_eq!(
+            vec![
+                ("b".into(), None, url("a")),
+                ("e".into(), url("a"), url("b")),
+                ("f".into(), url("b"), None),
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn displays_change() {
+        let change = Change {
+            path: "post.md".into(),
+            revision: revision("0123456789abcdef"),
+            old: None,
+            new: Some("x".into()),
+        };
+        assert_eq!(
+            "post.md: 2023-01-01 0123456789 Ann: (none) -> x",
+            change.to_string()
+        );
+    }
+
+    #[test]
+    fn attributes_keys_to_last_change() {
+        let versions = [
+            ("a", Some("---\ntitle: Draft\ndate: 2023-01-01\n---\n")),
+            ("b", Some("---\ntitle: Post\ndate: 2023-01-01\n---\n")),
+            (
+                "c",
+                Some("---\ndate: 2023-01-01\ntitle: Post\ntags: [a]\n---\nEdited\n"),
+            ),
+        ];
+        let versions = versions
+            .iter()
+            .map(|(commit, source)| (revision(commit), source.map(str::to_owned)));
+        let current: yaml::Value =
+            yaml::from_str("title: Post\ndate: 2023-01-01\ntags: [a, b]\n").unwrap();
+        let attributions = attribute(Some(&current), versions);
+        let commits: Vec<(&str, Option<&str>)> = attributions
+            .iter()
+            .map(|attribution| {
+                let commit = attribution.revision.as_ref().map(|r| r.commit.as_str());
+                (attribution.key.as_str(), commit)
+            })
+            .collect();
+        assert_eq!(
+            vec![("title", Some("b")), ("date", Some("a")), ("tags", None)],
+            commits
+        );
+        assert_eq!(
+            "0000000000 (not committed yet) tags: [\"a\",\"b\"]",
+            attributions[2].to_string()
+        );
+        assert_eq!(
+            "b (Ann 2023-01-01) title: Post",
+            attributions[0].to_string()
+        );
+    }
+}
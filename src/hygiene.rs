@@ -0,0 +1,68 @@
+/// Remove whitespace from the ends of lines, except for Markdown hard line
+/// breaks: two or more spaces before a line that continues the paragraph.
+///
+/// Returns the trimmed content, if anything was trimmed.
+pub fn trim_trailing_whitespace(content: &str) -> Option<String> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut trimmed = String::with_capacity(content.len());
+    for (i, line) in lines.iter().enumerate() {
+        let (text, ending) = split_ending(line);
+        let hard_break = text.ends_with("  ")
+            && !text.trim().is_empty()
+            && lines.get(i + 1).is_some_and(|next| !next.trim().is_empty());
+        if hard_break {
+            trimmed.push_str(line);
+        } else {
+            trimmed.push_str(text.trim_end());
+            trimmed.push_str(ending);
+        }
+    }
+    (trimmed != content).then_some(trimmed)
+}
+
+/// Add a newline to the end of non-empty content missing one.
+pub fn ensure_final_newline(content: &str) -> Option<String> {
+    if content.is_empty() || content.ends_with('\n') {
+        None
+    } else {
+        Some(format!("{}\n", content))
+    }
+}
+
+/// A line's text and its line ending, `\n` or `\r\n` or none.
+fn split_ending(line: &str) -> (&str, &str) {
+    let text = line
+        .strip_suffix('\n')
+        .map(|text| text.strip_suffix('\r').unwrap_or(text))
+        .unwrap_or(line);
+    (text, &line[text.len()..])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trims_trailing_whitespace() {
+        assert_eq!(
+            Some("a\n\nb\r\nc".to_owned()),
+            trim_trailing_whitespace("a \t\n  \nb \r\nc  ")
+        );
+        assert_eq!(None, trim_trailing_whitespace("a\nb\n"));
+    }
+
+    #[test]
+    fn keeps_hard_line_breaks() {
+        assert_eq!(
+            Some("line  \nbreak\n\nend\n".to_owned()),
+            trim_trailing_whitespace("line  \nbreak \n\nend  \n")
+        );
+    }
+
+    #[test]
+    fn ensures_final_newline() {
+        assert_eq!(Some("a\n".to_owned()), ensure_final_newline("a"));
+        assert_eq!(None, ensure_final_newline("a\n"));
+        assert_eq!(None, ensure_final_newline(""));
+    }
+}
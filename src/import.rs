@@ -0,0 +1,259 @@
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use eyre::{eyre, Context};
+use serde_yaml as yaml;
+
+use crate::{
+    feed::{expand_permalink, scalar},
+    frontmatter::{self, BlankLine, Format},
+    helpers::slugify,
+    render::Template,
+};
+
+/// Formats a dataset to import can be in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    /// Comma-separated values, with a header row naming the keys.
+    Csv,
+    /// A JSON array of objects.
+    Json,
+}
+
+impl FromStr for DataFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown data format {:?}, expected csv or json", s)),
+        }
+    }
+}
+
+impl DataFormat {
+    /// The format of the dataset at `path`, going by its extension.
+    pub fn for_path(path: &Path) -> eyre::Result<Self> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        extension
+            .to_ascii_lowercase()
+            .parse()
+            .map_err(|_| eyre!("can't tell the format of {}, see --format", path.display()))
+    }
+}
+
+/// Read the records in the dataset at `path`, each a mapping from keys to
+/// values. CSV cells are strings, except for numbers and `true` and
+/// `false`, and empty cells are left out.
+pub fn read_records(path: &Path, format: DataFormat) -> eyre::Result<Vec<yaml::Mapping>> {
+    let text = read_to_string(path).context(format!("couldn't read {}", path.display()))?;
+    match format {
+        DataFormat::Csv => {
+            let mut rows = parse_csv(&text)?.into_iter();
+            let header = rows.next().unwrap_or_default();
+            rows.enumerate()
+                .map(|(i, row)| {
+                    if row.len() > header.len() {
+                        return Err(eyre!("row {} has more cells than the header", i + 2));
+                    }
+                    Ok(header
+                        .iter()
+                        .zip(row)
+                        .filter(|(_, cell)| !cell.is_empty())
+                        .map(|(key, cell)| (key.as_str().into(), cell_value(cell)))
+                        .collect())
+                })
+                .collect()
+        }
+        DataFormat::Json => serde_json::from_str(&text).context(format!(
+            "expected a JSON array of objects in {}",
+            path.display()
+        )),
+    }
+}
+
+fn cell_value(cell: String) -> yaml::Value {
+    match cell.as_str() {
+        "true" => true.into(),
+        "false" => false.into(),
+        _ => match yaml::from_str::<yaml::Value>(&cell) {
+            Ok(number @ yaml::Value::Number(_)) => number,
+            _ => cell.into(),
+        },
+    }
+}
+
+/// Parse CSV as RFC 4180 has it: fields separated by commas, and quoted
+/// with `"` if they hold commas, quotes (doubled) or line breaks.
+fn parse_csv(text: &str) -> eyre::Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = text
+        .strip_prefix('\u{feff}')
+        .unwrap_or(text)
+        .chars()
+        .peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(eyre!("unclosed quote in CSV"));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    // blank lines aren't records
+    rows.retain(|row| row != &[""]);
+    Ok(rows)
+}
+
+/// Where and what to create for each record.
+pub struct Layout {
+    /// Each file's path, with `{KEY}` placeholders for the record's values,
+    /// slugified to be safe in file names.
+    pub path: String,
+    /// Each file's body, rendered with the record as `meta`.
+    pub body: Option<Template>,
+}
+
+impl Layout {
+    /// The path and contents of the file for a record.
+    pub fn file(&self, record: &yaml::Mapping) -> eyre::Result<(PathBuf, Vec<u8>)> {
+        let path = expand_permalink(&self.path, |key| {
+            record
+                .get(key)
+                .and_then(scalar)
+                .map(|value| slugify(&value))
+        })
+        .context(format!("couldn't make a path from {}", self.path))?;
+        let meta = yaml::Value::Mapping(record.clone());
+        let body = match &self.body {
+            Some(template) => {
+                let context = serde_json::json!({ "meta": meta, "path": path });
+                template.render(&context).context("couldn't render body")?
+            }
+            None => String::new(),
+        };
+        let contents =
+            frontmatter::to_bytes(Format::Yaml, BlankLine::Preserve, Some(&meta), &body)?;
+        Ok((PathBuf::from(path), contents))
+    }
+}
+
+/// Create a file for each record, returning their paths, without
+/// overwriting any that exist unless `force`. Nothing is written if any
+/// record can't be laid out, or if two would go to the same path.
+pub fn import(
+    records: &[yaml::Mapping],
+    layout: &Layout,
+    force: bool,
+    dry_run: bool,
+) -> eyre::Result<Vec<PathBuf>> {
+    let mut files: Vec<(PathBuf, Vec<u8>)> = Vec::with_capacity(records.len());
+    for (i, record) in records.iter().enumerate() {
+        let (path, contents) = layout.file(record).context(format!("record {}", i + 1))?;
+        if files.iter().any(|(claimed, _)| *claimed == path) {
+            return Err(eyre!(
+                "more than one record would create {}",
+                path.display()
+            ));
+        }
+        if !force && path.exists() {
+            return Err(eyre!(
+                "{} already exists, pass --force to overwrite it",
+                path.display()
+            ));
+        }
+        files.push((path, contents));
+    }
+    if !dry_run {
+        for (path, contents) in &files {
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent).context("couldn't create directory")?;
+            }
+            write(path, contents).context(format!("couldn't write {}", path.display()))?;
+        }
+    }
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn imports_csv_records() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let data = dir.path().join("posts.csv");
+        std::fs::write(
+            &data,
+            "title,date,rating,draft,notes\r\n\
+             Hello World,2023-01-02,5,false,\"says \"\"hi\"\", twice\"\r\n\
+             \r\n\
+             Second,2023-02-03,,true,\r\n",
+        )?;
+        let records = read_records(&data, DataFormat::for_path(&data)?)?;
+        assert_eq!(2, records.len());
+
+        let layout = Layout {
+            path: format!("{}/{{date}}-{{title}}.md", dir.path().display()),
+            body: Some(Template::parse("# {{ meta.title }}\n")?),
+        };
+        let created = import(&records, &layout, false, false)?;
+        assert_eq!(dir.path().join("2023-01-02-hello-world.md"), created[0]);
+        assert_eq!(
+            "---\ntitle: Hello World\ndate: 2023-01-02\nrating: 5\ndraft: false\n\
+             notes: says \"hi\", twice\n---\n# Hello World\n",
+            read_to_string(&created[0])?
+        );
+        assert_eq!(
+            "---\ntitle: Second\ndate: 2023-02-03\ndraft: true\n---\n# Second\n",
+            read_to_string(&created[1])?
+        );
+        assert!(
+            import(&records, &layout, false, false).is_err(),
+            "files exist"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn imports_json_records() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let data = dir.path().join("posts.json");
+        std::fs::write(&data, r#"[{"slug": "a", "tags": ["x"]}, {"slug": "a"}]"#)?;
+        let records = read_records(&data, DataFormat::Json)?;
+        assert_eq!(Some(&yaml::Value::from(vec!["x"])), records[0].get("tags"));
+        let layout = Layout {
+            path: format!("{}/{{slug}}.md", dir.path().display()),
+            body: None,
+        };
+        assert!(
+            import(&records, &layout, false, true).is_err(),
+            "same path twice"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,486 @@
+use std::fs::read_to_string;
+
+use eyre::{eyre, Context};
+use serde_yaml as yaml;
+
+use crate::{
+    coerce::{self, Kind},
+    feed::scalar,
+    frontmatter, git, keypath,
+    migrate::{Migration, Operation},
+};
+
+/// Separators a string might have been split on, or a list joined with,
+/// most likely first.
+const SEPARATORS: [&str; 5] = [", ", ",", ";", " | ", " "];
+
+/// Types a value might have been retyped to, as `--coerce` would.
+const KINDS: [Kind; 5] = [Kind::Int, Kind::Float, Kind::Bool, Kind::Date, Kind::String];
+
+/// An edit to frontmatter seen in examples.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inferred {
+    Migrate(Operation),
+    /// Retyping a value, which `--coerce` does rather than a migration.
+    Coerce {
+        key: String,
+        kind: Kind,
+    },
+}
+
+impl Inferred {
+    /// The key the edit is to, before any renaming.
+    fn subject(&self) -> &str {
+        match self {
+            Self::Migrate(
+                Operation::Rename { from: key, .. }
+                | Operation::Move { from: key, .. }
+                | Operation::Split { key, .. }
+                | Operation::Join { key, .. }
+                | Operation::MapValues { key, .. }
+                | Operation::Default { key, .. }
+                | Operation::Drop { key }
+                | Operation::Unnest { key, .. }
+                | Operation::Nest { key, .. },
+            )
+            | Self::Coerce { key, .. } => key,
+            Self::Migrate(
+                Operation::AddPrefix { prefix, .. } | Operation::StripPrefix { prefix },
+            ) => prefix,
+        }
+    }
+
+    /// Whether the edit would do anything to `before`.
+    fn applies_to(&self, before: &yaml::Value) -> bool {
+        let value = keypath::get(before, self.subject());
+        match (self, value) {
+            (Self::Migrate(Operation::Default { .. }), None | Some(yaml::Value::Null)) => true,
+            (Self::Migrate(Operation::Default { .. }), Some(_)) => false,
+            (_, None) => false,
+            (Self::Migrate(Operation::MapValues { mapping, .. }), Some(value)) => {
+                scalar(value).is_some_and(|value| mapping.iter().any(|(from, _)| *from == value))
+            }
+            (Self::Migrate(Operation::Split { .. }), Some(value)) => value.is_string(),
+            (Self::Migrate(Operation::Join { .. }), Some(value)) => value.is_sequence(),
+            (Self::Coerce { kind, .. }, Some(value)) => {
+                matches!(coerce::coerce(value, *kind), Ok(Some(_)))
+            }
+            (Self::Migrate(_), Some(_)) => true,
+        }
+    }
+
+    /// Take in the same edit seen in another example, failing if it's a
+    /// different one.
+    fn merge(&mut self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Migrate(Operation::MapValues { mapping, .. }),
+                Self::Migrate(Operation::MapValues { mapping: more, .. }),
+            ) => {
+                for (from, to) in more {
+                    match mapping.iter().find(|(seen, _)| seen == from) {
+                        Some((_, seen)) if seen != to => return false,
+                        Some(_) => {}
+                        None => mapping.push((from.clone(), to.clone())),
+                    }
+                }
+                true
+            }
+            (this, other) => this == other,
+        }
+    }
+
+    /// Where the edit goes in a migration: moves first, so the rest are
+    /// to keys' new names, and drops last, after whatever's moved out of
+    /// what they drop.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Migrate(Operation::Rename { .. } | Operation::Move { .. }) => 0,
+            Self::Migrate(Operation::Split { .. } | Operation::Join { .. }) => 1,
+            Self::Migrate(Operation::MapValues { .. }) => 2,
+            Self::Migrate(Operation::Default { .. }) => 3,
+            Self::Migrate(_) => 4,
+            Self::Coerce { .. } => 5,
+        }
+    }
+}
+
+/// What was done to the examples' frontmatter, as far as it could be told.
+#[derive(Debug, Default, PartialEq)]
+pub struct Inference {
+    /// Edits made the same way to every example they'd apply to.
+    pub edits: Vec<Inferred>,
+    /// Keys changed in ways that couldn't be told, or differently in
+    /// different examples, left for a script.
+    pub unsure: Vec<String>,
+    examples: usize,
+}
+
+/// Infer how frontmatter was edited from examples of it before and after,
+/// conservatively: only renames and moves, splitting and joining, mapping
+/// one value to another, defaults, drops and retyping, and only those done
+/// to every example they could have been.
+pub fn infer(examples: &[(yaml::Value, yaml::Value)]) -> Inference {
+    let mut inference = Inference {
+        examples: examples.len(),
+        ..Inference::default()
+    };
+    let diffs: Vec<Vec<Inferred>> = examples
+        .iter()
+        .map(|(before, after)| diff(before, after, &mut inference.unsure))
+        .collect();
+    let mut subjects: Vec<&str> = Vec::new();
+    for edit in diffs.iter().flatten() {
+        let subject = edit.subject();
+        if subjects.contains(&subject) || inference.unsure.iter().any(|key| key == subject) {
+            continue;
+        }
+        subjects.push(subject);
+        let mut seen = diffs
+            .iter()
+            .flatten()
+            .filter(|edit| edit.subject() == subject);
+        let mut merged = seen.next().expect("found above").clone();
+        let consistent = seen.all(|edit| merged.merge(edit))
+            && examples.iter().zip(&diffs).all(|((before, _), diff)| {
+                !merged.applies_to(before) || diff.iter().any(|edit| edit.subject() == subject)
+            });
+        if consistent {
+            inference.edits.push(merged);
+        } else {
+            inference.unsure.push(subject.to_owned());
+        }
+    }
+    inference.edits.sort_by_key(Inferred::rank);
+    inference
+}
+
+impl Inference {
+    /// The inferred edits as a migration file, a migration per line as
+    /// `--migrate` takes them, with what it can't do in comments.
+    pub fn migration(&self) -> String {
+        let mut migration = format!("# inferred from {} examples\n", self.examples);
+        let mut coercions = Vec::new();
+        let mut unsure = self.unsure.clone();
+        for edit in &self.edits {
+            match edit {
+                Inferred::Migrate(operation) => match migration_line(operation) {
+                    Some(line) => migration.push_str(&format!("{}\n", line)),
+                    None => unsure.push(edit.subject().to_owned()),
+                },
+                Inferred::Coerce { key, kind } => coercions.push(format!("{}={}", key, kind)),
+            }
+        }
+        if !coercions.is_empty() {
+            migration.push_str(&format!(
+                "# and run with --coerce {}\n",
+                coercions.join(",")
+            ));
+        }
+        if !unsure.is_empty() {
+            migration.push_str(&format!("# left for a script: {}\n", unsure.join(", ")));
+        }
+        migration
+    }
+}
+
+/// Examples of frontmatter before and after editing from files: pairs of a
+/// file as it was then as it is, or with `git`, each file as last
+/// committed and as it is now.
+pub fn read_examples(paths: &[String], git: bool) -> eyre::Result<Vec<(yaml::Value, yaml::Value)>> {
+    let parse = |text: &str, path: &str| -> eyre::Result<yaml::Value> {
+        let metadata = frontmatter::parse(text).0.transpose();
+        let metadata = metadata.context(format!("couldn't parse frontmatter in {}", path))?;
+        Ok(metadata.unwrap_or_else(|| yaml::Value::Mapping(yaml::Mapping::new())))
+    };
+    let read = |path: &str| {
+        let text = read_to_string(path).context(format!("couldn't read {}", path))?;
+        parse(&text, path)
+    };
+    if git {
+        return paths
+            .iter()
+            .map(|path| {
+                // ./ makes the path relative to here rather than the top
+                let committed = git::show("HEAD", &format!("./{}", path))
+                    .ok_or_else(|| eyre!("{} isn't in the last commit", path))?;
+                Ok((parse(&committed, path)?, read(path)?))
+            })
+            .collect();
+    }
+    let pairs = paths.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(eyre!(
+            "examples go in pairs, each file before editing then after"
+        ));
+    }
+    pairs
+        .map(|pair| Ok((read(&pair[0])?, read(&pair[1])?)))
+        .collect()
+}
+
+/// The edits that took `before` to `after`, noting keys changed in ways
+/// that can't be told in `unsure`.
+fn diff(before: &yaml::Value, after: &yaml::Value, unsure: &mut Vec<String>) -> Vec<Inferred> {
+    let before_leaves = leaves(before);
+    let after_leaves = leaves(after);
+    let mut added: Vec<&(String, &yaml::Value)> = after_leaves
+        .iter()
+        .filter(|(path, _)| !before_leaves.iter().any(|(before, _)| before == path))
+        .collect();
+    let mut edits = Vec::new();
+    for (path, value) in &before_leaves {
+        match after_leaves.iter().find(|(after, _)| after == path) {
+            Some((_, after)) if after == value => {}
+            Some((_, after)) => match change(path, value, after) {
+                Some(edit) => edits.push(edit),
+                None if unsure.contains(path) => {}
+                None => unsure.push(path.clone()),
+            },
+            None => match added.iter().position(|(_, after)| after == value) {
+                Some(i) => {
+                    let to = added.remove(i).0.clone();
+                    let from = path.clone();
+                    edits.push(Inferred::Migrate(
+                        if from.contains('.') || to.contains('.') {
+                            Operation::Move { from, to }
+                        } else {
+                            Operation::Rename { from, to }
+                        },
+                    ));
+                }
+                None => edits.push(Inferred::Migrate(Operation::Drop { key: path.clone() })),
+            },
+        }
+    }
+    for (key, value) in added {
+        edits.push(Inferred::Migrate(Operation::Default {
+            key: key.clone(),
+            value: (*value).clone(),
+        }));
+    }
+    // a mapping that's gone is dropped whole, after moving what's kept
+    let mut gone: Vec<String> = Vec::new();
+    for container in containers(before) {
+        let within_gone = gone
+            .iter()
+            .any(|gone| container.starts_with(&format!("{}.", gone)));
+        if keypath::get(after, &container).is_none() && !within_gone {
+            edits.retain(|edit| {
+                !matches!(edit, Inferred::Migrate(Operation::Drop { key })
+                    if key.starts_with(&format!("{}.", container)))
+            });
+            edits.push(Inferred::Migrate(Operation::Drop {
+                key: container.clone(),
+            }));
+            gone.push(container);
+        }
+    }
+    edits
+}
+
+/// The edit that changed a value at `key` from `before` to `after`.
+fn change(key: &str, before: &yaml::Value, after: &yaml::Value) -> Option<Inferred> {
+    let key = key.to_owned();
+    let split = |s: &str, separator: &str| -> Vec<yaml::Value> {
+        s.split(separator)
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(yaml::Value::from)
+            .collect()
+    };
+    match (before, after) {
+        (yaml::Value::String(s), yaml::Value::Sequence(items)) => SEPARATORS
+            .iter()
+            .find(|separator| split(s, separator) == *items)
+            .map(|separator| {
+                let separator = separator.trim().to_owned();
+                let separator = if separator.is_empty() {
+                    " ".to_owned()
+                } else {
+                    separator
+                };
+                Inferred::Migrate(Operation::Split { key, separator })
+            }),
+        (yaml::Value::Sequence(items), yaml::Value::String(s)) => {
+            let items: Vec<String> = items.iter().map(scalar).collect::<Option<_>>()?;
+            SEPARATORS
+                .iter()
+                .find(|separator| items.join(separator) == *s)
+                .map(|separator| {
+                    Inferred::Migrate(Operation::Join {
+                        key,
+                        separator: (*separator).to_owned(),
+                    })
+                })
+        }
+        (before, after) => {
+            if let Some(kind) = KINDS
+                .iter()
+                .find(|kind| coerce::coerce(before, **kind).ok().flatten().as_ref() == Some(after))
+            {
+                return Some(Inferred::Coerce { key, kind: *kind });
+            }
+            let from = scalar(before)?;
+            scalar(after)?;
+            Some(Inferred::Migrate(Operation::MapValues {
+                key,
+                mapping: vec![(from, after.clone())],
+            }))
+        }
+    }
+}
+
+/// The dotted path to each value within mappings, other than non-empty
+/// mappings, which are looked into instead.
+fn leaves(value: &yaml::Value) -> Vec<(String, &yaml::Value)> {
+    let mut leaves = Vec::new();
+    walk(value, "", &mut |path, value| match value {
+        yaml::Value::Mapping(mapping) if !mapping.is_empty() => {}
+        value => leaves.push((path.to_owned(), value)),
+    });
+    leaves
+}
+
+/// The dotted path to each non-empty mapping within `value`, outermost
+/// first.
+fn containers(value: &yaml::Value) -> Vec<String> {
+    let mut containers = Vec::new();
+    walk(value, "", &mut |path, value| match value {
+        yaml::Value::Mapping(mapping) if !mapping.is_empty() => containers.push(path.to_owned()),
+        _ => {}
+    });
+    containers
+}
+
+/// Call `visit` with the path to each value within `value`'s mappings,
+/// parents before what they hold.
+fn walk<'v>(value: &'v yaml::Value, prefix: &str, visit: &mut impl FnMut(&str, &'v yaml::Value)) {
+    let Some(mapping) = value.as_mapping() else {
+        return;
+    };
+    for (key, value) in mapping {
+        // keys with dots can't be told from nesting in a path
+        let Some(key) = key.as_str().filter(|key| !key.contains('.')) else {
+            continue;
+        };
+        let path = if prefix.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        visit(&path, value);
+        walk(value, &path, visit);
+    }
+}
+
+/// An operation as a `--migrate` line, if it can be written as one that
+/// reads back the same.
+fn migration_line(operation: &Operation) -> Option<String> {
+    let line = match operation {
+        Operation::Rename { from, to } => format!("rename {} {}", arg(from), arg(to)),
+        Operation::Move { from, to } => format!("move {} {}", arg(from), arg(to)),
+        Operation::Split { key, separator } => format!("split {} {}", arg(key), arg(separator)),
+        Operation::Join { key, separator } => format!("join {} {}", arg(key), arg(separator)),
+        Operation::MapValues { key, mapping } => {
+            let pairs: Vec<String> = mapping
+                .iter()
+                .map(|(from, to)| arg(&format!("{}={}", from, flow(to))))
+                .collect();
+            format!("map-values {} {}", arg(key), pairs.join(" "))
+        }
+        Operation::Default { key, value } => format!("default {} {}", arg(key), arg(&flow(value))),
+        Operation::Drop { key } => format!("drop {}", arg(key)),
+        _ => return None,
+    };
+    let parsed: Migration = line.parse().ok()?;
+    (parsed.operation == *operation).then_some(line)
+}
+
+/// A migration argument, quoted if it has spaces.
+fn arg(s: &str) -> String {
+    if s.is_empty() || s.contains(char::is_whitespace) {
+        format!("\"{}\"", s)
+    } else {
+        s.to_owned()
+    }
+}
+
+/// A value as inline YAML.
+fn flow(value: &yaml::Value) -> String {
+    match value {
+        yaml::Value::Null => "~".to_owned(),
+        yaml::Value::String(s) if yaml::from_str::<yaml::Value>(s).ok() == Some(value.clone()) => {
+            s.clone()
+        }
+        yaml::Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+        yaml::Value::Sequence(items) => {
+            let items: Vec<String> = items.iter().map(flow).collect();
+            format!("[{}]", items.join(", "))
+        }
+        yaml::Value::Mapping(mapping) => {
+            let entries: Vec<String> = mapping
+                .iter()
+                .map(|(k, v)| format!("{}: {}", flow(k), flow(v)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        value => scalar(value).unwrap_or_else(|| yaml::to_string(value).unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn examples(pairs: &[(&str, &str)]) -> Vec<(yaml::Value, yaml::Value)> {
+        pairs
+            .iter()
+            .map(|(before, after)| {
+                (
+                    yaml::from_str(before).unwrap(),
+                    yaml::from_str(after).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn infers_simple_edits() {
+        let inference = infer(&examples(&[
+            (
+                "author: Ann\nseo: {title: Hi}\ntags: a, b\nstatus: wip\nrating: '4'\nold: 1\n",
+                "authors: Ann\ntitle: Hi\ntags: [a, b]\nstatus: draft\nrating: 4\nlayout: post\n",
+            ),
+            (
+                "author: Bob\ntags: c\nstatus: done\nlayout: page\n",
+                "authors: Bob\ntags: [c]\nstatus: published\nlayout: page\n",
+            ),
+        ]));
+        assert_eq!(
+            "# inferred from 2 examples\n\
+             rename author authors\n\
+             move seo.title title\n\
+             split tags ,\n\
+             map-values status wip=draft done=published\n\
+             default layout post\n\
+             drop old\n\
+             drop seo\n\
+             # and run with --coerce rating=int\n",
+            inference.migration()
+        );
+    }
+
+    #[test]
+    fn leaves_inconsistent_edits_to_scripts() {
+        let inference = infer(&examples(&[
+            ("title: A\nsummary: x\n", "title: A\nlayout: post\n"),
+            (
+                "title: B\nsummary: y\n",
+                "title: B\nsummary: y\nlayout: page\n",
+            ),
+        ]));
+        assert!(inference.edits.is_empty());
+        assert_eq!(vec!["summary", "layout"], inference.unsure);
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use eyre::{eyre, Context};
+use serde_yaml as yaml;
+
+use crate::{frontmatter, links};
+
+/// The key naming the file whose frontmatter a file's inherits from, by a
+/// path relative to the file, e.g. `extends: _base.md`.
+pub const EXTENDS_KEY: &str = "extends";
+
+/// Resolves frontmatter inheritance through `extends`, remembering each
+/// base's resolved frontmatter, since many files tend to share one.
+///
+/// A file inherits every key it doesn't have from its base, which may
+/// extend another in turn, and mappings both have are merged the same way.
+/// Lists and other values aren't merged: a file's own replace its base's.
+#[derive(Debug, Default)]
+pub struct Inheritance {
+    bases: Mutex<HashMap<PathBuf, yaml::Mapping>>,
+}
+
+impl Inheritance {
+    /// The frontmatter of the file at `path` with what it inherits filled
+    /// in, where its `extends` key was, or `None` if it doesn't extend
+    /// anything.
+    pub fn resolve(
+        &self,
+        path: &Path,
+        metadata: &yaml::Value,
+    ) -> eyre::Result<Option<yaml::Value>> {
+        self.resolve_within(path, metadata, &mut Vec::new())
+            .map(|resolved| resolved.map(yaml::Value::Mapping))
+    }
+
+    /// Resolve `metadata`, reached through the bases in `chain`.
+    fn resolve_within(
+        &self,
+        path: &Path,
+        metadata: &yaml::Value,
+        chain: &mut Vec<PathBuf>,
+    ) -> eyre::Result<Option<yaml::Mapping>> {
+        let Some(mapping) = metadata.as_mapping() else {
+            return Ok(None);
+        };
+        let Some(base) = mapping.get(EXTENDS_KEY) else {
+            return Ok(None);
+        };
+        let base = base
+            .as_str()
+            .ok_or_else(|| eyre!("{} should be a path, not {:?}", EXTENDS_KEY, base))?;
+        let base = links::normalize(&path.parent().unwrap_or_else(|| Path::new("")).join(base));
+        let inherited = self
+            .base(&base, chain)
+            .context(format!("couldn't inherit from {}", base.display()))?;
+        Ok(Some(merge(mapping, &inherited, true)))
+    }
+
+    /// The resolved frontmatter of the base file at `path`.
+    fn base(&self, path: &Path, chain: &mut Vec<PathBuf>) -> eyre::Result<yaml::Mapping> {
+        if let Some(resolved) = self.bases.lock().expect("resolver panicked").get(path) {
+            return Ok(resolved.clone());
+        }
+        if chain.iter().any(|extended| extended == path) {
+            return Err(eyre!("{} extends itself", path.display()));
+        }
+        let text = read_to_string(path).context("couldn't read it")?;
+        let metadata = match frontmatter::parse(&text).0.transpose() {
+            Ok(Some(metadata)) => metadata,
+            Ok(None) => yaml::Value::Mapping(yaml::Mapping::new()),
+            Err(err) => return Err(err).context("couldn't parse its frontmatter"),
+        };
+        chain.push(path.to_owned());
+        let resolved = match self.resolve_within(path, &metadata, chain)? {
+            Some(resolved) => resolved,
+            None => match metadata {
+                yaml::Value::Mapping(mapping) => mapping,
+                _ => return Err(eyre!("its frontmatter isn't a mapping")),
+            },
+        };
+        chain.pop();
+        self.bases
+            .lock()
+            .expect("resolver panicked")
+            .insert(path.to_owned(), resolved.clone());
+        Ok(resolved)
+    }
+}
+
+/// `own` with what it doesn't have from `inherited`, merging mappings both
+/// have. At the top level inherited keys go where `extends` was, which is
+/// dropped; in nested mappings they go after their own.
+fn merge(own: &yaml::Mapping, inherited: &yaml::Mapping, top: bool) -> yaml::Mapping {
+    let mut merged = yaml::Mapping::with_capacity(own.len() + inherited.len());
+    let only_inherited = || {
+        inherited
+            .iter()
+            .filter(|(key, _)| !own.contains_key(*key) && key.as_str() != Some(EXTENDS_KEY))
+            .map(|(key, value)| (key.clone(), value.clone()))
+    };
+    for (key, value) in own {
+        if top && key.as_str() == Some(EXTENDS_KEY) {
+            merged.extend(only_inherited());
+            continue;
+        }
+        let value = match (value, inherited.get(key)) {
+            (yaml::Value::Mapping(value), Some(yaml::Value::Mapping(inherited))) => {
+                yaml::Value::Mapping(merge(value, inherited, false))
+            }
+            (value, _) => value.clone(),
+        };
+        merged.insert(key.clone(), value);
+    }
+    if !top {
+        merged.extend(only_inherited());
+    }
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::write;
+
+    use super::*;
+
+    #[test]
+    fn inherits_through_bases() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        write(
+            dir.path().join("_root.md"),
+            "---\nlayout: docs\nnav: {section: guide, order: 1}\ntags: [docs]\n---\n",
+        )?;
+        write(
+            dir.path().join("_base.md"),
+            "---\nextends: _root.md\nnav: {order: 5}\nauthor: Ann\n---\nBase body\n",
+        )?;
+        let page = dir.path().join("guide/page.md");
+        let metadata: yaml::Value =
+            yaml::from_str("title: Page\nextends: ../_base.md\nnav: {title: P}\ntags: [a]\n")?;
+
+        let inheritance = Inheritance::default();
+        let resolved = inheritance
+            .resolve(&page, &metadata)?
+            .expect("extends a base");
+        assert_eq!(
+            "title: Page\nlayout: docs\nauthor: Ann\nnav:\n  title: P\n  order: 5\n  \
+             section: guide\ntags:\n- a\n",
+            yaml::to_string(&resolved)?
+        );
+        assert_eq!(
+            None,
+            inheritance.resolve(&page, &yaml::from_str("title: Hi")?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_cycles() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        write(dir.path().join("a.md"), "---\nextends: b.md\n---\n")?;
+        write(dir.path().join("b.md"), "---\nextends: a.md\n---\n")?;
+        let metadata: yaml::Value = yaml::from_str("extends: a.md\n")?;
+        let inheritance = Inheritance::default();
+        assert!(inheritance
+            .resolve(&dir.path().join("c.md"), &metadata)
+            .is_err());
+        assert!(inheritance
+            .resolve(&dir.path().join("a.md"), &metadata)
+            .is_err());
+        let missing: yaml::Value = yaml::from_str("extends: missing.md\n")?;
+        assert!(inheritance
+            .resolve(&dir.path().join("c.md"), &missing)
+            .is_err());
+        Ok(())
+    }
+}
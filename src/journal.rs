@@ -0,0 +1,220 @@
+use std::{
+    fs::{self, read, read_to_string},
+    path::{Path, PathBuf},
+};
+
+use eyre::{eyre, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{atomic, hash};
+
+/// A record of the files a run modified and what they held before, so the
+/// run can be undone.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    files: Vec<JournalEntry>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct JournalEntry {
+    path: PathBuf,
+    /// Where the fixed file was moved to, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    moved_to: Option<PathBuf>,
+    /// Whether the run deleted the file rather than writing it.
+    #[serde(default, skip_serializing_if = "is_false")]
+    deleted: bool,
+    original: String,
+    /// The hash of what the run wrote, to tell whether it's been edited
+    /// since; empty for a deleted file.
+    output_sha256: String,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+impl Journal {
+    /// Record that the file at `path` held `original` and now holds `output`,
+    /// at `written_to`. If the run already wrote `written_to`, only what it
+    /// holds now is updated, so undoing restores what it held before the run.
+    pub fn record(&mut self, path: &str, original: &str, output: &[u8], written_to: &Path) {
+        let earlier = self.files.iter_mut().find(|entry| {
+            !entry.deleted && entry.moved_to.as_ref().unwrap_or(&entry.path) == written_to
+        });
+        if let Some(earlier) = earlier {
+            earlier.output_sha256 = hash::sha256_hex(output);
+            return;
+        }
+        let path = PathBuf::from(path);
+        self.files.push(JournalEntry {
+            moved_to: (written_to != path).then(|| written_to.to_owned()),
+            path,
+            deleted: false,
+            original: original.to_owned(),
+            output_sha256: hash::sha256_hex(output),
+        });
+    }
+
+    /// Record that the file at `path` held `original` and was deleted.
+    pub fn record_deletion(&mut self, path: &str, original: &str) {
+        self.files.push(JournalEntry {
+            path: PathBuf::from(path),
+            moved_to: None,
+            deleted: true,
+            original: original.to_owned(),
+            output_sha256: String::new(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let json =
+            read_to_string(path).context(format!("couldn't read journal {}", path.display()))?;
+        serde_json::from_str(&json).context(format!("couldn't parse journal {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        fs::write(path, json).context(format!("couldn't write journal {}", path.display()))
+    }
+
+    /// Put every file back as it was before the run, returning how many
+    /// were restored.
+    ///
+    /// Unless `force`d, nothing is restored if any file has changed since
+    /// the run, or a file it deleted has come back, so edits made afterwards
+    /// aren't lost.
+    pub fn undo(&self, force: bool) -> eyre::Result<usize> {
+        if !force {
+            let edited: Vec<String> = self
+                .files
+                .iter()
+                .filter_map(|entry| {
+                    let current = entry.moved_to.as_ref().unwrap_or(&entry.path);
+                    let unchanged = match read(current) {
+                        Ok(contents) => {
+                            !entry.deleted && hash::sha256_hex(&contents) == entry.output_sha256
+                        }
+                        // a deleted file should still be missing
+                        Err(_) => entry.deleted,
+                    };
+                    (!unchanged).then(|| current.display().to_string())
+                })
+                .collect();
+            if !edited.is_empty() {
+                return Err(eyre!(
+                    "not undoing: changed or missing since the run: {}",
+                    edited.join(", ")
+                ));
+            }
+        }
+        // in reverse, in case a file was touched more than once
+        for entry in self.files.iter().rev() {
+            let (path, original) = (&entry.path, entry.original.as_bytes());
+            // atomically, keeping the permissions of what's replaced
+            let restore = || -> eyre::Result<()> {
+                match &entry.moved_to {
+                    Some(moved_to) if moved_to.exists() && !path.exists() => {
+                        return atomic::relocate(moved_to, path, original, false);
+                    }
+                    _ if path.exists() => atomic::replace(path, original, false)?,
+                    // deleted, so with no permissions to keep
+                    _ => fs::write(path, original)?,
+                }
+                if let Some(moved_to) = entry.moved_to.as_ref().filter(|to| to.exists()) {
+                    fs::remove_file(moved_to)
+                        .context(format!("couldn't remove {}", moved_to.display()))?;
+                }
+                Ok(())
+            };
+            restore().context(format!("couldn't restore {}", path.display()))?;
+        }
+        Ok(self.files.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::write;
+
+    use super::*;
+
+    #[test]
+    fn undoes_modifications_and_moves() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let edited = dir.path().join("edited.md");
+        let moved = dir.path().join("moved.md");
+        let moved_to = dir.path().join("new/moved.md");
+        fs::create_dir(dir.path().join("new"))?;
+        let deleted = dir.path().join("deleted.md");
+        write(&edited, "fixed\n")?;
+        write(&moved_to, "moved\n")?;
+
+        let mut journal = Journal::default();
+        journal.record(&edited.to_string_lossy(), "original\n", b"fixed\n", &edited);
+        journal.record(&moved.to_string_lossy(), "before\n", b"moved\n", &moved_to);
+        journal.record_deletion(&deleted.to_string_lossy(), "duplicate\n");
+        let journal_path = dir.path().join("journal.json");
+        journal.save(&journal_path)?;
+        let journal = Journal::load(&journal_path)?;
+
+        write(&edited, "edited since\n")?;
+        assert!(journal.undo(false).is_err());
+        assert_eq!("edited since\n", read_to_string(&edited)?);
+
+        for path in [&edited, &moved_to] {
+            let mut permissions = fs::metadata(path)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(path, permissions)?;
+        }
+        assert_eq!(3, journal.undo(true)?);
+        assert_eq!("original\n", read_to_string(&edited)?);
+        assert!(fs::metadata(&edited)?.permissions().readonly());
+        assert_eq!("before\n", read_to_string(&moved)?);
+        assert!(fs::metadata(&moved)?.permissions().readonly());
+        assert_eq!("duplicate\n", read_to_string(&deleted)?);
+        assert!(!moved_to.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn undoes_deletions_unless_recreated() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let deleted = dir.path().join("deleted.md");
+        let mut journal = Journal::default();
+        journal.record_deletion(&deleted.to_string_lossy(), "duplicate\n");
+
+        write(&deleted, "recreated\n")?;
+        assert!(journal.undo(false).is_err());
+        fs::remove_file(&deleted)?;
+        assert_eq!(1, journal.undo(false)?);
+        assert_eq!("duplicate\n", read_to_string(&deleted)?);
+        Ok(())
+    }
+
+    #[test]
+    fn undoes_files_written_twice_to_before_the_run() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("post.md");
+        let moved_to = dir.path().join("moved.md");
+        write(&moved_to, "second\n")?;
+        let mut journal = Journal::default();
+        journal.record(&path.to_string_lossy(), "original\n", b"first\n", &moved_to);
+        journal.record(
+            &moved_to.to_string_lossy(),
+            "first\n",
+            b"second\n",
+            &moved_to,
+        );
+
+        assert_eq!(1, journal.undo(false)?);
+        assert_eq!("original\n", read_to_string(&path)?);
+        assert!(!moved_to.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use serde_yaml as yaml;
+
+/// Look up a dotted path into nested mappings, e.g. `author.name`.
+pub fn get<'v>(value: &'v yaml::Value, path: &str) -> Option<&'v yaml::Value> {
+    path.split('.').try_fold(value, |value, key| value.get(key))
+}
+
+pub fn get_mut<'v>(value: &'v mut yaml::Value, path: &str) -> Option<&'v mut yaml::Value> {
+    path.split('.')
+        .try_fold(value, |value, key| value.get_mut(key))
+}
+
+/// Set the value at a dotted path, creating mappings along the way.
+///
+/// Fails, returning the value back, if part of the path is something other
+/// than a mapping.
+pub fn set(value: &mut yaml::Value, path: &str, new: yaml::Value) -> Result<(), yaml::Value> {
+    let (parents, key) = match path.rsplit_once('.') {
+        Some((parents, key)) => (Some(parents), key),
+        None => (None, path),
+    };
+    let mut target = value;
+    for parent in parents.into_iter().flat_map(|parents| parents.split('.')) {
+        let mapping = match target.as_mapping_mut() {
+            Some(mapping) => mapping,
+            None => return Err(new),
+        };
+        target = mapping
+            .entry(parent.into())
+            .or_insert_with(|| yaml::Value::Mapping(yaml::Mapping::new()));
+    }
+    match target.as_mapping_mut() {
+        Some(mapping) => {
+            mapping.insert(key.into(), new);
+            Ok(())
+        }
+        None => Err(new),
+    }
+}
+
+/// Remove the value at a dotted path, returning it.
+pub fn remove(value: &mut yaml::Value, path: &str) -> Option<yaml::Value> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parents, key)) => (get_mut(value, parents)?, key),
+        None => (value, path),
+    };
+    let mapping = parent.as_mapping_mut()?;
+    let removed = mapping.get(key).cloned()?;
+    // retain rather than remove, so the other keys keep their order
+    mapping.retain(|k, _| k.as_str() != Some(key));
+    Some(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gets_nested_values() {
+        let meta: yaml::Value = yaml::from_str("author:\n  name: Bob\n").unwrap();
+        assert_eq!(Some(&"Bob".into()), get(&meta, "author.name"));
+        assert_eq!(None, get(&meta, "author.email"));
+        assert_eq!(None, get(&meta, "author.name.first"));
+    }
+
+    #[test]
+    fn gets_nested_values_mutably() {
+        let mut meta: yaml::Value = yaml::from_str("author:\n  name: Bob\n").unwrap();
+        *get_mut(&mut meta, "author.name").unwrap() = "Alice".into();
+        assert_eq!("author:\n  name: Alice\n", yaml::to_string(&meta).unwrap());
+    }
+
+    #[test]
+    fn sets_and_removes_nested_values() {
+        let mut meta: yaml::Value = yaml::from_str("author: Bob\n").unwrap();
+        set(&mut meta, "seo.title", "Hi".into()).unwrap();
+        assert_eq!(Some(&"Hi".into()), get(&meta, "seo.title"));
+        assert!(set(&mut meta, "author.name", "Bob".into()).is_err());
+        assert_eq!(Some("Hi".into()), remove(&mut meta, "seo.title"));
+        assert_eq!(None, remove(&mut meta, "seo.title"));
+        assert_eq!("author: Bob\nseo: {}\n", yaml::to_string(&meta).unwrap());
+        let mut meta: yaml::Value = yaml::from_str("a: 1\nb: 2\nc: 3\n").unwrap();
+        remove(&mut meta, "a");
+        assert_eq!("b: 2\nc: 3\n", yaml::to_string(&meta).unwrap());
+    }
+}
@@ -0,0 +1,86 @@
+//! Fix up the frontmatter of Markdown files with Lua scripts and built-in
+//! transforms.
+//!
+//! The `frontmatter-fixer` binary is a thin wrapper around [`batch::run`].
+//! To fix documents without touching the filesystem, use [`Fixer`] with
+//! [`frontmatter::write`]; to read and edit frontmatter from Rust, use
+//! [`Frontmatter`].
+
+mod atomic;
+pub mod batch;
+pub mod calendar;
+pub mod checksum;
+pub mod coerce;
+pub mod config;
+pub mod confirm;
+pub mod convert;
+pub mod corpus;
+mod date;
+pub mod diff;
+pub mod duplicates;
+pub mod edit;
+pub mod envelope;
+pub mod error;
+pub mod estimate;
+pub mod feed;
+pub mod fields;
+pub mod fixer;
+pub mod frontmatter;
+pub mod generate;
+mod git;
+mod glob;
+mod hash;
+mod helpers;
+pub mod history;
+pub mod hygiene;
+pub mod import;
+pub mod infer;
+pub mod inherit;
+pub mod journal;
+mod keypath;
+pub mod library;
+pub mod links;
+pub mod locales;
+mod lua_yaml;
+pub mod manifest;
+pub mod migrate;
+pub mod nfc;
+pub mod order;
+pub mod output;
+pub mod patch;
+pub mod permalink;
+mod plan;
+mod preserve;
+pub mod project;
+pub mod provenance;
+pub mod publish;
+pub mod query;
+pub mod recipes;
+pub mod redact;
+pub mod render;
+mod repl;
+pub mod replay;
+pub mod report;
+pub mod safety;
+pub mod schema;
+pub mod search;
+pub mod select;
+pub mod snapshot;
+mod spelling;
+pub mod stamp;
+pub mod stats;
+pub mod tally;
+pub mod title;
+pub mod translations;
+pub mod typos;
+pub mod walk;
+pub mod wasm;
+pub mod watch;
+pub mod why;
+
+pub use batch::{process, run, Pipeline, Processed};
+pub use config::Config;
+pub use date::Date;
+pub use error::FixError;
+pub use fields::Frontmatter;
+pub use fixer::Fixer;
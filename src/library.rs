@@ -0,0 +1,264 @@
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read, read_to_string, write},
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+use eyre::{eyre, Context};
+use serde::{Deserialize, Serialize};
+use serde_yaml as yaml;
+
+use crate::{hash, project::ProjectConfig};
+
+/// The lock file recording where each vendored script came from.
+pub const LOCK_FILE: &str = "scripts.lock";
+
+/// Where a shared script is fetched from.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Source {
+    Url(String),
+    File(PathBuf),
+}
+
+impl Source {
+    /// Understands `gh:ORG/REPO/PATH[@REF]`, `http(s)://` URLs and local paths.
+    pub fn parse(source: &str) -> eyre::Result<Self> {
+        if let Some(github) = source.strip_prefix("gh:") {
+            let (github, git_ref) = github.rsplit_once('@').unwrap_or((github, "HEAD"));
+            let mut parts = github.splitn(3, '/');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(org), Some(repo), Some(path)) if !path.is_empty() => Ok(Self::Url(format!(
+                    "https://raw.githubusercontent.com/{}/{}/{}/{}",
+                    org, repo, git_ref, path
+                ))),
+                _ => Err(eyre!("expected gh:ORG/REPO/PATH, got {}", source)),
+            }
+        } else if source.starts_with("https://") || source.starts_with("http://") {
+            Ok(Self::Url(source.to_owned()))
+        } else {
+            Ok(Self::File(source.into()))
+        }
+    }
+
+    fn fetch(&self) -> eyre::Result<Vec<u8>> {
+        match self {
+            Self::Url(url) => {
+                let output = Command::new("curl")
+                    .args([
+                        "--fail",
+                        "--silent",
+                        "--show-error",
+                        "--location",
+                        url.as_str(),
+                    ])
+                    .output()
+                    .context("couldn't run curl")?;
+                if !output.status.success() {
+                    return Err(eyre!(
+                        "couldn't fetch {}: {}",
+                        url,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                Ok(output.stdout)
+            }
+            Self::File(path) => read(path).context(format!("couldn't read {}", path.display())),
+        }
+    }
+
+    fn default_name(&self) -> Option<String> {
+        let path = match self {
+            Self::Url(url) => url.rsplit('/').next()?,
+            Self::File(path) => path.file_name()?.to_str()?,
+        };
+        Some(path.to_owned()).filter(|name| !name.is_empty())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedScript {
+    pub source: String,
+    pub sha256: String,
+}
+
+/// The vendored scripts in a script directory and their pinned hashes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Lock {
+    scripts: BTreeMap<String, LockedScript>,
+}
+
+impl Lock {
+    pub fn load(dir: &Path) -> eyre::Result<Self> {
+        let path = dir.join(LOCK_FILE);
+        match read_to_string(&path) {
+            Ok(source) => {
+                yaml::from_str(&source).context(format!("couldn't parse {}", path.display()))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context(format!("couldn't read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> eyre::Result<()> {
+        let path = dir.join(LOCK_FILE);
+        write(&path, yaml::to_string(self)?).context(format!("couldn't write {}", path.display()))
+    }
+}
+
+/// Vendor a shared script into `dir`, pinning its hash in the lock file,
+/// and with a `project`, add it to the scripts it runs.
+///
+/// If `expected_sha256` is given the fetched script must match it.
+/// Returns the path the script was written to.
+pub fn add(
+    dir: &Path,
+    source: &str,
+    name: Option<&str>,
+    expected_sha256: Option<&str>,
+    project: Option<&ProjectConfig>,
+) -> eyre::Result<PathBuf> {
+    let parsed = Source::parse(source)?;
+    let name = match name {
+        Some(name) => name.to_owned(),
+        None => parsed
+            .default_name()
+            .ok_or_else(|| eyre!("couldn't work out a name for {}, pass --name", source))?,
+    };
+    // written in `dir` and nowhere else
+    let mut components = Path::new(&name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.contains(['/', '\\']) => {}
+        _ => {
+            return Err(eyre!(
+                "script name {} must be a file name, not a path",
+                name
+            ))
+        }
+    }
+
+    let script = parsed.fetch()?;
+    let sha256 = hash::sha256_hex(&script);
+    if let Some(expected) = expected_sha256 {
+        if !expected.eq_ignore_ascii_case(&sha256) {
+            return Err(eyre!(
+                "{} has sha256 {}, but expected {}",
+                source,
+                sha256,
+                expected
+            ));
+        }
+    }
+
+    create_dir_all(dir).context(format!("couldn't create {}", dir.display()))?;
+    let script_path = dir.join(&name);
+    write(&script_path, &script).context(format!("couldn't write {}", script_path.display()))?;
+
+    let mut lock = Lock::load(dir)?;
+    lock.scripts.insert(
+        name,
+        LockedScript {
+            source: source.to_owned(),
+            sha256,
+        },
+    );
+    lock.save(dir)?;
+    if let Some(project) = project {
+        project.add_script(&script_path)?;
+    }
+    Ok(script_path)
+}
+
+/// Check every vendored script still matches its pinned hash, returning a
+/// description of each one that has drifted.
+pub fn verify(dir: &Path) -> eyre::Result<Vec<String>> {
+    let lock = Lock::load(dir)?;
+    let mut drifted = Vec::new();
+    for (name, locked) in &lock.scripts {
+        let path = dir.join(name);
+        match read(&path) {
+            Ok(script) if hash::sha256_hex(&script) == locked.sha256 => {}
+            Ok(_) => drifted.push(format!(
+                "{} no longer matches {} (pinned sha256 {})",
+                path.display(),
+                locked.source,
+                locked.sha256
+            )),
+            Err(e) => drifted.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    Ok(drifted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::project::CONFIG_FILE;
+
+    #[test]
+    fn parses_github_sources() -> eyre::Result<()> {
+        assert_eq!(
+            Source::Url("https://raw.githubusercontent.com/org/repo/HEAD/lua/fix-dates.lua".into()),
+            Source::parse("gh:org/repo/lua/fix-dates.lua")?
+        );
+        assert_eq!(
+            Source::Url("https://raw.githubusercontent.com/org/repo/v1/fix-dates.lua".into()),
+            Source::parse("gh:org/repo/fix-dates.lua@v1")?
+        );
+        let _ = Source::parse("gh:org/repo").expect_err("needs a path");
+        Ok(())
+    }
+
+    #[test]
+    fn vendors_and_verifies_local_scripts() -> eyre::Result<()> {
+        let shared = tempfile::tempdir()?;
+        let source = shared.path().join("fix-dates.lua");
+        write(&source, "meta.date = meta.date or '2020-01-01'\n")?;
+        let source = source.to_str().unwrap();
+
+        let project = tempfile::tempdir()?;
+        let dir = project.path().join("scripts");
+        write(project.path().join(CONFIG_FILE), "")?;
+        let config = ProjectConfig::find(project.path())?;
+        let vendored = add(&dir, source, None, None, config.as_ref())?;
+        assert_eq!(dir.join("fix-dates.lua"), vendored);
+        assert!(verify(&dir)?.is_empty());
+        assert_eq!(
+            format!(
+                "script = \"{}\"\n",
+                Path::new("scripts").join("fix-dates.lua").display()
+            ),
+            read_to_string(project.path().join(CONFIG_FILE))?
+        );
+        for name in ["../fix.lua", "lib/fix.lua", ".."] {
+            assert!(
+                add(&dir, source, Some(name), None, None).is_err(),
+                "{}",
+                name
+            );
+        }
+
+        write(&vendored, "-- local edit\n")?;
+        assert_eq!(1, verify(&dir)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unexpected_hash() -> eyre::Result<()> {
+        let shared = tempfile::tempdir()?;
+        let source = shared.path().join("fix.lua");
+        write(&source, "")?;
+        let project = tempfile::tempdir()?;
+        let _ = add(
+            project.path(),
+            source.to_str().unwrap(),
+            None,
+            Some("abc123"),
+            None,
+        )
+        .expect_err("hash doesn't match");
+        Ok(())
+    }
+}
@@ -0,0 +1,455 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_yaml as yaml;
+
+use crate::frontmatter;
+
+/// Which kind of link to convert internal links to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkStyle {
+    /// `[label](relative/path.md)`
+    Markdown,
+    /// `[[Target|label]]`
+    Wiki,
+}
+
+impl FromStr for LinkStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(Self::Markdown),
+            "wiki" => Ok(Self::Wiki),
+            _ => Err(format!("expected markdown or wiki, got {:?}", s)),
+        }
+    }
+}
+
+/// A document with its links converted.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Converted {
+    /// The converted document, if any link was converted.
+    pub content: Option<String>,
+    /// Link targets that didn't resolve to exactly one file, left as they
+    /// were.
+    pub unresolved: Vec<String>,
+}
+
+/// Which file each link target refers to, across the files being fixed.
+///
+/// Wiki links name their target by file name (without extension), or by
+/// any `title` or `aliases` in its frontmatter, ignoring case.
+#[derive(Debug, Default)]
+pub struct LinkIndex {
+    /// `None` where several files claim the same name.
+    names: HashMap<String, Option<PathBuf>>,
+    /// The name wiki links use for each file.
+    stems: HashMap<PathBuf, String>,
+}
+
+impl LinkIndex {
+    /// Index every file in `paths`. Files that can't be read or parsed are
+    /// indexed by file name only; they'll fail when processed.
+    pub fn build(paths: &[String]) -> Self {
+        let mut index = Self::default();
+        for path in paths {
+            let metadata = read_to_string(path)
+                .ok()
+                .and_then(|content| frontmatter::parse(&content).0?.ok());
+            index.add(Path::new(path), metadata.as_ref());
+        }
+        index
+    }
+
+    fn add(&mut self, path: &Path, metadata: Option<&yaml::Value>) {
+        let path = normalize(path);
+        let stem = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => return,
+        };
+        let mut names = vec![stem.clone()];
+        if let Some(metadata) = metadata {
+            let title = metadata.get("title").and_then(yaml::Value::as_str);
+            names.extend(title.map(str::to_owned));
+            match metadata.get("aliases") {
+                Some(yaml::Value::String(alias)) => names.push(alias.clone()),
+                Some(yaml::Value::Sequence(aliases)) => names.extend(
+                    aliases
+                        .iter()
+                        .filter_map(yaml::Value::as_str)
+                        .map(str::to_owned),
+                ),
+                _ => {}
+            }
+        }
+        for name in names {
+            let claimed = self
+                .names
+                .entry(name.trim().to_lowercase())
+                .or_insert_with(|| Some(path.clone()));
+            if claimed.as_ref() != Some(&path) {
+                *claimed = None;
+            }
+        }
+        self.stems.insert(path, stem);
+    }
+
+    fn resolve(&self, target: &str) -> Option<&Path> {
+        let lookup = |name: &str| self.names.get(&name.trim().to_lowercase());
+        let found = match lookup(target) {
+            Some(found) => found,
+            // [[folder/Note]] names a file by path
+            None => lookup(target.rsplit('/').next()?)?,
+        };
+        found.as_deref()
+    }
+
+    /// Convert the internal links in `content`, a document at `path`, to
+    /// `style`. Links in code are left alone, as are embeds (`![[...]]`)
+    /// and Markdown links with an anchor, which can't be mapped back to a
+    /// heading's text.
+    pub fn convert(&self, path: &Path, content: &str, style: LinkStyle) -> Converted {
+        let dir = normalize(path.parent().unwrap_or(Path::new("")));
+        let mut unresolved = Vec::new();
+        let converted = map_prose(content, |text| match style {
+            LinkStyle::Markdown => self.wiki_to_markdown(&dir, text, &mut unresolved),
+            LinkStyle::Wiki => self.markdown_to_wiki(&dir, text, &mut unresolved),
+        });
+        Converted {
+            content: (converted != content).then_some(converted),
+            unresolved,
+        }
+    }
+
+    fn wiki_to_markdown(&self, dir: &Path, text: &str, unresolved: &mut Vec<String>) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("[[") {
+            let inner_end = match rest[start + 2..].find("]]") {
+                Some(end) => start + 2 + end,
+                None => break,
+            };
+            let inner = &rest[start + 2..inner_end];
+            out.push_str(&rest[..start]);
+            let embed = rest[..start].ends_with('!');
+            let link = if embed || inner.contains(['[', ']']) {
+                None
+            } else {
+                self.wiki_link_to_markdown(dir, inner, unresolved)
+            };
+            out.push_str(link.as_deref().unwrap_or(&rest[start..inner_end + 2]));
+            rest = &rest[inner_end + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn wiki_link_to_markdown(
+        &self,
+        dir: &Path,
+        inner: &str,
+        unresolved: &mut Vec<String>,
+    ) -> Option<String> {
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target, Some(label.trim())),
+            None => (inner, None),
+        };
+        let (target, heading) = match target.split_once('#') {
+            Some((target, heading)) => (target.trim(), Some(heading.trim())),
+            None => (target.trim(), None),
+        };
+        let anchor = heading.map(|heading| format!("#{}", anchor(heading)));
+        let url = if target.is_empty() {
+            // [[#Heading]] links within the same document
+            anchor.clone()?
+        } else {
+            match self.resolve(target) {
+                Some(file) => {
+                    let relative = relative_path(dir, file);
+                    format!("{}{}", encode_url(&relative), anchor.unwrap_or_default())
+                }
+                None => {
+                    unresolved.push(target.to_owned());
+                    return None;
+                }
+            }
+        };
+        let label = label.unwrap_or(if target.is_empty() {
+            heading.unwrap_or_default()
+        } else {
+            target
+        });
+        Some(format!("[{}]({})", label, url))
+    }
+
+    fn markdown_to_wiki(&self, dir: &Path, text: &str, unresolved: &mut Vec<String>) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('[') {
+            let link = markdown_link(&rest[start..]);
+            let image = rest[..start].ends_with('!');
+            out.push_str(&rest[..start]);
+            match link {
+                Some((len, label, url)) if !image => {
+                    let source = &rest[start..start + len];
+                    match self.markdown_link_to_wiki(dir, label, url, unresolved) {
+                        Some(link) => out.push_str(&link),
+                        None => out.push_str(source),
+                    }
+                    rest = &rest[start + len..];
+                }
+                _ => {
+                    out.push('[');
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn markdown_link_to_wiki(
+        &self,
+        dir: &Path,
+        label: &str,
+        url: &str,
+        unresolved: &mut Vec<String>,
+    ) -> Option<String> {
+        if url.contains(':') || url.contains('#') || url.starts_with('/') || url.is_empty() {
+            return None;
+        }
+        let target = normalize(&dir.join(decode_url(url)));
+        let stem = match self.stems.get(&target) {
+            Some(stem) => stem,
+            None => {
+                unresolved.push(url.to_owned());
+                return None;
+            }
+        };
+        if label.trim().eq_ignore_ascii_case(stem) {
+            Some(format!("[[{}]]", stem))
+        } else {
+            Some(format!("[[{}|{}]]", stem, label))
+        }
+    }
+}
+
+/// Parse a Markdown inline link at the start of `text`, returning its length,
+/// label and URL. Only simple links are recognised: no nested brackets, no
+/// link titles.
+fn markdown_link(text: &str) -> Option<(usize, &str, &str)> {
+    let label_end = text[1..].find([']', '[', '\n'])? + 1;
+    if !text[label_end..].starts_with("](") {
+        return None;
+    }
+    let url_start = label_end + 2;
+    let url_end = url_start + text[url_start..].find([')', ' ', '\t', '\n'])?;
+    if !text[url_end..].starts_with(')') {
+        return None;
+    }
+    Some((url_end + 1, &text[1..label_end], &text[url_start..url_end]))
+}
+
+/// Apply `convert` to the parts of a Markdown document outside fenced code
+/// blocks and inline code spans.
+fn map_prose(content: &str, mut convert: impl FnMut(&str) -> String) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut fence: Option<&str> = None;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(*marker));
+        match (fence, marker) {
+            (None, Some(marker)) => {
+                fence = Some(marker);
+                out.push_str(line);
+                continue;
+            }
+            (Some(open), Some(marker)) if open == marker => {
+                fence = None;
+                out.push_str(line);
+                continue;
+            }
+            (Some(_), _) => {
+                out.push_str(line);
+                continue;
+            }
+            (None, None) => {}
+        }
+
+        // alternate between prose and code spans, which open and close
+        // with matching runs of backticks
+        let mut rest = line;
+        while let Some(start) = rest.find('`') {
+            out.push_str(&convert(&rest[..start]));
+            let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+            let run = &rest[start..start + ticks];
+            let code_end = match rest[start + ticks..].find(run) {
+                Some(end) => start + ticks + end + ticks,
+                None => rest.len(),
+            };
+            out.push_str(&rest[start..code_end]);
+            rest = &rest[code_end..];
+        }
+        out.push_str(&convert(rest));
+    }
+    out
+}
+
+/// A heading's anchor as Hugo and GitHub generate it: lowercase, with
+/// spaces as hyphens and punctuation dropped.
+fn anchor(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' | '-' => Some('-'),
+            c if c.is_alphanumeric() || c == '_' => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Resolve `.` and `..` without touching the filesystem.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The path from directory `from` to `to`, with `/` separators.
+fn relative_path(from: &Path, to: &Path) -> String {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let ups = (common..from.len()).map(|_| "..".to_owned());
+    let downs = to[common..]
+        .iter()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned());
+    ups.chain(downs).collect::<Vec<_>>().join("/")
+}
+
+fn encode_url(path: &str) -> String {
+    path.replace('%', "%25")
+        .replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+}
+
+fn decode_url(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| url.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn index() -> LinkIndex {
+        let mut index = LinkIndex::default();
+        let meta = yaml::from_str("title: Getting Started\naliases: [Intro, Setup]\n").unwrap();
+        index.add(Path::new("docs/start here.md"), Some(&meta));
+        index.add(Path::new("posts/hello.md"), None);
+        index.add(Path::new("notes/intro.md"), None);
+        index
+    }
+
+    fn convert(content: &str, style: LinkStyle) -> Converted {
+        index().convert(Path::new("./posts/hello.md"), content, style)
+    }
+
+    #[test]
+    fn converts_wiki_links_to_markdown() {
+        let converted = convert(
+            "See [[getting started]], [[Setup#First Steps|the steps]] and [[#Top]].\n",
+            LinkStyle::Markdown,
+        );
+        assert_eq!(
+            Some(
+                "See [getting started](../docs/start%20here.md), \
+                 [the steps](../docs/start%20here.md#first-steps) and [Top](#top).\n"
+                    .to_owned()
+            ),
+            converted.content
+        );
+        assert!(converted.unresolved.is_empty());
+    }
+
+    #[test]
+    fn leaves_unresolved_ambiguous_and_embedded_links() {
+        let converted = convert(
+            "[[Missing]] [[intro]] ![[start here]]\n",
+            LinkStyle::Markdown,
+        );
+        assert_eq!(None, converted.content);
+        assert_eq!(vec!["Missing", "intro"], converted.unresolved);
+    }
+
+    #[test]
+    fn converts_markdown_links_to_wiki() {
+        let converted = convert(
+            "[Start Here](../docs/start%20here.md), [hi](hello.md), [web](https://x.org) \
+             ![img](hello.md) [anchor](hello.md#top)\n",
+            LinkStyle::Wiki,
+        );
+        assert_eq!(
+            Some(
+                "[[start here]], [[hello|hi]], [web](https://x.org) \
+                 ![img](hello.md) [anchor](hello.md#top)\n"
+                    .to_owned()
+            ),
+            converted.content
+        );
+    }
+
+    #[test]
+    fn skips_code() {
+        let content = "`[[Setup]]` [[Setup]]\n```\n[[Setup]]\n```\n";
+        assert_eq!(
+            Some("`[[Setup]]` [Setup](../docs/start%20here.md)\n```\n[[Setup]]\n```\n".to_owned()),
+            convert(content, LinkStyle::Markdown).content
+        );
+    }
+
+    #[test]
+    fn computes_relative_paths() {
+        assert_eq!("b.md", relative_path(Path::new("a"), Path::new("a/b.md")));
+        assert_eq!(
+            "../c/d.md",
+            relative_path(Path::new("a/b"), Path::new("a/c/d.md"))
+        );
+        assert_eq!(Path::new("a/c"), normalize(Path::new("./a/b/../c")));
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use serde_yaml as yaml;
+
+use crate::{
+    frontmatter,
+    report::{Finding, Findings},
+};
+
+/// Files that are one page in different locales, and checks that keys agree
+/// across them.
+///
+/// A page's variants live either in locale directories,
+/// `content/en/foo.md` and `content/fr/foo.md`, or side by side with the
+/// locale before the extension, `foo.md` and `foo.fr.md`, where files
+/// without one are in the first locale.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Locales {
+    codes: Vec<String>,
+}
+
+/// A page in another locale.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub locale: String,
+    pub path: PathBuf,
+    /// Its frontmatter, if it has some that parses.
+    pub meta: Option<yaml::Value>,
+}
+
+impl Locales {
+    pub fn new(codes: Vec<String>) -> Self {
+        Self { codes }
+    }
+
+    /// The locale the file at `path` is in, if it's in one.
+    pub fn locale(&self, path: &Path) -> Option<&str> {
+        self.paths(path).map(|(locale, _)| locale)
+    }
+
+    /// The variants of the file at `path` in other locales that exist.
+    pub fn variants(&self, path: &Path) -> Vec<Variant> {
+        let Some((locale, paths)) = self.paths(path) else {
+            return Vec::new();
+        };
+        paths
+            .into_iter()
+            .filter(|(code, variant)| *code != locale && variant.is_file())
+            .map(|(code, variant)| Variant {
+                locale: code.to_owned(),
+                meta: read_to_string(&variant)
+                    .ok()
+                    .and_then(|text| frontmatter::parse(&text).0?.ok()),
+                path: variant,
+            })
+            .collect()
+    }
+
+    /// Fail if any of `keys` in `metadata` differs from the frontmatter of
+    /// the file's variants in other locales, including by being set in only
+    /// some of them.
+    pub fn check(
+        &self,
+        path: &Path,
+        metadata: &yaml::Value,
+        keys: &[String],
+    ) -> Result<(), Findings> {
+        let mut findings = Vec::new();
+        for variant in self.variants(path) {
+            let Some(meta) = &variant.meta else {
+                findings.push(Finding::new(
+                    "unreadable-locale-variant",
+                    format!("couldn't read frontmatter of {}", variant.path.display()),
+                ));
+                continue;
+            };
+            for key in keys {
+                let (here, there) = (metadata.get(key), meta.get(key));
+                if here != there {
+                    findings.push(Finding::for_key(
+                        "locale-mismatch",
+                        key,
+                        format!(
+                            "{} here but {} in {} ({})",
+                            describe(here),
+                            describe(there),
+                            variant.path.display(),
+                            variant.locale
+                        ),
+                    ));
+                }
+            }
+        }
+        if findings.is_empty() {
+            Ok(())
+        } else {
+            Err(Findings(findings))
+        }
+    }
+
+    /// The locale the file at `path` is in, and the path it would have in
+    /// each locale, its own included.
+    fn paths(&self, path: &Path) -> Option<(&str, Vec<(&str, PathBuf)>)> {
+        let name = path.file_name()?;
+        let dirs: Vec<_> = path.parent()?.iter().collect();
+        if let Some((i, locale)) = dirs
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, dir)| Some((i, self.code(dir.to_str()?)?)))
+        {
+            let paths = self.codes.iter().map(|code| {
+                let mut variant: PathBuf = dirs[..i].iter().collect();
+                variant.push(code);
+                variant.extend(&dirs[i + 1..]);
+                (code.as_str(), variant.join(name))
+            });
+            return Some((locale, paths.collect()));
+        }
+
+        let (stem, extension) = name.to_str()?.rsplit_once('.')?;
+        let (base, locale) = match stem.rsplit_once('.') {
+            Some((base, code)) => match self.code(code) {
+                Some(locale) => (base, locale),
+                None => (stem, self.codes.first()?.as_str()),
+            },
+            None => (stem, self.codes.first()?.as_str()),
+        };
+        let paths = self.codes.iter().enumerate().map(|(i, code)| {
+            let name = match i {
+                0 => format!("{}.{}", base, extension),
+                _ => format!("{}.{}.{}", base, code, extension),
+            };
+            (code.as_str(), path.with_file_name(name))
+        });
+        Some((locale, paths.collect()))
+    }
+
+    fn code(&self, s: &str) -> Option<&str> {
+        self.codes
+            .iter()
+            .map(String::as_str)
+            .find(|code| *code == s)
+    }
+}
+
+fn describe(value: Option<&yaml::Value>) -> String {
+    match value.map(yaml::to_string) {
+        Some(Ok(yaml)) => yaml.trim_end().to_owned(),
+        _ => "unset".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+
+    fn locales() -> Locales {
+        Locales::new(vec!["en".into(), "fr".into()])
+    }
+
+    #[test]
+    fn finds_variant_paths() {
+        let locales = locales();
+        let (locale, paths) = locales.paths(Path::new("content/fr/posts/a.md")).unwrap();
+        assert_eq!("fr", locale);
+        assert_eq!(
+            vec![
+                ("en", PathBuf::from("content/en/posts/a.md")),
+                ("fr", PathBuf::from("content/fr/posts/a.md"))
+            ],
+            paths
+        );
+
+        let (locale, paths) = locales.paths(Path::new("posts/a.fr.md")).unwrap();
+        assert_eq!("fr", locale);
+        assert_eq!(PathBuf::from("posts/a.md"), paths[0].1);
+        assert_eq!(Some("en"), locales.locale(Path::new("posts/a.md")));
+        assert_eq!(Some("en"), locales.locale(Path::new("posts/a.v2.md")));
+        assert_eq!(None, locales.locale(Path::new("README")));
+    }
+
+    #[test]
+    fn flags_keys_that_differ_between_locales() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let en = dir.path().join("en/a.md");
+        create_dir_all(en.parent().unwrap())?;
+        write(&en, "---\nslug: hello\ntranslationKey: a\n---\nHello\n")?;
+
+        let fr = dir.path().join("fr/a.md");
+        let keys = ["slug".to_owned(), "translationKey".to_owned()];
+        let variants = locales().variants(&fr);
+        assert_eq!(1, variants.len());
+        assert_eq!("en", variants[0].locale);
+
+        let matching: yaml::Value = yaml::from_str("slug: hello\ntranslationKey: a\ntitle: Salut")?;
+        locales().check(&fr, &matching, &keys).expect("keys agree");
+        let drifted: yaml::Value = yaml::from_str("slug: bonjour\n")?;
+        let findings = locales()
+            .check(&fr, &drifted, &keys)
+            .expect_err("keys differ");
+        assert_eq!(2, findings.0.len());
+        assert_eq!("locale-mismatch", findings.0[0].rule);
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use mlua::{Lua, LuaSerdeExt, Table};
+use serde_yaml as yaml;
+
+const MAP_METATABLE: &str = "frontmatter_fixer_map_metatable";
+
+/// Deeper than any sensible frontmatter, so probably a table that contains
+/// itself.
+const MAX_DEPTH: usize = 128;
+
+/// Iterates a map's keys in a stable order, numbers before strings, so
+/// `pairs(meta)` visits keys the same way every run.
+const MAP_METATABLE_SOURCE: &str = r#"
+local function before(a, b)
+    local ta, tb = type(a), type(b)
+    if ta ~= tb then
+        return ta < tb
+    elseif ta == "number" or ta == "string" then
+        return a < b
+    end
+    return tostring(a) < tostring(b)
+end
+return {
+    __pairs = function(t)
+        local keys = {}
+        for key in next, t do
+            keys[#keys + 1] = key
+        end
+        table.sort(keys, before)
+        local i = 0
+        return function()
+            i = i + 1
+            local key = keys[i]
+            if key ~= nil then
+                return key, t[key]
+            end
+        end, t, nil
+    end,
+}
+"#;
+
+/// The metatable marking a table as a YAML mapping, even if it's empty or
+/// its keys are 1 to n.
+pub(crate) fn map_metatable(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let existing: Option<Table> = lua.named_registry_value(MAP_METATABLE)?;
+    if let Some(metatable) = existing {
+        return Ok(metatable);
+    }
+    let metatable: Table = lua
+        .load(MAP_METATABLE_SOURCE)
+        .set_name("map metatable")?
+        .eval()?;
+    lua.set_named_registry_value(MAP_METATABLE, metatable.clone())?;
+    Ok(metatable)
+}
+
+/// Convert YAML to Lua, marking sequences with mlua's array metatable and
+/// mappings with [`map_metatable`] so that [`from_lua`] gives back the same
+/// kind of collection.
+pub(crate) fn to_lua<'lua>(lua: &'lua Lua, value: &yaml::Value) -> mlua::Result<mlua::Value<'lua>> {
+    Ok(match value {
+        yaml::Value::Null => lua.null(),
+        yaml::Value::Bool(b) => mlua::Value::Boolean(*b),
+        yaml::Value::Number(n) => match n.as_i64() {
+            Some(i) => mlua::Value::Integer(i),
+            None => mlua::Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        yaml::Value::String(s) => mlua::Value::String(lua.create_string(s)?),
+        yaml::Value::Sequence(items) => {
+            let table = lua.create_table_with_capacity(items.len() as i32, 0)?;
+            for item in items {
+                table.raw_push(to_lua(lua, item)?)?;
+            }
+            table.set_metatable(Some(lua.array_metatable()));
+            mlua::Value::Table(table)
+        }
+        yaml::Value::Mapping(mapping) => {
+            let table = lua.create_table_with_capacity(0, mapping.len() as i32)?;
+            for (key, value) in mapping {
+                table.raw_set(to_lua(lua, key)?, to_lua(lua, value)?)?;
+            }
+            table.set_metatable(Some(map_metatable(lua)?));
+            mlua::Value::Table(table)
+        }
+        yaml::Value::Tagged(_) => lua.to_value(value)?,
+    })
+}
+
+/// Convert Lua to YAML. Tables marked by [`to_lua`], `fm.array` or `fm.map`
+/// become the kind of collection they're marked as; other tables are
+/// sequences if their keys are 1 to n, and mappings otherwise, with their
+/// keys sorted. An empty unmarked table is a mapping.
+pub(crate) fn from_lua(lua: &Lua, value: mlua::Value) -> mlua::Result<yaml::Value> {
+    convert(lua, value, 0)
+}
+
+/// Like [`from_lua`], but `nil` is `None`.
+pub(crate) fn from_lua_option(lua: &Lua, value: mlua::Value) -> mlua::Result<Option<yaml::Value>> {
+    match value {
+        mlua::Value::Nil => Ok(None),
+        value => from_lua(lua, value).map(Some),
+    }
+}
+
+fn convert(lua: &Lua, value: mlua::Value, depth: usize) -> mlua::Result<yaml::Value> {
+    Ok(match value {
+        mlua::Value::Nil => yaml::Value::Null,
+        mlua::Value::LightUserData(ud) if ud.0.is_null() => yaml::Value::Null,
+        mlua::Value::Boolean(b) => yaml::Value::Bool(b),
+        mlua::Value::Integer(i) => yaml::Value::Number(i.into()),
+        mlua::Value::Number(n) => yaml::Value::Number(n.into()),
+        mlua::Value::String(s) => yaml::Value::String(s.to_str()?.to_owned()),
+        mlua::Value::Table(table) => {
+            if depth >= MAX_DEPTH {
+                return Err(mlua::Error::external(
+                    "couldn't convert table to YAML: nested too deeply, does it contain itself?",
+                ));
+            }
+            let metatable = table.get_metatable();
+            let is_array = metatable == Some(lua.array_metatable());
+            let is_map = metatable.is_some() && metatable == Some(map_metatable(lua)?);
+            let len = table.raw_len();
+            let keys = || table.clone().pairs::<mlua::Value, mlua::Value>().count();
+            if is_array || (!is_map && len > 0 && keys() == len as usize) {
+                let items = (1..=len)
+                    .map(|i| convert(lua, table.raw_get(i)?, depth + 1))
+                    .collect::<mlua::Result<_>>()?;
+                yaml::Value::Sequence(items)
+            } else {
+                let mut entries = table
+                    .pairs::<mlua::Value, mlua::Value>()
+                    .map(|pair| {
+                        let (key, value) = pair?;
+                        Ok((
+                            convert(lua, key, depth + 1)?,
+                            convert(lua, value, depth + 1)?,
+                        ))
+                    })
+                    .collect::<mlua::Result<Vec<_>>>()?;
+                entries.sort_by(|(a, _), (b, _)| key_order(a).cmp(&key_order(b)));
+                yaml::Value::Mapping(entries.into_iter().collect())
+            }
+        }
+        other => {
+            return Err(mlua::Error::external(format!(
+                "couldn't convert {} to YAML",
+                other.type_name()
+            )))
+        }
+    })
+}
+
+/// Sort numeric keys before string keys, as `pairs` does for maps.
+fn key_order(key: &yaml::Value) -> (u8, Option<i64>, Option<&str>) {
+    match key {
+        yaml::Value::Number(n) => (0, n.as_i64(), None),
+        yaml::Value::String(s) => (1, None, Some(s)),
+        _ => (2, None, None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(lua: &Lua, yaml: &str, script: &str) -> mlua::Result<String> {
+        let value: yaml::Value = yaml::from_str(yaml).unwrap();
+        lua.globals().set("meta", to_lua(lua, &value)?)?;
+        lua.load(script).exec()?;
+        let value = from_lua(lua, lua.globals().get("meta")?)?;
+        Ok(yaml::to_string(&value).unwrap())
+    }
+
+    #[test]
+    fn keeps_lists_and_maps_apart() -> mlua::Result<()> {
+        let lua = Lua::new();
+        crate::helpers::register(&lua)?;
+        let yaml = "tags: []\nextra: {}\nchapters:\n  1: Intro\n  2: Body\nlist:\n- a\n";
+        assert_eq!(
+            "chapters:\n  1: Intro\n  2: Body\nextra: {}\nlist:\n- a\ntags: []\n",
+            round_trip(&lua, yaml, "")?
+        );
+        assert_eq!(
+            "a: []\nb: {}\nc:\n  1: x\nd:\n- x\ne: {}\n",
+            round_trip(
+                &lua,
+                "{}",
+                "meta.a = fm.array{}; meta.b = fm.map{}; meta.c = fm.map{'x'}; \
+                 meta.d = {'x'}; meta.e = {}"
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn iterates_maps_in_order() -> mlua::Result<()> {
+        let lua = Lua::new();
+        let value: yaml::Value = yaml::from_str("c: 1\na: 2\nb: 3\n2: 4\n1: 5\n").unwrap();
+        lua.globals().set("meta", to_lua(&lua, &value)?)?;
+        let keys: String = lua
+            .load(
+                "local keys = {} for k in pairs(meta) do keys[#keys + 1] = k end \
+                   return table.concat(keys, ' ')",
+            )
+            .eval()?;
+        assert_eq!("1 2 a b c", keys);
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::hash;
+
+/// A record of a run's inputs and outputs, for build systems to use as a
+/// dependency snapshot.
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    tool_version: &'static str,
+    files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    input: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_sha256: Option<String>,
+    outputs: Vec<ManifestOutput>,
+    changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestOutput {
+    path: String,
+    sha256: String,
+}
+
+impl Default for RunManifest {
+    fn default() -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION"),
+            files: Vec::new(),
+        }
+    }
+}
+
+impl RunManifest {
+    /// Record a successfully processed file, and where its output was
+    /// written (if anywhere).
+    pub fn record_success(
+        &mut self,
+        input: &str,
+        original: &[u8],
+        output: &[u8],
+        written_to: Option<&Path>,
+    ) {
+        let outputs = written_to
+            .map(|path| ManifestOutput {
+                path: path.display().to_string(),
+                sha256: hash::sha256_hex(output),
+            })
+            .into_iter()
+            .collect();
+        self.files.push(ManifestEntry {
+            input: input.to_owned(),
+            input_sha256: Some(hash::sha256_hex(original)),
+            outputs,
+            changed: original != output,
+            error: None,
+        });
+    }
+
+    pub fn record_failure(&mut self, input: &str, error: &eyre::Report) {
+        self.files.push(ManifestEntry {
+            input: input.to_owned(),
+            input_sha256: None,
+            outputs: Vec::new(),
+            changed: false,
+            error: Some(format!("{:#}", error)),
+        });
+    }
+
+    pub fn to_json(&self) -> eyre::Result<Vec<u8>> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        Ok(json)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_hashes_before_and_after() -> eyre::Result<()> {
+        let mut manifest = RunManifest::default();
+        manifest.record_success("a.md", b"", b"", Some(Path::new("a.md")));
+        manifest.record_failure("b.md", &eyre::eyre!("bad yaml"));
+
+        let json: serde_json::Value = serde_json::from_slice(&manifest.to_json()?)?;
+        let empty_sha = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(empty_sha, json["files"][0]["input_sha256"]);
+        assert_eq!("a.md", json["files"][0]["outputs"][0]["path"]);
+        assert_eq!(empty_sha, json["files"][0]["outputs"][0]["sha256"]);
+        assert_eq!(Some(false), json["files"][0]["changed"].as_bool());
+        assert_eq!("bad yaml", json["files"][1]["error"]);
+        assert!(json["files"][1].get("input_sha256").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,737 @@
+use std::{
+    fmt,
+    fs::{read_dir, read_to_string, write},
+    path::Path,
+    str::FromStr,
+};
+
+use eyre::{eyre, Context};
+use serde_yaml as yaml;
+
+use crate::{feed::scalar, keypath, query::Condition};
+
+/// One step of a `--migrate` migration, changing a key without a script.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    /// Rename a top-level key, keeping its place among the others.
+    Rename { from: String, to: String },
+    /// Move a value from one dotted path to another.
+    Move { from: String, to: String },
+    /// Split a string into a list of the trimmed, non-empty parts between
+    /// `separator`.
+    Split { key: String, separator: String },
+    /// Join a list into a string with `separator` between the items.
+    Join { key: String, separator: String },
+    /// Replace values, or items of a list, equal to each `from` with `to`.
+    MapValues {
+        key: String,
+        mapping: Vec<(String, yaml::Value)>,
+    },
+    /// Set a key that's missing or null.
+    Default { key: String, value: yaml::Value },
+    /// Remove a key.
+    Drop { key: String },
+    /// Move the keys of the mapping at a dotted path up into the mapping
+    /// holding it, in its place, each with `prefix` added.
+    Unnest { key: String, prefix: String },
+    /// Move the top-level keys starting with `prefix` into the mapping at
+    /// a dotted path, without the prefix.
+    Nest { prefix: String, key: String },
+    /// Add `prefix` to the top-level keys that don't have it, other than
+    /// those in `except`.
+    AddPrefix { prefix: String, except: Vec<String> },
+    /// Remove `prefix` from the top-level keys that have it.
+    StripPrefix { prefix: String },
+}
+
+/// An operation and the `--where` style conditions a file's frontmatter
+/// must meet for it to apply, written like
+///
+/// ```text
+/// rename author authors
+/// move seo.title title
+/// split tags ,
+/// join categories " / "
+/// map-values status wip=draft done=published
+/// default layout post if !layout
+/// drop legacy_id if date<2020
+/// unnest seo
+/// nest og_ og
+/// add-prefix x_ except title date tags
+/// strip-prefix x_
+/// ```
+///
+/// Arguments with spaces go in double quotes, and conditions are joined
+/// with `and`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Migration {
+    pub operation: Operation,
+    pub conditions: Vec<Condition>,
+    source: String,
+}
+
+impl FromStr for Migration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = words(s)?;
+        let (step, conditions) = match words.iter().position(|word| word == "if") {
+            Some(i) => (&words[..i], &words[i + 1..]),
+            None => (&words[..], &[][..]),
+        };
+        let conditions = match conditions {
+            [] if step.len() < words.len() => return Err(format!("nothing after if in {:?}", s)),
+            [] => Vec::new(),
+            conditions => conditions
+                .split(|word| word == "and")
+                .map(|condition| condition.join(" ").parse())
+                .collect::<Result<_, _>>()?,
+        };
+        let args = |n: usize| -> Result<&[String], String> {
+            match step.get(1..) {
+                Some(args) if args.len() == n => Ok(args),
+                _ => Err(format!("{} takes {} arguments, in {:?}", step[0], n, s)),
+            }
+        };
+        let operation = match step.first().map(String::as_str) {
+            Some("rename") => {
+                let args = args(2)?;
+                if args[0].contains('.') || args[1].contains('.') {
+                    return Err(format!("rename is for top-level keys, use move: {:?}", s));
+                }
+                Operation::Rename {
+                    from: args[0].clone(),
+                    to: args[1].clone(),
+                }
+            }
+            Some("move") => {
+                let args = args(2)?;
+                Operation::Move {
+                    from: args[0].clone(),
+                    to: args[1].clone(),
+                }
+            }
+            Some("split") => {
+                let args = args(2)?;
+                Operation::Split {
+                    key: args[0].clone(),
+                    separator: args[1].clone(),
+                }
+            }
+            Some("join") => {
+                let args = args(2)?;
+                Operation::Join {
+                    key: args[0].clone(),
+                    separator: args[1].clone(),
+                }
+            }
+            Some("map-values") if step.len() > 2 => Operation::MapValues {
+                key: step[1].clone(),
+                mapping: step[2..]
+                    .iter()
+                    .map(|pair| match pair.split_once('=') {
+                        Some((from, to)) => Ok((from.to_owned(), value(to))),
+                        None => Err(format!("expected FROM=TO, got {:?}", pair)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            },
+            Some("map-values") => {
+                return Err(format!("map-values takes KEY FROM=TO..., in {:?}", s))
+            }
+            Some("default") => {
+                let args = args(2)?;
+                Operation::Default {
+                    key: args[0].clone(),
+                    value: value(&args[1]),
+                }
+            }
+            Some("drop") => Operation::Drop {
+                key: args(1)?[0].clone(),
+            },
+            Some("unnest") if step.len() == 2 || step.len() == 3 => Operation::Unnest {
+                key: step[1].clone(),
+                prefix: step.get(2).cloned().unwrap_or_default(),
+            },
+            Some("unnest") => return Err(format!("unnest takes KEY [PREFIX], in {:?}", s)),
+            Some("nest") => {
+                let args = args(2)?;
+                Operation::Nest {
+                    prefix: args[0].clone(),
+                    key: args[1].clone(),
+                }
+            }
+            Some("add-prefix") => match &step[1..] {
+                [prefix] => Operation::AddPrefix {
+                    prefix: prefix.clone(),
+                    except: Vec::new(),
+                },
+                [prefix, except, keys @ ..] if except == "except" && !keys.is_empty() => {
+                    Operation::AddPrefix {
+                        prefix: prefix.clone(),
+                        except: keys.to_vec(),
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "add-prefix takes PREFIX [except KEY...], in {:?}",
+                        s
+                    ))
+                }
+            },
+            Some("strip-prefix") => Operation::StripPrefix {
+                prefix: args(1)?[0].clone(),
+            },
+            _ => {
+                return Err(format!(
+                    "expected one of rename, move, split, join, map-values, default, drop, \
+                     unnest, nest, add-prefix, strip-prefix, got {:?}",
+                    s
+                ))
+            }
+        };
+        Ok(Self {
+            operation,
+            conditions,
+            source: s.trim().to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Migration {
+    /// Apply the operation to `metadata` if it meets the conditions,
+    /// returning whether that changed anything.
+    ///
+    /// Fails rather than overwrite a value already at the destination of a
+    /// rename or move.
+    pub fn apply(&self, metadata: &mut yaml::Value) -> eyre::Result<bool> {
+        if !self.conditions.iter().all(|c| c.matches(Some(metadata))) {
+            return Ok(false);
+        }
+        self.operation.apply(metadata)
+    }
+}
+
+impl Operation {
+    /// Apply the operation to `metadata`, returning whether that changed
+    /// anything, and failing rather than overwrite a value already at the
+    /// destination of a rename or move.
+    pub fn apply(&self, metadata: &mut yaml::Value) -> eyre::Result<bool> {
+        match self {
+            Operation::Rename { from, to } => {
+                let mapping = match metadata.as_mapping_mut() {
+                    Some(mapping) if mapping.contains_key(from.as_str()) => mapping,
+                    _ => return Ok(false),
+                };
+                if mapping.contains_key(to.as_str()) {
+                    return Err(eyre!(
+                        "can't rename {} to {}, which is already set",
+                        from,
+                        to
+                    ));
+                }
+                *mapping = std::mem::take(mapping)
+                    .into_iter()
+                    .map(|(key, value)| {
+                        if key.as_str() == Some(from.as_str()) {
+                            (to.as_str().into(), value)
+                        } else {
+                            (key, value)
+                        }
+                    })
+                    .collect();
+                Ok(true)
+            }
+            Operation::Move { from, to } => {
+                if keypath::get(metadata, from).is_none() {
+                    return Ok(false);
+                }
+                if keypath::get(metadata, to).is_some() {
+                    return Err(eyre!("can't move {} to {}, which is already set", from, to));
+                }
+                let value = keypath::remove(metadata, from).expect("checked above");
+                keypath::set(metadata, to, value)
+                    .map_err(|_| eyre!("can't move {} to {}, not within a mapping", from, to))?;
+                Ok(true)
+            }
+            Operation::Split { key, separator } => {
+                let value = match keypath::get_mut(metadata, key) {
+                    Some(value) if value.is_string() => value,
+                    _ => return Ok(false),
+                };
+                let items = value
+                    .as_str()
+                    .unwrap_or_default()
+                    .split(separator.as_str())
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(yaml::Value::from)
+                    .collect();
+                *value = yaml::Value::Sequence(items);
+                Ok(true)
+            }
+            Operation::Join { key, separator } => {
+                let value = match keypath::get_mut(metadata, key) {
+                    Some(value) if value.is_sequence() => value,
+                    _ => return Ok(false),
+                };
+                let items: Option<Vec<String>> = value
+                    .as_sequence()
+                    .into_iter()
+                    .flatten()
+                    .map(scalar)
+                    .collect();
+                let items =
+                    items.ok_or_else(|| eyre!("can't join {}, not a list of values", key))?;
+                *value = items.join(separator).into();
+                Ok(true)
+            }
+            Operation::MapValues { key, mapping } => {
+                let map = |value: &mut yaml::Value| {
+                    let to = scalar(value)
+                        .and_then(|from| mapping.iter().find(|(f, _)| *f == from))
+                        .map(|(_, to)| to);
+                    match to {
+                        Some(to) if *to != *value => {
+                            *value = to.clone();
+                            true
+                        }
+                        _ => false,
+                    }
+                };
+                Ok(match keypath::get_mut(metadata, key) {
+                    // every item, not just up to the first changed
+                    Some(yaml::Value::Sequence(items)) => {
+                        items.iter_mut().map(map).filter(|&changed| changed).count() > 0
+                    }
+                    Some(value) => map(value),
+                    None => false,
+                })
+            }
+            Operation::Default { key, value } => {
+                match keypath::get(metadata, key) {
+                    Some(existing) if !existing.is_null() => return Ok(false),
+                    _ => {}
+                }
+                keypath::set(metadata, key, value.clone())
+                    .map_err(|_| eyre!("can't default {}, not within a mapping", key))?;
+                Ok(true)
+            }
+            Operation::Drop { key } => Ok(keypath::remove(metadata, key).is_some()),
+            Operation::Unnest { key, prefix } => {
+                let (parent, name) = match key.rsplit_once('.') {
+                    Some((parent, name)) => (keypath::get_mut(metadata, parent), name),
+                    None => (Some(metadata), key.as_str()),
+                };
+                let Some(parent) = parent.and_then(yaml::Value::as_mapping_mut) else {
+                    return Ok(false);
+                };
+                let children = match parent.get(name) {
+                    Some(yaml::Value::Mapping(children)) => children,
+                    Some(_) => return Err(eyre!("can't unnest {}, not a mapping", key)),
+                    None => return Ok(false),
+                };
+                let mut unnested = yaml::Mapping::with_capacity(parent.len() + children.len());
+                for (k, value) in parent.iter() {
+                    if k.as_str() != Some(name) {
+                        insert_new(&mut unnested, k.clone(), value.clone())?;
+                        continue;
+                    }
+                    for (child, value) in children {
+                        let child = match child.as_str() {
+                            Some(child) => format!("{}{}", prefix, child).into(),
+                            None => child.clone(),
+                        };
+                        insert_new(&mut unnested, child, value.clone())?;
+                    }
+                }
+                *parent = unnested;
+                Ok(true)
+            }
+            Operation::Nest { prefix, key } => {
+                let Some(mapping) = metadata.as_mapping() else {
+                    return Ok(false);
+                };
+                let top = key.split('.').next().unwrap_or_default();
+                let nests = |k: &yaml::Value| {
+                    let k = k.as_str().filter(|&k| k != top)?;
+                    let rest = k.strip_prefix(prefix.as_str())?;
+                    (!rest.is_empty()).then(|| rest.to_owned())
+                };
+                if !mapping.keys().any(|k| nests(k).is_some()) {
+                    return Ok(false);
+                }
+                let mut nested = match keypath::get(metadata, key) {
+                    Some(yaml::Value::Mapping(nested)) => nested.clone(),
+                    Some(_) => return Err(eyre!("can't nest under {}, not a mapping", key)),
+                    None => yaml::Mapping::new(),
+                };
+                let mut rest = yaml::Mapping::with_capacity(mapping.len());
+                for (k, value) in mapping {
+                    match nests(k) {
+                        Some(name) => insert_new(&mut nested, name.into(), value.clone())?,
+                        None => insert_new(&mut rest, k.clone(), value.clone())?,
+                    }
+                }
+                *metadata = yaml::Value::Mapping(rest);
+                keypath::set(metadata, key, nested.into())
+                    .map_err(|_| eyre!("can't nest under {}, not within a mapping", key))?;
+                Ok(true)
+            }
+            Operation::AddPrefix { prefix, except } => rename_keys(metadata, |k| {
+                let keep = k.starts_with(prefix.as_str()) || except.iter().any(|e| e == k);
+                (!keep).then(|| format!("{}{}", prefix, k))
+            }),
+            Operation::StripPrefix { prefix } => rename_keys(metadata, |k| {
+                let rest = k.strip_prefix(prefix.as_str())?;
+                (!rest.is_empty()).then(|| rest.to_owned())
+            }),
+        }
+    }
+}
+
+/// Rename the top-level keys that `rename` gives new names, keeping their
+/// places, returning whether there were any.
+fn rename_keys(
+    metadata: &mut yaml::Value,
+    rename: impl Fn(&str) -> Option<String>,
+) -> eyre::Result<bool> {
+    let Some(mapping) = metadata.as_mapping_mut() else {
+        return Ok(false);
+    };
+    let mut renamed = yaml::Mapping::with_capacity(mapping.len());
+    let mut changed = false;
+    for (key, value) in mapping.iter() {
+        let key = match key.as_str().and_then(&rename) {
+            Some(new) => {
+                changed = true;
+                new.into()
+            }
+            None => key.clone(),
+        };
+        insert_new(&mut renamed, key, value.clone())?;
+    }
+    *mapping = renamed;
+    Ok(changed)
+}
+
+/// Insert into a mapping being rebuilt, failing rather than overwrite a
+/// key that's already there.
+fn insert_new(
+    mapping: &mut yaml::Mapping,
+    key: yaml::Value,
+    value: yaml::Value,
+) -> eyre::Result<()> {
+    if mapping.contains_key(&key) {
+        let key = scalar(&key).unwrap_or_default();
+        return Err(eyre!("can't move two values to {}", key));
+    }
+    mapping.insert(key, value);
+    Ok(())
+}
+
+/// Apply each migration in turn, returning those that changed anything.
+pub fn apply(metadata: &mut yaml::Value, migrations: &[Migration]) -> eyre::Result<Vec<String>> {
+    let mut applied = Vec::new();
+    for migration in migrations {
+        let changed = migration
+            .apply(metadata)
+            .map_err(|e| eyre!("couldn't migrate ({}): {}", migration, e))?;
+        if changed {
+            applied.push(migration.to_string());
+        }
+    }
+    Ok(applied)
+}
+
+/// Where `frontmatter-fixer migrate` looks for migration files by default.
+pub const MIGRATIONS_DIR: &str = "migrations";
+
+/// The frontmatter key recording the last versioned migration applied to
+/// a file, unless the whole corpus's version is kept in a state file.
+pub const VERSION_KEY: &str = "migration";
+
+/// Numbered migration files from a directory, applied in order to bring
+/// each file up to date, like a database's schema migrations.
+///
+/// Each file is named for its version, such as `001-authors-to-list` or
+/// `2_drop_legacy.txt`, and holds a migration per line as `--migrate`
+/// takes them, with blank lines and lines starting `#` ignored.
+///
+/// What's been applied is recorded either per file, in its `migration`
+/// key, or for the whole corpus in a state file holding the last version
+/// applied. Per file, files added later without the key get every
+/// migration; with a state file, only files present for a migration get
+/// it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Versions {
+    versions: Vec<(u64, String, Vec<Migration>)>,
+    /// The version the state file says the corpus is at, if there is one.
+    corpus_version: Option<u64>,
+}
+
+impl Versions {
+    /// Load the migrations in `dir`, and with `state`, the corpus's version
+    /// from it (0 if it doesn't exist yet).
+    pub fn load(dir: &Path, state: Option<&Path>) -> eyre::Result<Self> {
+        let mut versions = Vec::new();
+        let entries = read_dir(dir).context(format!("couldn't read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            if name.starts_with('.') || !path.is_file() {
+                continue;
+            }
+            let digits = name
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(name.len());
+            let version: u64 = name[..digits]
+                .parse()
+                .map_err(|_| eyre!("migration {} doesn't start with a version number", name))?;
+            let text = read_to_string(&path).context(format!("couldn't read {}", name))?;
+            let migrations = parse_lines(&text).context(format!("in migration {}", name))?;
+            versions.push((version, name, migrations));
+        }
+        versions.sort_by_key(|(version, _, _)| *version);
+        if let Some(pair) = versions.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(eyre!(
+                "migrations {} and {} have the same version",
+                pair[0].1,
+                pair[1].1
+            ));
+        }
+        let corpus_version = match state {
+            Some(state) if state.exists() => {
+                let text =
+                    read_to_string(state).context(format!("couldn't read {}", state.display()))?;
+                let version = text.trim().parse().map_err(|_| {
+                    eyre!("{} should hold a migration version number", state.display())
+                })?;
+                Some(version)
+            }
+            Some(_) => Some(0),
+            None => None,
+        };
+        Ok(Self {
+            versions,
+            corpus_version,
+        })
+    }
+
+    /// The last version, 0 if there are no migrations.
+    pub fn latest(&self) -> u64 {
+        self.versions.last().map_or(0, |(version, _, _)| *version)
+    }
+
+    /// Apply the migrations `metadata` hasn't had yet, returning those
+    /// that changed anything, and unless the corpus has a state file,
+    /// record the latest version in its `migration` key.
+    pub fn apply(&self, metadata: &mut yaml::Value) -> eyre::Result<Vec<String>> {
+        let current = match self.corpus_version {
+            Some(version) => version,
+            None => match metadata.get(VERSION_KEY) {
+                None => 0,
+                Some(version) => version
+                    .as_u64()
+                    .ok_or_else(|| eyre!("{} should be a migration version number", VERSION_KEY))?,
+            },
+        };
+        let mut applied = Vec::new();
+        for (_, name, migrations) in self.versions.iter().filter(|(v, _, _)| *v > current) {
+            let changed = apply(metadata, migrations).context(format!("in migration {}", name))?;
+            applied.extend(changed.into_iter().map(|m| format!("{}: {}", name, m)));
+        }
+        if self.corpus_version.is_none() && self.latest() > current {
+            keypath::set(metadata, VERSION_KEY, self.latest().into())
+                .map_err(|_| eyre!("frontmatter isn't a mapping"))?;
+        }
+        Ok(applied)
+    }
+
+    /// Record in `state` that the corpus is now at the latest version.
+    pub fn save_state(&self, state: &Path) -> eyre::Result<()> {
+        write(state, format!("{}\n", self.latest()))
+            .context(format!("couldn't write {}", state.display()))
+    }
+}
+
+/// The migrations in a versioned migration file.
+fn parse_lines(text: &str) -> eyre::Result<Vec<Migration>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| line.parse().map_err(|e| eyre!("line {}: {}", index + 1, e)))
+        .collect()
+}
+
+/// A value as YAML would read it, so `true` and `3` aren't strings.
+fn value(s: &str) -> yaml::Value {
+    yaml::from_str(s).unwrap_or_else(|_| s.into())
+}
+
+/// Split on whitespace, except within double quotes.
+fn words(s: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("unclosed quote in {:?}", s));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn migrate(yaml: &str, migrations: &[&str]) -> eyre::Result<(String, Vec<String>)> {
+        let migrations: Vec<Migration> = migrations
+            .iter()
+            .map(|m| m.parse().map_err(|e: String| eyre!(e)))
+            .collect::<eyre::Result<_>>()?;
+        let mut metadata: yaml::Value = yaml::from_str(yaml)?;
+        let applied = apply(&mut metadata, &migrations)?;
+        Ok((yaml::to_string(&metadata)?, applied))
+    }
+
+    #[test]
+    fn migrates_keys() -> eyre::Result<()> {
+        let (migrated, applied) = migrate(
+            "author: Ann\nseo:\n  title: Hi\ntags: a, b,\ncats: [x, y]\nstatus: wip\nold: 1\n",
+            &[
+                "rename author authors",
+                "move seo.title title",
+                "split tags ,",
+                "join cats \" / \"",
+                "map-values status wip=draft done=published",
+                "default layout post if status=draft",
+                "default draft true if !draft",
+                "drop old if old>2",
+                "drop missing",
+            ],
+        )?;
+        assert_eq!(
+            "authors: Ann\nseo: {}\ntags:\n- a\n- b\ncats: x / y\nstatus: draft\nold: 1\n\
+             title: Hi\nlayout: post\ndraft: true\n",
+            migrated
+        );
+        assert_eq!(7, applied.len());
+        assert_eq!("join cats \" / \"", applied[3]);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_only_new_versions() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("001-authors"),
+            "# list them\nsplit author ,\n",
+        )?;
+        std::fs::write(dir.path().join("2_rename.txt"), "\nrename author authors\n")?;
+        let versions = Versions::load(dir.path(), None)?;
+        assert_eq!(2, versions.latest());
+
+        let mut metadata: yaml::Value = yaml::from_str("author: Ann, Bob\n")?;
+        assert_eq!(2, versions.apply(&mut metadata)?.len());
+        assert!(versions.apply(&mut metadata)?.is_empty());
+        assert_eq!(
+            "authors:\n- Ann\n- Bob\nmigration: 2\n",
+            yaml::to_string(&metadata)?
+        );
+
+        let mut metadata: yaml::Value = yaml::from_str("author: Ann\nmigration: 1\n")?;
+        assert_eq!(
+            vec!["2_rename.txt: rename author authors"],
+            versions.apply(&mut metadata)?
+        );
+
+        let state_dir = tempfile::tempdir()?;
+        let state = state_dir.path().join("state");
+        std::fs::write(&state, "1\n")?;
+        let mut metadata: yaml::Value = yaml::from_str("author: Ann, Bob\n")?;
+        let versions = Versions::load(dir.path(), Some(&state))?;
+        versions.apply(&mut metadata)?;
+        assert_eq!("authors: Ann, Bob\n", yaml::to_string(&metadata)?);
+        versions.save_state(&state)?;
+        assert_eq!("2\n", read_to_string(&state)?);
+
+        std::fs::write(dir.path().join("02-again"), "drop a\n")?;
+        assert!(Versions::load(dir.path(), None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn moves_whole_namespaces() -> eyre::Result<()> {
+        let (migrated, applied) = migrate(
+            "title: Hi\nseo:\n  description: D\n  image: i.png\ndraft: true\nog_title: T\n\
+             og_type: article\nog: {site: S}\nsite:\n  meta: {a: 1}\n",
+            &[
+                "unnest seo",
+                "nest og_ og",
+                "unnest site.meta m_",
+                "add-prefix x_ except title og",
+                "strip-prefix y_",
+            ],
+        )?;
+        assert_eq!(
+            "title: Hi\nx_description: D\nx_image: i.png\nx_draft: true\n\
+             og:\n  site: S\n  title: T\n  type: article\nx_site:\n  m_a: 1\n",
+            migrated
+        );
+        assert_eq!(4, applied.len());
+        let (stripped, _) = migrate("x_a: 1\nb: 2\nx_: 3\n", &["strip-prefix x_"])?;
+        assert_eq!("a: 1\nb: 2\nx_: 3\n", stripped);
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_to_overwrite() {
+        assert!(migrate("a: 1\nb: 2\n", &["rename a b"]).is_err());
+        assert!(migrate("a: 1\nb:\n  c: 2\n", &["move a b.c"]).is_err());
+        assert!(migrate("a: 1\nb: 2\n", &["move a b.c"]).is_err());
+        assert!(migrate("a: 1\nb:\n  a: 2\n", &["unnest b"]).is_err());
+        assert!(migrate("x_a: 1\na: 2\n", &["strip-prefix x_"]).is_err());
+        assert!(migrate("og_a: 1\nog: {a: 2}\n", &["nest og_ og"]).is_err());
+        assert!(migrate("og_a: 1\nog: 2\n", &["nest og_ og"]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_migrations() {
+        for invalid in [
+            "rename a",
+            "rename a.b c",
+            "split tags",
+            "map-values status",
+            "map-values status wip",
+            "drop a if",
+            "join tags \"unclosed",
+            "unnest",
+            "nest og_",
+            "add-prefix x_ title",
+            "add-prefix x_ except",
+            "frobnicate a",
+        ] {
+            assert!(invalid.parse::<Migration>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::borrow::Cow;
+
+use eyre::eyre;
+use serde_yaml as yaml;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+/// Compose text into Unicode Normalization Form C, as macOS doesn't when
+/// it decomposes file names (and whatever's pasted from them). What comes
+/// out is always canonically equivalent to what went in.
+pub fn nfc(s: &str) -> Cow<'_, str> {
+    if is_nfc_quick(s.chars()) == IsNormalized::Yes {
+        return Cow::Borrowed(s);
+    }
+    let composed: String = s.nfc().collect();
+    if composed == s {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(composed)
+    }
+}
+
+/// Compose every string in frontmatter, keys included, returning whether
+/// any changed. Fails if two keys of a mapping are the same once composed,
+/// rather than keep only one of their values.
+pub fn nfc_value(value: &mut yaml::Value) -> eyre::Result<bool> {
+    match value {
+        yaml::Value::String(s) => match nfc(s) {
+            Cow::Owned(composed) => {
+                *s = composed;
+                Ok(true)
+            }
+            Cow::Borrowed(_) => Ok(false),
+        },
+        yaml::Value::Sequence(items) => items
+            .iter_mut()
+            .try_fold(false, |changed, item| Ok(nfc_value(item)? | changed)),
+        yaml::Value::Mapping(mapping) => {
+            let needs_keys = mapping.keys().any(|key| {
+                key.as_str()
+                    .is_some_and(|key| matches!(nfc(key), Cow::Owned(_)))
+            });
+            let mut changed = needs_keys;
+            if needs_keys {
+                let mut composed = yaml::Mapping::with_capacity(mapping.len());
+                for (mut key, value) in std::mem::take(mapping) {
+                    nfc_value(&mut key)?;
+                    if composed.contains_key(&key) {
+                        // only strings compose, so only they can collide
+                        return Err(eyre!(
+                            "frontmatter has key {:?} written both composed and decomposed",
+                            key.as_str().unwrap_or_default()
+                        ));
+                    }
+                    composed.insert(key, value);
+                }
+                *mapping = composed;
+            }
+            for value in mapping.values_mut() {
+                changed |= nfc_value(value)?;
+            }
+            Ok(changed)
+        }
+        yaml::Value::Tagged(tagged) => nfc_value(&mut tagged.value),
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn composes_decomposed_text() -> eyre::Result<()> {
+        assert_eq!("Caf\u{e9} cr\u{e8}me", nfc("Cafe\u{301} cre\u{300}me"));
+        // Vietnamese letters with two marks
+        assert_eq!("Vi\u{1ec7}t", nfc("Vie\u{323}\u{302}t"));
+        assert_eq!(
+            "\u{d55c}\u{ae00}",
+            nfc("\u{1112}\u{1161}\u{11ab}\u{1100}\u{1173}\u{11af}")
+        );
+        assert!(matches!(nfc("d\u{e9}j\u{e0} vu"), Cow::Borrowed(_)));
+        // a mark with nothing to compose with is left alone
+        assert_eq!("x\u{301}", nfc("x\u{301}"));
+
+        let mut metadata: yaml::Value = yaml::from_str("Nai\u{308}ve: [re\u{301}sume\u{301}]\n")?;
+        assert!(nfc_value(&mut metadata)?);
+        assert_eq!(
+            yaml::from_str::<yaml::Value>("Na\u{ef}ve: [r\u{e9}sum\u{e9}]\n")?,
+            metadata
+        );
+        assert!(!nfc_value(&mut metadata)?);
+
+        let mut metadata: yaml::Value = yaml::from_str("caf\u{e9}: 1\ncafe\u{301}: 2\n")?;
+        let err = nfc_value(&mut metadata).expect_err("keys collide once composed");
+        assert!(err.to_string().contains("caf\u{e9}"), "{}", err);
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::str::FromStr;
+
+use serde_yaml as yaml;
+
+/// An order for frontmatter's top-level keys: those listed first, in the
+/// order given, then the rest alphabetically, so that every file in a repo
+/// lays out its keys the same way.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyOrder {
+    first: Vec<String>,
+}
+
+impl FromStr for KeyOrder {
+    type Err = String;
+
+    /// Keys separated by commas, or nothing for alphabetical order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let first: Vec<String> = s
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_owned)
+            .collect();
+        for (i, key) in first.iter().enumerate() {
+            if first[..i].contains(key) {
+                return Err(format!("{} is listed twice", key));
+            }
+        }
+        Ok(Self { first })
+    }
+}
+
+impl KeyOrder {
+    pub fn new<S: Into<String>>(first: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            first: first.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Put the keys of a mapping in order, returning whether that moved any.
+    /// Keys that aren't strings go last, in the order they were in.
+    pub fn apply(&self, metadata: &mut yaml::Value) -> bool {
+        let Some(mapping) = metadata.as_mapping_mut() else {
+            return false;
+        };
+        let rank = |key: &yaml::Value| match key.as_str() {
+            Some(key) => match self.first.iter().position(|first| first == key) {
+                Some(position) => (0, position, key.to_owned()),
+                None => (1, 0, key.to_owned()),
+            },
+            None => (2, 0, String::new()),
+        };
+        let mut entries: Vec<_> = std::mem::take(mapping).into_iter().collect();
+        let before: Vec<yaml::Value> = entries.iter().map(|(key, _)| key.clone()).collect();
+        // stable, so keys that rank the same keep their order
+        entries.sort_by_cached_key(|(key, _)| rank(key));
+        let moved = entries.iter().map(|(key, _)| key).ne(before.iter());
+        *mapping = entries.into_iter().collect();
+        moved
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sorts_keys() -> Result<(), String> {
+        let mut metadata: yaml::Value =
+            yaml::from_str("draft: true\ntags: [a]\n1: one\nauthor: me\ntitle: Hi\n").unwrap();
+        assert!("title,date,tags".parse::<KeyOrder>()?.apply(&mut metadata));
+        assert_eq!(
+            "title: Hi\ntags:\n- a\nauthor: me\ndraft: true\n1: one\n",
+            yaml::to_string(&metadata).unwrap()
+        );
+        assert!(!"title,date,tags".parse::<KeyOrder>()?.apply(&mut metadata));
+        assert!(KeyOrder::default().apply(&mut metadata));
+        assert_eq!(
+            "author: me\ndraft: true\ntags:\n- a\ntitle: Hi\n1: one\n",
+            yaml::to_string(&metadata).unwrap()
+        );
+        assert!("title,title".parse::<KeyOrder>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    env, fmt,
+    io::{stderr, IsTerminal, Write},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::config::Config;
+
+/// Width of the bar itself, not counting the counts and path after it.
+const BAR_WIDTH: usize = 24;
+
+/// How much a run says on stderr.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors, with `--quiet`.
+    Quiet,
+    /// Notices and the summary at the end.
+    Normal,
+    /// Every file and what was done to it, with `--verbose`.
+    Verbose,
+}
+
+/// What happened to a file, for the progress bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Changed,
+    Unchanged,
+    Skipped,
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Changed => "changed",
+            Self::Unchanged => "unchanged",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        })
+    }
+}
+
+/// Reports a run's progress and outcome on stderr, at the verbosity the
+/// user asked for, with a progress bar when stderr is a terminal.
+///
+/// Clones share the progress bar, so workers on other threads can advance it
+/// and print without garbling it.
+#[derive(Clone, Debug)]
+pub struct Output {
+    verbosity: Verbosity,
+    progress: Option<Arc<Mutex<Progress>>>,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    total: usize,
+    done: usize,
+    failed: usize,
+    /// The bar as last drawn, to draw again after printing a message.
+    line: Option<String>,
+}
+
+impl Output {
+    /// Output at the verbosity `cfg` asks for, without a progress bar.
+    pub fn new(cfg: &Config) -> Self {
+        let verbosity = if cfg.quiet {
+            Verbosity::Quiet
+        } else if cfg.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        };
+        Self {
+            verbosity,
+            progress: None,
+        }
+    }
+
+    /// Add a progress bar, unless stderr isn't a terminal, the run is quiet,
+    /// or the bar would get in the way of prompts or of printing files.
+    pub fn with_progress(mut self, cfg: &Config) -> Self {
+        let in_the_way = cfg.interactive || cfg.repl || cfg.print_result || cfg.diff;
+        if self.verbosity > Verbosity::Quiet && !in_the_way && supports_ansi(&stderr()) {
+            self.progress = Some(Arc::default());
+        }
+        self
+    }
+
+    /// Print what happened to one file, with `--verbose`.
+    pub fn verbose(&self, message: impl fmt::Display) {
+        if self.verbosity >= Verbosity::Verbose {
+            self.print(message);
+        }
+    }
+
+    /// Print a notice or part of the summary, unless `--quiet`.
+    pub fn status(&self, message: impl fmt::Display) {
+        if self.verbosity >= Verbosity::Normal {
+            self.print(message);
+        }
+    }
+
+    /// Print an error, whatever the verbosity.
+    pub fn error(&self, message: impl fmt::Display) {
+        self.print(message);
+    }
+
+    /// Expect `files` more files to be processed.
+    pub fn start(&self, files: usize) {
+        if let Some(progress) = &self.progress {
+            progress.lock().expect("progress poisoned").total += files;
+        }
+    }
+
+    /// Count the file at `path` as done, and show what happened to it.
+    pub fn advance(&self, path: &str, status: Status) {
+        let Some(progress) = &self.progress else {
+            return;
+        };
+        let mut progress = progress.lock().expect("progress poisoned");
+        progress.done += 1;
+        if status == Status::Failed {
+            progress.failed += 1;
+        }
+        let line = progress.render(path, status, terminal_width());
+        draw(&line);
+        progress.line = Some(line);
+    }
+
+    /// Take the progress bar down, before printing the summary.
+    pub fn finish(&self) {
+        if let Some(progress) = &self.progress {
+            if progress
+                .lock()
+                .expect("progress poisoned")
+                .line
+                .take()
+                .is_some()
+            {
+                clear();
+            }
+        }
+    }
+
+    fn print(&self, message: impl fmt::Display) {
+        let Some(progress) = &self.progress else {
+            eprintln!("{}", message);
+            return;
+        };
+        let progress = progress.lock().expect("progress poisoned");
+        if progress.line.is_some() {
+            clear();
+        }
+        eprintln!("{}", message);
+        if let Some(line) = &progress.line {
+            draw(line);
+        }
+    }
+}
+
+impl Progress {
+    fn render(&self, path: &str, status: Status, width: usize) -> String {
+        let filled = match self.total {
+            0 => BAR_WIDTH,
+            total => (BAR_WIDTH * self.done.min(total)) / total,
+        };
+        let mut line = format!(
+            "[{}{}] {}/{}",
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total
+        );
+        if self.failed > 0 {
+            line.push_str(&format!(" ({} failed)", self.failed));
+        }
+        line.push_str(&format!(" {} ", status));
+        // the end of a long path says more than its start
+        let room = width.saturating_sub(line.chars().count() + 1);
+        let chars = path.chars().count();
+        if chars <= room {
+            line.push_str(path);
+        } else if room > 3 {
+            line.push_str("...");
+            line.extend(path.chars().skip(chars - (room - 3)));
+        }
+        line
+    }
+}
+
+fn draw(line: &str) {
+    let mut stderr = stderr().lock();
+    let _ = write!(stderr, "\r\x1b[K{}", line);
+    let _ = stderr.flush();
+}
+
+fn clear() {
+    let mut stderr = stderr().lock();
+    let _ = write!(stderr, "\r\x1b[K");
+    let _ = stderr.flush();
+}
+
+/// Whether the console shows ANSI escapes, once it's been got ready.
+static ANSI: OnceLock<bool> = OnceLock::new();
+
+/// Get the console ready for what a run prints: on Windows, switch it to
+/// UTF-8, so non-ASCII titles aren't mangled, and turn on ANSI escapes
+/// where it can. Elsewhere there's nothing to do.
+///
+/// Dropping what's returned switches the console back to the code page it
+/// had, if this call is what switched it, so the shell isn't left in UTF-8.
+#[must_use = "the console's code page is put back when this is dropped"]
+pub fn init_console() -> ConsoleGuard {
+    claim(&ANSI, console::init)
+}
+
+/// Puts the console's code page back when dropped; see [`init_console`].
+#[derive(Debug)]
+pub struct ConsoleGuard {
+    code_page: Option<u32>,
+}
+
+impl Drop for ConsoleGuard {
+    fn drop(&mut self) {
+        if let Some(code_page) = self.code_page.take() {
+            console::restore(code_page);
+        }
+    }
+}
+
+/// Get the console ready with `init` unless it already is, returning a
+/// guard that restores the code page `init` replaced, if it was called.
+fn claim(ansi: &OnceLock<bool>, init: impl FnOnce() -> (bool, Option<u32>)) -> ConsoleGuard {
+    let mut code_page = None;
+    ansi.get_or_init(|| {
+        let (ansi, replaced) = init();
+        code_page = replaced;
+        ansi
+    });
+    ConsoleGuard { code_page }
+}
+
+/// Whether `stream` is a terminal that shows ANSI escapes, for colours and
+/// the progress bar, and the user hasn't turned them off with `NO_COLOR`.
+pub fn supports_ansi(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal()
+        && env::var_os("NO_COLOR").is_none()
+        && env::var_os("TERM").is_none_or(|term| term != "dumb")
+        && console_ansi()
+}
+
+/// Whether the console shows ANSI escapes, getting it ready first if
+/// nothing has, in which case its code page stays switched.
+fn console_ansi() -> bool {
+    *ANSI.get_or_init(|| console::init().0)
+}
+
+#[cfg(not(windows))]
+mod console {
+    /// Terminals elsewhere speak UTF-8 and ANSI already.
+    pub fn init() -> (bool, Option<u32>) {
+        (true, None)
+    }
+
+    pub fn restore(_code_page: u32) {}
+}
+
+#[cfg(windows)]
+mod console {
+    use std::ffi::c_void;
+
+    const CP_UTF8: u32 = 65001;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetConsoleOutputCP() -> u32;
+        fn SetConsoleOutputCP(code_page: u32) -> i32;
+        fn GetStdHandle(std_handle: u32) -> *mut c_void;
+        fn GetConsoleMode(handle: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(handle: *mut c_void, mode: u32) -> i32;
+    }
+
+    /// Switch the console to UTF-8 and turn on ANSI escapes for stdout and
+    /// stderr, returning whether both now show them, and the code page
+    /// replaced, if one was.
+    pub fn init() -> (bool, Option<u32>) {
+        // SAFETY: these only query and set the console's state, on handles
+        // Windows gives us
+        unsafe {
+            // 0 if there's no console
+            let original = GetConsoleOutputCP();
+            let replaced =
+                (original != 0 && original != CP_UTF8 && SetConsoleOutputCP(CP_UTF8) != 0)
+                    .then_some(original);
+            let ansi = [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE]
+                .iter()
+                .all(|&std_handle| {
+                    let handle = GetStdHandle(std_handle);
+                    let mut mode = 0;
+                    GetConsoleMode(handle, &mut mode) != 0
+                        && (mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING)
+                                != 0)
+                });
+            (ansi, replaced)
+        }
+    }
+
+    pub fn restore(code_page: u32) {
+        // SAFETY: as for `init`
+        unsafe {
+            SetConsoleOutputCP(code_page);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn code_page() -> u32 {
+        // SAFETY: as for `init`
+        unsafe { GetConsoleOutputCP() }
+    }
+}
+
+/// The terminal's width if the shell says, or a safe guess.
+fn terminal_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_progress() {
+        let progress = Progress {
+            total: 4,
+            done: 2,
+            failed: 1,
+            line: None,
+        };
+        assert_eq!(
+            "[############            ] 2/4 (1 failed) changed posts/a.md",
+            progress.render("posts/a.md", Status::Changed, 80)
+        );
+        assert_eq!(
+            "[############            ] 2/4 (1 failed) failed ...a.md",
+            progress.render("posts/a.md", Status::Failed, 57)
+        );
+    }
+
+    #[test]
+    fn only_the_console_guard_that_switched_restores() {
+        let ansi = OnceLock::new();
+        let mut first = claim(&ansi, || (true, Some(437)));
+        let second = claim(&ansi, || panic!("already got ready"));
+        assert_eq!(Some(437), first.code_page);
+        assert_eq!(None, second.code_page);
+        assert_eq!(Some(&true), ansi.get());
+        // so dropping it doesn't touch this process's console
+        first.code_page = None;
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn restores_console_code_page() {
+        let original = console::code_page();
+        let guard = init_console();
+        if original == 0 || guard.code_page.is_none() {
+            // no console to switch, or already UTF-8
+            return;
+        }
+        assert_eq!(65001, console::code_page());
+        drop(guard);
+        assert_eq!(original, console::code_page());
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use eyre::{eyre, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_yaml as yaml;
+
+use crate::{fixer::restore_key_order, links::normalize};
+
+/// A single RFC 6902 JSON Patch operation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// A change to make to frontmatter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Patch {
+    /// RFC 6902 JSON Patch, a JSON array of operations.
+    Json(Vec<Operation>),
+    /// RFC 7396 JSON Merge Patch, a JSON object.
+    Merge(Value),
+}
+
+impl Patch {
+    fn from_value(value: Value) -> eyre::Result<Self> {
+        match value {
+            Value::Array(_) => Ok(Self::Json(
+                serde_json::from_value(value).context("invalid JSON Patch")?,
+            )),
+            Value::Object(_) => Ok(Self::Merge(value)),
+            other => Err(eyre!(
+                "expected a JSON Patch array or a JSON Merge Patch object, got {}",
+                other
+            )),
+        }
+    }
+
+    /// Apply the patch to frontmatter, where missing frontmatter is `null`,
+    /// keeping the order of keys that were already there.
+    pub fn apply(&self, metadata: Option<&yaml::Value>) -> eyre::Result<Option<yaml::Value>> {
+        let mut document =
+            serde_json::to_value(metadata).context("frontmatter can't be represented as JSON")?;
+        match self {
+            Self::Json(operations) => {
+                for operation in operations {
+                    apply_operation(&mut document, operation)?;
+                }
+            }
+            Self::Merge(patch) => merge(&mut document, patch),
+        }
+        let patched: Option<yaml::Value> = serde_json::from_value(document)?;
+        Ok(match (metadata, patched) {
+            (Some(metadata), Some(patched)) => Some(restore_key_order(metadata, patched)),
+            (_, patched) => patched,
+        })
+    }
+}
+
+/// The patches `--apply-patch` or `--apply-patch-map` asked for.
+#[derive(Debug)]
+pub enum Patches {
+    /// The same patch for every file.
+    All(Patch),
+    /// A patch for each file named, by normalized path.
+    PerFile(BTreeMap<PathBuf, Patch>),
+}
+
+impl Patches {
+    /// Load a single patch, or with `per_file` a JSON object mapping paths
+    /// to patches.
+    pub fn load(path: &Path, per_file: bool) -> eyre::Result<Self> {
+        let json =
+            read_to_string(path).context(format!("couldn't read patch file {}", path.display()))?;
+        let value: Value = serde_json::from_str(&json)
+            .context(format!("couldn't parse patch file {}", path.display()))?;
+        if !per_file {
+            return Ok(Self::All(Patch::from_value(value)?));
+        }
+        let patches = match value {
+            Value::Object(patches) => patches,
+            _ => {
+                return Err(eyre!(
+                    "patch map must be an object mapping paths to patches"
+                ))
+            }
+        };
+        patches
+            .into_iter()
+            .map(|(file, patch)| {
+                let patch =
+                    Patch::from_value(patch).context(format!("invalid patch for {}", file))?;
+                Ok((normalize(Path::new(&file)), patch))
+            })
+            .collect::<eyre::Result<_>>()
+            .map(Self::PerFile)
+    }
+
+    pub fn for_path(&self, path: &str) -> Option<&Patch> {
+        match self {
+            Self::All(patch) => Some(patch),
+            Self::PerFile(patches) => patches.get(&normalize(Path::new(path))),
+        }
+    }
+}
+
+/// The JSON Patch turning frontmatter `before` into `after`, where missing
+/// frontmatter is `null`.
+///
+/// Mappings (in key order) and sequences are diffed element by element, so
+/// the patch only touches what changed; anything else that differs is
+/// replaced whole.
+pub fn diff(
+    before: Option<&yaml::Value>,
+    after: Option<&yaml::Value>,
+) -> eyre::Result<Vec<Operation>> {
+    let before = serde_json::to_value(before)?;
+    let after = serde_json::to_value(after)?;
+    let mut operations = Vec::new();
+    diff_values(&before, &after, "", &mut operations);
+    Ok(operations)
+}
+
+fn diff_values(before: &Value, after: &Value, path: &str, operations: &mut Vec<Operation>) {
+    match (before, after) {
+        _ if before == after => {}
+        (Value::Object(before), Value::Object(after)) => {
+            for (key, value) in before {
+                let path = format!("{}/{}", path, escape(key));
+                match after.get(key) {
+                    Some(new_value) => diff_values(value, new_value, &path, operations),
+                    None => operations.push(Operation::Remove { path }),
+                }
+            }
+            for (key, value) in after.iter().filter(|(key, _)| !before.contains_key(*key)) {
+                operations.push(Operation::Add {
+                    path: format!("{}/{}", path, escape(key)),
+                    value: value.clone(),
+                });
+            }
+        }
+        (Value::Array(before), Value::Array(after)) => {
+            for (i, (value, new_value)) in before.iter().zip(after).enumerate() {
+                diff_values(value, new_value, &format!("{}/{}", path, i), operations);
+            }
+            // remove from the end so earlier indices stay valid
+            for i in (after.len()..before.len()).rev() {
+                operations.push(Operation::Remove {
+                    path: format!("{}/{}", path, i),
+                });
+            }
+            for value in after.iter().skip(before.len()) {
+                operations.push(Operation::Add {
+                    path: format!("{}/-", path),
+                    value: value.clone(),
+                });
+            }
+        }
+        _ => operations.push(Operation::Replace {
+            path: path.to_owned(),
+            value: after.clone(),
+        }),
+    }
+}
+
+fn apply_operation(document: &mut Value, operation: &Operation) -> eyre::Result<()> {
+    match operation {
+        Operation::Add { path, value } => add(document, path, value.clone()),
+        Operation::Remove { path } => remove(document, path).map(|_| ()),
+        Operation::Replace { path, value } => {
+            *pointer_mut(document, path)? = value.clone();
+            Ok(())
+        }
+        Operation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(eyre!("can't move {} into itself", from));
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        Operation::Copy { from, path } => {
+            let value = pointer_mut(document, from)?.clone();
+            add(document, path, value)
+        }
+        Operation::Test { path, value } => {
+            let actual = pointer_mut(document, path)?;
+            if actual != value {
+                return Err(eyre!("test failed: {} is {}, not {}", path, actual, value));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Split a JSON Pointer into its parent and unescaped last token.
+fn split_pointer(path: &str) -> eyre::Result<(&str, String)> {
+    match path.rsplit_once('/') {
+        Some((parent, token)) if path.starts_with('/') => Ok((parent, unescape(token))),
+        _ => Err(eyre!("invalid JSON Pointer {:?}", path)),
+    }
+}
+
+fn pointer_mut<'a>(document: &'a mut Value, path: &str) -> eyre::Result<&'a mut Value> {
+    document
+        .pointer_mut(path)
+        .ok_or_else(|| eyre!("no value at {:?}", path))
+}
+
+fn add(document: &mut Value, path: &str, value: Value) -> eyre::Result<()> {
+    if path.is_empty() {
+        *document = value;
+        return Ok(());
+    }
+    let (parent, token) = split_pointer(path)?;
+    match pointer_mut(document, parent)? {
+        Value::Object(object) => {
+            object.insert(token, value);
+        }
+        Value::Array(array) if token == "-" => array.push(value),
+        Value::Array(array) => match token.parse::<usize>() {
+            Ok(index) if index <= array.len() => array.insert(index, value),
+            _ => return Err(eyre!("can't add at {:?}: no such index", path)),
+        },
+        _ => return Err(eyre!("can't add at {:?}: parent isn't a container", path)),
+    }
+    Ok(())
+}
+
+fn remove(document: &mut Value, path: &str) -> eyre::Result<Value> {
+    let (parent, token) = split_pointer(path)?;
+    let removed = match pointer_mut(document, parent)? {
+        Value::Object(object) => object.remove(&token),
+        Value::Array(array) => match token.parse::<usize>() {
+            Ok(index) if index < array.len() => Some(array.remove(index)),
+            _ => None,
+        },
+        _ => None,
+    };
+    removed.ok_or_else(|| eyre!("no value at {:?} to remove", path))
+}
+
+/// Apply an RFC 7396 JSON Merge Patch.
+fn merge(document: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        _ => {
+            *document = patch.clone();
+            return;
+        }
+    };
+    if !document.is_object() {
+        *document = Value::Object(Default::default());
+    }
+    let object = document.as_object_mut().expect("just made an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            object.remove(key);
+        } else {
+            merge(object.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+fn unescape(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+/// Escape a key for use in a JSON Pointer.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn yaml(s: &str) -> yaml::Value {
+        yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn diffs_nested_frontmatter() -> eyre::Result<()> {
+        let before = yaml("title: Hi\ndraft: true\ntags: [a, b, c]\nsee/also: x\n");
+        let after = yaml("title: Hello\ntags: [a, z]\nsee/also: x\nslug: hi\n");
+        let patch = diff(Some(&before), Some(&after))?;
+        assert_eq!(
+            json!([
+                {"op": "remove", "path": "/draft"},
+                {"op": "replace", "path": "/tags/1", "value": "z"},
+                {"op": "remove", "path": "/tags/2"},
+                {"op": "replace", "path": "/title", "value": "Hello"},
+                {"op": "add", "path": "/slug", "value": "hi"},
+            ]),
+            serde_json::to_value(patch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn applies_json_patches() -> eyre::Result<()> {
+        let patch = Patch::from_value(json!([
+            {"op": "test", "path": "/title", "value": "Hi"},
+            {"op": "replace", "path": "/title", "value": "Hello"},
+            {"op": "add", "path": "/tags/0", "value": "first"},
+            {"op": "add", "path": "/tags/-", "value": "last"},
+            {"op": "move", "from": "/draft", "path": "/a~1b"},
+            {"op": "copy", "from": "/title", "path": "/slug"},
+            {"op": "remove", "path": "/tags/1"},
+        ]))?;
+        let before = yaml("title: Hi\ntags: [x]\ndraft: true\n");
+        assert_eq!(
+            Some(yaml(
+                "title: Hello\ntags: [first, last]\na/b: true\nslug: Hello\n"
+            )),
+            patch.apply(Some(&before))?
+        );
+
+        let failing = Patch::from_value(json!([{"op": "test", "path": "/title", "value": "No"}]))?;
+        assert!(failing.apply(Some(&before)).is_err());
+        let missing = Patch::from_value(json!([{"op": "remove", "path": "/nope"}]))?;
+        assert!(missing.apply(Some(&before)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn applies_merge_patches() -> eyre::Result<()> {
+        let patch = Patch::from_value(json!({"title": "Hello", "draft": null, "meta": {"a": 1}}))?;
+        let before = yaml("title: Hi\ndraft: true\ntags: [x]\n");
+        assert_eq!(
+            Some(yaml("title: Hello\ntags: [x]\nmeta: {a: 1}\n")),
+            patch.apply(Some(&before))?
+        );
+        assert_eq!(
+            Some(yaml("title: Hello\nmeta: {a: 1}\n")),
+            patch.apply(None)?
+        );
+        assert!(Patch::from_value(json!("title")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn diffs_added_and_unchanged_frontmatter() -> eyre::Result<()> {
+        let after = yaml("title: Hi\n");
+        assert_eq!(
+            vec![Operation::Replace {
+                path: "".into(),
+                value: json!({"title": "Hi"})
+            }],
+            diff(None, Some(&after))?
+        );
+        assert!(diff(Some(&after), Some(&after))?.is_empty());
+        assert_eq!("/a~1b~0c", format!("/{}", escape("a/b~c")));
+        Ok(())
+    }
+}
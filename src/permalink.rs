@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_yaml as yaml;
+
+use crate::{
+    date::Date,
+    feed::scalar,
+    frontmatter,
+    helpers::slugify,
+    links,
+    report::{Finding, Findings},
+};
+
+/// The tokens a permalink pattern can use.
+const TOKENS: [&str; 8] = [
+    "year", "month", "day", "slug", "title", "filename", "section", "sections",
+];
+
+/// A site's permalink pattern, as in Hugo's `permalinks` configuration,
+/// e.g. `/:section/:year/:month/:slug/`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern(Vec<Part>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Token(String),
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(colon) = rest.find(':') {
+            let name_len = rest[colon + 1..]
+                .find(|c: char| !c.is_ascii_lowercase())
+                .unwrap_or(rest.len() - colon - 1);
+            let name = &rest[colon + 1..colon + 1 + name_len];
+            if name.is_empty() {
+                // e.g. the colon in https://
+                parts.push(Part::Literal(rest[..colon + 1].to_owned()));
+            } else if TOKENS.contains(&name) {
+                parts.push(Part::Literal(rest[..colon].to_owned()));
+                parts.push(Part::Token(name.to_owned()));
+            } else {
+                return Err(format!(
+                    "unknown permalink token :{}, expected one of :{}",
+                    name,
+                    TOKENS.join(", :")
+                ));
+            }
+            rest = &rest[colon + 1 + name_len..];
+        }
+        parts.push(Part::Literal(rest.to_owned()));
+        parts.retain(|part| *part != Part::Literal(String::new()));
+        Ok(Self(parts))
+    }
+}
+
+/// Computes each file's eventual URL from its path and frontmatter.
+///
+/// In the pattern, `:year`, `:month` and `:day` come from the `date` key;
+/// `:slug` is the `slug` key, or else the slugified `title`, or else the
+/// file name; `:title` is the slugified `title`; `:filename` is the file
+/// name without its extension; `:section` is the first directory under the
+/// content root and `:sections` all of them. A file's `url` key, if it has
+/// one, is its URL whatever the pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Permalink {
+    pattern: Pattern,
+    /// The directory sections are under, if not the one paths are
+    /// relative to.
+    root: Option<PathBuf>,
+}
+
+impl Permalink {
+    pub fn new(pattern: Pattern, root: Option<PathBuf>) -> Self {
+        Self { pattern, root }
+    }
+
+    /// The URL of the file at `path`, or `None` if the pattern uses a token
+    /// the file has no value for, like `:year` without a `date`.
+    pub fn url(&self, path: &Path, metadata: Option<&yaml::Value>) -> Option<String> {
+        let get = |key| metadata?.get(key).and_then(scalar);
+        if let Some(url) = get("url") {
+            return Some(url);
+        }
+        let path = links::normalize(path);
+        let relative = match &self.root {
+            Some(root) => path.strip_prefix(links::normalize(root)).ok()?,
+            None => &path,
+        };
+        let sections: Vec<_> = relative
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .filter_map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect();
+        let filename = relative.file_stem()?.to_str()?;
+        let date = || get("date").as_deref().and_then(Date::parse);
+        let title = || get("title").map(|title| slugify(&title));
+
+        let mut url = String::new();
+        for part in &self.pattern.0 {
+            match part {
+                Part::Literal(literal) => url.push_str(literal),
+                Part::Token(token) => match token.as_str() {
+                    "year" => url.push_str(&date()?.year.to_string()),
+                    "month" => url.push_str(&format!("{:02}", date()?.month)),
+                    "day" => url.push_str(&format!("{:02}", date()?.day)),
+                    "slug" => url.push_str(
+                        &get("slug")
+                            .or_else(title)
+                            .unwrap_or_else(|| slugify(filename)),
+                    ),
+                    "title" => url.push_str(&title()?),
+                    "filename" => url.push_str(filename),
+                    "section" => url.push_str(sections.first().copied().unwrap_or_default()),
+                    "sections" => url.push_str(&sections.join("/")),
+                    _ => unreachable!("pattern has only known tokens"),
+                },
+            }
+        }
+        Some(collapse_slashes(&url))
+    }
+}
+
+/// Every file's URL, to find files that would share one.
+#[derive(Debug, Default)]
+pub struct Index {
+    paths: HashMap<String, Vec<PathBuf>>,
+}
+
+impl Index {
+    /// Compute the URL of every file in `paths`. Files that can't be read or
+    /// parsed are left out; they'll fail when processed.
+    pub fn build(permalink: &Permalink, paths: &[String]) -> Self {
+        let mut index = Self::default();
+        for path in paths {
+            let metadata = read_to_string(path)
+                .ok()
+                .and_then(|content| frontmatter::parse(&content).0?.ok());
+            if let Some(url) = permalink.url(Path::new(path), metadata.as_ref()) {
+                let entry = index.paths.entry(url).or_default();
+                entry.push(links::normalize(Path::new(path)));
+            }
+        }
+        index
+    }
+
+    /// Fail if the file at `path` has the same URL as any other file.
+    pub fn check(
+        &self,
+        permalink: &Permalink,
+        path: &Path,
+        metadata: &yaml::Value,
+    ) -> Result<(), Findings> {
+        let Some(url) = permalink.url(path, Some(metadata)) else {
+            return Ok(());
+        };
+        let path = links::normalize(path);
+        let others: Vec<_> = self
+            .paths
+            .get(&url)
+            .into_iter()
+            .flatten()
+            .filter(|other| **other != path)
+            .map(|other| other.display().to_string())
+            .collect();
+        if others.is_empty() {
+            return Ok(());
+        }
+        Err(Findings(vec![Finding::new(
+            "duplicate-permalink",
+            format!("{} is also the URL of {}", url, others.join(", ")),
+        )]))
+    }
+}
+
+/// Collapse runs of slashes left by empty tokens, except after a scheme's
+/// colon.
+fn collapse_slashes(url: &str) -> String {
+    let mut collapsed = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c == '/' && collapsed.ends_with('/') && !collapsed.ends_with(":/") {
+            continue;
+        }
+        collapsed.push(c);
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn url(pattern: &str, path: &str, yaml: &str) -> Option<String> {
+        let permalink = Permalink::new(pattern.parse().unwrap(), Some("content".into()));
+        let metadata: yaml::Value = yaml::from_str(yaml).unwrap();
+        permalink.url(Path::new(path), Some(&metadata))
+    }
+
+    #[test]
+    fn computes_urls() {
+        let post = "title: Hello, World!\ndate: 2023-04-05\n";
+        let pattern = "/:section/:year/:month/:slug/";
+        assert_eq!(
+            Some("/posts/2023/04/hello-world/".to_owned()),
+            url(pattern, "content/posts/a.md", post)
+        );
+        assert_eq!(
+            Some("/2023/04/a/".to_owned()),
+            url(pattern, "./content/a.md", "date: 2023-04-05\n")
+        );
+        assert_eq!(None, url(pattern, "content/posts/a.md", "title: Undated\n"));
+        assert_eq!(
+            Some("/custom/".to_owned()),
+            url(pattern, "content/posts/a.md", "url: /custom/\n")
+        );
+        assert_eq!(
+            Some("https://example.com/docs/api/a".to_owned()),
+            url(
+                "https://example.com/:sections/:filename",
+                "content/docs/api/a.md",
+                "{}"
+            )
+        );
+        assert!(":author/:slug".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn finds_duplicate_urls() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (a, b) = (dir.path().join("a.md"), dir.path().join("b.md"));
+        std::fs::write(&a, "---\nslug: same\n---\n")?;
+        std::fs::write(&b, "---\nslug: same\n---\n")?;
+        let permalink = Permalink::new("/:slug/".parse().unwrap(), Some(dir.path().into()));
+        let paths = [a.display().to_string(), b.display().to_string()];
+        let index = Index::build(&permalink, &paths);
+
+        let findings = index
+            .check(&permalink, &a, &yaml::from_str("slug: same")?)
+            .expect_err("b has the same URL");
+        assert_eq!("duplicate-permalink", findings.0[0].rule);
+        index
+            .check(&permalink, &a, &yaml::from_str("slug: different")?)
+            .expect("URL is unique");
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::PathBuf,
+};
+
+use eyre::eyre;
+
+/// Which source file is going to produce each output path, so that two
+/// sources can't silently clobber the same output.
+#[derive(Debug, Default)]
+pub struct OutputClaims {
+    claims: HashMap<PathBuf, String>,
+}
+
+impl OutputClaims {
+    /// Claim `output` on behalf of `source`, failing if a different source
+    /// already claimed it.
+    pub fn claim(&mut self, output: PathBuf, source: &str) -> eyre::Result<()> {
+        match self.claims.entry(output) {
+            Entry::Occupied(claimed) if claimed.get() != source => Err(eyre!(
+                "{} and {} would both write {}",
+                claimed.get(),
+                source,
+                claimed.key().display()
+            )),
+            Entry::Occupied(_) => Ok(()),
+            Entry::Vacant(unclaimed) => {
+                unclaimed.insert(source.to_owned());
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn allows_distinct_outputs() -> eyre::Result<()> {
+        let mut claims = OutputClaims::default();
+        claims.claim("out/a.md".into(), "a.md")?;
+        claims.claim("out/b.md".into(), "b.md")?;
+        claims.claim("out/a.md".into(), "a.md")?;
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_two_sources_for_one_output() {
+        let mut claims = OutputClaims::default();
+        claims.claim("out/a.md".into(), "a.md").unwrap();
+        let err = claims
+            .claim("out/a.md".into(), "/a.md")
+            .expect_err("should detect clobbering");
+        assert_eq!("a.md and /a.md would both write out/a.md", err.to_string());
+    }
+}
@@ -0,0 +1,213 @@
+use serde_yaml as yaml;
+
+/// A top-level key's lines in YAML frontmatter.
+#[derive(Debug, PartialEq, Eq)]
+struct Block<'a> {
+    /// `None` for comments and blank lines after the last key.
+    key: Option<String>,
+    /// The comments and blank lines before the key.
+    leading: &'a str,
+    /// The key's own lines, with any comments among or after them that are
+    /// indented.
+    body: &'a str,
+}
+
+/// Render `frontmatter` as YAML by editing `original`, the YAML it was read
+/// from: the lines of keys whose values are unchanged are kept as they
+/// were, with their comments, anchors and quoting, changed and added keys
+/// are written afresh after any comments before them, and removed keys'
+/// lines are dropped.
+///
+/// Returns `None` if that can't be done faithfully, such as for keys that
+/// aren't plain strings or an alias to an anchor that's been removed, when
+/// it's best to write the whole frontmatter afresh.
+pub fn edit(original: &str, frontmatter: &yaml::Value) -> Option<String> {
+    let before: yaml::Value = yaml::from_str(original).ok()?;
+    let (before, after) = (before.as_mapping()?, frontmatter.as_mapping()?);
+    let blocks = blocks(original)?;
+    let found = |key: &str| {
+        blocks
+            .iter()
+            .filter(|b| b.key.as_deref() == Some(key))
+            .count()
+    };
+    if before
+        .keys()
+        .any(|key| key.as_str().is_none_or(|key| found(key) != 1))
+    {
+        return None;
+    }
+
+    let mut edited = String::new();
+    for (key, value) in after {
+        let block = key.as_str().and_then(|key| {
+            blocks
+                .iter()
+                .find(|block| block.key.as_deref() == Some(key))
+        });
+        match block {
+            Some(block) if before.get(key) == Some(value) => {
+                edited.push_str(block.leading);
+                edited.push_str(block.body);
+            }
+            _ => {
+                if let Some(block) = block {
+                    edited.push_str(block.leading);
+                }
+                let mut mapping = yaml::Mapping::new();
+                mapping.insert(key.clone(), value.clone());
+                edited.push_str(&yaml::to_string(&mapping).ok()?);
+            }
+        }
+        if !edited.ends_with('\n') {
+            edited.push('\n');
+        }
+    }
+    if let Some(trailing) = blocks.iter().find(|block| block.key.is_none()) {
+        edited.push_str(trailing.leading);
+    }
+
+    // e.g. an alias whose anchor went with a changed value
+    let reread: yaml::Value = yaml::from_str(&edited).ok()?;
+    (reread.as_mapping() == Some(after)).then_some(edited)
+}
+
+/// Split YAML into its top-level keys' lines, or `None` if it isn't a
+/// simple enough mapping to tell where each key starts.
+fn blocks(raw: &str) -> Option<Vec<Block<'_>>> {
+    let mut blocks = Vec::new();
+    // the current key, where the comments before it start, and where its
+    // own lines start
+    let mut current: Option<(String, usize, usize)> = None;
+    // where the unindented comments and blank lines since the current key's
+    // last line start, which go with the next key
+    let mut gap_start: Option<usize> = None;
+    let mut offset = 0;
+    for line in raw.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            gap_start.get_or_insert(start);
+            continue;
+        }
+        if line.starts_with([' ', '\t', '-']) && !trimmed.starts_with("---") {
+            // more of the current key, so the gap was too
+            current.as_ref()?;
+            gap_start = None;
+            continue;
+        }
+        let key = top_level_key(trimmed)?;
+        let gap = gap_start.take().unwrap_or(start);
+        if let Some((key, leading, body)) = current.take() {
+            blocks.push(Block {
+                key: Some(key),
+                leading: &raw[leading..body],
+                body: &raw[body..gap],
+            });
+        }
+        current = Some((key, gap, start));
+    }
+    let gap = gap_start.unwrap_or(raw.len());
+    if let Some((key, leading, body)) = current {
+        blocks.push(Block {
+            key: Some(key),
+            leading: &raw[leading..body],
+            body: &raw[body..gap],
+        });
+    }
+    blocks.push(Block {
+        key: None,
+        leading: &raw[gap..],
+        body: "",
+    });
+    Some(blocks)
+}
+
+/// The key a line starting a top-level mapping entry sets, if that's what
+/// it does.
+fn top_level_key(line: &str) -> Option<String> {
+    let (key, rest) = match line.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = line[1..].find(quote)? + 2;
+            let key: String = yaml::from_str(&line[..end]).ok()?;
+            (key, &line[end..])
+        }
+        '?' | '[' | '{' | '&' | '*' | '!' | '|' | '>' | '%' | '@' | '`' => return None,
+        _ => {
+            let colon = line
+                .match_indices(':')
+                .map(|(i, _)| i)
+                .find(|&i| matches!(line[i + 1..].chars().next(), None | Some(' ' | '\t')))?;
+            (line[..colon].trim_end().to_owned(), &line[colon..])
+        }
+    };
+    rest.trim_start().starts_with(':').then_some(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ORIGINAL: &str = "\
+# written by hand
+title: 'Hello'  # keep quoted
+defaults: &defaults
+  layout: post
+
+# publishing
+date: 2023-01-01
+tags:
+- a
+- b
+extra: *defaults
+# the end
+";
+
+    fn edit_with(change: impl Fn(&mut yaml::Mapping)) -> Option<String> {
+        let mut frontmatter: yaml::Value = yaml::from_str(ORIGINAL).unwrap();
+        change(frontmatter.as_mapping_mut().unwrap());
+        edit(ORIGINAL, &frontmatter)
+    }
+
+    #[test]
+    fn keeps_unchanged_lines() {
+        assert_eq!(Some(ORIGINAL.to_owned()), edit_with(|_| {}));
+        assert_eq!(
+            Some(
+                ORIGINAL
+                    .replace("title: 'Hello'  # keep quoted", "title: Goodbye")
+                    .replace("# the end", "draft: true\n# the end")
+            ),
+            edit_with(|m| {
+                m.insert("title".into(), "Goodbye".into());
+                m.insert("draft".into(), true.into());
+            })
+        );
+        assert_eq!(
+            Some(ORIGINAL.replace("tags:\n- a\n- b\n", "")),
+            edit_with(|m| {
+                m.remove("tags");
+            })
+        );
+    }
+
+    #[test]
+    fn falls_back_when_anchor_changes() {
+        let edited = edit_with(|m| {
+            m.insert("defaults".into(), "none".into());
+        });
+        assert_eq!(None, edited);
+        assert_eq!(None, edit("[a, b]\n", &yaml::from_str("[a]").unwrap()));
+    }
+
+    #[test]
+    fn finds_top_level_keys() {
+        assert_eq!(Some("a".to_owned()), top_level_key("a: 1"));
+        assert_eq!(Some("a:b".to_owned()), top_level_key("a:b: 1"));
+        assert_eq!(Some("odd key".to_owned()), top_level_key("\"odd key\" : x"));
+        assert_eq!(Some("url".to_owned()), top_level_key("url: http://x"));
+        assert_eq!(None, top_level_key("? complex"));
+        assert_eq!(None, top_level_key("just text"));
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+use eyre::{eyre, Context};
+
+use crate::config;
+
+/// The file in a project's root directory holding its default options and
+/// named tasks.
+pub const CONFIG_FILE: &str = "frontmatter-fixer.toml";
+
+/// The key in a task for the files it fixes, rather than an option.
+const FILES_KEY: &str = "files";
+
+/// The option for the scripts to run.
+const SCRIPT_KEY: &str = "script";
+
+/// Options read from a `frontmatter-fixer.toml`, as command line arguments.
+///
+/// Each key is an option's long name, e.g. `script = "fix.lua"` for
+/// `--script fix.lua`: true turns a flag on, and an array repeats the
+/// option for each of its values. Each table under `tasks` is a named set
+/// of options added to the defaults by `frontmatter-fixer run NAME`, which
+/// can also list `files` to fix:
+///
+/// ```toml
+/// script = "fix.lua"
+/// recursive = true
+/// include = ["**/*.md"]
+///
+/// [tasks.normalize-dates]
+/// eval = "meta.date = meta.date and tostring(meta.date)"
+/// files = ["content"]
+///
+/// [tasks.authors-to-list]
+/// migrate = ["rename author authors", "split authors ,"]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProjectConfig {
+    pub path: PathBuf,
+    defaults: Vec<String>,
+    tasks: BTreeMap<String, Task>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Task {
+    options: Vec<String>,
+    files: Vec<String>,
+}
+
+impl ProjectConfig {
+    /// Find the config file in `dir` or its nearest ancestor that has one.
+    pub fn find(dir: &Path) -> eyre::Result<Option<Self>> {
+        for dir in dir.ancestors() {
+            let path = dir.join(CONFIG_FILE);
+            if path.is_file() {
+                let text =
+                    read_to_string(&path).context(format!("couldn't read {}", path.display()))?;
+                return Self::parse(&path, &text)
+                    .context(format!("invalid config file {}", path.display()))
+                    .map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn parse(path: &Path, text: &str) -> eyre::Result<Self> {
+        let mut table: toml::Table = text.parse()?;
+        let tasks = match table.remove("tasks") {
+            Some(toml::Value::Table(tasks)) => tasks
+                .into_iter()
+                .map(|(name, options)| match options {
+                    toml::Value::Table(mut options) => {
+                        let files = match options.remove(FILES_KEY) {
+                            Some(toml::Value::Array(files)) => {
+                                files.into_iter().map(scalar).collect::<eyre::Result<_>>()
+                            }
+                            Some(file) => scalar(file).map(|file| vec![file]),
+                            None => Ok(Vec::new()),
+                        }
+                        .context(format!("in task {} files", name))?;
+                        let options = to_args(options).context(format!("in task {}", name))?;
+                        config::reject_command_line_only(&options, path)?;
+                        Ok((name, Task { options, files }))
+                    }
+                    _ => Err(eyre!("task {} must be a table of options", name)),
+                })
+                .collect::<eyre::Result<_>>()?,
+            Some(_) => return Err(eyre!("tasks must be a table of named tasks")),
+            None => BTreeMap::new(),
+        };
+        if table.contains_key(FILES_KEY) {
+            return Err(eyre!("only tasks can list {}", FILES_KEY));
+        }
+        let defaults = to_args(table)?;
+        config::reject_command_line_only(&defaults, path)?;
+        Ok(Self {
+            path: path.to_owned(),
+            defaults,
+            tasks,
+        })
+    }
+
+    /// The arguments to parse: the defaults, then `task`'s options if
+    /// given, then those from the command line (without the program name),
+    /// so the command line has the last word, then the task's files.
+    pub fn args(&self, task: Option<&str>, cli: &[OsString]) -> eyre::Result<Vec<OsString>> {
+        let empty = Task::default();
+        let task = match task {
+            Some(task) => self.tasks.get(task).ok_or_else(|| {
+                let names: Vec<&str> = self.tasks.keys().map(String::as_str).collect();
+                eyre!(
+                    "no task {} in {} (tasks: {})",
+                    task,
+                    self.path.display(),
+                    names.join(", ")
+                )
+            })?,
+            None => &empty,
+        };
+        Ok(self
+            .defaults
+            .iter()
+            .chain(&task.options)
+            .map(OsString::from)
+            .chain(cli.iter().cloned())
+            .chain(task.files.iter().map(OsString::from))
+            .collect())
+    }
+
+    /// Add the script at `path` to those every run of the project runs,
+    /// unless it's there already, keeping the rest of the file as it was.
+    /// The path is written relative to the config file's directory if it's
+    /// inside it. Returns whether it was added.
+    pub fn add_script(&self, path: &Path) -> eyre::Result<bool> {
+        let absolute = env::current_dir()?.join(path);
+        let script = match self.path.parent().map(|root| absolute.strip_prefix(root)) {
+            Some(Ok(relative)) => relative,
+            _ => path,
+        };
+        let script = script.display().to_string();
+
+        let text =
+            read_to_string(&self.path).context(format!("couldn't read {}", self.path.display()))?;
+        let mut document: toml_edit::DocumentMut = text
+            .parse()
+            .context(format!("couldn't parse {}", self.path.display()))?;
+        let scripts = &mut document[SCRIPT_KEY];
+        match scripts {
+            toml_edit::Item::None => *scripts = toml_edit::value(script),
+            toml_edit::Item::Value(toml_edit::Value::String(existing)) => {
+                if *existing.value() == script {
+                    return Ok(false);
+                }
+                let list = toml_edit::Array::from_iter([existing.value().as_str(), &script]);
+                *scripts = toml_edit::value(list);
+            }
+            toml_edit::Item::Value(toml_edit::Value::Array(list)) => {
+                if list
+                    .iter()
+                    .any(|existing| existing.as_str() == Some(&script))
+                {
+                    return Ok(false);
+                }
+                list.push(script);
+            }
+            _ => {
+                return Err(eyre!(
+                    "{} in {} should be a script or a list of them",
+                    SCRIPT_KEY,
+                    self.path.display()
+                ))
+            }
+        }
+        write(&self.path, document.to_string())
+            .context(format!("couldn't write {}", self.path.display()))?;
+        Ok(true)
+    }
+}
+
+/// Turn a table of options into command line arguments.
+fn to_args(table: toml::Table) -> eyre::Result<Vec<String>> {
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let option = format!("--{}", key);
+        match value {
+            toml::Value::Boolean(true) => args.push(option),
+            toml::Value::Boolean(false) => {}
+            toml::Value::Array(values) => {
+                for value in values {
+                    let value = scalar(value).context(format!("in {}", key))?;
+                    args.push(format!("{}={}", option, value));
+                }
+            }
+            value => {
+                let value = scalar(value).context(format!("in {}", key))?;
+                args.push(format!("{}={}", option, value));
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn scalar(value: toml::Value) -> eyre::Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Datetime(datetime) => Ok(datetime.to_string()),
+        other => Err(eyre!("expected a string or number, got {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+script = "fix.lua"
+recursive = true
+dry-run = false
+include = ["**/*.md", "**/*.markdown"]
+jobs = 4
+
+[tasks.normalize-dates]
+eval = "meta.date = tostring(meta.date)"
+files = ["content"]
+"#;
+
+    fn strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn turns_options_into_args() -> eyre::Result<()> {
+        let config = ProjectConfig::parse(Path::new(CONFIG_FILE), CONFIG)?;
+        let defaults = [
+            "--script=fix.lua",
+            "--recursive",
+            "--include=**/*.md",
+            "--include=**/*.markdown",
+            "--jobs=4",
+        ];
+        assert_eq!(
+            defaults.to_vec(),
+            strings(config.args(None, &["-n".into()])?)[..5]
+        );
+
+        let task = strings(config.args(Some("normalize-dates"), &["-n".into()])?);
+        assert_eq!(
+            ["--eval=meta.date = tostring(meta.date)", "-n", "content"].to_vec(),
+            task[5..]
+        );
+        assert!(config.args(Some("nope"), &[]).is_err());
+        let invalid = |text: &str| ProjectConfig::parse(Path::new(CONFIG_FILE), text).is_err();
+        assert!(invalid("[other]\na = 1\n"));
+        assert!(invalid("files = [\"content\"]\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn only_the_command_line_can_loosen_the_sandbox() {
+        let err = |text: &str| {
+            let err = ProjectConfig::parse(Path::new(CONFIG_FILE), text).unwrap_err();
+            format!("{:#}", err)
+        };
+        assert!(err("no-sandbox = true\n").contains("--no-sandbox can only be given"));
+        assert!(err("lua-path = [\"lib\"]\n").contains("--lua-path"));
+        assert!(err("[tasks.t]\nscript-timeout = 60\n").contains("--script-timeout"));
+    }
+
+    #[test]
+    fn adds_scripts_keeping_the_rest() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(CONFIG_FILE);
+        write(
+            &path,
+            "# fix everything\nrecursive = true\n\n[tasks.t]\neval = \"\"\n",
+        )?;
+        let config = ProjectConfig::find(dir.path())?.unwrap();
+        let added = |script: &str| config.add_script(&dir.path().join(script));
+        assert!(added("scripts/a.lua")?);
+        assert!(added("scripts/b.lua")?);
+        assert!(!added("scripts/a.lua")?);
+        assert_eq!(
+            "# fix everything\nrecursive = true\nscript = [\"scripts/a.lua\", \"scripts/b.lua\"]\n\n\
+             [tasks.t]\neval = \"\"\n",
+            read_to_string(&path)?
+        );
+        Ok(())
+    }
+}
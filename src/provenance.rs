@@ -0,0 +1,247 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+
+use serde_yaml as yaml;
+
+use crate::{
+    date::Date,
+    frontmatter::{self, Format},
+};
+
+/// The transforms that can mark the keys they touch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Transform {
+    Script,
+    Inherit,
+    Wasm,
+    Patch,
+    Set,
+    Migrate,
+    KeyTypos,
+    Coerce,
+    Publish,
+    Archive,
+    ContentHash,
+}
+
+impl Transform {
+    /// Every transform, in the order they run.
+    pub const ALL: [Self; 11] = [
+        Self::Script,
+        Self::Inherit,
+        Self::Wasm,
+        Self::Patch,
+        Self::Set,
+        Self::Migrate,
+        Self::KeyTypos,
+        Self::Coerce,
+        Self::Publish,
+        Self::Archive,
+        Self::ContentHash,
+    ];
+}
+
+impl FromStr for Transform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "script" => Ok(Self::Script),
+            "inherit" => Ok(Self::Inherit),
+            "wasm" => Ok(Self::Wasm),
+            "patch" => Ok(Self::Patch),
+            "set" => Ok(Self::Set),
+            "migrate" => Ok(Self::Migrate),
+            "key-typos" => Ok(Self::KeyTypos),
+            "coerce" => Ok(Self::Coerce),
+            "publish" => Ok(Self::Publish),
+            "archive" => Ok(Self::Archive),
+            "content-hash" => Ok(Self::ContentHash),
+            _ => Err(format!(
+                "unknown transform {:?}, \
+                 expected one of script, inherit, wasm, patch, set, migrate, key-typos, coerce, \
+                 publish, archive, content-hash",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Script => "script",
+            Self::Inherit => "inherit",
+            Self::Wasm => "wasm",
+            Self::Patch => "patch",
+            Self::Set => "set",
+            Self::Migrate => "migrate",
+            Self::KeyTypos => "key-typos",
+            Self::Coerce => "coerce",
+            Self::Publish => "publish",
+            Self::Archive => "archive",
+            Self::ContentHash => "content-hash",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Tracks which top-level keys each transform added or modified in a file,
+/// so they can be marked with trailing comments.
+///
+/// Like any other comment, the marks don't survive the file being fixed
+/// again.
+#[derive(Debug)]
+pub struct Provenance<'a> {
+    transforms: &'a [Transform],
+    snapshot: Option<yaml::Value>,
+    /// Whether each touched key was added or modified, by its first touch.
+    touched: BTreeMap<String, &'static str>,
+    /// The transforms that touched any keys.
+    touched_by: BTreeSet<Transform>,
+}
+
+impl<'a> Provenance<'a> {
+    /// Start tracking from a file's frontmatter as it was read.
+    pub fn new(transforms: &'a [Transform], original: Option<&yaml::Value>) -> Self {
+        Self {
+            transforms,
+            snapshot: (!transforms.is_empty())
+                .then(|| original.cloned())
+                .flatten(),
+            touched: BTreeMap::new(),
+            touched_by: BTreeSet::new(),
+        }
+    }
+
+    /// Note the keys `transform` touched since the last checkpoint.
+    pub fn checkpoint(&mut self, transform: Transform, current: Option<&yaml::Value>) {
+        if self.transforms.is_empty() {
+            return;
+        }
+        if self.transforms.contains(&transform) {
+            if let Some(mapping) = current.and_then(yaml::Value::as_mapping) {
+                for (key, value) in mapping {
+                    let key = match key.as_str() {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    let verb = match self.snapshot.as_ref().and_then(|before| before.get(key)) {
+                        None => "added",
+                        Some(before) if before != value => "modified",
+                        Some(_) => continue,
+                    };
+                    self.touched.entry(key.to_owned()).or_insert(verb);
+                    self.touched_by.insert(transform);
+                }
+            }
+        }
+        self.snapshot = current.cloned();
+    }
+
+    /// The transforms that added or modified keys, in the order they ran.
+    pub fn touched_by(&self) -> Vec<Transform> {
+        self.touched_by.iter().copied().collect()
+    }
+
+    /// Add a trailing comment to the line of each touched key in rendered
+    /// output.
+    pub fn annotate(&self, output: Vec<u8>) -> Vec<u8> {
+        if self.touched.is_empty() {
+            return output;
+        }
+        let output = match String::from_utf8(output) {
+            Ok(output) => output,
+            Err(e) => return e.into_bytes(),
+        };
+        let (format, raw_frontmatter, content) = match frontmatter::detect(&output) {
+            Some(format) => match frontmatter::parse_raw(&output) {
+                (Some(raw_frontmatter), content) => (format, raw_frontmatter, content),
+                _ => return output.into_bytes(),
+            },
+            None => return output.into_bytes(),
+        };
+
+        let today = Date::today();
+        let mut annotated = String::from(format.rule());
+        for line in raw_frontmatter.lines() {
+            annotated.push_str(line);
+            if let Some(verb) = self.touched_on(format, line) {
+                annotated.push_str(&format!("  # {} by frontmatter-fixer {}", verb, today));
+            }
+            annotated.push('\n');
+        }
+        annotated.push_str(format.rule());
+        annotated.push_str(content);
+        annotated.into_bytes()
+    }
+
+    /// Whether `line` starts a touched top-level key.
+    fn touched_on(&self, format: Format, line: &str) -> Option<&'static str> {
+        let separator = match format {
+            Format::Yaml => ':',
+            Format::Toml => '=',
+        };
+        let (key, _) = line.split_once(separator)?;
+        if key.starts_with(char::is_whitespace) {
+            return None;
+        }
+        self.touched.get(key.trim_end()).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontmatter::BlankLine;
+
+    #[test]
+    fn marks_keys_touched_by_enabled_transforms() -> eyre::Result<()> {
+        let original: yaml::Value = yaml::from_str("title: Hello\ntags: a\n")?;
+        let transforms = [Transform::Script, Transform::Coerce];
+        let mut provenance = Provenance::new(&transforms, Some(&original));
+
+        let scripted: yaml::Value = yaml::from_str("title: Hello\ntags: a\nauthor: Ann\n")?;
+        provenance.checkpoint(Transform::Script, Some(&scripted));
+        let typo_fixed: yaml::Value =
+            yaml::from_str("title: Hello\ntags: a\nauthor: Ann\ndraft: true\n")?;
+        provenance.checkpoint(Transform::KeyTypos, Some(&typo_fixed));
+        let coerced: yaml::Value =
+            yaml::from_str("title: Hello\ntags: [a]\nauthor: Ann\ndraft: true\n")?;
+        provenance.checkpoint(Transform::Coerce, Some(&coerced));
+
+        let output = frontmatter::to_bytes(
+            Format::Yaml,
+            BlankLine::Preserve,
+            Some(&coerced),
+            "# Hello\n",
+        )?;
+        let annotated = String::from_utf8(provenance.annotate(output))?;
+        let today = Date::today();
+        let expected = [
+            "---".to_owned(),
+            "title: Hello".to_owned(),
+            format!("tags:  # modified by frontmatter-fixer {}", today),
+            "- a".to_owned(),
+            format!("author: Ann  # added by frontmatter-fixer {}", today),
+            "draft: true".to_owned(),
+            "---".to_owned(),
+            "# Hello\n".to_owned(),
+        ];
+        assert_eq!(expected.join("\n"), annotated);
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_output_alone_when_disabled() -> eyre::Result<()> {
+        let mut provenance = Provenance::new(&[], None);
+        let meta: yaml::Value = yaml::from_str("title: Hello\n")?;
+        provenance.checkpoint(Transform::Script, Some(&meta));
+        let output = frontmatter::to_bytes(Format::Yaml, BlankLine::Preserve, Some(&meta), "")?;
+        assert_eq!(output.clone(), provenance.annotate(output));
+        Ok(())
+    }
+}
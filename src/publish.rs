@@ -0,0 +1,192 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_yaml as yaml;
+
+use crate::{date, feed::scalar};
+
+/// Keys holding when a draft is due to be published, in order of
+/// precedence.
+const PUBLISH_KEYS: [&str; 2] = ["publish_at", "date"];
+
+/// Keys holding when content expires, in order of precedence.
+const EXPIRY_KEYS: [&str; 2] = ["expires", "sunset"];
+
+/// The directory, alongside each file, that expired files are moved into.
+pub const ARCHIVE_DIR: &str = "archive";
+
+/// The key `--archive-expired banner=TEXT` sets.
+const BANNER_KEY: &str = "banner";
+
+/// A scheduled time that has come.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Due {
+    /// The key the time was found under.
+    pub key: &'static str,
+    /// The time, as written.
+    pub value: String,
+}
+
+/// What to do with content that has expired.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Archive {
+    /// Set `archived: true`.
+    Mark,
+    /// Move the file into an `archive` directory alongside it.
+    Move,
+    /// Set the `banner` key to this text, for templates to show.
+    Banner(String),
+}
+
+impl FromStr for Archive {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "mark" => Ok(Self::Mark),
+            None if s == "move" => Ok(Self::Move),
+            Some(("banner", text)) if !text.is_empty() => Ok(Self::Banner(text.to_owned())),
+            _ => Err(format!("expected mark, move or banner=TEXT, got {:?}", s)),
+        }
+    }
+}
+
+/// Publish `metadata` if it's a draft (`draft: true`) scheduled for no
+/// later than `now`, in seconds since the Unix epoch, by setting `draft`
+/// to false.
+///
+/// The time is `publish_at` if it's set, otherwise `date`; see
+/// [`date::parse_timestamp`] for the formats understood. Drafts without a
+/// time that parses are left alone.
+pub fn promote(metadata: &mut yaml::Value, now: i64) -> Option<Due> {
+    if metadata.get("draft") != Some(&yaml::Value::Bool(true)) {
+        return None;
+    }
+    let due = due(metadata, &PUBLISH_KEYS, now)?;
+    *metadata.get_mut("draft")? = yaml::Value::Bool(false);
+    Some(due)
+}
+
+/// Archive the file at `path` as `archive` says if its `expires` (or
+/// failing that `sunset`) time is no later than `now`.
+///
+/// Returns when it expired and, for [`Archive::Move`], where to move the
+/// file. Files already archived are left alone.
+pub fn expire(
+    metadata: &mut yaml::Value,
+    path: &Path,
+    archive: &Archive,
+    now: i64,
+) -> Option<(Due, Option<PathBuf>)> {
+    let due = due(metadata, &EXPIRY_KEYS, now)?;
+    let mapping = metadata.as_mapping_mut()?;
+    match archive {
+        Archive::Mark => {
+            let archived = yaml::Value::Bool(true);
+            if mapping.get("archived") == Some(&archived) {
+                return None;
+            }
+            mapping.insert("archived".into(), archived);
+            Some((due, None))
+        }
+        Archive::Move => {
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            if dir.file_name().is_some_and(|name| name == ARCHIVE_DIR) {
+                return None;
+            }
+            Some((due, Some(dir.join(ARCHIVE_DIR).join(path.file_name()?))))
+        }
+        Archive::Banner(text) => {
+            let banner = yaml::Value::String(text.clone());
+            if mapping.get(BANNER_KEY) == Some(&banner) {
+                return None;
+            }
+            mapping.insert(BANNER_KEY.into(), banner);
+            Some((due, None))
+        }
+    }
+}
+
+/// The first of `keys` set in `metadata`, if its time parses and is no
+/// later than `now`.
+fn due(metadata: &yaml::Value, keys: &[&'static str], now: i64) -> Option<Due> {
+    let (key, value) = keys
+        .iter()
+        .find_map(|&key| Some((key, metadata.get(key).and_then(scalar)?)))?;
+    (date::parse_timestamp(&value)? <= now).then_some(Due { key, value })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn promote_at(yaml: &str, now: &str) -> (Option<Due>, yaml::Value) {
+        let mut metadata: yaml::Value = yaml::from_str(yaml).unwrap();
+        let promotion = promote(&mut metadata, date::parse_timestamp(now).unwrap());
+        (promotion, metadata)
+    }
+
+    #[test]
+    fn promotes_past_drafts() {
+        let (promotion, metadata) = promote_at(
+            "draft: true\ndate: 2023-01-01\npublish_at: 2023-01-31T09:00:00+01:00\n",
+            "2023-01-31T08:00:00Z",
+        );
+        assert_eq!(Some("publish_at"), promotion.map(|p| p.key));
+        assert_eq!(Some(&yaml::Value::Bool(false)), metadata.get("draft"));
+
+        let (promotion, _) = promote_at("draft: true\ndate: 2023-01-31\n", "2023-01-31");
+        assert_eq!(Some("2023-01-31".to_owned()), promotion.map(|p| p.value));
+    }
+
+    #[test]
+    fn leaves_future_and_unscheduled_drafts() {
+        let now = "2023-01-31T07:59:59Z";
+        let scheduled = "draft: true\npublish_at: 2023-01-31T09:00:00+01:00\n";
+        assert_eq!(None, promote_at(scheduled, now).0);
+        assert_eq!(None, promote_at("draft: true\ndate: someday\n", now).0);
+        assert_eq!(
+            None,
+            promote_at("draft: \"true\"\ndate: 2023-01-01\n", now).0
+        );
+        assert_eq!(None, promote_at("date: 2023-01-01\n", now).0);
+    }
+
+    #[test]
+    fn archives_expired_content() {
+        let now = date::parse_timestamp("2023-06-01").unwrap();
+        let expire_at = |yaml: &str, path: &str, archive: &Archive| {
+            let mut metadata: yaml::Value = yaml::from_str(yaml).unwrap();
+            let expired = expire(&mut metadata, Path::new(path), archive, now);
+            (expired, metadata)
+        };
+
+        let (expired, metadata) = expire_at("sunset: 2023-05-31\n", "old.md", &Archive::Mark);
+        assert_eq!(Some("sunset"), expired.map(|(due, _)| due.key));
+        assert_eq!(Some(&yaml::Value::Bool(true)), metadata.get("archived"));
+        let archived = "sunset: 2023-05-31\narchived: true\n";
+        assert_eq!(None, expire_at(archived, "old.md", &Archive::Mark).0);
+        assert_eq!(
+            None,
+            expire_at("expires: 2023-06-02\n", "old.md", &Archive::Mark).0
+        );
+
+        let expires = "expires: 2023-06-01\n";
+        let (expired, _) = expire_at(expires, "docs/old.md", &Archive::Move);
+        let moved_to = expired.and_then(|(_, moved_to)| moved_to);
+        assert_eq!(Some(PathBuf::from("docs/archive/old.md")), moved_to);
+        assert_eq!(
+            None,
+            expire_at(expires, "docs/archive/old.md", &Archive::Move).0
+        );
+
+        let banner = "banner=This page is out of date".parse().unwrap();
+        let (_, metadata) = expire_at(expires, "old.md", &banner);
+        assert_eq!(
+            Some("This page is out of date"),
+            metadata["banner"].as_str()
+        );
+    }
+}
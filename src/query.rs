@@ -0,0 +1,517 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::read_to_string,
+    io::Write,
+    str::FromStr,
+};
+
+use eyre::Context;
+use serde_yaml as yaml;
+
+use crate::{
+    config::Config, corpus, date::Date, envelope, feed::scalar, frontmatter, keypath,
+    schema::Schema,
+};
+
+const OPERATOR_CHARS: [char; 4] = ['=', '<', '>', '!'];
+
+/// What `--group-by` counts files without the key under.
+const NO_VALUE: &str = "(none)";
+
+/// How a `--where` condition compares a key's value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A function `--agg` computes over the values of a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Function {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// An `--agg` aggregation: `sum(KEY)` or `avg(KEY)` over numbers, or
+/// `min(KEY)` or `max(KEY)` over any values, compared as `--where` does so
+/// dates work, where KEY is a dotted path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Aggregation {
+    function: Function,
+    key: String,
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected sum, avg, min or max(KEY), got {:?}", s);
+        let (name, key) = s
+            .trim()
+            .strip_suffix(')')
+            .and_then(|call| call.split_once('('))
+            .ok_or_else(invalid)?;
+        let function = match name.trim() {
+            "sum" => Function::Sum,
+            "avg" => Function::Avg,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            _ => return Err(invalid()),
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            function,
+            key: key.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.function {
+            Function::Sum => "sum",
+            Function::Avg => "avg",
+            Function::Min => "min",
+            Function::Max => "max",
+        };
+        write!(f, "{}({})", name, self.key)
+    }
+}
+
+impl Aggregation {
+    /// The aggregate of the key over the documents, or `None` if none has a
+    /// value to aggregate. Every element of a list counts, and sum and avg
+    /// ignore values that aren't numbers.
+    fn compute(&self, documents: &[Option<&yaml::Value>]) -> Option<String> {
+        let values = documents
+            .iter()
+            .filter_map(|&metadata| metadata.and_then(|metadata| keypath::get(metadata, &self.key)))
+            .flat_map(|value| match value {
+                yaml::Value::Sequence(items) => items.iter().filter_map(scalar).collect(),
+                value => scalar(value).into_iter().collect::<Vec<_>>(),
+            });
+        match self.function {
+            Function::Sum | Function::Avg => {
+                let numbers: Vec<f64> = values.filter_map(|value| value.parse().ok()).collect();
+                if numbers.is_empty() {
+                    return None;
+                }
+                let sum: f64 = numbers.iter().sum();
+                Some(match self.function {
+                    Function::Sum => sum.to_string(),
+                    _ => ((sum / numbers.len() as f64 * 100.0).round() / 100.0).to_string(),
+                })
+            }
+            Function::Min => values.min_by(|a, b| compare(a, b)).map(normalize),
+            Function::Max => values.max_by(|a, b| compare(a, b)).map(normalize),
+        }
+    }
+}
+
+/// A `--where` condition on a file's frontmatter: `KEY` (present and not
+/// false, null or empty), `!KEY` (the opposite), or `KEY OP VALUE` with
+/// `=` (or `==`), `!=`, `<`, `<=`, `>` or `>=`, where KEY is a dotted path.
+/// `KEY = nil` (or `null`) holds if the key is missing or null.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    key: String,
+    test: Test,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Test {
+    Truthy(bool),
+    Compare(Op, String),
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected KEY, !KEY or KEY OP VALUE, got {:?}", s);
+        let negated = s
+            .strip_prefix('!')
+            .filter(|key| !key.contains(OPERATOR_CHARS));
+        let (key, test) = match (negated, s.find(OPERATOR_CHARS)) {
+            (Some(key), _) => (key, Test::Truthy(false)),
+            (None, None) => (s, Test::Truthy(true)),
+            (None, Some(start)) => {
+                let rest = &s[start..];
+                let (op, len) = [
+                    ("!=", Op::Ne),
+                    ("==", Op::Eq),
+                    ("<=", Op::Le),
+                    (">=", Op::Ge),
+                    ("=", Op::Eq),
+                    ("<", Op::Lt),
+                    (">", Op::Gt),
+                ]
+                .into_iter()
+                .find(|(symbol, _)| rest.starts_with(symbol))
+                .map(|(symbol, op)| (op, symbol.len()))
+                .ok_or_else(invalid)?;
+                (
+                    &s[..start],
+                    Test::Compare(op, rest[len..].trim().to_owned()),
+                )
+            }
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(OPERATOR_CHARS) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            key: key.to_owned(),
+            test,
+        })
+    }
+}
+
+impl Condition {
+    /// A condition that the value of `key` compares to `value` as `op`
+    /// says.
+    pub(crate) fn comparing(key: &str, op: Op, value: &str) -> Self {
+        Self {
+            key: key.to_owned(),
+            test: Test::Compare(op, value.to_owned()),
+        }
+    }
+
+    /// Whether frontmatter (`None` if the file has none) meets the
+    /// condition.
+    ///
+    /// Comparing against a list tests whether any element compares that way,
+    /// so `tags=rust` matches files tagged rust. Values compare as numbers
+    /// if both are numbers, and otherwise as text, with dates in any format
+    /// `Date` understands taken as `YYYY-MM-DD` so that `date<2020` works.
+    pub fn matches(&self, metadata: Option<&yaml::Value>) -> bool {
+        let value = metadata.and_then(|metadata| keypath::get(metadata, &self.key));
+        match &self.test {
+            Test::Truthy(expected) => value.is_some_and(is_truthy) == *expected,
+            Test::Compare(op @ (Op::Eq | Op::Ne), expected) if is_nil(expected) => {
+                matches!(value, None | Some(yaml::Value::Null)) == (*op == Op::Eq)
+            }
+            Test::Compare(Op::Ne, expected) => {
+                !self.compares(value, expected, |ordering| ordering == Ordering::Equal)
+            }
+            Test::Compare(op, expected) => self.compares(value, expected, |ordering| match op {
+                Op::Eq => ordering == Ordering::Equal,
+                Op::Lt => ordering == Ordering::Less,
+                Op::Le => ordering != Ordering::Greater,
+                Op::Gt => ordering == Ordering::Greater,
+                Op::Ge => ordering != Ordering::Less,
+                Op::Ne => unreachable!("handled above"),
+            }),
+        }
+    }
+
+    fn compares(
+        &self,
+        value: Option<&yaml::Value>,
+        expected: &str,
+        accept: impl Fn(Ordering) -> bool,
+    ) -> bool {
+        match value {
+            Some(yaml::Value::Sequence(items)) => items
+                .iter()
+                .filter_map(scalar)
+                .any(|item| accept(compare(&item, expected))),
+            Some(value) => scalar(value).is_some_and(|value| accept(compare(&value, expected))),
+            None => false,
+        }
+    }
+}
+
+fn is_nil(value: &str) -> bool {
+    matches!(value, "nil" | "null" | "~")
+}
+
+fn is_truthy(value: &yaml::Value) -> bool {
+    match value {
+        yaml::Value::Null | yaml::Value::Bool(false) => false,
+        yaml::Value::String(s) => !s.is_empty(),
+        yaml::Value::Sequence(items) => !items.is_empty(),
+        yaml::Value::Mapping(mapping) => !mapping.is_empty(),
+        _ => true,
+    }
+}
+
+/// Order two values as numbers if both are, and otherwise as text, with
+/// dates normalized.
+pub(crate) fn compare(value: &str, expected: &str) -> Ordering {
+    if let (Ok(value), Ok(expected)) = (value.parse::<f64>(), expected.parse::<f64>()) {
+        return value.partial_cmp(&expected).unwrap_or(Ordering::Equal);
+    }
+    normalize(value.to_owned()).cmp(&normalize(expected.to_owned()))
+}
+
+/// Dates in any format `Date` understands as `YYYY-MM-DD`, so they compare
+/// in order.
+fn normalize(value: String) -> String {
+    Date::parse(&value).map_or(value, |date| date.to_string())
+}
+
+/// Print the path of each file `cfg` asks for whose frontmatter meets every
+/// `--where` condition and, given `--schema`, conforms to the schema.
+///
+/// Nothing is run or modified, so this can select files for other tools.
+pub fn list(cfg: &Config) -> eyre::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    for (path, _) in select(cfg)? {
+        writeln!(stdout, "{}", path)?;
+    }
+    Ok(())
+}
+
+/// Print how many files `cfg` selects, as for [`list`], or with `--group-by`
+/// how many have each value of a key, most common first.
+///
+/// A file counts once for each element of a list, so grouping by `tags`
+/// counts the files with each tag. With `--agg`, a table follows instead,
+/// with a column for each aggregation after the count.
+pub fn count(cfg: &Config) -> eyre::Result<()> {
+    let selected = select(cfg)?;
+    let documents = selected.iter().map(|(_, metadata)| metadata.as_ref());
+    let groups = match &cfg.group_by {
+        Some(key) => group(documents, key),
+        None => vec![(String::new(), documents.collect())],
+    };
+    let mut stdout = std::io::stdout().lock();
+    if cfg.aggregations.is_empty() {
+        if cfg.group_by.is_none() {
+            writeln!(stdout, "{}", selected.len())?;
+            return Ok(());
+        }
+        let width = groups
+            .first()
+            .map_or(0, |(_, group)| group.len().to_string().len());
+        for (value, group) in groups {
+            writeln!(stdout, "{:>width$} {}", group.len(), value, width = width)?;
+        }
+        return Ok(());
+    }
+
+    let mut header = Vec::new();
+    header.extend(cfg.group_by.clone());
+    header.push("count".to_owned());
+    header.extend(cfg.aggregations.iter().map(Aggregation::to_string));
+    let mut rows = vec![header];
+    for (value, group) in &groups {
+        let mut row = Vec::new();
+        if cfg.group_by.is_some() {
+            row.push(value.clone());
+        }
+        row.push(group.len().to_string());
+        for aggregation in &cfg.aggregations {
+            row.push(aggregation.compute(group).unwrap_or_else(|| "-".to_owned()));
+        }
+        rows.push(row);
+    }
+    write_table(&mut stdout, &rows, cfg.group_by.is_some())
+}
+
+/// Write rows in aligned columns, numbers to the right, with the first
+/// column to the left if it `labels` the rows.
+fn write_table(out: &mut impl Write, rows: &[Vec<String>], labels: bool) -> eyre::Result<()> {
+    let columns = rows.first().map_or(0, Vec::len);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, &width))| {
+                if labels && column == 0 {
+                    format!("{:<width$}", cell, width = width)
+                } else {
+                    format!("{:>width$}", cell, width = width)
+                }
+            })
+            .collect();
+        writeln!(out, "{}", cells.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+/// The files `cfg` asks for that `--where` and `--schema` select, with
+/// their frontmatter.
+fn select(cfg: &Config) -> eyre::Result<Vec<(String, Option<yaml::Value>)>> {
+    let mut selected = Vec::new();
+    for corpus in corpus::load(cfg)? {
+        let cfg = &corpus.cfg;
+        let schema = cfg.schema_path.as_deref().map(Schema::load).transpose()?;
+        for path in &cfg.paths {
+            let source = read_to_string(path).context(format!("couldn't read {}", path))?;
+            let (_, document) = frontmatter::split_prefix(&source, &cfg.frontmatter_prefix);
+            let document = envelope::unwrap(&cfg.envelope(path), document);
+            let metadata = frontmatter::parse_as(&document, cfg.from_format)
+                .0
+                .transpose()
+                .context(format!("couldn't parse frontmatter in {}", path))?;
+            let conforms = schema.as_ref().is_none_or(|schema| {
+                let metadata = metadata.clone().unwrap_or(yaml::Value::Null);
+                schema.validate(&metadata, cfg.deny_unknown_keys).is_ok()
+            });
+            if conforms && matches_all(&cfg.conditions, metadata.as_ref()) {
+                selected.push((path.clone(), metadata));
+            }
+        }
+    }
+    Ok(selected)
+}
+
+/// The documents having each value of `key`, most common first, with
+/// `(none)` for those without it.
+fn group<'a>(
+    documents: impl Iterator<Item = Option<&'a yaml::Value>>,
+    key: &str,
+) -> Vec<(String, Vec<Option<&'a yaml::Value>>)> {
+    let mut groups: BTreeMap<String, Vec<Option<&'a yaml::Value>>> = BTreeMap::new();
+    for metadata in documents {
+        let values = match metadata.and_then(|metadata| keypath::get(metadata, key)) {
+            Some(yaml::Value::Sequence(items)) => items.iter().filter_map(scalar).collect(),
+            Some(value) => scalar(value).into_iter().collect(),
+            None => Vec::new(),
+        };
+        let values = if values.is_empty() {
+            vec![NO_VALUE.to_owned()]
+        } else {
+            values
+        };
+        // a file listing a value twice is still only in its group once
+        for value in values.into_iter().collect::<BTreeSet<_>>() {
+            groups.entry(value).or_default().push(metadata);
+        }
+    }
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    // stable, so equal counts stay in order of value
+    groups.sort_by_key(|(_, group)| Reverse(group.len()));
+    groups
+}
+
+pub fn matches_all(conditions: &[Condition], metadata: Option<&yaml::Value>) -> bool {
+    conditions
+        .iter()
+        .all(|condition| condition.matches(metadata))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(condition: &str, metadata: &str) -> bool {
+        let condition: Condition = condition.parse().unwrap();
+        condition.matches(Some(&yaml::from_str(metadata).unwrap()))
+    }
+
+    #[test]
+    fn parses_conditions() {
+        assert_eq!(
+            Ok(Condition {
+                key: "date".into(),
+                test: Test::Compare(Op::Lt, "2020".into())
+            }),
+            "date < 2020".parse()
+        );
+        assert_eq!(
+            Ok(Condition {
+                key: "draft".into(),
+                test: Test::Truthy(false)
+            }),
+            "!draft".parse()
+        );
+        assert!("=x".parse::<Condition>().is_err());
+        assert!("a=<b".parse::<Condition>().is_ok());
+        assert!("a!b".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn matches_frontmatter() {
+        let post = "draft: true\ndate: 2019-06-01\nweight: 10\ntags: [rust, cli]\n\
+                    author: {name: Bob}\n";
+        assert!(matches("draft", post));
+        assert!(!matches("!draft", post));
+        assert!(matches("draft=true", post));
+        assert!(matches("date<2020", post));
+        assert!(!matches("date >= 2019-07", post));
+        assert!(matches("date > June 1, 2018", post));
+        assert!(matches("weight>9", post));
+        assert!(!matches("weight>=90", post));
+        assert!(matches("tags=rust", post));
+        assert!(!matches("tags!=cli", post));
+        assert!(matches("author.name=Bob", post));
+        assert!(!matches("missing", post));
+        assert!(matches("missing!=x", post));
+        assert!(!matches("missing<x", post));
+        assert!(matches("missing == nil", post));
+        assert!(!matches("draft = nil", post));
+        assert!(matches("draft != null", post));
+    }
+
+    #[test]
+    fn counts_values_exploding_lists() {
+        let documents: Vec<yaml::Value> = [
+            "category: rust\ntags: [cli, tools, cli]\n",
+            "category: rust\ntags: [cli]\n",
+            "category: go\n",
+        ]
+        .iter()
+        .map(|metadata| yaml::from_str(metadata).unwrap())
+        .collect();
+        let counts = |key: &str| -> Vec<(String, usize)> {
+            group(documents.iter().map(Some).chain([None]), key)
+                .into_iter()
+                .map(|(value, group)| (value, group.len()))
+                .collect()
+        };
+        assert_eq!(
+            vec![("(none)".into(), 2), ("cli".into(), 2), ("tools".into(), 1)],
+            counts("tags")
+        );
+        assert_eq!(
+            vec![("rust".into(), 2), ("(none)".into(), 1), ("go".into(), 1)],
+            counts("category")
+        );
+    }
+
+    #[test]
+    fn aggregates_values() {
+        let documents: Vec<yaml::Value> = [
+            "reading_time: 5\ndate: 2021-03-01\n",
+            "reading_time: 12\ndate: June 1, 2019\n",
+            "reading_time: lots\ndate: 2020-01-01\n",
+        ]
+        .iter()
+        .map(|metadata| yaml::from_str(metadata).unwrap())
+        .collect();
+        let documents: Vec<_> = documents.iter().map(Some).chain([None]).collect();
+        let compute = |aggregation: &str| {
+            let aggregation: Aggregation = aggregation.parse().unwrap();
+            aggregation.compute(&documents)
+        };
+        assert_eq!(Some("17".into()), compute("sum(reading_time)"));
+        assert_eq!(Some("8.5".into()), compute("avg( reading_time )"));
+        assert_eq!(Some("2019-06-01".into()), compute("min(date)"));
+        assert_eq!(Some("2021-03-01".into()), compute("max(date)"));
+        assert_eq!(None, compute("sum(missing)"));
+        assert!("median(date)".parse::<Aggregation>().is_err());
+        assert!("sum()".parse::<Aggregation>().is_err());
+        assert_eq!(
+            "max(date)",
+            "max(date)".parse::<Aggregation>().unwrap().to_string()
+        );
+    }
+}
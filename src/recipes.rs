@@ -0,0 +1,225 @@
+use std::str::FromStr;
+
+/// A built-in Lua script for a common fix, run with `--recipe NAME`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Recipe {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub source: &'static str,
+}
+
+pub const RECIPES: [Recipe; 10] = [
+    Recipe {
+        name: "add-missing-date",
+        description: "set date to the file's modification date if it's missing",
+        source: r#"
+if meta and meta.date == nil then
+    meta.date = fm.format_date((file and file.mtime) or os.date("!%Y-%m-%d"))
+end
+"#,
+    },
+    Recipe {
+        name: "add-missing-title",
+        description: "set title from the first # heading, or the file name, if it's missing",
+        source: r#"
+if meta and meta.title == nil then
+    for _, line in ipairs(content_lines) do
+        local heading = line:match("^#%s+(.-)%s*$")
+        if heading then
+            meta.title = heading
+            break
+        end
+    end
+    if meta.title == nil and file then
+        meta.title = fm.titlecase((file.basename:gsub("%.[^.]*$", ""):gsub("[-_]", " ")))
+    end
+end
+"#,
+    },
+    Recipe {
+        name: "add-missing-slug",
+        description: "set slug from the title if it's missing",
+        source: r#"
+if meta and meta.slug == nil and type(meta.title) == "string" then
+    meta.slug = fm.slugify(meta.title)
+end
+"#,
+    },
+    Recipe {
+        name: "tags-to-list",
+        description: "split tags given as one comma-separated string into a list",
+        source: r#"
+if meta and type(meta.tags) == "string" then
+    local tags = {}
+    for _, tag in ipairs(fm.split(meta.tags, ",")) do
+        tag = fm.trim(tag)
+        if tag ~= "" then
+            table.insert(tags, tag)
+        end
+    end
+    meta.tags = tags
+end
+"#,
+    },
+    Recipe {
+        name: "lowercase-tags",
+        description: "lowercase every tag",
+        source: r#"
+if meta and type(meta.tags) == "table" then
+    for i, tag in ipairs(meta.tags) do
+        if type(tag) == "string" then
+            meta.tags[i] = tag:lower()
+        end
+    end
+end
+"#,
+    },
+    Recipe {
+        name: "dedupe-tags",
+        description: "remove repeated tags, keeping the first of each",
+        source: r#"
+if meta and type(meta.tags) == "table" then
+    local seen, tags = {}, {}
+    for _, tag in ipairs(meta.tags) do
+        if not seen[tag] then
+            seen[tag] = true
+            table.insert(tags, tag)
+        end
+    end
+    meta.tags = tags
+end
+"#,
+    },
+    Recipe {
+        name: "sort-tags",
+        description: "sort tags alphabetically",
+        source: r#"
+if meta and type(meta.tags) == "table" then
+    local tags = {}
+    for _, tag in ipairs(meta.tags) do
+        if type(tag) ~= "string" then
+            return
+        end
+        table.insert(tags, tag)
+    end
+    table.sort(tags)
+    meta.tags = tags
+end
+"#,
+    },
+    Recipe {
+        name: "lowercase-keys",
+        description: "lowercase top-level keys, unless that would clash with another key",
+        source: r#"
+if meta then
+    local renames = {}
+    for key in pairs(meta) do
+        if type(key) == "string" and key ~= key:lower() and meta[key:lower()] == nil then
+            renames[key] = key:lower()
+        end
+    end
+    for key, lower in pairs(renames) do
+        meta[lower], meta[key] = meta[key], nil
+    end
+end
+"#,
+    },
+    Recipe {
+        name: "trim-strings",
+        description: "remove whitespace from the ends of top-level string values",
+        source: r#"
+if meta then
+    for key, value in pairs(meta) do
+        if type(value) == "string" then
+            meta[key] = fm.trim(value)
+        end
+    end
+end
+"#,
+    },
+    Recipe {
+        name: "remove-empty",
+        description: "remove top-level keys whose values are empty strings, lists or maps",
+        source: r#"
+if meta then
+    for key, value in pairs(meta) do
+        if value == "" or (type(value) == "table" and next(value) == nil) then
+            meta[key] = nil
+        end
+    end
+end
+"#,
+    },
+];
+
+impl FromStr for Recipe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RECIPES
+            .iter()
+            .find(|recipe| recipe.name == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = RECIPES.iter().map(|recipe| recipe.name).collect();
+                format!(
+                    "unknown recipe {:?}, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_yaml as yaml;
+
+    use super::*;
+    use crate::fixer::{Fixer, Script, ScriptOptions};
+
+    fn cook(names: &[&str], document: &str) -> eyre::Result<String> {
+        let scripts: Vec<Script> = names
+            .iter()
+            .map(|name| {
+                let recipe: Recipe = name.parse().unwrap();
+                Script {
+                    name: recipe.name.to_owned(),
+                    source: recipe.source.to_owned(),
+                }
+            })
+            .collect();
+        let fixer = Fixer::chained(&scripts, &ScriptOptions::default())?;
+        let (meta, _) = fixer.fix(document, None)?;
+        Ok(yaml::to_string(&meta)?)
+    }
+
+    #[test]
+    fn cooks_tags() -> eyre::Result<()> {
+        let fixed = cook(
+            &["tags-to-list", "lowercase-tags", "dedupe-tags", "sort-tags"],
+            "---\ntags: Rust, lua, rust,\n---\n",
+        )?;
+        assert_eq!("tags:\n- lua\n- rust\n", fixed);
+        Ok(())
+    }
+
+    #[test]
+    fn cooks_missing_keys() -> eyre::Result<()> {
+        let fixed = cook(
+            &[
+                "lowercase-keys",
+                "remove-empty",
+                "add-missing-title",
+                "add-missing-slug",
+            ],
+            "---\nSubtitle: Hi\ntags: []\n---\n# Hello World\n",
+        )?;
+        assert_eq!(
+            "slug: hello-world\nsubtitle: Hi\ntitle: Hello World\n",
+            fixed
+        );
+        assert!("no-such-recipe".parse::<Recipe>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::str::FromStr;
+
+use serde_yaml as yaml;
+
+use crate::glob;
+
+/// Keys to strip from frontmatter before it leaves the source tree.
+#[derive(Clone, Debug)]
+pub struct Redaction {
+    key_patterns: Vec<String>,
+}
+
+impl FromStr for Redaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .strip_prefix("keys=")
+            .ok_or_else(|| format!("expected keys=PATTERN[,PATTERN...], got {:?}", s))?;
+        let key_patterns: Vec<String> = keys
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect();
+        if key_patterns.is_empty() {
+            return Err("must specify at least one key pattern to redact".into());
+        }
+        Ok(Self { key_patterns })
+    }
+}
+
+impl Redaction {
+    /// Remove every key whose dotted path matches one of the patterns.
+    ///
+    /// Returns the dotted paths of the keys that were removed.
+    pub fn apply(&self, metadata: &mut yaml::Value) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.redact_mapping(metadata, "", &mut removed);
+        removed
+    }
+
+    fn redact_mapping(&self, value: &mut yaml::Value, prefix: &str, removed: &mut Vec<String>) {
+        let mapping = match value {
+            yaml::Value::Mapping(mapping) => mapping,
+            _ => return,
+        };
+        let mut doomed = Vec::new();
+        for (key, child) in mapping.iter_mut() {
+            let key_str = match key.as_str() {
+                Some(key_str) => key_str,
+                None => continue,
+            };
+            let path = if prefix.is_empty() {
+                key_str.to_owned()
+            } else {
+                format!("{}.{}", prefix, key_str)
+            };
+            if self.matches(&path) {
+                doomed.push((key.clone(), path));
+            } else {
+                self.redact_mapping(child, &path, removed);
+            }
+        }
+        // retain rather than remove, so surviving keys keep their order
+        mapping.retain(|key, _| !doomed.iter().any(|(doomed_key, _)| doomed_key == key));
+        removed.extend(doomed.into_iter().map(|(_, path)| path));
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        self.key_patterns
+            .iter()
+            .any(|pattern| glob::matches(pattern, path, '.'))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn example() -> yaml::Value {
+        yaml::from_str(
+            "\
+title: Hello
+reviewer: alice
+internal_notes: needs work
+author:
+  name: Bob
+  internal_id: 42
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_key_patterns() {
+        let redaction: Redaction = "keys=internal_*, reviewer".parse().unwrap();
+        assert_eq!(vec!["internal_*", "reviewer"], redaction.key_patterns);
+    }
+
+    #[test]
+    fn rejects_missing_keys_prefix() {
+        let _ = "internal_*"
+            .parse::<Redaction>()
+            .expect_err("should require keys=");
+    }
+
+    #[test]
+    fn removes_matching_top_level_keys() {
+        let redaction: Redaction = "keys=internal_*,reviewer".parse().unwrap();
+        let mut meta = example();
+        let removed = redaction.apply(&mut meta);
+        assert_eq!(vec!["reviewer", "internal_notes"], removed);
+        assert_eq!(
+            "title: Hello\nauthor:\n  name: Bob\n  internal_id: 42\n",
+            yaml::to_string(&meta).unwrap()
+        );
+    }
+
+    #[test]
+    fn removes_matching_nested_keys() {
+        let redaction: Redaction = "keys=**.internal_*".parse().unwrap();
+        let mut meta = example();
+        let removed = redaction.apply(&mut meta);
+        assert_eq!(vec!["author.internal_id", "internal_notes"], removed);
+    }
+}
@@ -0,0 +1,141 @@
+use std::{fs::read_to_string, path::Path};
+
+use eyre::{eyre, Context};
+use serde_json::Value;
+use tera::Tera;
+
+/// A [Tera](https://keats.github.io/tera/docs/) template to export each
+/// file through, e.g. `{{ meta.title }}`, `{% for tag in meta.tags %}` or
+/// `{{ meta.tags | join(sep=", ") }}`.
+///
+/// One named for HTML or XML, like `item.html.tera` or `entry.xml.tera`,
+/// escapes everything it renders unless piped through `safe`; otherwise
+/// nothing is escaped unless asked, with `escape`.
+#[derive(Debug)]
+pub struct Template {
+    tera: Tera,
+    name: String,
+}
+
+impl Template {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let source =
+            read_to_string(path).context(format!("couldn't read template {}", path.display()))?;
+        // named for what it renders, so Tera knows whether to escape it
+        let name = path
+            .file_name()
+            .map_or_else(|| "template".into(), |name| name.to_string_lossy());
+        let name = name.strip_suffix(".tera").unwrap_or(&name);
+        Self::named(name, &source).context(format!("invalid template {}", path.display()))
+    }
+
+    /// A template that escapes nothing unless asked.
+    pub fn parse(source: &str) -> eyre::Result<Self> {
+        Self::named("template", source)
+    }
+
+    fn named(name: &str, source: &str) -> eyre::Result<Self> {
+        let mut tera = Tera::default();
+        tera.add_raw_template(name, source)
+            .map_err(|err| eyre!("{}", describe(&err)))?;
+        Ok(Self {
+            tera,
+            name: name.to_owned(),
+        })
+    }
+
+    /// Render with `context`, an object of the variables the template can
+    /// use.
+    pub fn render(&self, context: &Value) -> eyre::Result<String> {
+        let context = tera::Context::from_value(context.clone())
+            .map_err(|err| eyre!("{}", describe(&err)))?;
+        self.tera
+            .render(&self.name, &context)
+            .map_err(|err| eyre!("{}", describe(&err)))
+    }
+}
+
+/// Tera's error and what caused it, which is usually the useful part.
+fn describe(err: &tera::Error) -> String {
+    let mut description = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        description.push_str(": ");
+        description.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    description
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(template: &str, context: Value) -> eyre::Result<String> {
+        Template::parse(template)?.render(&context)
+    }
+
+    #[test]
+    fn renders_variables_and_filters() -> eyre::Result<()> {
+        let context = json!({
+            "meta": {"title": "Fish & Chips", "tags": ["a", "b"]},
+            "path": "posts/fish.md",
+        });
+        assert_eq!(
+            "<title>Fish &amp; Chips</title> a, b (2) posts/fish.md \"a\" none",
+            render(
+                "<title>{{ meta.title | escape }}</title> {{ meta.tags | join(sep=\", \") }} \
+                 ({{ meta.tags | length }}) {{path}} {{ meta.tags.0 | json_encode }} \
+                 {{ meta.author | default(value=\"none\") }}",
+                context
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn renders_loops_and_conditions() -> eyre::Result<()> {
+        let context = json!({"meta": {"draft": false, "tags": ["a", "b"]}});
+        let template = "\
+            {% if not meta.draft %}live{% else %}draft{% endif %}\n\
+            {%- for tag in meta.tags %}\n  <tag>{{ tag | upper }}</tag>\n{%- endfor %}\
+            {# ignored #}";
+        assert_eq!(
+            "live\n  <tag>A</tag>\n  <tag>B</tag>",
+            render(template, context)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn escapes_html_and_xml() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let context = json!({"meta": {"title": "Fish & Chips"}});
+        for (name, expected) in [
+            ("item.html.tera", "<h1>Fish &amp; Chips</h1>\n"),
+            ("item.xml.tera", "<h1>Fish &amp; Chips</h1>\n"),
+            ("item.md.tera", "<h1>Fish & Chips</h1>\n"),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "<h1>{{ meta.title }}</h1>\n")?;
+            assert_eq!(
+                expected,
+                Template::load(&path)?.render(&context)?,
+                "{}",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        assert!(Template::parse("{{ meta.title").is_err());
+        assert!(Template::parse("{% if meta.draft %}").is_err());
+        assert!(Template::parse("{% endfor %}").is_err());
+        assert!(render("{{ meta.missing }}", json!({"meta": {}})).is_err());
+        assert!(render("{{ meta | shout }}", json!({"meta": 1})).is_err());
+    }
+}
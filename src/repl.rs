@@ -0,0 +1,399 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    io::{self, BufRead, IsTerminal, Write},
+    path::PathBuf,
+};
+
+use eyre::Context;
+use mlua::{Lua, MultiValue, Value};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+/// Indentation per level of a pretty-printed table.
+const INDENT: &str = "  ";
+
+/// Where what's entered at a terminal is kept between runs, in the home
+/// directory.
+const HISTORY_FILE: &str = ".frontmatter-fixer_history";
+
+const HELP: &str = "\
+Enter Lua to run on this file, such as meta.title = 'Hello'; an expression
+like meta shows its value. A statement can go over several lines.
+
+:write, :next   keep the changes to this file and go on to the next
+:skip           leave this file as it was and go on to the next
+:quit           leave this and every remaining file as they were (as does
+                end of input)
+:meta           show this file's frontmatter
+:history        list what's been entered, numbered
+!N              run entry N of the history again
+:help           show this help
+";
+
+/// What to do with a file once the REPL is done with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Keep the changes made to it.
+    Write,
+    /// Leave it as it was.
+    Skip,
+}
+
+/// An interactive Lua session over each file in turn, remembering what's
+/// been entered across files.
+#[derive(Debug, Default)]
+pub struct Repl {
+    history: RefCell<Vec<String>>,
+    quit: Cell<bool>,
+    /// Line editing, once a session at a terminal needs it.
+    terminal: RefCell<Option<Terminal>>,
+}
+
+/// Where a session reads what's entered.
+pub trait Input {
+    /// The next line entered after showing `prompt`, without its line
+    /// ending, or `None` at the end of input.
+    fn next_line(&mut self, prompt: &str, output: &mut impl Write) -> eyre::Result<Option<String>>;
+}
+
+/// Piped input, with prompts written to the output.
+impl<R: BufRead> Input for R {
+    fn next_line(&mut self, prompt: &str, output: &mut impl Write) -> eyre::Result<Option<String>> {
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+        let mut line = String::new();
+        if BufRead::read_line(self, &mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_owned()))
+    }
+}
+
+/// A terminal, with line editing and history kept in [`HISTORY_FILE`].
+pub struct Terminal {
+    editor: DefaultEditor,
+    history_path: Option<PathBuf>,
+}
+
+impl Terminal {
+    fn new() -> eyre::Result<Self> {
+        let mut editor = DefaultEditor::new().context("couldn't set up line editing")?;
+        let history_path =
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        if let Some(path) = &history_path {
+            // there's none the first time
+            let _ = editor.load_history(path);
+        }
+        Ok(Self {
+            editor,
+            history_path,
+        })
+    }
+}
+
+impl Input for Terminal {
+    fn next_line(&mut self, prompt: &str, _: &mut impl Write) -> eyre::Result<Option<String>> {
+        match self.editor.readline(prompt) {
+            Ok(line) => {
+                // history is a convenience, not worth stopping the session
+                // over
+                let _ = self.editor.add_history_entry(line.as_str());
+                if let Some(path) = &self.history_path {
+                    let _ = self.editor.save_history(path);
+                }
+                Ok(Some(line))
+            }
+            // ^C as well as ^D leaves the files alone
+            Err(ReadlineError::Eof | ReadlineError::Interrupted) => Ok(None),
+            Err(err) => Err(err).context("couldn't read input"),
+        }
+    }
+}
+
+impl fmt::Debug for Terminal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Terminal")
+            .field("history_path", &self.history_path)
+            .finish()
+    }
+}
+
+impl Repl {
+    /// Whether `:quit` was entered, so the remaining files are left alone.
+    pub fn quit(&self) -> bool {
+        self.quit.get()
+    }
+
+    /// Run a session for the current file on stdin, editing lines if it's
+    /// a terminal, and stderr.
+    pub fn interact(&self, lua: &Lua, name: &str) -> eyre::Result<Step> {
+        if !io::stdin().is_terminal() {
+            return self.session(lua, name, &mut io::stdin().lock(), &mut io::stderr());
+        }
+        let mut terminal = self.terminal.borrow_mut();
+        let terminal = match &mut *terminal {
+            Some(terminal) => terminal,
+            none => none.insert(Terminal::new()?),
+        };
+        self.session(lua, name, terminal, &mut io::stderr())
+    }
+
+    /// Read and run Lua for the current file from `input` until a command
+    /// moves on, writing prompts and results to `output`. `name` is shown
+    /// in the prompt.
+    pub fn session(
+        &self,
+        lua: &Lua,
+        name: &str,
+        input: &mut impl Input,
+        output: &mut impl Write,
+    ) -> eyre::Result<Step> {
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() {
+                format!("{}> ", name)
+            } else {
+                format!("{}>> ", " ".repeat(name.len()))
+            };
+            let line = match input.next_line(&prompt, output)? {
+                Some(line) => line,
+                None => {
+                    // end of input, as when it's closed by accident: leave
+                    // this file and the rest alone, as with :quit
+                    writeln!(output)?;
+                    self.quit.set(true);
+                    return Ok(Step::Skip);
+                }
+            };
+            let line = line.as_str();
+
+            if buffer.is_empty() {
+                match line.trim() {
+                    "" => continue,
+                    ":write" | ":next" => return Ok(Step::Write),
+                    ":skip" => return Ok(Step::Skip),
+                    ":quit" => {
+                        self.quit.set(true);
+                        return Ok(Step::Skip);
+                    }
+                    ":help" => {
+                        write!(output, "{}", HELP)?;
+                        continue;
+                    }
+                    ":history" => {
+                        for (index, entry) in self.history.borrow().iter().enumerate() {
+                            writeln!(output, "{:>4}  {}", index + 1, entry.replace('\n', " "))?;
+                        }
+                        continue;
+                    }
+                    ":meta" => {
+                        let meta: Value = lua.globals().get("meta")?;
+                        writeln!(output, "{}", pretty(&meta))?;
+                        continue;
+                    }
+                    command if command.starts_with(':') => {
+                        writeln!(output, "unknown command {}, try :help", command)?;
+                        continue;
+                    }
+                    recall if recall.starts_with('!') => {
+                        let entry = recall[1..]
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|n| self.history.borrow().get(n.wrapping_sub(1)).cloned());
+                        match entry {
+                            Some(entry) => {
+                                writeln!(output, "{}", entry)?;
+                                self.run(lua, &entry, output)?;
+                            }
+                            None => writeln!(output, "no history entry {}", &recall[1..])?,
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+            } else {
+                buffer.push('\n');
+            }
+            buffer.push_str(line);
+
+            if is_incomplete(lua, &buffer) {
+                continue;
+            }
+            let entry = std::mem::take(&mut buffer);
+            self.history.borrow_mut().push(entry.clone());
+            self.run(lua, &entry, output)?;
+        }
+    }
+
+    /// Run `source`, showing its value if it's an expression, or the error.
+    fn run(&self, lua: &Lua, source: &str, output: &mut impl Write) -> eyre::Result<()> {
+        match lua.load(source).eval::<MultiValue>() {
+            Ok(values) => {
+                for value in values {
+                    writeln!(output, "{}", pretty(&value))?;
+                }
+            }
+            Err(err) => writeln!(output, "error: {}", err)?,
+        }
+        Ok(())
+    }
+}
+
+/// Whether `source` is the start of a statement or expression that needs
+/// more lines.
+fn is_incomplete(lua: &Lua, source: &str) -> bool {
+    let incomplete = |result: mlua::Result<mlua::Function>| {
+        matches!(
+            result,
+            Err(mlua::Error::SyntaxError {
+                incomplete_input: true,
+                ..
+            })
+        )
+    };
+    let expression = format!("return {}", source);
+    incomplete(lua.load(source).into_function()) && lua.load(&expression).into_function().is_err()
+}
+
+/// A Lua value as Lua source, with tables spread over indented lines and
+/// their keys sorted.
+fn pretty(value: &Value) -> String {
+    let mut out = String::new();
+    write_pretty(value, 0, &mut out);
+    out
+}
+
+fn write_pretty(value: &Value, depth: usize, out: &mut String) {
+    match value {
+        Value::Nil => out.push_str("nil"),
+        Value::Boolean(b) => out.push_str(&b.to_string()),
+        Value::Integer(i) => out.push_str(&i.to_string()),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => out.push_str(&format!("{:?}", s.to_string_lossy())),
+        Value::Table(table) => {
+            // integer keys first, in numeric order, then the rest by name
+            let mut entries: Vec<(Option<i64>, String, Value)> = table
+                .clone()
+                .pairs::<Value, Value>()
+                .filter_map(Result::ok)
+                .map(|(key, value)| {
+                    let index = match key {
+                        Value::Integer(i) => Some(i),
+                        _ => None,
+                    };
+                    let key = match key {
+                        Value::String(s) => {
+                            let s = s.to_string_lossy().into_owned();
+                            if is_identifier(&s) {
+                                s
+                            } else {
+                                format!("[{:?}]", s)
+                            }
+                        }
+                        key => {
+                            let mut rendered = String::new();
+                            write_pretty(&key, depth + 1, &mut rendered);
+                            format!("[{}]", rendered)
+                        }
+                    };
+                    (index, key, value)
+                })
+                .collect();
+            if entries.is_empty() {
+                out.push_str("{}");
+                return;
+            }
+            entries.sort_by(|a, b| (a.0.is_none(), a.0, &a.1).cmp(&(b.0.is_none(), b.0, &b.1)));
+            out.push_str("{\n");
+            for (_, key, value) in entries {
+                out.push_str(&INDENT.repeat(depth + 1));
+                out.push_str(&key);
+                out.push_str(" = ");
+                write_pretty(&value, depth + 1, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&INDENT.repeat(depth));
+            out.push('}');
+        }
+        other => out.push_str(&format!("<{}>", other.type_name())),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn session(repl: &Repl, lua: &Lua, input: &str) -> eyre::Result<(Step, String)> {
+        let mut output = Vec::new();
+        let step = repl.session(lua, "post.md", &mut Cursor::new(input), &mut output)?;
+        Ok((step, String::from_utf8(output)?))
+    }
+
+    #[test]
+    fn runs_statements_and_commands() -> eyre::Result<()> {
+        let lua = Lua::new();
+        lua.load("meta = { title = 'Hi', tags = { 'a' } }").exec()?;
+        let repl = Repl::default();
+        let (step, output) = session(
+            &repl,
+            &lua,
+            "meta.title = 'Hello'\nfunction shout(s)\nreturn s:upper()\nend\n\
+             shout(meta.title)\n:history\n:next\nmeta.title = 'ignored'\n",
+        )?;
+        assert_eq!(Step::Write, step);
+        assert!(output.contains("\"HELLO\"\n"));
+        // continuation lines get a prompt lined up under the file's
+        assert!(output.contains("post.md>        >> "), "{}", output);
+        assert!(output.contains("   2  function shout(s) return s:upper() end\n"));
+        assert_eq!("Hello", lua.load("meta.title").eval::<String>()?);
+
+        let (step, output) = session(&repl, &lua, "!3\n:skip\n")?;
+        assert_eq!(Step::Skip, step);
+        assert!(output.contains("\"HELLO\"\n"));
+        assert!(!repl.quit());
+
+        let (step, _) = session(&repl, &lua, ":quit\n")?;
+        assert_eq!(Step::Skip, step);
+        assert!(repl.quit());
+
+        let repl = Repl::default();
+        let (step, _) = session(&repl, &lua, "meta.title = 'unconfirmed'\n")?;
+        assert_eq!(Step::Skip, step);
+        assert!(repl.quit());
+        Ok(())
+    }
+
+    #[test]
+    fn pretty_prints_tables() -> eyre::Result<()> {
+        let lua = Lua::new();
+        let value: Value = lua
+            .load("{ title = 'Hi', tags = { 'a', 'b' }, ['odd key'] = true, empty = {} }")
+            .eval()?;
+        assert_eq!(
+            "{\n  [\"odd key\"] = true,\n  empty = {},\n  tags = {\n    [1] = \"a\",\n    \
+             [2] = \"b\",\n  },\n  title = \"Hi\",\n}",
+            pretty(&value)
+        );
+
+        let list: Value = lua
+            .load("{ 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', x = 1 }")
+            .eval()?;
+        let pretty = pretty(&list);
+        let lines: Vec<_> = pretty.lines().map(str::trim).collect();
+        assert_eq!(
+            vec!["[9] = \"i\",", "[10] = \"j\",", "x = 1,", "}"],
+            lines[9..]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, read, read_to_string, write},
+    io::Read,
+    path::{Component, Path},
+};
+
+use eyre::{eyre, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::frontmatter;
+
+/// The archive entry describing the run; the files' contents are the other
+/// entries, named by [`entry_name`].
+const MANIFEST_ENTRY: &str = "bundle.json";
+
+/// Everything needed to reproduce a run somewhere else: the arguments it was
+/// given, and each corpus's options, scripts, schema, files and the other
+/// inputs it read.
+///
+/// Saved as a zstd-compressed tar archive, e.g. `bundle.tar.zst`, holding
+/// `bundle.json`, each file's contents under `files/` and each other
+/// input's under `inputs/`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    pub tool_version: String,
+    pub args: Vec<String>,
+    pub corpora: Vec<RecordedCorpus>,
+}
+
+/// A corpus's inputs, as they were when recording.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedCorpus {
+    /// `None` for the files given on the command line.
+    pub name: Option<String>,
+    /// The options from its `.frontmatter-fixer` file, if it had one;
+    /// otherwise it was fixed as the recorded arguments say.
+    pub options: Option<Vec<String>>,
+    /// The source of each script it ran, in order.
+    pub scripts: Vec<String>,
+    pub schema: Option<String>,
+    /// What its `--migration-state` file held, if it had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub migration_state: Option<String>,
+    pub files: Vec<RecordedFile>,
+    /// Everything else it read: modules its scripts could `require`, WASM
+    /// modules, patches and migrations.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<RecordedInput>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedFile {
+    pub path: String,
+    /// `None` if the file couldn't be read when recording. Kept in its own
+    /// archive entry rather than in `bundle.json`.
+    #[serde(skip)]
+    pub contents: Option<String>,
+}
+
+/// Another file a run read, to be replayed from `path` in the corpus's
+/// scratch directory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedInput {
+    pub path: String,
+    /// Kept in its own archive entry rather than in `bundle.json`.
+    #[serde(skip)]
+    pub contents: Vec<u8>,
+}
+
+impl RecordedInput {
+    /// Write the input into `dir`, so long as its path stays inside it.
+    pub fn write_to(&self, dir: &Path) -> eyre::Result<()> {
+        let path = Path::new(&self.path);
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(eyre!("recorded input {} is outside the bundle", self.path));
+        }
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(&path, &self.contents).context(format!("couldn't write {}", path.display()))
+    }
+}
+
+impl RecordedCorpus {
+    /// Capture a corpus's inputs, given the source of the scripts it runs,
+    /// all but those in [`Self::inputs`]. With `redact_content`, only frontmatter is kept and each file's body
+    /// is replaced with a placeholder.
+    pub fn record(
+        name: Option<String>,
+        options: Option<Vec<String>>,
+        scripts: Vec<String>,
+        schema: Option<String>,
+        migration_state: Option<String>,
+        paths: &[String],
+        redact_content: bool,
+    ) -> Self {
+        let files = paths
+            .iter()
+            .map(|path| {
+                let contents = read_to_string(path).ok().map(|contents| {
+                    if redact_content {
+                        redact_body(&contents)
+                    } else {
+                        contents
+                    }
+                });
+                RecordedFile {
+                    path: path.clone(),
+                    contents,
+                }
+            })
+            .collect();
+        Self {
+            name,
+            options,
+            scripts,
+            schema,
+            migration_state,
+            files,
+            inputs: Vec::new(),
+        }
+    }
+}
+
+impl Bundle {
+    pub fn new(args: Vec<String>, corpora: Vec<RecordedCorpus>) -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_owned(),
+            args,
+            corpora,
+        }
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let compressed = read(path).context(format!("couldn't read bundle {}", path.display()))?;
+        Self::from_archive(&compressed).context(format!("couldn't parse bundle {}", path.display()))
+    }
+
+    fn from_archive(compressed: &[u8]) -> eyre::Result<Self> {
+        let tar = zstd::decode_all(compressed).context("couldn't decompress")?;
+        let mut entries = HashMap::new();
+        for entry in tar::Archive::new(tar.as_slice()).entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            entry
+                .read_to_end(&mut contents)
+                .context(format!("couldn't read {}", name))?;
+            entries.insert(name, contents);
+        }
+        let json = entries
+            .remove(MANIFEST_ENTRY)
+            .ok_or_else(|| eyre!("no {} in archive", MANIFEST_ENTRY))?;
+        let mut bundle: Self = serde_json::from_slice(&json)?;
+        for (c, corpus) in bundle.corpora.iter_mut().enumerate() {
+            for (f, file) in corpus.files.iter_mut().enumerate() {
+                file.contents = entries
+                    .remove(&entry_name(c, f, &file.path))
+                    .and_then(|contents| String::from_utf8(contents).ok());
+            }
+            for input in &mut corpus.inputs {
+                let name = input_entry_name(c, &input.path);
+                input.contents = entries
+                    .remove(&name)
+                    .ok_or_else(|| eyre!("no {} in archive", name))?;
+            }
+        }
+        Ok(bundle)
+    }
+
+    pub fn to_archive(&self) -> eyre::Result<Vec<u8>> {
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        append(&mut tar, MANIFEST_ENTRY, &json)?;
+        for (c, corpus) in self.corpora.iter().enumerate() {
+            for (f, file) in corpus.files.iter().enumerate() {
+                if let Some(contents) = &file.contents {
+                    append(&mut tar, &entry_name(c, f, &file.path), contents.as_bytes())?;
+                }
+            }
+            for input in &corpus.inputs {
+                append(&mut tar, &input_entry_name(c, &input.path), &input.contents)?;
+            }
+        }
+        let tar = tar.into_inner()?;
+        zstd::encode_all(tar.as_slice(), 0).context("couldn't compress bundle")
+    }
+}
+
+/// The archive entry holding the contents of corpus `c`'s file `f`, at
+/// `path`: numbered, so paths that mirror to the same place don't clash,
+/// and named after the file, so the archive can be looked through.
+fn entry_name(c: usize, f: usize, path: &str) -> String {
+    let name = Path::new(path)
+        .file_name()
+        .map_or_else(|| "file".into(), |name| name.to_string_lossy());
+    format!("files/{}/{}/{}", c, f, name)
+}
+
+/// The archive entry holding the contents of corpus `c`'s input at `path`.
+fn input_entry_name(c: usize, path: &str) -> String {
+    format!("inputs/{}/{}", c, path)
+}
+
+fn append(tar: &mut tar::Builder<Vec<u8>>, name: &str, contents: &[u8]) -> eyre::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, name, contents)
+        .context(format!("couldn't add {} to bundle", name))
+}
+
+fn redact_body(contents: &str) -> String {
+    let (raw_frontmatter, body) = frontmatter::parse_raw(contents);
+    let placeholder = format!("[{} bytes of content redacted]\n", body.len());
+    match (raw_frontmatter, frontmatter::detect(contents)) {
+        (Some(raw_frontmatter), Some(format)) => {
+            let rule = format.rule();
+            format!("{}{}{}{}", rule, raw_frontmatter, rule, placeholder)
+        }
+        _ => placeholder,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redacts_body_but_keeps_frontmatter() {
+        assert_eq!(
+            "---\nhello: world\n---\n[8 bytes of content redacted]\n",
+            redact_body("---\nhello: world\n---\n# Title\n")
+        );
+        assert_eq!("[8 bytes of content redacted]\n", redact_body("# Title\n"));
+    }
+
+    #[test]
+    fn round_trips_through_archive() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let post = dir.path().join("post.md");
+        std::fs::write(&post, "---\ntitle: Hi\n---\nBody\n")?;
+        let corpus = |name: &str| {
+            let mut corpus = RecordedCorpus::record(
+                Some(name.to_owned()),
+                None,
+                vec!["".into()],
+                None,
+                None,
+                &[post.display().to_string(), "does-not-exist.md".into()],
+                false,
+            );
+            corpus.inputs.push(RecordedInput {
+                path: "wasm/0.wasm".into(),
+                contents: vec![0, 0x61, 0x73, 0x6d],
+            });
+            corpus
+        };
+        let bundle = Bundle::new(
+            vec!["frontmatter-fixer".into(), "-e".into(), "".into()],
+            vec![corpus("blog"), corpus("docs")],
+        );
+        let loaded = Bundle::from_archive(&bundle.to_archive()?)?;
+        assert_eq!(bundle.args, loaded.args);
+        assert_eq!(2, loaded.corpora.len());
+        assert_eq!(Some("docs"), loaded.corpora[1].name.as_deref());
+        assert_eq!(
+            Some("---\ntitle: Hi\n---\nBody\n"),
+            loaded.corpora[1].files[0].contents.as_deref()
+        );
+        assert_eq!(None, loaded.corpora[1].files[1].contents);
+        assert_eq!(
+            vec![0, 0x61, 0x73, 0x6d],
+            loaded.corpora[1].inputs[0].contents
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_inputs_only_inside_the_scratch_directory() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = |path: &str| RecordedInput {
+            path: path.to_owned(),
+            contents: b"return 1\n".to_vec(),
+        };
+        input("lua/0/util/answer.lua").write_to(dir.path())?;
+        assert!(dir.path().join("lua/0/util/answer.lua").is_file());
+        assert!(input("../escaped.lua").write_to(dir.path()).is_err());
+        assert!(input("/tmp/escaped.lua").write_to(dir.path()).is_err());
+        Ok(())
+    }
+}
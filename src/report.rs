@@ -0,0 +1,645 @@
+use std::{error::Error, fmt, path::PathBuf, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{error::FixError, hash, patch::Operation};
+
+/// Machine-readable formats findings can be reported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// GitHub Actions workflow commands (`::error file=...::msg`).
+    Github,
+    /// GitLab code quality JSON.
+    Gitlab,
+    /// JUnit XML, with a test case per file.
+    Junit,
+    /// SARIF 2.1.0, for code scanning UIs.
+    Sarif,
+    /// JSON with each file's outcome, whether it changed and any findings,
+    /// and a summary of the run.
+    Json,
+    /// A JSON object mapping each file whose frontmatter changed to the
+    /// RFC 6902 JSON Patch making that change.
+    JsonPatch,
+}
+
+/// A `--report FORMAT[=PATH]` request; reports go to stdout without a path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportSpec {
+    pub format: ReportFormat,
+    pub path: Option<PathBuf>,
+}
+
+impl FromStr for ReportSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = match s.split_once('=') {
+            Some((format, path)) => (format, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        let format = match format {
+            "github" => ReportFormat::Github,
+            "gitlab" => ReportFormat::Gitlab,
+            "junit" => ReportFormat::Junit,
+            "sarif" => ReportFormat::Sarif,
+            "json" => ReportFormat::Json,
+            "json-patch" => ReportFormat::JsonPatch,
+            _ => {
+                return Err(format!(
+                    "unknown report format {:?}, \
+                     expected github, gitlab, junit, sarif, json or json-patch",
+                    format
+                ))
+            }
+        };
+        Ok(Self { format, path })
+    }
+}
+
+/// A single problem found in a file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    /// Short identifier for the kind of problem, e.g. `unknown-key`.
+    pub rule: &'static str,
+    pub message: String,
+    /// The frontmatter key the problem is about, if any.
+    pub key: Option<String>,
+    /// 1-based line number within the file, if known.
+    pub line: Option<usize>,
+}
+
+impl Finding {
+    pub fn new(rule: &'static str, message: String) -> Self {
+        Self {
+            rule,
+            message,
+            key: None,
+            line: None,
+        }
+    }
+
+    pub fn for_key(rule: &'static str, key: &str, message: String) -> Self {
+        Self {
+            key: Some(key.to_owned()),
+            ..Self::new(rule, message)
+        }
+    }
+
+    /// A finding for a file that failed, under a rule for the kind of
+    /// failure.
+    fn from_fix_error(fix_error: &FixError, error: &eyre::Report) -> Self {
+        let message = format!("{:#}", error);
+        match fix_error {
+            FixError::Parse { line, .. } => Self {
+                line: *line,
+                ..Self::new("parse-error", message)
+            },
+            FixError::Script { .. } => Self::new("script-error", message),
+            FixError::Write { .. } => Self::new("write-error", message),
+        }
+    }
+}
+
+/// Problems found in a file, as an error that can travel through `eyre`
+/// and be recovered intact by reporters.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Findings(pub Vec<Finding>);
+
+impl Findings {
+    /// Fill in line numbers for findings about keys, by looking for the key
+    /// in the frontmatter of `source`, which follows `preceding_lines` lines
+    /// of the file.
+    pub fn locate_keys(mut self, source: &str, preceding_lines: usize) -> Self {
+        for finding in &mut self.0 {
+            if let (None, Some(key)) = (finding.line, &finding.key) {
+                finding.line =
+                    crate::frontmatter::key_line(source, key).map(|line| line + preceding_lines);
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for Findings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<&str> = self.0.iter().map(|f| f.message.as_str()).collect();
+        f.write_str(&messages.join("; "))
+    }
+}
+
+impl Error for Findings {}
+
+/// What happened to a single file.
+#[derive(Debug)]
+enum Outcome {
+    Passed { changed: bool },
+    Failed(Vec<Finding>),
+    Skipped(String),
+}
+
+/// Per-file outcomes collected across a run, for rendering into reports.
+#[derive(Debug, Default)]
+pub struct Report {
+    files: Vec<(String, Outcome)>,
+    patches: Vec<(String, Vec<Operation>)>,
+}
+
+impl Report {
+    pub fn record_success(&mut self, path: &str, changed: bool) {
+        self.files
+            .push((path.to_owned(), Outcome::Passed { changed }));
+    }
+
+    pub fn record_skipped(&mut self, path: &str, reason: &str) {
+        self.files
+            .push((path.to_owned(), Outcome::Skipped(reason.to_owned())));
+    }
+
+    /// Record why a file failed, keeping structured findings where the
+    /// error carries them, in place of anything recorded for it before
+    /// (e.g. by the first of two passes).
+    pub fn record_failure(&mut self, path: &str, error: &eyre::Report) {
+        let findings = match (error.downcast_ref::<Findings>(), error.downcast_ref()) {
+            (Some(findings), _) => findings.0.clone(),
+            (None, Some(fix_error)) => vec![Finding::from_fix_error(fix_error, error)],
+            (None, None) => vec![Finding::new("processing-error", format!("{:#}", error))],
+        };
+        self.files.retain(|(recorded, _)| recorded != path);
+        self.files
+            .push((path.to_owned(), Outcome::Failed(findings)));
+    }
+
+    /// Record how a file's frontmatter changed, if it did.
+    pub fn record_patch(&mut self, path: &str, patch: Vec<Operation>) {
+        if !patch.is_empty() {
+            self.patches.push((path.to_owned(), patch));
+        }
+    }
+
+    /// Every finding in the run, alongside the path it was found in.
+    fn findings(&self) -> impl Iterator<Item = (&str, &Finding)> {
+        self.files.iter().flat_map(|(path, outcome)| {
+            let findings = match outcome {
+                Outcome::Failed(findings) => findings.as_slice(),
+                _ => &[],
+            };
+            findings.iter().map(move |finding| (path.as_str(), finding))
+        })
+    }
+
+    pub fn render(&self, format: ReportFormat) -> eyre::Result<Vec<u8>> {
+        match format {
+            ReportFormat::Github => Ok(self.render_github().into_bytes()),
+            ReportFormat::Gitlab => self.render_gitlab(),
+            ReportFormat::Junit => Ok(self.render_junit().into_bytes()),
+            ReportFormat::Sarif => self.render_sarif(),
+            ReportFormat::Json => self.render_json(),
+            ReportFormat::JsonPatch => self.render_json_patch(),
+        }
+    }
+
+    fn render_json_patch(&self) -> eyre::Result<Vec<u8>> {
+        let patches: serde_json::Map<String, serde_json::Value> = self
+            .patches
+            .iter()
+            .map(|(path, patch)| Ok((path.clone(), serde_json::to_value(patch)?)))
+            .collect::<eyre::Result<_>>()?;
+        let mut json = serde_json::to_vec_pretty(&patches)?;
+        json.push(b'\n');
+        Ok(json)
+    }
+
+    fn render_github(&self) -> String {
+        let mut out = String::new();
+        for (path, finding) in self.findings() {
+            let mut properties = format!("file={}", escape_github_property(path));
+            if let Some(line) = finding.line {
+                properties.push_str(&format!(",line={}", line));
+            }
+            properties.push_str(&format!(",title={}", escape_github_property(finding.rule)));
+            out.push_str(&format!(
+                "::error {}::{}\n",
+                properties,
+                escape_github_data(&finding.message)
+            ));
+        }
+        out
+    }
+
+    fn render_json(&self) -> eyre::Result<Vec<u8>> {
+        #[derive(Serialize)]
+        struct Log<'a> {
+            files: Vec<File<'a>>,
+            summary: Summary,
+        }
+        #[derive(Serialize)]
+        struct File<'a> {
+            path: &'a str,
+            outcome: &'static str,
+            changed: bool,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            reason: Option<&'a str>,
+            #[serde(skip_serializing_if = "<[_]>::is_empty")]
+            findings: &'a [Finding],
+        }
+        #[derive(Default, Serialize)]
+        struct Summary {
+            passed: usize,
+            skipped: usize,
+            failed: usize,
+            changed: usize,
+        }
+
+        let mut summary = Summary::default();
+        let files = self
+            .files
+            .iter()
+            .map(|(path, outcome)| {
+                let file = File {
+                    path,
+                    outcome: "passed",
+                    changed: false,
+                    reason: None,
+                    findings: &[],
+                };
+                match outcome {
+                    Outcome::Passed { changed } => {
+                        summary.passed += 1;
+                        summary.changed += usize::from(*changed);
+                        File {
+                            changed: *changed,
+                            ..file
+                        }
+                    }
+                    Outcome::Skipped(reason) => {
+                        summary.skipped += 1;
+                        File {
+                            outcome: "skipped",
+                            reason: Some(reason.as_str()),
+                            ..file
+                        }
+                    }
+                    Outcome::Failed(findings) => {
+                        summary.failed += 1;
+                        File {
+                            outcome: "failed",
+                            findings,
+                            ..file
+                        }
+                    }
+                }
+            })
+            .collect();
+        let mut json = serde_json::to_vec_pretty(&Log { files, summary })?;
+        json.push(b'\n');
+        Ok(json)
+    }
+
+    fn render_gitlab(&self) -> eyre::Result<Vec<u8>> {
+        #[derive(Serialize)]
+        struct Issue<'a> {
+            description: &'a str,
+            check_name: &'a str,
+            fingerprint: String,
+            severity: &'static str,
+            location: Location<'a>,
+        }
+        #[derive(Serialize)]
+        struct Location<'a> {
+            path: &'a str,
+            lines: Lines,
+        }
+        #[derive(Serialize)]
+        struct Lines {
+            begin: usize,
+        }
+
+        let issues: Vec<Issue> = self
+            .findings()
+            .map(|(path, finding)| Issue {
+                description: &finding.message,
+                check_name: finding.rule,
+                fingerprint: hash::sha256_hex(
+                    format!("{}\0{}\0{}", path, finding.rule, finding.message).as_bytes(),
+                ),
+                severity: "major",
+                location: Location {
+                    path,
+                    lines: Lines {
+                        begin: finding.line.unwrap_or(1),
+                    },
+                },
+            })
+            .collect();
+        let mut json = serde_json::to_vec_pretty(&issues)?;
+        json.push(b'\n');
+        Ok(json)
+    }
+
+    fn render_sarif(&self) -> eyre::Result<Vec<u8>> {
+        #[derive(Serialize)]
+        struct Log<'a> {
+            #[serde(rename = "$schema")]
+            schema: &'static str,
+            version: &'static str,
+            runs: [Run<'a>; 1],
+        }
+        #[derive(Serialize)]
+        struct Run<'a> {
+            tool: Tool<'a>,
+            results: Vec<SarifResult<'a>>,
+        }
+        #[derive(Serialize)]
+        struct Tool<'a> {
+            driver: Driver<'a>,
+        }
+        #[derive(Serialize)]
+        struct Driver<'a> {
+            name: &'static str,
+            version: &'static str,
+            rules: Vec<Rule<'a>>,
+        }
+        #[derive(Serialize)]
+        struct Rule<'a> {
+            id: &'a str,
+        }
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct SarifResult<'a> {
+            rule_id: &'a str,
+            level: &'static str,
+            message: Message<'a>,
+            locations: [Location<'a>; 1],
+        }
+        #[derive(Serialize)]
+        struct Message<'a> {
+            text: &'a str,
+        }
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Location<'a> {
+            physical_location: PhysicalLocation<'a>,
+        }
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct PhysicalLocation<'a> {
+            artifact_location: ArtifactLocation<'a>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            region: Option<Region>,
+        }
+        #[derive(Serialize)]
+        struct ArtifactLocation<'a> {
+            uri: &'a str,
+        }
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Region {
+            start_line: usize,
+        }
+
+        let mut rule_ids: Vec<&str> = self.findings().map(|(_, finding)| finding.rule).collect();
+        rule_ids.sort_unstable();
+        rule_ids.dedup();
+        let results = self
+            .findings()
+            .map(|(path, finding)| SarifResult {
+                rule_id: finding.rule,
+                level: "error",
+                message: Message {
+                    text: &finding.message,
+                },
+                locations: [Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: ArtifactLocation { uri: path },
+                        region: finding.line.map(|start_line| Region { start_line }),
+                    },
+                }],
+            })
+            .collect();
+        let log = Log {
+            schema: "https://json.schemastore.org/sarif-2.1.0.json",
+            version: "2.1.0",
+            runs: [Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: env!("CARGO_PKG_NAME"),
+                        version: env!("CARGO_PKG_VERSION"),
+                        rules: rule_ids.into_iter().map(|id| Rule { id }).collect(),
+                    },
+                },
+                results,
+            }],
+        };
+        let mut json = serde_json::to_vec_pretty(&log)?;
+        json.push(b'\n');
+        Ok(json)
+    }
+
+    fn render_junit(&self) -> String {
+        let count = |wanted: fn(&Outcome) -> bool| {
+            self.files
+                .iter()
+                .filter(|(_, outcome)| wanted(outcome))
+                .count()
+        };
+        let tests = self.files.len();
+        let failures = count(|outcome| matches!(outcome, Outcome::Failed(_)));
+        let skipped = count(|outcome| matches!(outcome, Outcome::Skipped(_)));
+        let counts = format!(
+            "tests=\"{}\" failures=\"{}\" skipped=\"{}\"",
+            tests, failures, skipped
+        );
+
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(&format!("<testsuites {}>\n", counts));
+        out.push_str(&format!(
+            "  <testsuite name=\"frontmatter-fixer\" {}>\n",
+            counts
+        ));
+        for (path, outcome) in &self.files {
+            let open = format!(
+                "    <testcase classname=\"frontmatter-fixer\" name=\"{}\"",
+                escape_xml(path)
+            );
+            match outcome {
+                Outcome::Passed { .. } => out.push_str(&format!("{}/>\n", open)),
+                Outcome::Skipped(reason) => out.push_str(&format!(
+                    "{}>\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                    open,
+                    escape_xml(reason)
+                )),
+                Outcome::Failed(findings) => {
+                    out.push_str(&format!("{}>\n", open));
+                    for finding in findings {
+                        let location = finding
+                            .line
+                            .map(|line| format!("{}:{}: ", path, line))
+                            .unwrap_or_default();
+                        out.push_str(&format!(
+                            "      <failure type=\"{}\" message=\"{}\">{}{}</failure>\n",
+                            finding.rule,
+                            escape_xml(&finding.message),
+                            escape_xml(&location),
+                            escape_xml(&finding.message)
+                        ));
+                    }
+                    out.push_str("    </testcase>\n");
+                }
+            }
+        }
+        out.push_str("  </testsuite>\n</testsuites>\n");
+        out
+    }
+}
+
+fn escape_github_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_github_property(s: &str) -> String {
+    escape_github_data(s)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+pub(crate) fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn example_report() -> Report {
+        let mut report = Report::default();
+        let findings = Findings(vec![Finding {
+            line: Some(2),
+            ..Finding::for_key("unknown-key", "tiitle", "key tiitle isn't declared".into())
+        }]);
+        report.record_failure("a.md", &findings.into());
+        report.record_failure("b,c.md", &eyre::eyre!("bad\nyaml"));
+        report.record_success("ok.md", true);
+        report.record_skipped("draft.md", "draft");
+        report
+    }
+
+    #[test]
+    fn parses_report_specs() {
+        assert_eq!(
+            Ok(ReportSpec {
+                format: ReportFormat::Github,
+                path: None
+            }),
+            "github".parse()
+        );
+        assert_eq!(
+            Ok(ReportSpec {
+                format: ReportFormat::Gitlab,
+                path: Some("cq.json".into())
+            }),
+            "gitlab=cq.json".parse()
+        );
+        assert!("teamcity".parse::<ReportSpec>().is_err());
+    }
+
+    #[test]
+    fn renders_github_annotations() -> eyre::Result<()> {
+        let rendered = String::from_utf8(example_report().render(ReportFormat::Github)?)?;
+        assert_eq!(
+            "::error file=a.md,line=2,title=unknown-key::key tiitle isn't declared\n\
+             ::error file=b%2Cc.md,title=processing-error::bad%0Ayaml\n",
+            rendered
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn renders_gitlab_code_quality() -> eyre::Result<()> {
+        let rendered = example_report().render(ReportFormat::Gitlab)?;
+        let json: serde_json::Value = serde_json::from_slice(&rendered)?;
+        assert_eq!("unknown-key", json[0]["check_name"]);
+        assert_eq!("a.md", json[0]["location"]["path"]);
+        assert_eq!(2, json[0]["location"]["lines"]["begin"]);
+        assert_eq!(1, json[1]["location"]["lines"]["begin"]);
+        Ok(())
+    }
+
+    #[test]
+    fn renders_junit_test_cases() -> eyre::Result<()> {
+        let rendered = String::from_utf8(example_report().render(ReportFormat::Junit)?)?;
+        assert!(rendered.contains(r#"<testsuites tests="4" failures="2" skipped="1">"#));
+        assert!(rendered.contains(
+            r#"<failure type="unknown-key" message="key tiitle isn&apos;t declared">a.md:2: "#
+        ));
+        assert!(rendered.contains(r#"<testcase classname="frontmatter-fixer" name="ok.md"/>"#));
+        assert!(rendered.contains(r#"<skipped message="draft"/>"#));
+        Ok(())
+    }
+
+    #[test]
+    fn renders_sarif_results() -> eyre::Result<()> {
+        let rendered = example_report().render(ReportFormat::Sarif)?;
+        let json: serde_json::Value = serde_json::from_slice(&rendered)?;
+        let run = &json["runs"][0];
+        assert_eq!("processing-error", run["tool"]["driver"]["rules"][0]["id"]);
+        assert_eq!("unknown-key", run["tool"]["driver"]["rules"][1]["id"]);
+        let location = &run["results"][0]["locations"][0]["physicalLocation"];
+        assert_eq!("a.md", location["artifactLocation"]["uri"]);
+        assert_eq!(2, location["region"]["startLine"]);
+        assert!(run["results"][1]["locations"][0]["physicalLocation"]["region"].is_null());
+        Ok(())
+    }
+
+    #[test]
+    fn renders_json_outcomes() -> eyre::Result<()> {
+        let rendered = example_report().render(ReportFormat::Json)?;
+        let json: serde_json::Value = serde_json::from_slice(&rendered)?;
+        assert_eq!(
+            serde_json::json!({"passed": 1, "skipped": 1, "failed": 2, "changed": 1}),
+            json["summary"]
+        );
+        let a = &json["files"][0];
+        assert_eq!("failed", a["outcome"]);
+        assert_eq!("tiitle", a["findings"][0]["key"]);
+        assert_eq!(2, a["findings"][0]["line"]);
+        assert_eq!(
+            serde_json::json!({"path": "ok.md", "outcome": "passed", "changed": true}),
+            json["files"][2]
+        );
+        assert_eq!("draft", json["files"][3]["reason"]);
+        Ok(())
+    }
+
+    #[test]
+    fn renders_json_patches() -> eyre::Result<()> {
+        let mut report = example_report();
+        report.record_patch(
+            "ok.md",
+            vec![Operation::Remove {
+                path: "/draft".into(),
+            }],
+        );
+        report.record_patch("draft.md", Vec::new());
+        let rendered = report.render(ReportFormat::JsonPatch)?;
+        let json: serde_json::Value = serde_json::from_slice(&rendered)?;
+        assert_eq!(
+            serde_json::json!({"ok.md": [{"op": "remove", "path": "/draft"}]}),
+            json
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn locates_keys_in_frontmatter() {
+        let findings = Findings(vec![Finding::for_key("unknown-key", "tiitle", "".into())]);
+        let located = findings.locate_keys("---\ntitle: a\ntiitle: b\n---\n", 0);
+        assert_eq!(Some(3), located.0[0].line);
+    }
+}
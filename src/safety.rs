@@ -0,0 +1,97 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde_yaml as yaml;
+
+/// A change that loses something from a file, which is only made with
+/// `--yes` or when confirmed with `--interactive`, and is listed by
+/// `--dry-run`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Destructive {
+    RemoveKey(String),
+    StripFrontmatter,
+    Move(PathBuf),
+    EditContent,
+    Delete,
+}
+
+impl fmt::Display for Destructive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RemoveKey(key) => write!(f, "remove key {}", key),
+            Self::StripFrontmatter => write!(f, "strip frontmatter"),
+            Self::Move(to) => write!(f, "move to {}", to.display()),
+            Self::EditContent => write!(f, "edit content"),
+            Self::Delete => write!(f, "delete file"),
+        }
+    }
+}
+
+/// The destructive parts of changing a file's frontmatter from `original`
+/// to `fixed` and its content from `original_content` to `content`,
+/// moving it to `renamed_to` if given.
+pub fn classify(
+    original: Option<&yaml::Value>,
+    fixed: Option<&yaml::Value>,
+    original_content: &str,
+    content: &str,
+    renamed_to: Option<&Path>,
+) -> Vec<Destructive> {
+    let mut destructive = Vec::new();
+    match (original.and_then(yaml::Value::as_mapping), fixed) {
+        (Some(_), None) => destructive.push(Destructive::StripFrontmatter),
+        (Some(original), Some(fixed)) => destructive.extend(
+            original
+                .keys()
+                .filter(|key| fixed.get(key).is_none())
+                .map(|key| match key.as_str() {
+                    Some(key) => Destructive::RemoveKey(key.to_owned()),
+                    None => Destructive::RemoveKey(format!("{:?}", key)),
+                }),
+        ),
+        (None, _) => {}
+    }
+    if let Some(renamed_to) = renamed_to {
+        destructive.push(Destructive::Move(renamed_to.to_owned()));
+    }
+    if content != original_content {
+        destructive.push(Destructive::EditContent);
+    }
+    destructive
+}
+
+/// Describe `destructive` changes in a list, e.g. for an error.
+pub fn describe(destructive: &[Destructive]) -> String {
+    let changes: Vec<_> = destructive.iter().map(ToString::to_string).collect();
+    changes.join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classifies_destructive_changes() {
+        let original: yaml::Value = yaml::from_str("title: Hi\ndraft: true\n").unwrap();
+        let fixed: yaml::Value = yaml::from_str("title: Hello\n").unwrap();
+        assert_eq!(
+            vec![Destructive::RemoveKey("draft".into())],
+            classify(Some(&original), Some(&fixed), "Body\n", "Body\n", None)
+        );
+        assert_eq!(
+            "strip frontmatter, move to b.md, edit content",
+            describe(&classify(
+                Some(&original),
+                None,
+                "a",
+                "b",
+                Some(Path::new("b.md"))
+            ))
+        );
+        let added: yaml::Value = yaml::from_str("title: Hi\ndraft: true\ntags: []\n").unwrap();
+        assert!(classify(Some(&original), Some(&added), "", "", None).is_empty());
+        assert!(classify(None, Some(&added), "", "", None).is_empty());
+    }
+}
@@ -0,0 +1,355 @@
+use std::{fs::read_to_string, path::Path};
+
+use eyre::{eyre, Context};
+use serde_yaml as yaml;
+
+use crate::{
+    coerce::{type_name, Kind},
+    date::Date,
+    report::{Finding, Findings},
+    spelling,
+};
+
+/// The frontmatter keys a corpus is expected to use, loaded from a YAML
+/// file mapping each key to its declaration.
+///
+/// A declaration is a type (`string`, `date`, `bool`, `int`, `float`,
+/// `list` or `list of TYPEs`), optionally followed by `, required`; or a
+/// mapping with any of `type`, `required: true` and `values`, a list of the
+/// values allowed (or allowed items, for lists). A null declaration allows
+/// anything.
+#[derive(Debug)]
+pub struct Schema {
+    declarations: Vec<(String, Declaration)>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Declaration {
+    kind: Option<Kind>,
+    /// For lists, the type of each item.
+    item_kind: Option<Kind>,
+    required: bool,
+    /// If not empty, the only values allowed.
+    values: Vec<yaml::Value>,
+}
+
+impl Schema {
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let source = read_to_string(path)
+            .context(format!("couldn't read schema file {}", path.display()))?;
+        Self::parse(&source).context(format!("invalid schema file {}", path.display()))
+    }
+
+    pub fn parse(source: &str) -> eyre::Result<Self> {
+        let declarations: yaml::Mapping =
+            yaml::from_str(source).context("schema must be a mapping of key declarations")?;
+        let declarations = declarations
+            .iter()
+            .map(|(key, declaration)| {
+                let key = key
+                    .as_str()
+                    .ok_or_else(|| eyre!("schema keys must be strings, got {:?}", key))?;
+                let declaration = Declaration::parse(declaration)
+                    .context(format!("invalid declaration for {}", key))?;
+                Ok((key.to_owned(), declaration))
+            })
+            .collect::<eyre::Result<_>>()?;
+        Ok(Self { declarations })
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.declarations.iter().map(|(key, _)| key.as_str())
+    }
+
+    /// Top-level keys in `metadata` that the schema doesn't declare, each
+    /// with a suggested declared key if it looks like a typo.
+    pub fn unknown_keys(&self, metadata: &yaml::Value) -> Vec<(String, Option<&str>)> {
+        let mapping = match metadata.as_mapping() {
+            Some(mapping) => mapping,
+            None => return Vec::new(),
+        };
+        mapping
+            .keys()
+            .map(|key| match key.as_str() {
+                Some(key) => key.to_owned(),
+                None => format!("{:?}", key),
+            })
+            .filter(|key| !self.keys().any(|declared| declared == key))
+            .map(|key| {
+                let suggestion = spelling::closest(&key, self.keys());
+                (key, suggestion)
+            })
+            .collect()
+    }
+
+    /// Fail if `metadata` has keys the schema doesn't declare.
+    pub fn deny_unknown_keys(&self, metadata: &yaml::Value) -> Result<(), Findings> {
+        into_result(self.unknown_key_findings(metadata))
+    }
+
+    /// Fail if `metadata` is missing required keys, or has keys with the
+    /// wrong type or a value that isn't allowed, or with `deny_unknown_keys`
+    /// has keys the schema doesn't declare.
+    pub fn validate(
+        &self,
+        metadata: &yaml::Value,
+        deny_unknown_keys: bool,
+    ) -> Result<(), Findings> {
+        let mut findings = Vec::new();
+        for (key, declaration) in &self.declarations {
+            match metadata.get(key.as_str()) {
+                None | Some(yaml::Value::Null) => {
+                    if declaration.required {
+                        let message = format!("required key {} is missing", key);
+                        findings.push(Finding::for_key("missing-required-key", key, message));
+                    }
+                }
+                Some(value) => findings.extend(declaration.check(key, value)),
+            }
+        }
+        if deny_unknown_keys {
+            findings.extend(self.unknown_key_findings(metadata));
+        }
+        into_result(findings)
+    }
+
+    fn unknown_key_findings(&self, metadata: &yaml::Value) -> Vec<Finding> {
+        self.unknown_keys(metadata)
+            .into_iter()
+            .map(|(key, suggestion)| {
+                let message = match suggestion {
+                    Some(suggestion) => format!(
+                        "key {} isn't declared in schema (did you mean {}?)",
+                        key, suggestion
+                    ),
+                    None => format!("key {} isn't declared in schema", key),
+                };
+                Finding::for_key("unknown-key", &key, message)
+            })
+            .collect()
+    }
+}
+
+impl Declaration {
+    fn parse(declaration: &yaml::Value) -> eyre::Result<Self> {
+        match declaration {
+            yaml::Value::Null => Ok(Self::default()),
+            yaml::Value::String(declaration) => Self::parse_str(declaration),
+            yaml::Value::Mapping(mapping) => {
+                let mut parsed = match mapping.get("type") {
+                    Some(yaml::Value::String(kind)) => Self::parse_str(kind)?,
+                    Some(other) => return Err(eyre!("type must be a string, got {:?}", other)),
+                    None => Self::default(),
+                };
+                if let Some(required) = mapping.get("required") {
+                    parsed.required |= required
+                        .as_bool()
+                        .ok_or_else(|| eyre!("required must be true or false"))?;
+                }
+                if let Some(values) = mapping.get("values") {
+                    parsed.values = values
+                        .as_sequence()
+                        .ok_or_else(|| eyre!("values must be a list"))?
+                        .clone();
+                }
+                Ok(parsed)
+            }
+            other => Err(eyre!("expected a type or a mapping, got {:?}", other)),
+        }
+    }
+
+    /// Parse e.g. `list of strings, required`.
+    fn parse_str(declaration: &str) -> eyre::Result<Self> {
+        let parse_kind = |kind: &str| kind.parse::<Kind>().map_err(|e| eyre!(e));
+        let mut parsed = Self::default();
+        for part in declaration.split(',').map(str::trim) {
+            match part {
+                "" | "any" => {}
+                "required" => parsed.required = true,
+                _ => match part.strip_prefix("list of ") {
+                    Some(item_kind) => {
+                        let item_kind = item_kind.trim();
+                        parsed.kind = Some(Kind::List);
+                        parsed.item_kind = Some(parse_kind(
+                            item_kind.strip_suffix('s').unwrap_or(item_kind),
+                        )?);
+                    }
+                    None => parsed.kind = Some(parse_kind(part)?),
+                },
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn check(&self, key: &str, value: &yaml::Value) -> Vec<Finding> {
+        if let Some(kind) = self.kind {
+            if !has_kind(value, kind) {
+                let message = format!("{} should be a {}, not a {}", key, kind, type_name(value));
+                return vec![Finding::for_key("wrong-type", key, message)];
+            }
+        }
+
+        let items = match value {
+            yaml::Value::Sequence(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        let mut findings = Vec::new();
+        if let Some(item_kind) = self.item_kind {
+            for item in items.iter().filter(|item| !has_kind(item, item_kind)) {
+                let message = format!(
+                    "{} should be a list of {}s, but has a {}",
+                    key,
+                    item_kind,
+                    type_name(item)
+                );
+                findings.push(Finding::for_key("wrong-type", key, message));
+            }
+        }
+        if !self.values.is_empty() {
+            for item in items.iter().filter(|item| !self.values.contains(item)) {
+                let allowed: Vec<String> = self.values.iter().map(describe).collect();
+                let message = format!(
+                    "{} isn't an allowed value for {} (expected one of {})",
+                    describe(item),
+                    key,
+                    allowed.join(", ")
+                );
+                findings.push(Finding::for_key("disallowed-value", key, message));
+            }
+        }
+        findings
+    }
+}
+
+fn has_kind(value: &yaml::Value, kind: Kind) -> bool {
+    match kind {
+        Kind::List => value.is_sequence(),
+        Kind::Date => value
+            .as_str()
+            .is_some_and(|date| Date::parse(date).is_some()),
+        Kind::Bool => value.is_bool(),
+        Kind::Int => value.is_i64() || value.is_u64(),
+        Kind::Float => value.is_number(),
+        Kind::String => value.is_string(),
+    }
+}
+
+fn describe(value: &yaml::Value) -> String {
+    match value.as_str() {
+        Some(s) => s.to_owned(),
+        None => yaml::to_string(value)
+            .map(|yaml| yaml.trim_end().to_owned())
+            .unwrap_or_else(|_| format!("{:?}", value)),
+    }
+}
+
+fn into_result(findings: Vec<Finding>) -> Result<(), Findings> {
+    if findings.is_empty() {
+        Ok(())
+    } else {
+        Err(Findings(findings))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCHEMA: &'_ str = "\
+title: string
+date: date
+tags: list
+";
+
+    #[test]
+    fn parses_declared_keys() -> eyre::Result<()> {
+        let schema = Schema::parse(SCHEMA)?;
+        assert_eq!(
+            vec!["title", "date", "tags"],
+            schema.keys().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_declared_keys() -> eyre::Result<()> {
+        let schema = Schema::parse(SCHEMA)?;
+        let meta: yaml::Value = yaml::from_str("title: Hello\ntags: []\n")?;
+        schema.deny_unknown_keys(&meta)?;
+        Ok(())
+    }
+
+    #[test]
+    fn validates_types_required_keys_and_values() -> eyre::Result<()> {
+        let schema = Schema::parse(
+            "\
+title: string, required
+date: date, required
+tags: list of strings
+draft: bool
+status: {values: [draft, published]}
+categories: {type: list, values: [rust, lua]}
+",
+        )?;
+        let meta: yaml::Value =
+            yaml::from_str("title: Hello\ndate: 2023-01-31\ntags: [a]\nstatus: draft\n")?;
+        schema.validate(&meta, false)?;
+
+        let meta: yaml::Value = yaml::from_str(
+            "title: 3\ntags: [a, 2]\ndraft: 'yes'\nstatus: gone\ncategories: [rust, go]\n",
+        )?;
+        let findings = schema
+            .validate(&meta, false)
+            .expect_err("invalid frontmatter should fail");
+        let rules: Vec<_> = findings.0.iter().map(|finding| finding.rule).collect();
+        assert_eq!(
+            vec![
+                "wrong-type",
+                "missing-required-key",
+                "wrong-type",
+                "wrong-type",
+                "disallowed-value",
+                "disallowed-value"
+            ],
+            rules
+        );
+        assert_eq!(
+            "title should be a string, not a int; \
+             required key date is missing; \
+             tags should be a list of strings, but has a int; \
+             draft should be a bool, not a string; \
+             gone isn't an allowed value for status (expected one of draft, published); \
+             go isn't an allowed value for categories (expected one of rust, lua)",
+            findings.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        assert!(Schema::parse("title: text\n").is_err());
+        assert!(Schema::parse("title: {required: maybe}\n").is_err());
+    }
+
+    #[test]
+    fn reports_unknown_keys_with_suggestions() -> eyre::Result<()> {
+        let schema = Schema::parse(SCHEMA)?;
+        let meta: yaml::Value = yaml::from_str("tiitle: Hello\nauthor: Bob\n")?;
+        assert_eq!(
+            vec![
+                ("tiitle".to_owned(), Some("title")),
+                ("author".to_owned(), None)
+            ],
+            schema.unknown_keys(&meta)
+        );
+        let err = schema
+            .deny_unknown_keys(&meta)
+            .expect_err("unknown keys should fail");
+        assert_eq!(
+            "key tiitle isn't declared in schema (did you mean title?); \
+             key author isn't declared in schema",
+            err.to_string()
+        );
+        Ok(())
+    }
+}
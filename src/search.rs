@@ -0,0 +1,289 @@
+use std::{fs::read_to_string, str::FromStr};
+
+use eyre::Context;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    frontmatter, helpers,
+    walk::{self, Filter},
+};
+
+/// Formats a search index can be exported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// A JSON array of documents, as Lunr's index builder takes them.
+    Lunr,
+    /// A JSON array of documents, with ids Meilisearch accepts.
+    Meilisearch,
+    /// Elasticsearch bulk API NDJSON, an index action before each document.
+    Elasticsearch,
+}
+
+impl FromStr for IndexFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lunr" => Ok(Self::Lunr),
+            "meilisearch" => Ok(Self::Meilisearch),
+            "elasticsearch" => Ok(Self::Elasticsearch),
+            _ => Err(format!(
+                "unknown index format {:?}, expected lunr, meilisearch or elasticsearch",
+                s
+            )),
+        }
+    }
+}
+
+/// A `--field NAME=KEY` mapping frontmatter key KEY to document field NAME.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldMapping {
+    pub name: String,
+    pub key: String,
+}
+
+impl FromStr for FieldMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, key) = s.split_once('=').unwrap_or((s, s));
+        if name.is_empty() || key.is_empty() {
+            return Err(format!("expected NAME=KEY or KEY, got {:?}", s));
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            key: key.to_owned(),
+        })
+    }
+}
+
+/// Build a search document for each file in `paths`, searching any
+/// directories recursively: an `id` (the path), the mapped frontmatter
+/// fields the file has, and its body as plain text under `content_field`.
+pub fn documents(
+    paths: &[String],
+    fields: &[FieldMapping],
+    content_field: &str,
+) -> eyre::Result<Vec<Map<String, Value>>> {
+    let mut documents = Vec::new();
+    for path in walk::expand(paths, &Filter::default())? {
+        let source = read_to_string(&path).context(format!("couldn't read {}", path))?;
+        let (metadata, content) = frontmatter::parse(&source);
+        let metadata = metadata
+            .transpose()
+            .context(format!("couldn't parse frontmatter in {}", path))?;
+
+        let mut document = Map::new();
+        document.insert("id".to_owned(), Value::String(path.clone()));
+        for field in fields {
+            if let Some(value) = metadata.as_ref().and_then(|m| m.get(&field.key)) {
+                let value = serde_json::to_value(value)
+                    .context(format!("can't index {} in {}", field.key, path))?;
+                document.insert(field.name.clone(), value);
+            }
+        }
+        document.insert(content_field.to_owned(), Value::String(plain_text(content)));
+        documents.push(document);
+    }
+    Ok(documents)
+}
+
+/// Render documents for the index `index_name` (used by Elasticsearch).
+pub fn render(
+    format: IndexFormat,
+    index_name: &str,
+    mut documents: Vec<Map<String, Value>>,
+) -> eyre::Result<String> {
+    match format {
+        IndexFormat::Lunr => Ok(serde_json::to_string_pretty(&documents)? + "\n"),
+        IndexFormat::Meilisearch => {
+            // ids may only contain letters, digits, hyphens and underscores
+            for document in &mut documents {
+                if let Some(Value::String(id)) = document.get("id") {
+                    let id = helpers::slugify(id);
+                    document.insert("id".to_owned(), Value::String(id));
+                }
+            }
+            Ok(serde_json::to_string_pretty(&documents)? + "\n")
+        }
+        IndexFormat::Elasticsearch => {
+            let mut out = String::new();
+            for mut document in documents {
+                let id = document.remove("id").unwrap_or(Value::Null);
+                let action = json!({"index": {"_index": index_name, "_id": id}});
+                out.push_str(&serde_json::to_string(&action)?);
+                out.push('\n');
+                out.push_str(&serde_json::to_string(&document)?);
+                out.push('\n');
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// The words of a Markdown document without its markup, on one line.
+pub fn plain_text(markdown: &str) -> String {
+    let mut words = Vec::new();
+    let mut in_fence = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        let text = if in_fence {
+            trimmed.to_owned()
+        } else {
+            strip_inline(strip_block_markers(trimmed))
+        };
+        words.extend(text.split_whitespace().map(str::to_owned));
+    }
+    words.join(" ")
+}
+
+/// Remove heading, quote and list markers, and lines that are only rules.
+fn strip_block_markers(line: &str) -> &str {
+    let is_rule = line.len() >= 3
+        && ['-', '*', '_', '=']
+            .iter()
+            .any(|c| line.chars().all(|l| l == *c || l == ' '));
+    if is_rule {
+        return "";
+    }
+    let mut line = line;
+    loop {
+        let stripped = line
+            .strip_prefix('>')
+            .or_else(|| line.strip_prefix(|c| c == '-' || c == '*' || c == '+'))
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+            .or_else(|| {
+                let hashes = line.len() - line.trim_start_matches('#').len();
+                (hashes > 0 && hashes <= 6).then(|| &line[hashes..])
+            })
+            .or_else(|| {
+                let digits =
+                    line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                (digits > 0)
+                    .then(|| line[digits..].strip_prefix(['.', ')']))
+                    .flatten()
+                    .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+            });
+        match stripped {
+            Some(rest) => line = rest.trim_start(),
+            None => return line,
+        }
+    }
+}
+
+/// Replace links and images with their text, and drop emphasis, code and
+/// HTML tags.
+fn strip_inline(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '*' | '`' | '~' => rest = &rest[1..],
+            '!' if rest[1..].starts_with('[') => rest = &rest[1..],
+            '[' => match link_text(rest) {
+                Some((text, after)) => {
+                    out.push_str(text);
+                    rest = after;
+                }
+                None => {
+                    out.push('[');
+                    rest = &rest[1..];
+                }
+            },
+            '<' if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') => {
+                match rest.find('>') {
+                    Some(end) => {
+                        out.push(' ');
+                        rest = &rest[end + 1..];
+                    }
+                    None => {
+                        out.push('<');
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            c => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+/// For `[text](url)` at the start of `s`, the text and what follows.
+fn link_text(s: &str) -> Option<(&str, &str)> {
+    let close = s.find(']')?;
+    let after = s[close + 1..].strip_prefix('(')?;
+    let end = after.find(')')?;
+    Some((&s[1..close], &after[end + 1..]))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::write;
+
+    use super::*;
+
+    #[test]
+    fn strips_markdown() {
+        let markdown = "\
+# A *big* title
+
+> Some [linked](https://example.com) text with `code`,
+> an ![image](a.png) and <em>HTML</em>.
+
+- one
+2. two
+
+---
+```rust
+let x = 1;
+```
+";
+        assert_eq!(
+            "A big title Some linked text with code, an image and HTML . one two let x = 1;",
+            plain_text(markdown)
+        );
+    }
+
+    #[test]
+    fn strips_numbered_list_markers() {
+        assert_eq!("twelve", strip_block_markers("12. twelve"));
+        assert_eq!("three", strip_block_markers("3) three"));
+        assert_eq!("", strip_block_markers("4."));
+        assert_eq!("1.5 litres", strip_block_markers("1.5 litres"));
+        assert_eq!("2024 was a year", strip_block_markers("2024 was a year"));
+    }
+
+    #[test]
+    fn exports_documents() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("a.md");
+        write(&path, "---\ntitle: Hello\ntags: [a, b]\n---\nSome *text*\n")?;
+        let path = path.display().to_string();
+        let fields: Vec<FieldMapping> = ["title", "keywords=tags"]
+            .iter()
+            .map(|field| field.parse().unwrap())
+            .collect();
+        let documents = documents(std::slice::from_ref(&path), &fields, "body")?;
+        assert_eq!(
+            json!([{"id": path, "title": "Hello", "keywords": ["a", "b"], "body": "Some text"}]),
+            Value::from(documents.clone())
+        );
+
+        let bulk = render(IndexFormat::Elasticsearch, "posts", documents)?;
+        let lines: Vec<Value> = bulk
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(json!({"index": {"_index": "posts", "_id": path}}), lines[0]);
+        assert_eq!("Hello", lines[1]["title"]);
+        assert!(lines[1].get("id").is_none());
+        Ok(())
+    }
+}
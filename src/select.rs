@@ -0,0 +1,796 @@
+use std::{cmp::Ordering, fs::read_to_string, io::Write, iter, str::FromStr};
+
+use eyre::Context;
+use serde_json::{Map, Value};
+use serde_yaml as yaml;
+
+use crate::{
+    feed::scalar,
+    frontmatter, keypath,
+    query::{self, Condition, Op},
+    walk::{self, Filter},
+};
+
+/// The table a `SELECT` reads from: a row for each file.
+const TABLE: &str = "files";
+
+/// The column holding each file's path, alongside its frontmatter keys.
+const PATH_COLUMN: &str = "path";
+
+const KEYWORDS: [&str; 15] = [
+    "SELECT", "FROM", "WHERE", "AND", "OR", "NOT", "ORDER", "BY", "ASC", "DESC", "LIMIT", "AS",
+    "IS", "NULL", "LIKE",
+];
+
+/// How the `query` and `get` subcommands print the rows they select.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryFormat {
+    /// Aligned columns under a header, for reading.
+    Table,
+    /// Tab-separated values under a header, for other tools.
+    Tsv,
+    /// Comma-separated values under a header, quoted as spreadsheets
+    /// expect.
+    Csv,
+    /// A JSON array with an object for each row.
+    Json,
+}
+
+impl FromStr for QueryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "tsv" => Ok(Self::Tsv),
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown query format {:?}, expected table, tsv, csv or json",
+                s
+            )),
+        }
+    }
+}
+
+/// A `SELECT` in the `query` subcommand's query language, over a table
+/// `files` with a row for each file: its `path`, and a column for each
+/// frontmatter key, with dotted paths like `author.name` reaching into
+/// nested keys.
+///
+/// The language isn't SQL, only a limited query language borrowing its
+/// syntax: `SELECT` columns (or `*`) with `AS`
+/// aliases, `WHERE` conditions combined with `AND`, `OR`, `NOT` and
+/// parentheses, `ORDER BY` with `ASC` or `DESC`, and `LIMIT`. Conditions
+/// compare a column to a literal with `=`, `!=`, `<>`, `<`, `<=`, `>` or
+/// `>=` just as `--where` does, so lists match if any element does and
+/// dates compare as dates; `IS [NOT] NULL` tests whether a key has a value,
+/// and `[NOT] LIKE` matches text with `%` and `_` wildcards, ignoring case.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Select {
+    /// `None` for `*`.
+    columns: Option<Vec<Column>>,
+    filter: Option<Expr>,
+    /// Each column to sort by, and whether in descending order.
+    order_by: Vec<(String, bool)>,
+    limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Column {
+    key: String,
+    alias: Option<String>,
+}
+
+impl Column {
+    fn name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.key)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Condition),
+    IsNull(String),
+    Like(String, String),
+}
+
+impl Expr {
+    fn matches(&self, path: &str, metadata: Option<&yaml::Value>) -> bool {
+        match self {
+            Self::And(a, b) => a.matches(path, metadata) && b.matches(path, metadata),
+            Self::Or(a, b) => a.matches(path, metadata) || b.matches(path, metadata),
+            Self::Not(expr) => !expr.matches(path, metadata),
+            Self::Compare(condition) => condition.matches(Some(&with_path(path, metadata))),
+            Self::IsNull(key) => {
+                matches!(lookup(path, metadata, key), None | Some(yaml::Value::Null))
+            }
+            Self::Like(key, pattern) => match lookup(path, metadata, key) {
+                Some(yaml::Value::Sequence(items)) => items
+                    .iter()
+                    .filter_map(scalar)
+                    .any(|item| like(&item, pattern)),
+                Some(value) => scalar(&value).is_some_and(|value| like(&value, pattern)),
+                None => false,
+            },
+        }
+    }
+}
+
+/// The frontmatter with the file's path added under `path`, so conditions
+/// can test it like any other key.
+fn with_path(path: &str, metadata: Option<&yaml::Value>) -> yaml::Value {
+    let mut mapping = match metadata {
+        Some(yaml::Value::Mapping(mapping)) => mapping.clone(),
+        _ => yaml::Mapping::new(),
+    };
+    mapping.insert(PATH_COLUMN.into(), path.into());
+    yaml::Value::Mapping(mapping)
+}
+
+/// The value of column `key` in a file's row.
+fn lookup(path: &str, metadata: Option<&yaml::Value>, key: &str) -> Option<yaml::Value> {
+    if key == PATH_COLUMN {
+        return Some(path.into());
+    }
+    metadata
+        .and_then(|metadata| keypath::get(metadata, key))
+        .cloned()
+}
+
+/// Whether `text` matches a LIKE `pattern`, where `%` matches any run of
+/// characters and `_` any one, ignoring case.
+///
+/// Goes through the pattern once, tracking which prefixes of the text the
+/// pattern so far can match, so it takes time proportional to the lengths
+/// multiplied, however many `%`s there are.
+fn like(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    // matched[i]: whether the pattern so far matches the first i chars
+    let mut matched = vec![false; text.len() + 1];
+    matched[0] = true;
+    for expected in pattern.to_lowercase().chars() {
+        if expected == '%' {
+            // once a prefix matches, so does every longer one
+            if let Some(first) = matched.iter().position(|&m| m) {
+                matched[first..].fill(true);
+            }
+        } else {
+            for i in (0..text.len()).rev() {
+                matched[i + 1] = matched[i] && (expected == '_' || text[i] == expected);
+            }
+            matched[0] = false;
+        }
+    }
+    matched[text.len()]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    /// A `"quoted"` identifier, for keys that aren't plain words.
+    Quoted(String),
+    Text(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Self::Word(word) | Self::Number(word) => word.clone(),
+            Self::Quoted(name) => format!("\"{}\"", name),
+            Self::Text(text) => format!("'{}'", text),
+            Self::Symbol(symbol) => symbol.to_string(),
+        }
+    }
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    const SYMBOLS: [&str; 12] = [
+        "<=", ">=", "!=", "<>", "=", "<", ">", ",", "*", "(", ")", ";",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = sql.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if c == '\'' || c == '"' {
+            // a doubled quote stands for the quote itself
+            let mut text = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, next)) if next == c => {
+                        if rest[1 + i + 1..].starts_with(c) {
+                            text.push(c);
+                            chars.next();
+                        } else {
+                            break 1 + i + 1;
+                        }
+                    }
+                    Some((_, next)) => text.push(next),
+                    None => return Err(format!("unterminated {} in {:?}", c, sql)),
+                }
+            };
+            tokens.push(if c == '\'' {
+                Token::Text(text)
+            } else {
+                Token::Quoted(text)
+            });
+            rest = &rest[end..];
+        } else if c.is_ascii_digit() || c == '-' || c == '.' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(rest[..end].to_owned()));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.' || c == '-'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_owned()));
+            rest = &rest[end..];
+        } else {
+            return Err(format!("unexpected {:?} in {:?}", c, sql));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(token) => format!("expected {}, got {}", expected, token.describe()),
+            None => format!("expected {}, got the end", expected),
+        }
+    }
+
+    /// Consume `keyword` if it's next.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.next += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    /// Consume `symbol` if it's next.
+    fn symbol(&mut self, symbol: &'static str) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let name = match self.peek() {
+            Some(Token::Word(word))
+                if !KEYWORDS
+                    .iter()
+                    .any(|keyword| word.eq_ignore_ascii_case(keyword)) =>
+            {
+                word.clone()
+            }
+            Some(Token::Quoted(name)) => name.clone(),
+            _ => return Err(self.unexpected("a column name")),
+        };
+        self.next += 1;
+        Ok(name)
+    }
+
+    fn select(&mut self) -> Result<Select, String> {
+        self.expect_keyword("SELECT")?;
+        let columns = if self.symbol("*") {
+            None
+        } else {
+            let mut columns = Vec::new();
+            loop {
+                let key = self.name()?;
+                let alias = if self.keyword("AS") {
+                    Some(self.name()?)
+                } else {
+                    None
+                };
+                columns.push(Column { key, alias });
+                if !self.symbol(",") {
+                    break Some(columns);
+                }
+            }
+        };
+        self.expect_keyword("FROM")?;
+        if !self.keyword(TABLE) {
+            return Err(self.unexpected(TABLE));
+        }
+        let filter = if self.keyword("WHERE") {
+            Some(self.or()?)
+        } else {
+            None
+        };
+        let mut order_by = Vec::new();
+        if self.keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let key = self.name()?;
+                let descending = self.keyword("DESC");
+                if !descending {
+                    self.keyword("ASC");
+                }
+                order_by.push((key, descending));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = if self.keyword("LIMIT") {
+            match self.peek() {
+                Some(Token::Number(number)) => {
+                    let limit = number
+                        .parse()
+                        .map_err(|_| format!("invalid LIMIT {}", number))?;
+                    self.next += 1;
+                    Some(limit)
+                }
+                _ => return Err(self.unexpected("a number")),
+            }
+        } else {
+            None
+        };
+        self.symbol(";");
+        if self.peek().is_some() {
+            return Err(self.unexpected("the end"));
+        }
+        Ok(Select {
+            columns,
+            filter,
+            order_by,
+            limit,
+        })
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.symbol("(") {
+            let expr = self.or()?;
+            if !self.symbol(")") {
+                return Err(self.unexpected(")"));
+            }
+            return Ok(expr);
+        }
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> Result<Expr, String> {
+        let key = self.name()?;
+        if self.keyword("IS") {
+            let negated = self.keyword("NOT");
+            self.expect_keyword("NULL")?;
+            let expr = Expr::IsNull(key);
+            return Ok(if negated {
+                Expr::Not(Box::new(expr))
+            } else {
+                expr
+            });
+        }
+        let negated = self.keyword("NOT");
+        if self.keyword("LIKE") {
+            let pattern = match self.peek() {
+                Some(Token::Text(pattern)) => pattern.clone(),
+                _ => return Err(self.unexpected("a 'pattern'")),
+            };
+            self.next += 1;
+            let expr = Expr::Like(key, pattern);
+            return Ok(if negated {
+                Expr::Not(Box::new(expr))
+            } else {
+                expr
+            });
+        }
+        if negated {
+            return Err(self.unexpected("LIKE"));
+        }
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => Op::Eq,
+            Some(Token::Symbol("!=" | "<>")) => Op::Ne,
+            Some(Token::Symbol("<")) => Op::Lt,
+            Some(Token::Symbol("<=")) => Op::Le,
+            Some(Token::Symbol(">")) => Op::Gt,
+            Some(Token::Symbol(">=")) => Op::Ge,
+            _ => return Err(self.unexpected("a comparison")),
+        };
+        self.next += 1;
+        let value = match self.peek() {
+            Some(Token::Text(value) | Token::Number(value)) => value.clone(),
+            Some(Token::Word(word))
+                if word.eq_ignore_ascii_case("true") || word.eq_ignore_ascii_case("false") =>
+            {
+                word.to_lowercase()
+            }
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("NULL") => {
+                return Err("compare with NULL using IS NULL or IS NOT NULL".to_owned())
+            }
+            _ => return Err(self.unexpected("a 'string', number, TRUE or FALSE")),
+        };
+        self.next += 1;
+        Ok(Expr::Compare(Condition::comparing(&key, op, &value)))
+    }
+}
+
+impl FromStr for Select {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser {
+            tokens: tokenize(s)?,
+            next: 0,
+        }
+        .select()
+    }
+}
+
+/// The columns selected and each matching row's value for each, in order.
+pub struct Rows {
+    pub names: Vec<String>,
+    pub rows: Vec<Vec<Option<yaml::Value>>>,
+}
+
+impl Select {
+    /// `SELECT path, KEY... FROM files`.
+    pub fn keys(keys: &[String]) -> Self {
+        let columns = iter::once(PATH_COLUMN)
+            .chain(keys.iter().map(String::as_str))
+            .map(|key| Column {
+                key: key.to_owned(),
+                alias: None,
+            })
+            .collect();
+        Self {
+            columns: Some(columns),
+            filter: None,
+            order_by: Vec::new(),
+            limit: None,
+        }
+    }
+
+    /// Run the query over the files given, and those in any directories
+    /// given and their subdirectories.
+    pub fn run(&self, paths: &[String]) -> eyre::Result<Rows> {
+        let mut documents = Vec::new();
+        for path in walk::expand(paths, &Filter::default())? {
+            let source = read_to_string(&path).context(format!("couldn't read {}", path))?;
+            let metadata = frontmatter::parse(&source)
+                .0
+                .transpose()
+                .context(format!("couldn't parse frontmatter in {}", path))?;
+            documents.push((path, metadata));
+        }
+        Ok(self.query(documents))
+    }
+
+    fn query(&self, documents: Vec<(String, Option<yaml::Value>)>) -> Rows {
+        let mut documents: Vec<_> = documents
+            .into_iter()
+            .filter(|(path, metadata)| {
+                self.filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.matches(path, metadata.as_ref()))
+            })
+            .collect();
+        let columns = match &self.columns {
+            Some(columns) => columns.clone(),
+            None => all_columns(&documents),
+        };
+        let sort_keys: Vec<&str> = self
+            .order_by
+            .iter()
+            .map(|(name, _)| {
+                // ordering by an alias orders by the key it names
+                columns
+                    .iter()
+                    .find(|column| column.alias.as_deref() == Some(name))
+                    .map_or(name.as_str(), |column| column.key.as_str())
+            })
+            .collect();
+        documents.sort_by(|(a_path, a), (b_path, b)| {
+            self.order_by
+                .iter()
+                .zip(&sort_keys)
+                .map(|((_, descending), key)| {
+                    let a = lookup(a_path, a.as_ref(), key).as_ref().and_then(scalar);
+                    let b = lookup(b_path, b.as_ref(), key).as_ref().and_then(scalar);
+                    // files without a value come first
+                    let ordering = match (a, b) {
+                        (Some(a), Some(b)) => query::compare(&a, &b),
+                        (a, b) => a.is_some().cmp(&b.is_some()),
+                    };
+                    if *descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        let rows = documents
+            .iter()
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|(path, metadata)| {
+                columns
+                    .iter()
+                    .map(|column| lookup(path, metadata.as_ref(), &column.key))
+                    .collect()
+            })
+            .collect();
+        Rows {
+            names: columns
+                .iter()
+                .map(|column| column.name().to_owned())
+                .collect(),
+            rows,
+        }
+    }
+}
+
+/// For `*`, the path and every top-level key any of the documents has, in
+/// the order they first appear.
+fn all_columns(documents: &[(String, Option<yaml::Value>)]) -> Vec<Column> {
+    let mut keys = vec![PATH_COLUMN.to_owned()];
+    for (_, metadata) in documents {
+        if let Some(yaml::Value::Mapping(mapping)) = metadata {
+            for key in mapping.keys().filter_map(scalar) {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+    }
+    keys.into_iter()
+        .map(|key| Column { key, alias: None })
+        .collect()
+}
+
+impl Rows {
+    pub fn write(&self, out: &mut impl Write, format: QueryFormat) -> eyre::Result<()> {
+        match format {
+            QueryFormat::Json => {
+                let rows: Vec<Value> = self
+                    .rows
+                    .iter()
+                    .map(|row| -> eyre::Result<Value> {
+                        let mut object = Map::new();
+                        for (name, value) in self.names.iter().zip(row) {
+                            object.insert(name.clone(), serde_json::to_value(value)?);
+                        }
+                        Ok(Value::Object(object))
+                    })
+                    .collect::<eyre::Result<_>>()?;
+                serde_json::to_writer_pretty(&mut *out, &rows)?;
+                writeln!(out)?;
+            }
+            QueryFormat::Tsv => {
+                for row in self.text_rows() {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .map(|cell| cell.replace(['\t', '\n'], " "))
+                        .collect();
+                    writeln!(out, "{}", cells.join("\t"))?;
+                }
+            }
+            QueryFormat::Csv => {
+                for row in self.text_rows() {
+                    let cells: Vec<String> = row.iter().map(|cell| csv_field(cell)).collect();
+                    writeln!(out, "{}", cells.join(","))?;
+                }
+            }
+            QueryFormat::Table => {
+                let rows = self.text_rows();
+                let widths: Vec<usize> = (0..self.names.len())
+                    .map(|column| {
+                        rows.iter()
+                            .map(|row| row[column].chars().count())
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                for row in &rows {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+                        .collect();
+                    writeln!(out, "{}", cells.join("  ").trim_end())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The header and rows as text, lists joined with commas.
+    fn text_rows(&self) -> Vec<Vec<String>> {
+        let text = |value: &Option<yaml::Value>| match value {
+            None | Some(yaml::Value::Null) => String::new(),
+            Some(yaml::Value::Sequence(items)) => items
+                .iter()
+                .map(|item| scalar(item).unwrap_or_else(|| json_text(item)))
+                .collect::<Vec<_>>()
+                .join(", "),
+            Some(value) => scalar(value).unwrap_or_else(|| json_text(value)),
+        };
+        let mut rows = vec![self.names.clone()];
+        rows.extend(self.rows.iter().map(|row| row.iter().map(text).collect()));
+        rows
+    }
+}
+
+/// A CSV field, quoted if it has a comma, quote or line break (RFC 4180).
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_owned()
+    }
+}
+
+fn json_text(value: &yaml::Value) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn documents() -> Vec<(String, Option<yaml::Value>)> {
+        [
+            ("a.md", Some("title: Old\ndate: 2022-06-01\ntags: [rust]\n")),
+            (
+                "b.md",
+                Some("title: New\ndate: March 3, 2023\nauthor: {name: Ann}\n"),
+            ),
+            (
+                "c.md",
+                Some("title: Newer\ndate: 2023-09-09\ndraft: true\n"),
+            ),
+            ("d.md", None),
+        ]
+        .into_iter()
+        .map(|(path, metadata)| {
+            (
+                path.to_owned(),
+                metadata.map(|m| yaml::from_str(m).unwrap()),
+            )
+        })
+        .collect()
+    }
+
+    fn query(sql: &str) -> Vec<Vec<String>> {
+        let select: Select = sql.parse().unwrap();
+        let rows = select.query(documents());
+        rows.text_rows()
+    }
+
+    #[test]
+    fn selects_filters_and_orders() {
+        assert_eq!(
+            vec![
+                vec!["path", "title"],
+                vec!["c.md", "Newer"],
+                vec!["b.md", "New"],
+            ],
+            query(
+                "SELECT path, title FROM files WHERE date > '2023-01-01' \
+                 ORDER BY date DESC"
+            )
+        );
+        assert_eq!(
+            vec![vec!["t"], vec!["Old"]],
+            query("select title as t from files where tags = 'rust' or author.name = 'Bob'")
+        );
+        assert_eq!(
+            vec![vec!["path"], vec!["d.md"], vec!["a.md"]],
+            query("SELECT path FROM files WHERE NOT (title LIKE 'new%') ORDER BY title LIMIT 5;")
+        );
+        assert_eq!(
+            vec![vec!["path"], vec!["a.md"], vec!["b.md"]],
+            query("SELECT path FROM files WHERE draft IS NULL AND title IS NOT NULL")
+        );
+        assert_eq!(
+            vec!["path", "title", "date", "tags", "author", "draft"],
+            query("SELECT * FROM files LIMIT 0")[0]
+        );
+        assert_eq!(
+            vec!["Ann"],
+            query("SELECT author.name FROM files WHERE path = 'b.md'")[1]
+        );
+    }
+
+    #[test]
+    fn gets_keys_as_csv() -> eyre::Result<()> {
+        let rows = Select::keys(&["title".into(), "tags".into(), "author.name".into()])
+            .query(documents()[..2].to_vec());
+        let mut out = Vec::new();
+        rows.write(&mut out, QueryFormat::Csv)?;
+        assert_eq!(
+            "path,title,tags,author.name\na.md,Old,rust,\nb.md,New,,Ann\n",
+            String::from_utf8(out)?
+        );
+        assert_eq!("\"say \"\"hi\"\", then\"", csv_field("say \"hi\", then"));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        let error = |sql: &str| sql.parse::<Select>().unwrap_err();
+        assert_eq!("expected FROM, got the end", error("SELECT title"));
+        assert_eq!(
+            "expected files, got posts",
+            error("SELECT title FROM posts")
+        );
+        assert!(error("SELECT title FROM files WHERE draft = NULL").contains("IS NULL"));
+        assert!(error("SELECT 'x FROM files").contains("unterminated"));
+        assert!("SELECT \"odd key\" FROM files WHERE \"it''s\" != 'it''s'"
+            .parse::<Select>()
+            .is_ok());
+    }
+
+    #[test]
+    fn matches_like_patterns() {
+        assert!(like("Hello, world", "hello%"));
+        assert!(like("cat", "c_t"));
+        assert!(!like("cart", "c_t"));
+        assert!(like("", "%"));
+        assert!(like("a title", "%TITLE"));
+        assert!(like("a title", "%_%t%"));
+        assert!(!like("a title", "%x%"));
+        assert!(!like("", "_"));
+
+        // would backtrack through every way of placing the %s
+        let title = "a".repeat(10_000);
+        assert!(!like(&title, "%a%a%a%a%a%b"));
+        assert!(like(&format!("{}b", title), "%a%a%a%a%a%b"));
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::{self, read_to_string},
+    path::Path,
+};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use serde_yaml as yaml;
+
+use crate::{
+    frontmatter, hash,
+    walk::{self, Filter},
+};
+
+/// How a change shows a key not being set.
+const NO_VALUE: &str = "(none)";
+
+/// Every file's parsed frontmatter and a hash of its body at some moment,
+/// to tell later what a run changed without git.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The files and directories the snapshot was taken of, to compare
+    /// against by default.
+    roots: Vec<String>,
+    files: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    /// `None` if the file has no frontmatter.
+    metadata: Option<yaml::Value>,
+    content_sha256: String,
+}
+
+/// What happened to a file since a snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    /// A top-level frontmatter key's value changed, was set or was removed.
+    Key {
+        path: String,
+        key: String,
+        old: Option<yaml::Value>,
+        new: Option<yaml::Value>,
+    },
+    Content(String),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(path) => write!(f, "{}: added", path),
+            Self::Removed(path) => write!(f, "{}: removed", path),
+            Self::Key {
+                path,
+                key,
+                old,
+                new,
+            } => write!(
+                f,
+                "{}: {}: {} -> {}",
+                path,
+                key,
+                inline(old.as_ref()),
+                inline(new.as_ref())
+            ),
+            Self::Content(path) => write!(f, "{}: content changed", path),
+        }
+    }
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added(path) | Self::Removed(path) | Self::Content(path) => path,
+            Self::Key { path, .. } => path,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Take a snapshot of the files given, and those in any directories
+    /// given and their subdirectories.
+    pub fn create(paths: &[String]) -> eyre::Result<Self> {
+        let files = walk::expand(paths, &Filter::default())?
+            .into_iter()
+            .map(|path| {
+                let entry = Entry::read(&path)?;
+                Ok((path, entry))
+            })
+            .collect::<eyre::Result<_>>()?;
+        Ok(Self {
+            roots: paths.to_vec(),
+            files,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let json =
+            read_to_string(path).context(format!("couldn't read snapshot {}", path.display()))?;
+        serde_json::from_str(&json).context(format!("couldn't parse snapshot {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        let mut json = serde_json::to_vec(self)?;
+        json.push(b'\n');
+        fs::write(path, json).context(format!("couldn't write snapshot {}", path.display()))
+    }
+
+    /// What changed between this snapshot and the files as they are now,
+    /// taking the same files and directories as the snapshot did unless
+    /// `paths` are given.
+    pub fn diff(&self, paths: &[String]) -> eyre::Result<Vec<Change>> {
+        let roots = if paths.is_empty() { &self.roots } else { paths };
+        Ok(self.changes(&Self::create(roots)?))
+    }
+
+    /// The changes from this snapshot to `now`, by path.
+    fn changes(&self, now: &Self) -> Vec<Change> {
+        let paths: BTreeSet<&String> = self.files.keys().chain(now.files.keys()).collect();
+        let mut changes = Vec::new();
+        for path in paths {
+            match (self.files.get(path), now.files.get(path)) {
+                (Some(_), None) => changes.push(Change::Removed(path.clone())),
+                (None, Some(_)) => changes.push(Change::Added(path.clone())),
+                (Some(before), Some(after)) => before.changes(path, after, &mut changes),
+                (None, None) => unreachable!("path came from one of the snapshots"),
+            }
+        }
+        changes
+    }
+}
+
+impl Entry {
+    fn read(path: &str) -> eyre::Result<Self> {
+        let source = read_to_string(path).context(format!("couldn't read {}", path))?;
+        let (metadata, content) = frontmatter::parse(&source);
+        let metadata = metadata
+            .transpose()
+            .context(format!("couldn't parse frontmatter in {}", path))?;
+        Ok(Self {
+            metadata,
+            content_sha256: hash::sha256_hex(content.as_bytes()),
+        })
+    }
+
+    fn changes(&self, path: &str, after: &Self, changes: &mut Vec<Change>) {
+        changes.extend(key_changes(
+            path,
+            self.metadata.as_ref(),
+            after.metadata.as_ref(),
+        ));
+        if self.content_sha256 != after.content_sha256 {
+            changes.push(Change::Content(path.to_owned()));
+        }
+    }
+}
+
+/// How the top-level keys of a file's frontmatter changed from `before` to
+/// `after`, in the order they appear.
+pub fn key_changes(
+    path: &str,
+    before: Option<&yaml::Value>,
+    after: Option<&yaml::Value>,
+) -> Vec<Change> {
+    let before_keys = keys(before);
+    let after_keys = keys(after);
+    let mut seen = BTreeSet::new();
+    let mut changes = Vec::new();
+    for (key, _) in before_keys.iter().chain(&after_keys) {
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+        let old = before_keys.iter().find(|(k, _)| k == key).map(|(_, v)| *v);
+        let new = after_keys.iter().find(|(k, _)| k == key).map(|(_, v)| *v);
+        if old != new {
+            changes.push(Change::Key {
+                path: path.to_owned(),
+                key: key.clone(),
+                old: old.cloned(),
+                new: new.cloned(),
+            });
+        }
+    }
+    changes
+}
+
+/// The top-level keys of frontmatter, in order, with their values; if it
+/// isn't a mapping, the whole of it under the key `.`.
+fn keys(metadata: Option<&yaml::Value>) -> Vec<(String, &yaml::Value)> {
+    match metadata {
+        Some(yaml::Value::Mapping(mapping)) => mapping
+            .iter()
+            .map(|(key, value)| (inline(Some(key)), value))
+            .collect(),
+        Some(other) => vec![(".".to_owned(), other)],
+        None => Vec::new(),
+    }
+}
+
+/// A value on one line: a string as it is, anything else as JSON.
+fn inline(value: Option<&yaml::Value>) -> String {
+    match value {
+        None => NO_VALUE.to_owned(),
+        Some(yaml::Value::String(s)) => s.clone(),
+        Some(value) => serde_json::to_string(value).unwrap_or_else(|_| format!("{:?}", value)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::write;
+
+    use super::*;
+
+    #[test]
+    fn diffs_against_snapshot() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (a, b, c) = (
+            dir.path().join("a.md"),
+            dir.path().join("b.md"),
+            dir.path().join("c.md"),
+        );
+        write(&a, "---\ntitle: Old\ntags: [x]\n---\nBody\n")?;
+        write(&b, "---\ntitle: Gone\n---\n")?;
+        let roots = vec![dir.path().to_string_lossy().into_owned()];
+        let snapshots = tempfile::tempdir()?;
+        let path = snapshots.path().join("meta.snap");
+        Snapshot::create(&roots)?.save(&path)?;
+        let snapshot = Snapshot::load(&path)?;
+        assert_eq!(2, snapshot.len());
+        assert!(snapshot.diff(&[])?.is_empty());
+
+        write(&a, "---\ntitle: New\ntags: [x]\ndraft: true\n---\nEdited\n")?;
+        fs::remove_file(&b)?;
+        write(&c, "New file\n")?;
+        let changes: Vec<String> = snapshot.diff(&[])?.iter().map(|c| c.to_string()).collect();
+        let (a, b, c) = (a.display(), b.display(), c.display());
+        assert_eq!(
+            vec![
+                format!("{}: title: Old -> New", a),
+                format!("{}: draft: (none) -> true", a),
+                format!("{}: content changed", a),
+                format!("{}: removed", b),
+                format!("{}: added", c),
+            ],
+            changes
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+/// How many edits apart two keys can be and still plausibly be a typo.
+pub fn max_typo_distance(word: &str) -> usize {
+    match word.chars().count() {
+        0..=4 => 1,
+        5..=8 => 2,
+        _ => 3,
+    }
+}
+
+/// Find the candidate closest to `word`, if any is close enough to be a
+/// likely typo of it. Exact matches don't count.
+pub fn closest<'c, I>(word: &str, candidates: I) -> Option<&'c str>
+where
+    I: IntoIterator<Item = &'c str>,
+{
+    let max_distance = max_typo_distance(word);
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::osa_distance(word, candidate), candidate))
+        .filter(|&(distance, _)| distance > 0 && distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suggests_close_keys() {
+        let keys = ["title", "date", "categories", "tags"];
+        assert_eq!(Some("title"), closest("tiitle", keys));
+        assert_eq!(Some("categories"), closest("catagories", keys));
+        assert_eq!(Some("tags"), closest("tgas", keys));
+    }
+
+    #[test]
+    fn ignores_distant_and_identical_keys() {
+        let keys = ["title", "date"];
+        assert_eq!(None, closest("author", keys));
+        assert_eq!(None, closest("title", keys));
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use eyre::Context;
+use serde_yaml as yaml;
+use tempfile::NamedTempFile;
+
+use crate::{date, hash};
+
+/// The frontmatter key a stamp is recorded under.
+pub const STAMP_KEY: &str = "fixer";
+
+/// Where to record which pass modified a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StampTarget {
+    /// In a `fixer:` block in the file's own frontmatter.
+    Frontmatter,
+    /// In a YAML manifest mapping each modified path to its stamp.
+    Manifest(PathBuf),
+}
+
+impl FromStr for StampTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "frontmatter" => Ok(Self::Frontmatter),
+            Some(("manifest", path)) if !path.is_empty() => Ok(Self::Manifest(path.into())),
+            _ => Err(format!(
+                "expected frontmatter or manifest=PATH, got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// A record of the tool version and script that processed a file.
+#[derive(Clone, Debug)]
+pub struct Stamp {
+    version: &'static str,
+    script_sha256: Option<String>,
+    timestamp: String,
+}
+
+impl Stamp {
+    pub fn new(script: Option<&str>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            script_sha256: script.map(|script| hash::sha256_hex(script.as_bytes())),
+            timestamp: date::now_timestamp(),
+        }
+    }
+
+    pub fn to_yaml(&self) -> yaml::Value {
+        let mut stamp = yaml::Mapping::new();
+        stamp.insert("version".into(), self.version.into());
+        if let Some(script_sha256) = &self.script_sha256 {
+            stamp.insert("script_sha256".into(), script_sha256.as_str().into());
+        }
+        stamp.insert("timestamp".into(), self.timestamp.as_str().into());
+        yaml::Value::Mapping(stamp)
+    }
+
+    /// Record the stamp in the file's frontmatter, replacing any previous one.
+    pub fn apply(&self, metadata: &mut yaml::Value) {
+        if let Some(mapping) = metadata.as_mapping_mut() {
+            mapping.insert(STAMP_KEY.into(), self.to_yaml());
+        }
+    }
+}
+
+/// A sidecar manifest of stamps, keyed by path.
+#[derive(Debug)]
+pub struct Manifest {
+    path: PathBuf,
+    entries: yaml::Mapping,
+}
+
+impl Manifest {
+    /// Load an existing manifest, or start a new one if it doesn't exist yet.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let entries = match read_to_string(path) {
+            Ok(source) => yaml::from_str(&source)
+                .context(format!("couldn't parse stamp manifest {}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => yaml::Mapping::new(),
+            Err(e) => {
+                return Err(e).context(format!("couldn't read stamp manifest {}", path.display()))
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn record(&mut self, file: &str, stamp: &Stamp) {
+        self.entries.insert(file.into(), stamp.to_yaml());
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self) -> eyre::Result<()> {
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut tmpfile = NamedTempFile::new_in(dir)?;
+        yaml::to_writer(&mut tmpfile, &self.entries).context("couldn't serialize manifest")?;
+        tmpfile
+            .persist(&self.path)
+            .context("couldn't rename tempfile over stamp manifest")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!(Ok(StampTarget::Frontmatter), "frontmatter".parse());
+        assert_eq!(
+            Ok(StampTarget::Manifest("stamps.yaml".into())),
+            "manifest=stamps.yaml".parse()
+        );
+        assert!("manifest=".parse::<StampTarget>().is_err());
+        assert!("sidecar".parse::<StampTarget>().is_err());
+    }
+
+    #[test]
+    fn stamps_frontmatter() {
+        let stamp = Stamp::new(Some(""));
+        let mut meta: yaml::Value = yaml::from_str("title: Hello\n").unwrap();
+        stamp.apply(&mut meta);
+        let fixer = &meta[STAMP_KEY];
+        assert_eq!(Some(env!("CARGO_PKG_VERSION")), fixer["version"].as_str());
+        assert_eq!(
+            Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            fixer["script_sha256"].as_str()
+        );
+        assert!(fixer["timestamp"].is_string());
+    }
+
+    #[test]
+    fn manifest_records_stamps_by_path() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stamps.yaml");
+
+        let mut manifest = Manifest::load(&path)?;
+        manifest.record("a.md", &Stamp::new(None));
+        manifest.save()?;
+
+        let manifest = Manifest::load(&path)?;
+        assert!(manifest.entries.contains_key("a.md"));
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use std::{fs::OpenOptions, io::Write, path::Path, time::Duration};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::date;
+
+/// Aggregates for a single run, appended as one JSON line to a local stats
+/// file so corpus health can be tracked over time.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunStats {
+    pub timestamp: String,
+    pub dry_run: bool,
+    pub files: usize,
+    pub changed: usize,
+    pub failed: usize,
+    pub total_ms: u64,
+    pub mean_file_ms: f64,
+}
+
+impl RunStats {
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            timestamp: date::now_timestamp(),
+            dry_run,
+            ..Self::default()
+        }
+    }
+
+    pub fn record_file(&mut self, elapsed: Duration, changed: bool, failed: bool) {
+        self.files += 1;
+        self.changed += usize::from(changed);
+        self.failed += usize::from(failed);
+        self.total_ms += elapsed.as_millis() as u64;
+        self.mean_file_ms = self.total_ms as f64 / self.files as f64;
+    }
+
+    pub fn append_to(&self, path: &Path) -> eyre::Result<()> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(format!("couldn't open stats file {}", path.display()))?;
+        file.write_all(&line)
+            .context(format!("couldn't append to stats file {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accumulates_file_outcomes() {
+        let mut stats = RunStats::new(false);
+        stats.record_file(Duration::from_millis(10), true, false);
+        stats.record_file(Duration::from_millis(30), false, true);
+        assert_eq!(2, stats.files);
+        assert_eq!(1, stats.changed);
+        assert_eq!(1, stats.failed);
+        assert_eq!(40, stats.total_ms);
+        assert_eq!(20.0, stats.mean_file_ms);
+    }
+
+    #[test]
+    fn appends_one_line_per_run() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stats.jsonl");
+        RunStats::new(false).append_to(&path)?;
+        RunStats::new(true).append_to(&path)?;
+
+        let lines: Vec<RunStats> = std::fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(2, lines.len());
+        assert!(lines[1].dry_run);
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use serde_yaml as yaml;
+
+use crate::keypath;
+
+/// The frontmatter of every file in a corpus, gathered by an aggregating
+/// script's collect pass, for its `fix` function to ask how often keys and
+/// values turn up through `corpus.values` and `corpus.count`.
+#[derive(Clone, Debug, Default)]
+pub struct Tally {
+    documents: Vec<yaml::Value>,
+}
+
+impl Tally {
+    pub fn record(&mut self, metadata: yaml::Value) {
+        self.documents.push(metadata);
+    }
+
+    /// How many files have each value at `path`, a dotted path like
+    /// `author.name`, in the order the values were first seen. A list
+    /// counts for each of its items, but only once for a file however many
+    /// times they're repeated; other mappings and nulls aren't counted.
+    pub fn values(&self, path: &str) -> yaml::Mapping {
+        let mut counts: Vec<(&yaml::Value, u64)> = Vec::new();
+        for value in self
+            .documents
+            .iter()
+            .filter_map(|doc| keypath::get(doc, path))
+        {
+            let items = match value {
+                yaml::Value::Sequence(items) => &items[..],
+                value => std::slice::from_ref(value),
+            };
+            for (i, item) in items.iter().enumerate() {
+                if !is_countable(item) || items[..i].contains(item) {
+                    continue;
+                }
+                match counts.iter_mut().find(|(seen, _)| *seen == item) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((item, 1)),
+                }
+            }
+        }
+        counts
+            .into_iter()
+            .map(|(value, count)| (value.clone(), count.into()))
+            .collect()
+    }
+
+    /// How many files have a value at `path`, or with `value`, the value
+    /// there or an item of the list there.
+    pub fn count(&self, path: &str, value: Option<&yaml::Value>) -> usize {
+        self.documents
+            .iter()
+            .filter_map(|doc| keypath::get(doc, path))
+            .filter(|found| match (value, found) {
+                (None, found) => !found.is_null(),
+                (Some(value), yaml::Value::Sequence(items)) => items.contains(value),
+                (Some(value), found) => *found == value,
+            })
+            .count()
+    }
+}
+
+fn is_countable(value: &yaml::Value) -> bool {
+    matches!(
+        value,
+        yaml::Value::String(_) | yaml::Value::Number(_) | yaml::Value::Bool(_)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_values_across_files() -> eyre::Result<()> {
+        let mut tally = Tally::default();
+        for doc in [
+            "tags: [rust, lua, rust]\ncategory: news\nauthor: {name: Sam}\n",
+            "tags: [lua]\ncategory: news\n",
+            "tags: go\ncategory: ~\nauthor: {name: Ann}\n",
+        ] {
+            tally.record(yaml::from_str(doc)?);
+        }
+        assert_eq!(
+            yaml::from_str::<yaml::Mapping>("rust: 1\nlua: 2\ngo: 1\n")?,
+            tally.values("tags")
+        );
+        assert_eq!(
+            yaml::from_str::<yaml::Mapping>("news: 2\n")?,
+            tally.values("category")
+        );
+        assert_eq!(2, tally.values("author.name").len());
+        assert_eq!(2, tally.count("category", None));
+        assert_eq!(2, tally.count("tags", Some(&"lua".into())));
+        assert_eq!(1, tally.count("author.name", Some(&"Ann".into())));
+        assert_eq!(0, tally.count("missing", None));
+        Ok(())
+    }
+}
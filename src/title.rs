@@ -0,0 +1,325 @@
+use std::{ops::Range, str::FromStr};
+
+use serde_yaml as yaml;
+
+/// Which copy to remove when the body's first heading repeats the title.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dedupe {
+    /// Remove the heading from the body.
+    Heading,
+    /// Remove the `title` key from the frontmatter.
+    Title,
+}
+
+impl FromStr for Dedupe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "heading" => Ok(Self::Heading),
+            "title" => Ok(Self::Title),
+            _ => Err(format!("expected heading or title, got {:?}", s)),
+        }
+    }
+}
+
+/// A level 1 heading found at the start of a Markdown body.
+#[derive(Debug, PartialEq, Eq)]
+struct Heading<'a> {
+    text: &'a str,
+    /// The heading's lines and any blank lines following it.
+    span: Range<usize>,
+}
+
+/// Remove the duplicate if the first thing in `content` is a level 1 heading
+/// matching `metadata`'s title.
+///
+/// Returns the content with the heading removed, if it was.
+pub fn dedupe(metadata: &mut yaml::Value, content: &str, remove: Dedupe) -> Option<String> {
+    let title = metadata.get("title")?.as_str()?;
+    let heading = first_heading(content)?;
+    if normalize(title) != normalize(heading.text) {
+        return None;
+    }
+    match remove {
+        Dedupe::Heading => {
+            let mut deduped = String::with_capacity(content.len());
+            deduped.push_str(&content[..heading.span.start]);
+            deduped.push_str(&content[heading.span.end..]);
+            Some(deduped)
+        }
+        Dedupe::Title => {
+            if let Some(mapping) = metadata.as_mapping_mut() {
+                mapping.retain(|key, _| key.as_str() != Some("title"));
+            }
+            None
+        }
+    }
+}
+
+/// Turn the level 1 heading at the start of `content` into the title: set
+/// it as `metadata`'s title unless it already has one, remove it from the
+/// body, and demote the remaining headings one level so the title is the
+/// document's only level 1 heading.
+///
+/// Nothing changes if the existing title differs from the heading, or if
+/// the frontmatter isn't a mapping. Returns the promoted content, if the
+/// heading was promoted.
+pub fn promote(metadata: &mut Option<yaml::Value>, content: &str) -> Option<String> {
+    let heading = first_heading(content)?;
+    match metadata {
+        Some(yaml::Value::Mapping(mapping)) => match mapping.get("title") {
+            Some(title) if title.as_str().map(normalize) != Some(normalize(heading.text)) => {
+                return None
+            }
+            _ => {}
+        },
+        Some(_) => return None,
+        None => {}
+    }
+
+    let mut promoted = content[..heading.span.start].to_owned();
+    promoted.push_str(&demote_headings(&content[heading.span.end..]));
+    let metadata = metadata.get_or_insert_with(|| yaml::Value::Mapping(Default::default()));
+    if let Some(mapping) = metadata.as_mapping_mut() {
+        if !mapping.contains_key("title") {
+            mapping.insert("title".into(), heading.text.into());
+        }
+    }
+    Some(promoted)
+}
+
+/// Increase the level of every heading outside code blocks by one, up to
+/// level 6.
+fn demote_headings(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut demoted = String::with_capacity(content.len() + lines.len());
+    let mut fence: Option<char> = None;
+    let mut previous_blank = true;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let next = lines.get(i + 1).copied();
+        i += 1;
+
+        let marker = fence_marker(line);
+        match (fence, marker) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            _ => {}
+        }
+        if fence.is_some() || marker.is_some() {
+            demoted.push_str(line);
+            previous_blank = false;
+            continue;
+        }
+
+        let blank = line.trim().is_empty();
+        let setext = !blank && previous_blank && indent(line) <= 3;
+        previous_blank = blank;
+        match (atx_level(line), next) {
+            (Some(level), _) => {
+                if level < 6 {
+                    let indent = indent(line);
+                    demoted.push_str(&line[..indent]);
+                    demoted.push('#');
+                    demoted.push_str(&line[indent..]);
+                } else {
+                    demoted.push_str(line);
+                }
+            }
+            (None, Some(underline)) if setext && is_underline(underline, '=') => {
+                demoted.push_str(line);
+                demoted.push_str(&underline.replace('=', "-"));
+                i += 1;
+            }
+            (None, Some(underline)) if setext && is_underline(underline, '-') => {
+                demoted.push_str("### ");
+                demoted.push_str(line.trim_start());
+                i += 1;
+            }
+            (None, _) => demoted.push_str(line),
+        }
+    }
+    demoted
+}
+
+/// The character a line opening or closing a fenced code block uses.
+fn fence_marker(line: &str) -> Option<char> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let trimmed = line.trim_start_matches(' ');
+    ["```", "~~~"]
+        .into_iter()
+        .find(|marker| trimmed.starts_with(*marker))
+        .and_then(|marker| marker.chars().next())
+}
+
+fn first_heading(content: &str) -> Option<Heading<'_>> {
+    let mut lines = content.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line))
+    });
+    let (start, line) = lines.find(|(_, line)| !line.trim().is_empty())?;
+    let (text, mut end) = match atx_heading(line) {
+        Some(text) => (text, start + line.len()),
+        None => {
+            // setext headings are underlined with =
+            let (underline_start, underline) = lines.next()?;
+            if indent(line) > 3 || !is_underline(underline, '=') {
+                return None;
+            }
+            (line.trim(), underline_start + underline.len())
+        }
+    };
+    for (_, line) in lines {
+        if !line.trim().is_empty() {
+            break;
+        }
+        end += line.len();
+    }
+    Some(Heading {
+        text,
+        span: start..end,
+    })
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// The level of an ATX heading (`#` to `######`).
+fn atx_level(line: &str) -> Option<usize> {
+    if indent(line) > 3 {
+        return None;
+    }
+    let hashes = line.trim_start_matches(' ');
+    let rest = hashes.trim_start_matches('#');
+    let level = hashes.len() - rest.len();
+    let separated = matches!(rest.chars().next(), Some(' ' | '\t' | '\r' | '\n') | None);
+    ((1..=6).contains(&level) && separated).then_some(level)
+}
+
+fn atx_heading(line: &str) -> Option<&str> {
+    if atx_level(line) != Some(1) {
+        return None;
+    }
+    let rest = line.trim_start_matches(' ').strip_prefix('#')?;
+    let text = match rest.chars().next() {
+        Some(' ' | '\t') => rest.trim(),
+        Some('\r' | '\n') | None => "",
+        Some(_) => return None,
+    };
+    // an optional closing sequence of #s must be separated by a space
+    let closed = text.trim_end_matches('#');
+    if closed.is_empty() || closed.ends_with([' ', '\t']) {
+        Some(closed.trim_end())
+    } else {
+        Some(text)
+    }
+}
+
+/// Whether `line` underlines a setext heading with `c`: `=` for level 1,
+/// `-` for level 2.
+fn is_underline(line: &str, c: char) -> bool {
+    let underline = line.trim();
+    indent(line) <= 3 && !underline.is_empty() && underline.chars().all(|u| u == c)
+}
+
+/// Compare titles ignoring case, whitespace and inline formatting.
+fn normalize(title: &str) -> String {
+    title
+        .split_whitespace()
+        .map(|word| word.replace(['*', '_', '`', '~'], "").to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn meta() -> yaml::Value {
+        yaml::from_str("title: Hello  World\n").unwrap()
+    }
+
+    #[test]
+    fn finds_atx_and_setext_headings() {
+        let heading = first_heading("\n# Hello #\n\nBody\n").unwrap();
+        assert_eq!("Hello", heading.text);
+        assert_eq!(1..12, heading.span);
+        assert_eq!("C#", first_heading("# C#\n").unwrap().text);
+        assert_eq!("Hello", first_heading("Hello\n=====\nBody\n").unwrap().text);
+        assert_eq!(None, first_heading("Body\n# Hello\n"));
+        assert_eq!(None, first_heading("## Hello\n"));
+        assert_eq!(None, first_heading("#Hello\n"));
+    }
+
+    #[test]
+    fn removes_duplicate_heading() {
+        let mut meta = meta();
+        let content = "# Hello *world*\n\nBody\n";
+        assert_eq!(
+            Some("Body\n".to_owned()),
+            dedupe(&mut meta, content, Dedupe::Heading)
+        );
+        assert!(meta["title"].is_string());
+    }
+
+    #[test]
+    fn removes_duplicate_title() {
+        let mut meta = meta();
+        assert_eq!(None, dedupe(&mut meta, "# hello world\n", Dedupe::Title));
+        assert!(meta.get("title").is_none());
+    }
+
+    #[test]
+    fn promotes_heading_to_title() {
+        let content = "# Hello\n\nIntro\n\n## Part\n\n# Other\n\nSub\n---\n```\n# code\n```\n";
+        let promoted = "Intro\n\n### Part\n\n## Other\n\n### Sub\n```\n# code\n```\n";
+        let mut meta = Some(yaml::from_str("draft: true\n").unwrap());
+        assert_eq!(Some(promoted.to_owned()), promote(&mut meta, content));
+        assert_eq!(
+            "draft: true\ntitle: Hello\n",
+            yaml::to_string(&meta).unwrap()
+        );
+
+        let mut meta = None;
+        assert_eq!(
+            Some("Body\n".to_owned()),
+            promote(&mut meta, "Hello\n===\nBody\n")
+        );
+        assert_eq!("title: Hello\n", yaml::to_string(&meta).unwrap());
+    }
+
+    #[test]
+    fn doesnt_promote_over_a_different_title() {
+        let mut meta = Some(meta());
+        assert_eq!(None, promote(&mut meta, "# Goodbye\n\n## Part\n"));
+        assert_eq!(
+            Some("Body\n".to_owned()),
+            promote(&mut meta, "# hello world\nBody\n")
+        );
+        assert_eq!(Some("Hello  World"), meta.unwrap()["title"].as_str());
+    }
+
+    #[test]
+    fn demotes_headings_up_to_level_six() {
+        assert_eq!(
+            "## A\n###### B\n#5\n",
+            demote_headings("# A\n###### B\n#5\n")
+        );
+        assert_eq!("### Text\n", demote_headings("Text\n---\n"));
+        assert_eq!("Two\nlines\n---\n", demote_headings("Two\nlines\n---\n"));
+    }
+
+    #[test]
+    fn leaves_different_titles_alone() {
+        let mut meta = meta();
+        assert_eq!(None, dedupe(&mut meta, "# Goodbye\n", Dedupe::Title));
+        assert!(meta["title"].is_string());
+    }
+}
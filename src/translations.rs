@@ -0,0 +1,136 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use serde_yaml as yaml;
+
+use crate::{
+    checksum, frontmatter,
+    report::{Finding, Findings},
+};
+
+/// Translations can name their source explicitly under this key, rather
+/// than relying on the sibling directory layout.
+pub const TRANSLATED_FROM_KEY: &str = "translated_from";
+
+/// The key translations record their source's body hash under by default.
+pub const DEFAULT_HASH_KEY: &str = "source_hash";
+
+/// Finds translations whose source has changed since they were translated.
+///
+/// Translations live in sibling directories of the source language, e.g.
+/// `content/de/foo.md` translates `content/en/foo.md`, and record the hash
+/// of the source's body when they were made.
+#[derive(Debug)]
+pub struct Translations {
+    source_dir: PathBuf,
+    hash_key: String,
+}
+
+impl Translations {
+    pub fn new(source_dir: &Path, hash_key: Option<&str>) -> Self {
+        Self {
+            source_dir: source_dir.to_path_buf(),
+            hash_key: hash_key.unwrap_or(DEFAULT_HASH_KEY).to_owned(),
+        }
+    }
+
+    /// The source a file in a sibling language directory translates, if
+    /// it's in one.
+    fn source_for(&self, path: &Path) -> Option<PathBuf> {
+        let languages = self.source_dir.parent()?;
+        let mut components = path.strip_prefix(languages).ok()?.components();
+        let language = components.next()?;
+        if Some(language.as_os_str()) == self.source_dir.file_name() {
+            return None;
+        }
+        Some(self.source_dir.join(components.as_path()))
+    }
+
+    /// Fail if `path` is a translation whose recorded source hash no longer
+    /// matches its source. Untracked translations, without a recorded hash,
+    /// pass.
+    pub fn check(&self, path: &Path, metadata: &yaml::Value) -> Result<(), Findings> {
+        let stored = match metadata.get(&self.hash_key).and_then(yaml::Value::as_str) {
+            Some(stored) => stored,
+            None => return Ok(()),
+        };
+        let source = match metadata
+            .get(TRANSLATED_FROM_KEY)
+            .and_then(yaml::Value::as_str)
+        {
+            Some(source) => PathBuf::from(source),
+            None => match self.source_for(path) {
+                Some(source) => source,
+                None => return Ok(()),
+            },
+        };
+
+        let finding = match read_to_string(&source) {
+            Ok(source_text) => {
+                let (_, body) = frontmatter::parse_raw(&source_text);
+                if checksum::body_hash(body) == stored {
+                    return Ok(());
+                }
+                Finding::for_key(
+                    "stale-translation",
+                    &self.hash_key,
+                    format!("{} has changed since this was translated", source.display()),
+                )
+            }
+            Err(e) => Finding::for_key(
+                "missing-translation-source",
+                &self.hash_key,
+                format!("couldn't read source {}: {}", source.display(), e),
+            ),
+        };
+        Err(Findings(vec![finding]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+
+    #[test]
+    fn finds_sources_in_sibling_directories() {
+        let translations = Translations::new(Path::new("content/en"), None);
+        assert_eq!(
+            Some(PathBuf::from("content/en/posts/foo.md")),
+            translations.source_for(Path::new("content/de/posts/foo.md"))
+        );
+        assert_eq!(
+            None,
+            translations.source_for(Path::new("content/en/foo.md"))
+        );
+        assert_eq!(None, translations.source_for(Path::new("static/de/foo.md")));
+    }
+
+    #[test]
+    fn flags_stale_translations() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("en/foo.md");
+        create_dir_all(source.parent().unwrap())?;
+        write(&source, "---\ntitle: Hello\n---\nHello\n")?;
+
+        let translations = Translations::new(&dir.path().join("en"), None);
+        let translation = dir.path().join("de/foo.md");
+        let fresh = format!("source_hash: {}\n", checksum::body_hash("Hello\n"));
+        translations
+            .check(&translation, &yaml::from_str(&fresh)?)
+            .expect("translation is up to date");
+        translations
+            .check(&translation, &yaml::from_str("title: Hallo\n")?)
+            .expect("untracked translations pass");
+
+        write(&source, "---\ntitle: Hello\n---\nHello, world\n")?;
+        let findings = translations
+            .check(&translation, &yaml::from_str(&fresh)?)
+            .expect_err("source has changed");
+        assert_eq!("stale-translation", findings.0[0].rule);
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+
+use eyre::eyre;
+use serde_yaml as yaml;
+
+use crate::{schema::Schema, spelling};
+
+/// A key only counts as canonical if it appears in at least this many times
+/// as many files as the suspected typo.
+const CANONICAL_FREQUENCY_RATIO: usize = 3;
+
+/// How many files across the corpus use each top-level key.
+#[derive(Debug, Default)]
+pub struct KeyStats {
+    file_counts: BTreeMap<String, usize>,
+}
+
+impl KeyStats {
+    pub fn record(&mut self, metadata: &yaml::Value) {
+        if let Some(mapping) = metadata.as_mapping() {
+            for key in mapping.keys().filter_map(yaml::Value::as_str) {
+                *self.file_counts.entry(key.to_owned()).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// Renames keys that look like misspellings of a canonical key.
+#[derive(Debug)]
+pub struct TypoFixer {
+    renames: BTreeMap<String, String>,
+}
+
+impl TypoFixer {
+    /// Work out which keys are typos: those a small edit distance from a key
+    /// declared in the schema, or from a key used much more often.
+    pub fn new(stats: &KeyStats, schema: Option<&Schema>) -> Self {
+        let mut renames = BTreeMap::new();
+        for (key, &count) in &stats.file_counts {
+            if schema.is_some_and(|schema| schema.keys().any(|k| k == key.as_str())) {
+                continue;
+            }
+            let frequent = stats
+                .file_counts
+                .iter()
+                .filter(|&(_, &other_count)| other_count >= count * CANONICAL_FREQUENCY_RATIO)
+                .map(|(other, _)| other.as_str());
+            let canonical = schema.into_iter().flat_map(Schema::keys).chain(frequent);
+            if let Some(canonical) = spelling::closest(key, canonical) {
+                renames.insert(key.clone(), canonical.to_owned());
+            }
+        }
+        Self { renames }
+    }
+
+    /// Rename typo'd top-level keys in place, preserving key order.
+    ///
+    /// Returns a description of each rename applied.
+    pub fn apply(&self, metadata: &mut yaml::Value) -> eyre::Result<Vec<String>> {
+        let mapping = match metadata.as_mapping_mut() {
+            Some(mapping) => mapping,
+            None => return Ok(Vec::new()),
+        };
+        let mut fixes = Vec::new();
+        for key in mapping.keys().filter_map(yaml::Value::as_str) {
+            if let Some(canonical) = self.renames.get(key) {
+                if mapping.contains_key(canonical.as_str()) {
+                    return Err(eyre!(
+                        "key {} looks like a typo of {}, but {} is also present",
+                        key,
+                        canonical,
+                        canonical
+                    ));
+                }
+                if let Some((other, _)) = fixes.iter().find(|(_, other)| other == canonical) {
+                    return Err(eyre!(
+                        "keys {} and {} both look like typos of {}",
+                        other,
+                        key,
+                        canonical
+                    ));
+                }
+                fixes.push((key.to_owned(), canonical.clone()));
+            }
+        }
+        if fixes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        *mapping = std::mem::take(mapping)
+            .into_iter()
+            .map(|(key, value)| {
+                let renamed = key
+                    .as_str()
+                    .and_then(|key| self.renames.get(key))
+                    .map(|canonical| yaml::Value::from(canonical.as_str()));
+                (renamed.unwrap_or(key), value)
+            })
+            .collect();
+
+        Ok(fixes
+            .into_iter()
+            .map(|(typo, canonical)| format!("{} -> {}", typo, canonical))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats(files: &[&str]) -> KeyStats {
+        let mut stats = KeyStats::default();
+        for file in files {
+            stats.record(&yaml::from_str(file).unwrap());
+        }
+        stats
+    }
+
+    #[test]
+    fn renames_rare_keys_close_to_common_ones() -> eyre::Result<()> {
+        let stats = stats(&[
+            "categories: [a]\n",
+            "categories: [b]\n",
+            "categories: [c]\n",
+            "title: x\ncatagories: [d]\n",
+        ]);
+        let fixer = TypoFixer::new(&stats, None);
+        let mut meta: yaml::Value = yaml::from_str("catagories: [d]\ntitle: x\n")?;
+        let fixes = fixer.apply(&mut meta)?;
+        assert_eq!(vec!["catagories -> categories"], fixes);
+        assert_eq!("categories:\n- d\ntitle: x\n", yaml::to_string(&meta)?);
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_equally_common_keys_alone() -> eyre::Result<()> {
+        let stats = stats(&["tag: a\n", "tags: [b]\n"]);
+        let fixer = TypoFixer::new(&stats, None);
+        let mut meta: yaml::Value = yaml::from_str("tag: a\n")?;
+        assert!(fixer.apply(&mut meta)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn schema_keys_are_canonical() -> eyre::Result<()> {
+        let schema = Schema::parse("title: string\n")?;
+        let stats = stats(&["tiitle: a\n", "tiitle: b\n"]);
+        let fixer = TypoFixer::new(&stats, Some(&schema));
+        let mut meta: yaml::Value = yaml::from_str("tiitle: a\n")?;
+        assert_eq!(vec!["tiitle -> title"], fixer.apply(&mut meta)?);
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_to_clobber_existing_canonical_key() -> eyre::Result<()> {
+        let schema = Schema::parse("title: string\n")?;
+        let fixer = TypoFixer::new(&stats(&["tiitle: a\n"]), Some(&schema));
+        let mut meta: yaml::Value = yaml::from_str("tiitle: a\ntitle: b\n")?;
+        let _ = fixer
+            .apply(&mut meta)
+            .expect_err("shouldn't overwrite title");
+
+        let fixer = TypoFixer::new(&stats(&["tiitle: a\n", "titel: b\n"]), Some(&schema));
+        let mut meta: yaml::Value = yaml::from_str("tiitle: a\ntitel: b\n")?;
+        let err = fixer
+            .apply(&mut meta)
+            .expect_err("shouldn't drop either value");
+        assert!(err.to_string().contains("tiitle and titel"), "{}", err);
+        Ok(())
+    }
+}
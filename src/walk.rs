@@ -0,0 +1,127 @@
+use std::{
+    fs::read_dir,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+
+use crate::glob;
+
+/// Which files found under a directory get processed.
+#[derive(Debug, Default)]
+pub struct Filter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl Filter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    /// `relative` is the path from the directory being walked, with `/`
+    /// separators. With no include globs, everything is included.
+    fn accepts(&self, relative: &str) -> bool {
+        let matches = |pattern: &String| glob::matches(pattern, relative, '/');
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Replace each directory in `paths` with the files underneath it that pass
+/// `filter`, in sorted order. Other paths are kept as given.
+///
+/// Hidden files and directories (starting with `.`) are skipped, as are
+/// symlinks to directories.
+pub fn expand(paths: &[String], filter: &Filter) -> eyre::Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        let root = Path::new(path);
+        if root.is_dir() {
+            let mut files = Vec::new();
+            walk(root, root, filter, &mut files)?;
+            files.sort();
+            expanded.extend(files.iter().map(|file| file.display().to_string()));
+        } else {
+            expanded.push(path.clone());
+        }
+    }
+    Ok(expanded)
+}
+
+fn walk(root: &Path, dir: &Path, filter: &Filter, files: &mut Vec<PathBuf>) -> eyre::Result<()> {
+    let entries = read_dir(dir).context(format!("couldn't read directory {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.context(format!("couldn't read directory {}", dir.display()))?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            walk(root, &path, filter, files)?;
+        } else if path.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let relative: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            if filter.accepts(&relative.join("/")) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+
+    fn example_tree() -> eyre::Result<tempfile::TempDir> {
+        let dir = tempfile::tempdir()?;
+        for file in ["a.md", "b.txt", "posts/c.md", "drafts/d.md", ".git/config"] {
+            let path = dir.path().join(file);
+            create_dir_all(path.parent().unwrap())?;
+            write(path, "")?;
+        }
+        Ok(dir)
+    }
+
+    fn relative_files(dir: &Path, filter: &Filter) -> eyre::Result<Vec<String>> {
+        let root = dir.display().to_string();
+        Ok(expand(std::slice::from_ref(&root), filter)?
+            .iter()
+            .map(|path| path[root.len() + 1..].to_owned())
+            .collect())
+    }
+
+    #[test]
+    fn walks_directories_skipping_hidden() -> eyre::Result<()> {
+        let dir = example_tree()?;
+        assert_eq!(
+            vec!["a.md", "b.txt", "drafts/d.md", "posts/c.md"],
+            relative_files(dir.path(), &Filter::default())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn filters_with_include_and_exclude_globs() -> eyre::Result<()> {
+        let dir = example_tree()?;
+        let filter = Filter::new(vec!["**/*.md".into()], vec!["drafts/**".into()]);
+        assert_eq!(
+            vec!["a.md", "posts/c.md"],
+            relative_files(dir.path(), &filter)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_plain_files_as_given() -> eyre::Result<()> {
+        let paths = vec!["does-not-exist.md".to_owned()];
+        assert_eq!(paths, expand(&paths, &Filter::default())?);
+        Ok(())
+    }
+}
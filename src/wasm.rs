@@ -0,0 +1,197 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::{eyre, Context};
+use serde::Serialize;
+use serde_yaml as yaml;
+use wasmtime::{Engine, InstancePre, Linker, Store};
+use wasmtime_wasi::{
+    pipe::{MemoryInputPipe, MemoryOutputPipe},
+    preview1::{self, WasiP1Ctx},
+    I32Exit, WasiCtxBuilder,
+};
+
+use crate::fixer::restore_key_order;
+
+/// The most a module may write to stdout, or to stderr.
+const OUTPUT_LIMIT: usize = 64 << 20;
+
+/// A transform compiled to a WASI module, from Rust, JS or anything else
+/// that targets `wasm32-wasi`, run in a sandbox by an embedded WASI runtime.
+///
+/// The ABI is a command's: the module reads a JSON object from stdin with
+/// the file's `path`, its `metadata` (`null` if it has none) and its
+/// `content`, and writes the fixed metadata to stdout as JSON, `null` to
+/// remove the frontmatter. Anything it writes to stderr is shown if it
+/// exits with an error. It gets no directories, environment, arguments or
+/// network, so it can only see what it's given.
+#[derive(Clone)]
+pub struct Module {
+    path: PathBuf,
+    module: InstancePre<WasiP1Ctx>,
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    path: &'a str,
+    metadata: Option<&'a yaml::Value>,
+    content: &'a str,
+}
+
+impl Module {
+    /// Compile the module at `path`, linked to nothing but WASI.
+    pub fn new(path: &Path) -> eyre::Result<Self> {
+        let bytes =
+            fs::read(path).context(format!("couldn't read WASM module {}", path.display()))?;
+        let engine = Engine::default();
+        let compile = || -> wasmtime::Result<_> {
+            let module = wasmtime::Module::new(&engine, &bytes)?;
+            let mut linker = Linker::new(&engine);
+            preview1::add_to_linker_sync(&mut linker, |wasi| wasi)?;
+            linker.instantiate_pre(&module)
+        };
+        let module = compile()
+            .map_err(|err| eyre!("couldn't load WASM module {}: {:#}", path.display(), err))?;
+        Ok(Self {
+            path: path.to_owned(),
+            module,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// Run the module on the file at `path`, returning the metadata it
+    /// fixed, with keys that were already there kept in their order.
+    pub fn apply(
+        &self,
+        path: &str,
+        metadata: Option<&yaml::Value>,
+        content: &str,
+    ) -> eyre::Result<Option<yaml::Value>> {
+        let input = serde_json::to_vec(&Input {
+            path,
+            metadata,
+            content,
+        })
+        .context("frontmatter can't be represented as JSON")?;
+        let stdout = MemoryOutputPipe::new(OUTPUT_LIMIT);
+        let stderr = MemoryOutputPipe::new(OUTPUT_LIMIT);
+        // nothing but stdio: no preopened directories, environment,
+        // arguments or network
+        let wasi = WasiCtxBuilder::new()
+            .stdin(MemoryInputPipe::new(input))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone())
+            .build_p1();
+        let mut store = Store::new(self.module.module().engine(), wasi);
+        let run = self.module.instantiate(&mut store).and_then(|instance| {
+            instance
+                .get_typed_func::<(), ()>(&mut store, "_start")?
+                .call(&mut store, ())
+        });
+        let status = match run {
+            Ok(()) => 0,
+            Err(err) => match err.downcast_ref::<I32Exit>() {
+                Some(exit) => exit.0,
+                None => return Err(eyre!("WASM module {} failed: {:#}", self.name(), err)),
+            },
+        };
+        if status != 0 {
+            return Err(eyre!(
+                "WASM module {} failed (exit status {}): {}",
+                self.name(),
+                status,
+                String::from_utf8_lossy(&stderr.contents()).trim()
+            ));
+        }
+        let fixed: Option<yaml::Value> = serde_json::from_slice(&stdout.contents()).context(
+            format!("WASM module {} didn't return JSON metadata", self.name()),
+        )?;
+        Ok(match (metadata, fixed) {
+            (Some(metadata), Some(fixed)) => Some(restore_key_order(metadata, fixed)),
+            (_, fixed) => fixed,
+        })
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Module").field("path", &self.path).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Copies stdin to stdout, so returns its input as the metadata.
+    const ECHO: &str = r#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "_start")
+            ;; one iovec at 0 for a 4096 byte buffer at 16; counts at 8
+            (i32.store (i32.const 0) (i32.const 16))
+            (i32.store (i32.const 4) (i32.const 4096))
+            (block $done
+              (loop $copy
+                (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                (br_if $done (i32.eqz (i32.load (i32.const 8))))
+                (i32.store (i32.const 4) (i32.load (i32.const 8)))
+                (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 12)))
+                (i32.store (i32.const 4) (i32.const 4096))
+                (br $copy)))))
+    "#;
+
+    /// Exits with what asking for the first preopened directory returns.
+    const LOOK_FOR_FILES: &str = r#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_prestat_get"
+            (func $fd_prestat_get (param i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+          (memory (export "memory") 1)
+          (func (export "_start")
+            (call $proc_exit (call $fd_prestat_get (i32.const 3) (i32.const 0)))))
+    "#;
+
+    fn module(dir: &Path, name: &str, text: &str) -> eyre::Result<Module> {
+        let path = dir.join(name);
+        fs::write(&path, wat::parse_str(text)?)?;
+        Module::new(&path)
+    }
+
+    #[test]
+    fn runs_module() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let echo = module(dir.path(), "echo.wasm", ECHO)?;
+        let metadata: yaml::Value = yaml::from_str("title: Broken\n")?;
+        let fixed = echo.apply("a.md", Some(&metadata), "Hello")?.unwrap();
+        assert_eq!(Some(&"a.md".into()), fixed.get("path"));
+        assert_eq!(Some(&metadata), fixed.get("metadata"));
+
+        // more than one read's worth
+        let content = "x".repeat(1 << 20);
+        let fixed = echo.apply("a.md", None, &content)?.unwrap();
+        assert_eq!(Some(&content.into()), fixed.get("content"));
+
+        assert!(Module::new(&dir.path().join("missing.wasm")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn gives_modules_no_files() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let module = module(dir.path(), "look.wasm", LOOK_FOR_FILES)?;
+        let err = module.apply("a.md", None, "").unwrap_err();
+        // EBADF: there's no directory at fd 3
+        assert!(err.to_string().contains("exit status 8"), "{}", err);
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use eyre::{eyre, Context};
+use notify::{Event, RecursiveMode, Watcher as _};
+
+use crate::{
+    batch::{self, STDIN_PATH},
+    config::Config,
+    walk::{self, Filter},
+};
+
+/// How long a file must go unchanged before it's fixed, so a burst of saves
+/// gets fixed once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Fix the files `cfg` asks for whenever they change, until interrupted.
+///
+/// The OS tells us when something changes under the paths given, and
+/// then which files changed is found by comparing their sizes and
+/// modification times with what was last seen. Files being fixed don't
+/// count as changed by their own fixing, nor does anything else a run writes.
+pub fn run(cfg: Config) -> eyre::Result<()> {
+    if cfg.paths.iter().any(|path| path == STDIN_PATH) {
+        return Err(eyre!("can't watch stdin ({})", STDIN_PATH));
+    }
+    let mut watcher = Watcher::new(&cfg)?;
+    let (sender, events) = mpsc::channel();
+    let mut notifier =
+        notify::recommended_watcher(sender).context("couldn't watch for file changes")?;
+    for path in &cfg.paths {
+        notifier
+            .watch(Path::new(path), RecursiveMode::Recursive)
+            .context(format!("couldn't watch {}", path))?;
+    }
+    eprintln!("watching {} files for changes", watcher.known.len());
+    loop {
+        let changed = watcher.wait(&events)?;
+        let mut batch_cfg = cfg.clone();
+        batch_cfg.watch = false;
+        batch_cfg.recursive = false;
+        batch_cfg.paths = changed;
+        // one bad file shouldn't stop the watch
+        let mut written = Vec::new();
+        if let Err(e) = batch::run_noting_writes(batch_cfg, &mut written) {
+            eprintln!("{:?}", e);
+        }
+        watcher.ignore_own_writes(&written);
+    }
+}
+
+/// What a file looked like when last seen, to tell whether it's changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Snapshot {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Snapshot {
+    /// `None` if the file has gone.
+    fn take(path: &str) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+struct Watcher {
+    paths: Vec<String>,
+    filter: Filter,
+    known: HashMap<String, Snapshot>,
+    /// Changed files waiting to settle, with when each last changed.
+    pending: HashMap<String, Instant>,
+}
+
+impl Watcher {
+    /// Start watching, taking the files as they are now as unchanged.
+    fn new(cfg: &Config) -> eyre::Result<Self> {
+        let mut watcher = Self {
+            paths: cfg.paths.clone(),
+            filter: Filter::new(cfg.include.clone(), cfg.exclude.clone()),
+            known: HashMap::new(),
+            pending: HashMap::new(),
+        };
+        watcher.known = watcher.scan()?;
+        Ok(watcher)
+    }
+
+    fn scan(&self) -> eyre::Result<HashMap<String, Snapshot>> {
+        let files = walk::expand(&self.paths, &self.filter)?;
+        Ok(files
+            .into_iter()
+            .filter_map(|file| Snapshot::take(&file).map(|snapshot| (file, snapshot)))
+            .collect())
+    }
+
+    /// Wait for `events` to say something changed and for the files that
+    /// changed to settle, returning them in sorted order.
+    fn wait(&mut self, events: &Receiver<notify::Result<Event>>) -> eyre::Result<Vec<String>> {
+        let stopped = || eyre!("stopped getting file change events");
+        loop {
+            // with changes pending, they're checked again once they could
+            // have settled whether or not anything else happens
+            let event = if self.pending.is_empty() {
+                events.recv().map_err(|_| stopped())?
+            } else {
+                match events.recv_timeout(DEBOUNCE) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => Ok(Event::default()),
+                    Err(RecvTimeoutError::Disconnected) => return Err(stopped()),
+                }
+            };
+            if let Err(err) = event {
+                eprintln!("error watching files: {}", err);
+            }
+            // one look covers however many events came at once
+            while events.try_recv().is_ok() {}
+
+            let settled = self.check(Instant::now())?;
+            if !settled.is_empty() {
+                return Ok(settled);
+            }
+        }
+    }
+
+    /// Look for changes, returning the changed files that have since
+    /// settled, in sorted order.
+    fn check(&mut self, now: Instant) -> eyre::Result<Vec<String>> {
+        let current = self.scan()?;
+        for (path, snapshot) in &current {
+            if self.known.get(path) != Some(snapshot) {
+                self.pending.insert(path.clone(), now);
+            }
+        }
+        self.pending.retain(|path, _| current.contains_key(path));
+        self.known = current;
+
+        let mut settled: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= DEBOUNCE)
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled.sort();
+        for path in &settled {
+            self.pending.remove(path);
+        }
+        Ok(settled)
+    }
+
+    /// Take the files a run wrote as seen as they are now, so they don't
+    /// trigger fixing them again: not just the files fixed, but any they
+    /// were moved to and any backups, sidecars and reports written alongside
+    /// them. Anything else changed meanwhile, like a file edited during the
+    /// run, is still found by the next check.
+    fn ignore_own_writes(&mut self, written: &[PathBuf]) {
+        for path in written {
+            let path = path.display().to_string();
+            match Snapshot::take(&path) {
+                Some(snapshot) => {
+                    self.known.insert(path, snapshot);
+                }
+                None => {
+                    self.known.remove(&path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::write;
+
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn fixes_changed_files_once_settled() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let post = dir.path().join("post.md");
+        write(&post, "---\ntitle: a\n---\n")?;
+        let root = dir.path().display().to_string();
+        let cfg = Config::try_parse_from(["frontmatter-fixer", "--watch", &root])?;
+        let start = Instant::now();
+        let mut watcher = Watcher::new(&cfg)?;
+        assert!(watcher.check(start)?.is_empty());
+
+        write(&post, "---\ntitle: changed\n---\n")?;
+        assert!(watcher.check(start)?.is_empty());
+        let post = post.display().to_string();
+        assert_eq!(vec![post.clone()], watcher.check(start + DEBOUNCE)?);
+        assert!(watcher.check(start + DEBOUNCE * 2)?.is_empty());
+
+        write(&post, "---\ntitle: fixed by us\n---\n")?;
+        let moved = dir.path().join("moved.md");
+        write(&moved, "---\ntitle: moved by us\n---\n")?;
+        let report = dir.path().join("report.md");
+        write(&report, "# Fixed 1 file\n")?;
+        // edited while the run was going
+        let other = dir.path().join("other.md");
+        write(&other, "---\ntitle: edited meanwhile\n---\n")?;
+        watcher.ignore_own_writes(&[PathBuf::from(&post), moved, report]);
+        assert!(watcher.check(start + DEBOUNCE * 4)?.is_empty());
+        let other = other.display().to_string();
+        assert_eq!(vec![other], watcher.check(start + DEBOUNCE * 5)?);
+        Ok(())
+    }
+
+    #[test]
+    fn waits_for_the_os_to_report_changes() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let post = dir.path().join("post.md");
+        write(&post, "---\ntitle: a\n---\n")?;
+        let root = dir.path().display().to_string();
+        let cfg = Config::try_parse_from(["frontmatter-fixer", "--watch", &root])?;
+        let mut watcher = Watcher::new(&cfg)?;
+        let (sender, events) = mpsc::channel();
+        let mut notifier = notify::recommended_watcher(sender)?;
+        notifier.watch(dir.path(), RecursiveMode::Recursive)?;
+
+        write(&post, "---\ntitle: changed\n---\n")?;
+        assert_eq!(vec![post.display().to_string()], watcher.wait(&events)?);
+        Ok(())
+    }
+}
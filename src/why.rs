@@ -0,0 +1,327 @@
+use std::{borrow::Cow, fmt, fs::read_to_string, path::Path};
+
+use eyre::eyre;
+
+use crate::{
+    batch::{self, Pipeline},
+    config::Config,
+    convert::Converter,
+    envelope,
+    error::FixError,
+    frontmatter::{self, Format, TextStyle},
+    provenance::Transform,
+    report::Findings,
+};
+
+/// A step-by-step account of what fixing a file does, for `why`: reading
+/// it, finding and parsing its frontmatter, then running it through the
+/// pipeline as a dry run, with its scripts and checks, up to the step that
+/// fails, if one does.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Account {
+    steps: Vec<Step>,
+    /// Whether fixing the file fails.
+    pub failed: bool,
+}
+
+/// One step of fixing a file, with more about how it went a line each.
+#[derive(Debug, PartialEq, Eq)]
+struct Step {
+    name: &'static str,
+    outcome: String,
+    details: Vec<String>,
+}
+
+impl Account {
+    fn step(&mut self, name: &'static str, outcome: impl Into<String>) -> &mut Vec<String> {
+        self.steps.push(Step {
+            name,
+            outcome: outcome.into(),
+            details: Vec::new(),
+        });
+        &mut self.steps.last_mut().expect("just added a step").details
+    }
+
+    /// Note the step that failed, with the first line of `message` as its
+    /// outcome and the rest as details.
+    fn fail(&mut self, name: &'static str, message: &str) -> &mut Vec<String> {
+        self.failed = true;
+        let mut lines = message.lines();
+        let outcome = format!("failed: {}", lines.next().unwrap_or_default());
+        let rest = lines.map(str::to_owned).collect::<Vec<_>>();
+        let details = self.step(name, outcome);
+        details.extend(rest);
+        details
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (number, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{}. {}: {}", number + 1, step.name, step.outcome)?;
+            for detail in &step.details {
+                writeln!(f, "     {}", detail)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Go through fixing the one file `cfg` names as a run with `cfg` would,
+/// without writing anything, noting each line its scripts run if `trace`.
+pub fn explain(cfg: &Config, trace: bool) -> eyre::Result<Account> {
+    let path = match cfg.paths.as_slice() {
+        [path] if path != batch::STDIN_PATH => path,
+        _ => return Err(eyre!("why explains one file at a time")),
+    };
+    if cfg.repl {
+        return Err(eyre!("can't explain fixing a file in the REPL"));
+    }
+    let mut account = Account::default();
+
+    let original = match read_to_string(path) {
+        Ok(original) => original,
+        Err(err) => {
+            account.fail("read", &err.to_string());
+            return Ok(account);
+        }
+    };
+    account.step("read", format!("{} bytes", original.len()));
+
+    let converter = Converter::for_path(&cfg.convert, Path::new(path));
+    let converted = match converter
+        .map(|converter| converter.convert(&original))
+        .transpose()
+    {
+        Ok(converted) => converted,
+        Err(err) => {
+            account.fail("convert", &format!("{:#}", err));
+            return Ok(account);
+        }
+    };
+    let envelope = match converted {
+        Some(_) => {
+            account.step("convert", "converted to Markdown");
+            cfg.envelope(&Path::new(path).with_extension("md").to_string_lossy())
+        }
+        None => cfg.envelope(path),
+    };
+    let source = converted.as_deref().unwrap_or(&original);
+    let style = TextStyle::detect(source);
+    let text = style.normalize(source);
+    let (prefix, enveloped) = frontmatter::split_prefix(&text, &cfg.frontmatter_prefix);
+    let document = envelope::unwrap(&envelope, enveloped);
+
+    let Some(found) = frontmatter::detect(&document) else {
+        account.step("detect", "no frontmatter");
+        return fix(account, cfg, path, trace);
+    };
+    let format = cfg.from_format.unwrap_or(found);
+    let details = account.step(
+        "detect",
+        format!(
+            "{} frontmatter between {} rules",
+            language(format),
+            found.rule().trim_end()
+        ),
+    );
+    if format != found {
+        details.push(format!("read as {} by --from-format", language(format)));
+    }
+    if style.bom {
+        details.push("after a byte order mark".to_owned());
+    }
+    if style.crlf {
+        details.push("with CRLF line endings".to_owned());
+    }
+    if !prefix.is_empty() {
+        let lines = prefix.lines().count();
+        details.push(format!(
+            "after {} lines matching --frontmatter-prefix",
+            lines
+        ));
+    }
+    if matches!(document, Cow::Owned(_)) {
+        details.push("inside comments".to_owned());
+    }
+
+    match frontmatter::parse_as(&document, cfg.from_format).0 {
+        Some(Ok(metadata)) => match metadata.as_mapping() {
+            Some(mapping) if mapping.is_empty() => {
+                account.step("parse", "no keys");
+            }
+            Some(mapping) => {
+                let keys = mapping
+                    .keys()
+                    .filter_map(|key| key.as_str())
+                    .collect::<Vec<_>>();
+                account.step("parse", format!("keys {}", keys.join(", ")));
+            }
+            None => {
+                account.step("parse", "not a mapping of keys to values");
+            }
+        },
+        Some(Err(err)) if cfg.repair => {
+            let details = account.step("parse", "doesn't parse, so scripts get to repair it");
+            details.extend(format!("{:#}", err).lines().map(str::to_owned));
+        }
+        Some(Err(err)) => {
+            account
+                .fail("parse", &format!("{:#}", err))
+                .push("scripts can repair it with --repair".to_owned());
+            return Ok(account);
+        }
+        None => {}
+    }
+    fix(account, cfg, path, trace)
+}
+
+/// Run the file at `path` through the pipeline as a dry run, adding how it
+/// went to `account`.
+fn fix(mut account: Account, cfg: &Config, path: &str, trace: bool) -> eyre::Result<Account> {
+    let cfg = Config {
+        dry_run: true,
+        interactive: false,
+        // to see which transforms touch the file
+        provenance_comments: Transform::ALL.to_vec(),
+        ..cfg.clone()
+    };
+    let setup = Pipeline::new(&cfg).and_then(|mut pipeline| {
+        if trace {
+            pipeline.trace_scripts();
+        }
+        let fixer = pipeline.fixer()?;
+        Ok((pipeline, fixer))
+    });
+    let (pipeline, fixer) = match setup {
+        Ok(setup) => setup,
+        Err(err) => {
+            account.fail("set up", &format!("{:#}", err));
+            return Ok(account);
+        }
+    };
+
+    match batch::process(&pipeline, &fixer, path, &cfg) {
+        Ok(processed) => {
+            if let Some(skip) = processed.skipped {
+                account.step("fix", format!("left alone: {}", skip.reason()));
+            } else if !processed.changed() {
+                account.step("fix", "would leave it unchanged");
+            } else {
+                let details = account.step("fix", "would change it");
+                if !processed.touched_by.is_empty() {
+                    let touched_by: Vec<_> = processed
+                        .touched_by
+                        .iter()
+                        .map(Transform::to_string)
+                        .collect();
+                    details.push(format!("keys changed by {}", touched_by.join(", ")));
+                }
+                if let Some(renamed_to) = &processed.renamed_to {
+                    details.push(format!("would move it to {}", renamed_to.display()));
+                }
+                let destructive = processed.destructive.iter();
+                details.extend(destructive.map(|change| format!("would {}", change)));
+            }
+        }
+        Err(err) => explain_failure(&mut account, &err),
+    }
+
+    if trace {
+        let lines = fixer.trace();
+        let outcome = format!("scripts ran {} lines, {}", lines.len(), fixer.usage());
+        account.step("trace", outcome).extend(lines);
+    }
+    Ok(account)
+}
+
+/// Add the step that failed to `account`, going by what `err` says failed.
+fn explain_failure(account: &mut Account, err: &eyre::Report) {
+    if let Some(findings) = err.downcast_ref::<Findings>() {
+        let details = account.fail("check", &format!("{} problems found", findings.0.len()));
+        for finding in &findings.0 {
+            details.push(match finding.line {
+                Some(line) => format!("line {}: {} ({})", line, finding.message, finding.rule),
+                None => format!("{} ({})", finding.message, finding.rule),
+            });
+        }
+        return;
+    }
+    match err.downcast_ref::<FixError>() {
+        Some(FixError::Script { traceback, .. }) => {
+            account.fail("script", traceback);
+        }
+        // scripts repairing frontmatter didn't
+        Some(FixError::Parse { .. }) => {
+            account.fail("parse", &format!("{:#}", err));
+        }
+        _ => {
+            account.fail("fix", &format!("{:#}", err));
+        }
+    }
+}
+
+fn language(format: Format) -> &'static str {
+    match format {
+        Format::Yaml => "YAML",
+        Format::Toml => "TOML",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::write;
+
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn explains_failures() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let post = dir.path().join("post.md");
+        let schema = dir.path().join("schema.yaml");
+        write(&schema, "title: string, required\n")?;
+        let why = |extra: &[&str], trace| -> eyre::Result<Account> {
+            let mut args = vec!["frontmatter-fixer"];
+            args.extend(extra);
+            args.push(post.to_str().unwrap());
+            explain(&Config::try_parse_from(args)?, trace)
+        };
+
+        write(&post, "---\ntitle: [Hi\n---\nBody\n")?;
+        let account = why(&["-e", "meta.seen = true"], false)?;
+        assert!(account.failed);
+        let steps = account.to_string();
+        assert!(steps.starts_with(
+            "1. read: 24 bytes\n2. detect: YAML frontmatter between --- rules\n3. parse: failed: "
+        ));
+        assert_eq!(3, account.steps.len());
+
+        write(&post, "---\ndraft: true\n---\nBody\n")?;
+        let account = why(&["--schema", schema.to_str().unwrap()], false)?;
+        assert!(account.failed);
+        assert_eq!(Some("check"), account.steps.last().map(|step| step.name));
+        assert!(account.to_string().contains("title"), "{}", account);
+
+        assert!(why(&["-e", "meta.seen = true", "other.md"], false).is_err());
+        let account = why(&["-e", "error('oops')"], true)?;
+        assert!(account.failed);
+        let steps = account.to_string();
+        assert!(steps.contains("4. script: failed: "), "{}", steps);
+        assert!(steps.contains("oops"), "{}", steps);
+        assert!(steps.contains("5. trace: scripts ran "), "{}", steps);
+        assert!(steps.contains("\n     script:1\n"), "{}", steps);
+        let untraced = why(&["-e", "error('oops')"], false)?.to_string();
+        assert!(!untraced.contains(". trace: "), "{}", untraced);
+
+        let account = why(&["-e", "meta.seen = true"], false)?;
+        assert!(!account.failed);
+        assert_eq!(
+            "1. read: 25 bytes\n2. detect: YAML frontmatter between --- rules\n\
+             3. parse: keys draft\n4. fix: would change it\n     keys changed by script\n",
+            account.to_string()
+        );
+        Ok(())
+    }
+}